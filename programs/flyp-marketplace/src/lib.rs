@@ -1,21 +1,451 @@
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, solana_program::keccak, system_program};
 use anchor_spl::{
-    associated_token::AssociatedToken,
-    token::{self, Mint, Token, TokenAccount}
+    associated_token::{self, get_associated_token_address_with_program_id, AssociatedToken},
+    token::spl_token::native_mint,
+    token_2022,
+    token_interface::{self, Mint, TokenAccount, TokenInterface},
 };
-use mpl_token_metadata::types::Creator;
+use mpl_token_metadata::{accounts::Metadata, instructions::TransferV1CpiBuilder, types::Creator};
 
 
 declare_id!("BWMAGH4P6JzUrP5xsyGsX2LXQXkFnHWMwNg8PpYfNsRK");
 
+// Constants
+const FEE_DENOMINATOR: u64 = 10000; // For handling basis points (100% = 10000)
+const MARKETPLACE_FEE_SHARE: u64 = 9000; // 90% of the fee goes to the marketplace
+const SECOND_BIDDER_FEE_SHARE: u64 = 1000; // 10% of the fee goes to the second highest bidder
+const MAX_FEE_BPS: u64 = 1000; // Platform fee is capped at 10%
+const MAX_DEFAULT_ROYALTY_BPS: u64 = 1500; // Fallback royalty is capped at 15%
+const MAX_BUNDLE_SIZE: usize = 10; // Maximum number of NFTs in a bundle listing
+const PRICE_HISTORY_LEN: usize = 10; // Number of recent sales kept per NFT
+const AUCTION_EXTENSION_WINDOW: i64 = 60; // Late auction bids push the end back by a minute
+const MAX_CRANK_BATCH: usize = 10; // Maximum number of expired listings or bids closed per crank call
+const MAX_CANCEL_BATCH: usize = 10; // Maximum number of listings a seller cancels per call
+const MAX_SWEEP_SIZE: usize = 5; // Maximum number of listings bought in one floor sweep
+const MAX_BULK_BIDS: usize = 10; // Maximum number of bids placed in one bulk_place_bids call
+const ACCOUNT_VERSION: u8 = 1; // Schema version written to new listings and bids
+const DEFAULT_MIN_BID_INCREMENT_BPS: u64 = 100; // New bids must beat the best bid by 1%
+const MAX_CO_OWNERS: usize = 5; // Split listings are capped to stay within the compute budget
+const MAX_CACHED_CREATORS: usize = 5; // Token metadata allows at most five creators
+const MAX_LISTING_METADATA_LEN: usize = 200; // Room for an Arweave URI or a short note on a listing
+const MAX_CREATORS: usize = 5; // Safety bound on royalty transfers per sale, so a sale can't run out of compute
+const FEE_TIER_1_VOLUME: u128 = 10_000_000_000; // 10 SOL of volume unlocks tier 1
+const FEE_TIER_2_VOLUME: u128 = 100_000_000_000; // 100 SOL of volume unlocks tier 2
+const FEE_TIER_3_VOLUME: u128 = 1_000_000_000_000; // 1000 SOL of volume unlocks tier 3
+const MAX_FEE_TIER: u8 = 3; // Highest fee tier a trader can reach
+const MPL_TOKEN_AUTH_RULES_ID: Pubkey =
+    anchor_lang::solana_program::pubkey!("auth9SigNpDKz4sJJ1DfCTuZrZNSAgh9sFD3rboVmgg"); // Metaplex token authorization rules
+
 #[program]
 pub mod flyp_marketplace {
     use super::*;
+    use crate::helpers::*;
+
+    // Initialize the marketplace configuration
+    pub fn initialize_marketplace(ctx: Context<InitializeMarketplace>, fee_bps: u64) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+
+        let config = &mut ctx.accounts.config;
+        config.fee_bps = fee_bps;
+        config.fee_recipient = ctx.accounts.fee_recipient.key();
+        config.authority = ctx.accounts.authority.key();
+        config.paused = false;
+        config.bump = ctx.bumps.config;
+        config.rebate_unused_second_bidder_fee = false;
+        config.pending_authority = None;
+        config.crank_reward_lamports = 0;
+        config.enforce_royalties = false;
+        config.min_bid_increment = 0;
+        config.min_bid_increment_bps = DEFAULT_MIN_BID_INCREMENT_BPS;
+        config.royalty_override_bps = None;
+        config.royalty_override_collection = None;
+        config.enforce_nft = false;
+        config.default_royalty_bps = 0;
+        config.default_royalty_treasury = Pubkey::default();
+        config.expiry_grace_secs = 0;
+        config.maker_fee_bps = fee_bps;
+        config.taker_fee_bps = fee_bps;
+        config.maker_taker_fees = false;
+
+        ctx.accounts.fee_vault.bump = ctx.bumps.fee_vault;
+
+        Ok(())
+    }
+
+    // Update the platform fee
+    pub fn update_marketplace_fee(ctx: Context<UpdateMarketplaceFee>, fee_bps: u64) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+
+        // A flat fee replaces any separate maker and taker rates
+        let config = &mut ctx.accounts.config;
+        config.fee_bps = fee_bps;
+        config.maker_taker_fees = false;
+
+        Ok(())
+    }
+
+    // Update the platform fee and the wallet that fee withdrawals are paid to
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        fee_bps: u64,
+        fee_recipient: Pubkey,
+    ) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+
+        let config = &mut ctx.accounts.config;
+        let old_fee_bps = config.fee_bps;
+        let old_fee_recipient = config.fee_recipient;
+        config.fee_bps = fee_bps;
+        config.fee_recipient = fee_recipient;
+
+        emit!(ConfigUpdated {
+            authority: ctx.accounts.authority.key(),
+            old_fee_bps,
+            new_fee_bps: fee_bps,
+            old_fee_recipient,
+            new_fee_recipient: fee_recipient,
+        });
+
+        Ok(())
+    }
+
+    // Charge the side whose order was resting and the side that filled it at different rates
+    pub fn set_maker_taker_fees(
+        ctx: Context<UpdateConfig>,
+        maker_fee_bps: u64,
+        taker_fee_bps: u64,
+    ) -> Result<()> {
+        require!(maker_fee_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+        require!(taker_fee_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+
+        let config = &mut ctx.accounts.config;
+        config.maker_fee_bps = maker_fee_bps;
+        config.taker_fee_bps = taker_fee_bps;
+        config.maker_taker_fees = true;
+
+        Ok(())
+    }
+
+    // Choose whether an unused second-bidder fee is rebated to the seller
+    pub fn set_second_bidder_rebate(ctx: Context<UpdateConfig>, enabled: bool) -> Result<()> {
+        ctx.accounts.config.rebate_unused_second_bidder_fee = enabled;
+
+        Ok(())
+    }
+
+    // Require full creator royalties on every sale, e.g. for programmable NFTs
+    pub fn set_enforce_royalties(ctx: Context<UpdateConfig>, enabled: bool) -> Result<()> {
+        ctx.accounts.config.enforce_royalties = enabled;
+
+        Ok(())
+    }
+
+    // Reject listings of fungible or multi-edition mints
+    pub fn set_enforce_nft(ctx: Context<UpdateConfig>, enabled: bool) -> Result<()> {
+        ctx.accounts.config.enforce_nft = enabled;
+
+        Ok(())
+    }
+
+    // Set how much a new bid must beat the current best bid on the same NFT by
+    pub fn set_min_bid_increment(ctx: Context<UpdateConfig>, min_bid_increment: u64) -> Result<()> {
+        ctx.accounts.config.min_bid_increment = min_bid_increment;
+
+        Ok(())
+    }
+
+    // Set how much a new bid must beat the current best bid by, in basis points
+    pub fn set_min_bid_increment_bps(
+        ctx: Context<UpdateConfig>,
+        min_bid_increment_bps: u64,
+    ) -> Result<()> {
+        ctx.accounts.config.min_bid_increment_bps = min_bid_increment_bps;
+
+        Ok(())
+    }
+
+    // Honor a collection's announced royalty waiver ahead of a metadata update. Passing
+    // None for either value turns the override off.
+    pub fn update_royalty_override(
+        ctx: Context<UpdateConfig>,
+        royalty_override_collection: Option<Pubkey>,
+        royalty_override_bps: Option<u64>,
+    ) -> Result<()> {
+        require!(
+            royalty_override_bps.map_or(true, |bps| bps <= FEE_DENOMINATOR),
+            ErrorCode::InvalidRoyaltyOverride
+        );
+
+        let config = &mut ctx.accounts.config;
+        config.royalty_override_collection = royalty_override_collection;
+        config.royalty_override_bps = royalty_override_bps;
+
+        Ok(())
+    }
+
+    // Set the royalty paid to a treasury when a sale's metadata pays none
+    pub fn set_default_royalty(
+        ctx: Context<UpdateConfig>,
+        default_royalty_bps: u64,
+        default_royalty_treasury: Pubkey,
+    ) -> Result<()> {
+        require!(
+            default_royalty_bps <= MAX_DEFAULT_ROYALTY_BPS,
+            ErrorCode::InvalidDefaultRoyalty
+        );
+
+        let config = &mut ctx.accounts.config;
+        config.default_royalty_bps = default_royalty_bps;
+        config.default_royalty_treasury = default_royalty_treasury;
+
+        Ok(())
+    }
+
+    // Keep expired listings fillable for a grace period after their expiry
+    pub fn set_expiry_grace(ctx: Context<UpdateConfig>, expiry_grace_secs: i64) -> Result<()> {
+        require!(expiry_grace_secs >= 0, ErrorCode::InvalidExpiryGrace);
+        ctx.accounts.config.expiry_grace_secs = expiry_grace_secs;
+
+        Ok(())
+    }
+
+    // Set how much of an expired listing's rent goes to the crank that closes it
+    pub fn set_crank_reward(ctx: Context<UpdateConfig>, crank_reward_lamports: u64) -> Result<()> {
+        ctx.accounts.config.crank_reward_lamports = crank_reward_lamports;
+
+        Ok(())
+    }
+
+    // Nominate a new authority, who must accept before it takes effect
+    pub fn propose_authority_transfer(
+        ctx: Context<UpdateConfig>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.pending_authority = Some(new_authority);
+
+        emit!(AuthorityTransferProposed {
+            authority: config.authority,
+            pending_authority: new_authority,
+        });
+
+        Ok(())
+    }
+
+    // Complete an authority transfer as the nominated authority
+    pub fn accept_authority_transfer(ctx: Context<AcceptAuthorityTransfer>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let pending_authority = config
+            .pending_authority
+            .ok_or(ErrorCode::NoPendingAuthority)?;
+        require_keys_eq!(
+            ctx.accounts.new_authority.key(),
+            pending_authority,
+            ErrorCode::InvalidPendingAuthority
+        );
+
+        let old_authority = config.authority;
+        config.authority = pending_authority;
+        config.pending_authority = None;
+
+        emit!(AuthorityTransferCompleted {
+            old_authority,
+            new_authority: pending_authority,
+        });
+
+        Ok(())
+    }
+
+    // Pause or resume trading
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        ctx.accounts.config.paused = paused;
+
+        Ok(())
+    }
+
+    // Create the marketplace-wide stats account that sales roll into
+    pub fn initialize_market_stats(ctx: Context<InitializeMarketStats>) -> Result<()> {
+        let market_stats = &mut ctx.accounts.market_stats;
+        market_stats.total_volume = 0;
+        market_stats.total_sales = 0;
+        market_stats.total_fees_collected = 0;
+        market_stats.bump = ctx.bumps.market_stats;
+
+        Ok(())
+    }
+
+    // Open a trader's fee tier so their sales start counting toward reduced fees
+    pub fn initialize_fee_tier(ctx: Context<InitializeFeeTier>) -> Result<()> {
+        let fee_tier = &mut ctx.accounts.fee_tier;
+        if fee_tier.trader == Pubkey::default() {
+            fee_tier.trader = ctx.accounts.trader.key();
+            fee_tier.bump = ctx.bumps.fee_tier;
+        }
+
+        Ok(())
+    }
+
+    // Manually advance a trader to a higher fee tier
+    pub fn promote_fee_tier(ctx: Context<PromoteFeeTier>, tier: u8) -> Result<()> {
+        let fee_tier = &mut ctx.accounts.fee_tier;
+        require!(
+            tier > fee_tier.tier && tier <= MAX_FEE_TIER,
+            ErrorCode::InvalidFeeTier
+        );
+        fee_tier.tier = tier;
+
+        emit!(FeeTierPromoted {
+            trader: fee_tier.trader,
+            tier,
+            volume_traded: fee_tier.volume_traded,
+        });
+
+        Ok(())
+    }
+
+    // Withdraw accumulated fees of one payment mint from the fee vault
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        let seeds = &[b"fee_vault".as_ref(), &[ctx.accounts.fee_vault.bump]];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.fee_vault_token_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: ctx.accounts.fee_vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        emit!(FeesWithdrawn {
+            authority: ctx.accounts.authority.key(),
+            payment_mint: ctx.accounts.payment_mint.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // Withdraw accumulated SOL fees, which sit on the fee vault itself
+    pub fn withdraw_sol_fees(ctx: Context<WithdrawSolFees>, amount: u64) -> Result<()> {
+        let fee_vault = ctx.accounts.fee_vault.to_account_info();
+        let available = fee_vault
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(fee_vault.data_len()));
+        require!(amount <= available, ErrorCode::InsufficientFeeBalance);
+
+        LamportSource::Escrow(fee_vault)
+            .transfer(ctx.accounts.destination.to_account_info(), amount)?;
+
+        emit!(FeesWithdrawn {
+            authority: ctx.accounts.authority.key(),
+            payment_mint: native_mint::ID,
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // Sweep tokens stranded in a vault or bid escrow whose Listing or Bid no longer
+    // exists. `record` is that closed account, which also signs for bid escrows.
+    pub fn admin_recover(ctx: Context<AdminRecover>, target: RecoveryTarget) -> Result<()> {
+        let mint = ctx.accounts.mint.key();
+        let stuck_account = ctx.accounts.stuck_account.to_account_info();
+        let token_program = ctx.accounts.token_program.to_account_info();
+
+        let (record_key, authority, seeds): (Pubkey, AccountInfo, Vec<Vec<u8>>) = match target {
+            RecoveryTarget::Vault { seller } => {
+                let (vault_key, vault_bump) = Pubkey::find_program_address(
+                    &[b"vault", seller.as_ref(), mint.as_ref()],
+                    ctx.program_id,
+                );
+                require_keys_eq!(stuck_account.key(), vault_key, ErrorCode::InvalidRecoveryTarget);
+                let (listing_key, _) = Pubkey::find_program_address(
+                    &[b"listing", seller.as_ref(), mint.as_ref()],
+                    ctx.program_id,
+                );
+                (
+                    listing_key,
+                    stuck_account.clone(),
+                    vec![
+                        b"vault".to_vec(),
+                        seller.to_bytes().to_vec(),
+                        mint.to_bytes().to_vec(),
+                        vec![vault_bump],
+                    ],
+                )
+            }
+            RecoveryTarget::BidEscrow {
+                bidder,
+                nft_mint,
+                nonce,
+            } => {
+                let nonce = nonce.to_le_bytes();
+                let (bid_key, bid_bump) = Pubkey::find_program_address(
+                    &[b"bid", bidder.as_ref(), nft_mint.as_ref(), nonce.as_ref()],
+                    ctx.program_id,
+                );
+                require_keys_eq!(
+                    stuck_account.key(),
+                    get_associated_token_address_with_program_id(&bid_key, &mint, &token_program.key()),
+                    ErrorCode::InvalidRecoveryTarget
+                );
+                (
+                    bid_key,
+                    ctx.accounts.record.to_account_info(),
+                    vec![
+                        b"bid".to_vec(),
+                        bidder.to_bytes().to_vec(),
+                        nft_mint.to_bytes().to_vec(),
+                        nonce.to_vec(),
+                        vec![bid_bump],
+                    ],
+                )
+            }
+        };
+
+        // Only accounts whose Listing or Bid has been closed are considered stuck
+        let record = &ctx.accounts.record;
+        require_keys_eq!(record.key(), record_key, ErrorCode::InvalidRecoveryTarget);
+        require!(
+            record.data_is_empty() && record.lamports() == 0,
+            ErrorCode::EscrowStillOwned
+        );
+
+        let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+        let signer = &[&seeds[..]];
+        let amount = ctx.accounts.stuck_account.amount;
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: stuck_account.clone(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: authority.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
 
-    // Constants
-    const FEE_DENOMINATOR: u64 = 10000; // For handling basis points (100% = 10000)
-    const MARKETPLACE_FEE_SHARE: u64 = 9000; // 90% of the fee goes to the marketplace
-    const SECOND_BIDDER_FEE_SHARE: u64 = 1000; // 10% of the fee goes to the second highest bidder
+        // Reclaim the stuck account's rent for the authority running the recovery
+        let cpi_accounts = token_interface::CloseAccount {
+            account: stuck_account.clone(),
+            destination: ctx.accounts.authority.to_account_info(),
+            authority,
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(FundsRecovered {
+            account: stuck_account.key(),
+            mint,
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+
+        Ok(())
+    }
 
     // Create a new listing
     pub fn create_listing(
@@ -23,26 +453,112 @@ pub mod flyp_marketplace {
         price: u64,
         quantity: u64,
         expiry: i64,
+        whitelist_enabled: bool,
+        payment_mint: Option<Pubkey>,
+        royalty_enforcement: RoyaltyEnforcementMode,
+        reserve_price: u64,
+        max_quantity_per_buyer: u64,
+        goes_live_at: i64,
+        buyer_allowlist: Option<Pubkey>,
+        listing_metadata: String,
     ) -> Result<()> {
+        log_compute_units("create_listing: start");
+
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(price > 0, ErrorCode::InvalidPrice);
+        require!(quantity > 0, ErrorCode::InvalidQuantity);
+        require!(
+            listing_metadata.len() <= MAX_LISTING_METADATA_LEN,
+            ErrorCode::MetadataTooLong
+        );
+        if ctx.accounts.config.enforce_nft {
+            require!(
+                ctx.accounts.nft_mint.decimals == 0 && ctx.accounts.nft_mint.supply == 1,
+                ErrorCode::NotAnNft
+            );
+        }
+
         let listing = &mut ctx.accounts.listing;
         let clock = Clock::get()?;
 
+        // An expiry of zero means the listing never expires
+        require!(
+            expiry == 0 || expiry > clock.unix_timestamp,
+            ErrorCode::ListingExpired
+        );
+        require!(
+            expiry == 0 || goes_live_at <= expiry,
+            ErrorCode::InvalidGoLiveTime
+        );
+
         listing.seller = ctx.accounts.seller.key();
         listing.nft_mint = ctx.accounts.nft_mint.key();
         listing.price = price;
         listing.quantity = quantity;
+        listing.listing_status = ListingStatus::Active;
         listing.created_at = clock.unix_timestamp;
         listing.expiry = expiry;
+        listing.token_program_type = if ctx.accounts.token_program.key() == token_2022::ID {
+            TokenProgramType::Token2022
+        } else {
+            TokenProgramType::Legacy
+        };
+        listing.whitelist_enabled = whitelist_enabled;
+        // The wrapped SOL mint is accepted as a sentinel for native SOL
+        listing.payment_mint = payment_mint.filter(|mint| *mint != native_mint::ID);
+        listing.royalty_enforcement = royalty_enforcement;
+        listing.reserve_price = reserve_price;
+        listing.max_quantity_per_buyer = max_quantity_per_buyer;
+        listing.goes_live_at = goes_live_at;
+        listing.bump = ctx.bumps.listing;
+        listing.version = ACCOUNT_VERSION;
+        listing.buyer_allowlist = buyer_allowlist;
+        listing.listing_metadata = listing_metadata.clone();
+        listing.auto_accept_threshold = 0;
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &listing.nft_mint)?;
+
+        // Cache the verified creators so sales don't depend on re-reading metadata
+        listing.creator_cache = cache_creators(&metadata.creators)?;
+
+        // A collection's price policy catches fat-fingered prices before the NFT is escrowed.
+        // The policy PDA is required for any verified collection so it can't be left out.
+        if let Some(collection) = metadata.collection.as_ref().filter(|c| c.verified) {
+            let policy_info = ctx
+                .accounts
+                .collection_policy
+                .as_ref()
+                .ok_or(ErrorCode::InvalidCollectionPolicy)?;
+            let (policy_key, _) = Pubkey::find_program_address(
+                &[b"policy", collection.key.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(
+                policy_info.key(),
+                policy_key,
+                ErrorCode::InvalidCollectionPolicy
+            );
+
+            if !policy_info.data_is_empty() {
+                let policy =
+                    CollectionPolicy::try_deserialize(&mut &policy_info.try_borrow_data()?[..])?;
+                require!(
+                    price >= policy.min_price && price <= policy.max_price,
+                    ErrorCode::PriceOutOfPolicyRange
+                );
+            }
+        }
 
         // Transfer NFT to PDA
-        let cpi_accounts = token::Transfer {
+        let cpi_accounts = token_interface::TransferChecked {
             from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
             to: ctx.accounts.vault_nft_account.to_account_info(),
             authority: ctx.accounts.seller.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, quantity)?;
+        token_interface::transfer_checked(cpi_ctx, quantity, ctx.accounts.nft_mint.decimals)?;
 
         emit!(ListingCreated {
             listing_id: listing.key(),
@@ -51,8 +567,16 @@ pub mod flyp_marketplace {
             price,
             quantity,
             expiry,
+            reserve_price,
+            goes_live_at,
+            buyer_allowlist,
+            vault: ctx.accounts.vault_nft_account.key(),
+            vault_bump: ctx.bumps.vault_nft_account,
+            listing_metadata,
         });
 
+        log_compute_units("create_listing: end");
+
         Ok(())
     }
 
@@ -63,574 +587,11399 @@ pub mod flyp_marketplace {
         // Transfer NFT back to seller
         let seeds = &[
             b"vault".as_ref(),
+            listing.seller.as_ref(),
             listing.nft_mint.as_ref(),
             &[ctx.bumps.vault_nft_account],
         ];
         let signer = &[&seeds[..]];
 
-        let cpi_accounts = token::Transfer {
+        let cpi_accounts = token_interface::TransferChecked {
             from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
             to: ctx.accounts.seller_nft_account.to_account_info(),
             authority: ctx.accounts.vault_nft_account.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, listing.quantity)?;
+        token_interface::transfer_checked(
+            cpi_ctx,
+            listing.quantity,
+            ctx.accounts.nft_mint.decimals,
+        )?;
+
+        // Reclaim the vault rent now that the NFT is back with the seller
+        ctx.accounts.vault_nft_account.reload()?;
+        require!(
+            ctx.accounts.vault_nft_account.amount == 0,
+            ErrorCode::VaultNotEmpty
+        );
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vault_nft_account.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(listing.seller, ctx.bumps.seller_stats)?;
+        seller_stats.total_cancellations = seller_stats
+            .total_cancellations
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
 
+        // The close constraint wipes the listing once this returns
+        listing.listing_status = ListingStatus::Cancelled;
         emit!(ListingCancelled {
             listing_id: listing.key(),
             seller: ctx.accounts.seller.key(),
             nft_mint: listing.nft_mint,
         });
+        emit!(ListingClosed {
+            listing_id: listing.key(),
+            seller: listing.seller,
+            nft_mint: listing.nft_mint,
+            status: listing.listing_status,
+        });
 
         Ok(())
     }
 
-    // Execute a sale
-    pub fn execute_sale(ctx: Context<ExecuteSale>, second_highest_bid: u64) -> Result<()> {
-        let listing = &ctx.accounts.listing;
-        let metadata = &ctx.accounts.metadata;
+    // Cancel several of the seller's listings at once and return their NFTs.
+    // Expects (listing, nft_mint, vault_nft_account, seller_nft_account) groups in
+    // remaining_accounts.
+    pub fn batch_cancel_listings<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchCancelListings<'info>>,
+    ) -> Result<()> {
+        let groups = ctx.remaining_accounts;
+        require!(
+            !groups.is_empty() && groups.len() % 4 == 0,
+            ErrorCode::InvalidCancelBatch
+        );
+        require!(groups.len() / 4 <= MAX_CANCEL_BATCH, ErrorCode::BatchTooLarge);
 
-        // Calculate royalties
-        let (creator_payments, remaining_payment) = calculate_creator_payments(
-            listing.price,
-            &metadata.data.creators,
-        )?;
+        let seller = ctx.accounts.seller.to_account_info();
 
-        // Calculate platform fee and distribute it
-        let (marketplace_fee, second_bidder_fee, seller_payment) = calculate_and_distribute_fee(
-            remaining_payment,
-            second_highest_bid,
-        )?;
+        for group in groups.chunks(4) {
+            let mut listing = Account::<Listing>::try_from(&group[0])?;
+            let nft_mint = &group[1];
+            let vault_nft_account = &group[2];
+            let seller_nft_account = &group[3];
 
-        // Transfer payments
-        transfer_payments(
-            ctx,
-            seller_payment,
-            &creator_payments,
-            marketplace_fee,
-            second_bidder_fee,
-        )?;
+            require!(listing.version == ACCOUNT_VERSION, ErrorCode::UnsupportedVersion);
+            require_keys_eq!(listing.seller, seller.key(), ErrorCode::InvalidCancelBatch);
+            require_keys_eq!(
+                listing.key(),
+                Pubkey::create_program_address(
+                    &[
+                        b"listing",
+                        listing.seller.as_ref(),
+                        listing.nft_mint.as_ref(),
+                        &[listing.bump],
+                    ],
+                    ctx.program_id,
+                )
+                .map_err(|_| ErrorCode::InvalidCancelBatch)?,
+                ErrorCode::InvalidCancelBatch
+            );
+            require_keys_eq!(nft_mint.key(), listing.nft_mint, ErrorCode::InvalidCancelBatch);
+            let (vault_key, vault_bump) = Pubkey::find_program_address(
+                &[b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(vault_nft_account.key(), vault_key, ErrorCode::InvalidCancelBatch);
+            require_keys_eq!(
+                seller_nft_account.key(),
+                get_associated_token_address_with_program_id(
+                    &listing.seller,
+                    &listing.nft_mint,
+                    &ctx.accounts.token_program.key(),
+                ),
+                ErrorCode::InvalidCancelBatch
+            );
+            let mint = Mint::try_deserialize(&mut &nft_mint.try_borrow_data()?[..])?;
 
-        // Transfer NFT from vault to buyer
+            // Transfer NFT back to seller
+            let seeds = &[
+                b"vault".as_ref(),
+                listing.seller.as_ref(),
+                listing.nft_mint.as_ref(),
+                &[vault_bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = token_interface::TransferChecked {
+                from: vault_nft_account.clone(),
+                mint: nft_mint.clone(),
+                to: seller_nft_account.clone(),
+                authority: vault_nft_account.clone(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::transfer_checked(cpi_ctx, listing.quantity, mint.decimals)?;
+
+            // Reclaim the vault rent now that the NFT is back with the seller
+            let vault = TokenAccount::try_deserialize(&mut &vault_nft_account.try_borrow_data()?[..])?;
+            require!(vault.amount == 0, ErrorCode::VaultNotEmpty);
+            let cpi_accounts = token_interface::CloseAccount {
+                account: vault_nft_account.clone(),
+                destination: seller.clone(),
+                authority: vault_nft_account.clone(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+
+            close_listing(&mut listing, ListingStatus::Cancelled, seller.clone())?;
+
+            emit!(ListingCancelled {
+                listing_id: listing.key(),
+                seller: listing.seller,
+                nft_mint: listing.nft_mint,
+            });
+        }
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(seller.key(), ctx.bumps.seller_stats)?;
+        seller_stats.total_cancellations = seller_stats
+            .total_cancellations
+            .checked_add((groups.len() / 4) as u64)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    // Close expired listings and return their NFTs to the sellers.
+    // Expects (listing, seller, nft_mint, vault_nft_account, seller_nft_account) groups in
+    // remaining_accounts. The crank is paid up to crank_reward_lamports out of each
+    // listing's rent and the rest of the rent goes back to the seller.
+    pub fn batch_cancel_expired_listings<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchCancelExpiredListings<'info>>,
+    ) -> Result<()> {
+        let groups = ctx.remaining_accounts;
+        require!(
+            !groups.is_empty() && groups.len() % 5 == 0 && groups.len() / 5 <= MAX_CRANK_BATCH,
+            ErrorCode::InvalidCrankBatch
+        );
+
+        let clock = Clock::get()?;
+        let crank_operator = ctx.accounts.crank_operator.to_account_info();
+
+        for group in groups.chunks(5) {
+            let mut listing = Account::<Listing>::try_from(&group[0])?;
+            let seller = &group[1];
+            let nft_mint = &group[2];
+            let vault_nft_account = &group[3];
+            let seller_nft_account = &group[4];
+
+            require!(listing.version == ACCOUNT_VERSION, ErrorCode::UnsupportedVersion);
+            require!(
+                listing_expired(listing.expiry, &ctx.accounts.config, clock.unix_timestamp),
+                ErrorCode::ListingNotExpired
+            );
+            require_keys_eq!(
+                listing.key(),
+                Pubkey::create_program_address(
+                    &[
+                        b"listing",
+                        listing.seller.as_ref(),
+                        listing.nft_mint.as_ref(),
+                        &[listing.bump],
+                    ],
+                    ctx.program_id,
+                )
+                .map_err(|_| ErrorCode::InvalidCrankBatch)?,
+                ErrorCode::InvalidCrankBatch
+            );
+            require_keys_eq!(seller.key(), listing.seller, ErrorCode::InvalidCrankBatch);
+            require_keys_eq!(nft_mint.key(), listing.nft_mint, ErrorCode::InvalidCrankBatch);
+            let (vault_key, vault_bump) = Pubkey::find_program_address(
+                &[b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(vault_nft_account.key(), vault_key, ErrorCode::InvalidCrankBatch);
+            require_keys_eq!(
+                seller_nft_account.key(),
+                get_associated_token_address_with_program_id(
+                    &listing.seller,
+                    &listing.nft_mint,
+                    &ctx.accounts.token_program.key(),
+                ),
+                ErrorCode::InvalidCrankBatch
+            );
+            let mint = Mint::try_deserialize(&mut &nft_mint.try_borrow_data()?[..])?;
+
+            // Transfer NFT back to seller
+            let seeds = &[
+                b"vault".as_ref(),
+                listing.seller.as_ref(),
+                listing.nft_mint.as_ref(),
+                &[vault_bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = token_interface::TransferChecked {
+                from: vault_nft_account.clone(),
+                mint: nft_mint.clone(),
+                to: seller_nft_account.clone(),
+                authority: vault_nft_account.clone(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::transfer_checked(cpi_ctx, listing.quantity, mint.decimals)?;
+
+            // Pay the crank out of the listing rent, then close it to the seller
+            let listing_info = listing.to_account_info();
+            let crank_reward = listing_info
+                .lamports()
+                .min(ctx.accounts.config.crank_reward_lamports);
+            LamportSource::Escrow(listing_info).transfer(crank_operator.clone(), crank_reward)?;
+            close_listing(&mut listing, ListingStatus::Expired, seller.clone())?;
+
+            emit!(ListingExpiredClosed {
+                listing_id: listing.key(),
+                seller: listing.seller,
+                nft_mint: listing.nft_mint,
+                crank_operator: crank_operator.key(),
+                crank_reward,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Let anyone return an expired listing's NFT to the seller. The keeper is tipped
+    // up to crank_reward_lamports out of the listing rent and the seller gets the rest.
+    pub fn close_expired_listing(ctx: Context<CloseExpiredListing>) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let clock = Clock::get()?;
+
+        // Listings in their grace period can still be bought, so they aren't closed yet
+        require!(
+            listing_expired(listing.expiry, &ctx.accounts.config, clock.unix_timestamp),
+            ErrorCode::ListingNotExpired
+        );
+
+        // Transfer NFT back to seller
         let seeds = &[
             b"vault".as_ref(),
+            listing.seller.as_ref(),
             listing.nft_mint.as_ref(),
             &[ctx.bumps.vault_nft_account],
         ];
         let signer = &[&seeds[..]];
 
-        let cpi_accounts = token::Transfer {
+        let cpi_accounts = token_interface::TransferChecked {
             from: ctx.accounts.vault_nft_account.to_account_info(),
-            to: ctx.accounts.buyer_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.seller_nft_account.to_account_info(),
             authority: ctx.accounts.vault_nft_account.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, 1)?;
+        token_interface::transfer_checked(
+            cpi_ctx,
+            listing.quantity,
+            ctx.accounts.nft_mint.decimals,
+        )?;
 
-        // Update or close the listing
-        if ctx.accounts.listing.quantity == 1 {
-            // Close the listing account
-            let dest_account_info = ctx.accounts.seller.to_account_info();
-            let close_account_info = ctx.accounts.listing.to_account_info();
-            let dest_starting_lamports = dest_account_info.lamports();
-            **dest_account_info.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(close_account_info.lamports())
-                .unwrap();
-            **close_account_info.lamports.borrow_mut() = 0;
-        } else {
-            ctx.accounts.listing.quantity -= 1;
-        }
+        // Tip the keeper out of the listing rent, then close it to the seller
+        let listing_info = listing.to_account_info();
+        let crank_reward = listing_info
+            .lamports()
+            .min(ctx.accounts.config.crank_reward_lamports);
+        LamportSource::Escrow(listing_info)
+            .transfer(ctx.accounts.keeper.to_account_info(), crank_reward)?;
 
-        emit!(SaleExecuted {
+        emit!(ListingExpiredClosed {
             listing_id: listing.key(),
-            buyer: ctx.accounts.buyer.key(),
             seller: listing.seller,
             nft_mint: listing.nft_mint,
-            price: listing.price,
+            crank_operator: ctx.accounts.keeper.key(),
+            crank_reward,
         });
 
+        close_listing(
+            &mut ctx.accounts.listing,
+            ListingStatus::Expired,
+            ctx.accounts.seller.to_account_info(),
+        )?;
+
         Ok(())
-    } 
+    }
 
-    // Place a bid on an NFT
-    pub fn place_bid(ctx: Context<PlaceBid>, price: u64, expiry: i64) -> Result<()> {
-        let bid = &mut ctx.accounts.bid;
-        let clock = Clock::get()?;
+    // Return the rent of the seller's vault once it is empty and its listing has closed
+    pub fn close_empty_vault(ctx: Context<CloseEmptyVault>) -> Result<()> {
+        require!(
+            ctx.accounts.listing.data_is_empty(),
+            ErrorCode::ListingStillOpen
+        );
 
-        bid.bidder = ctx.accounts.bidder.key();
-        bid.nft_mint = ctx.accounts.nft_mint.key();
-        bid.price = price;
-        bid.created_at = clock.unix_timestamp;
-        bid.expiry = expiry;
+        let seller = ctx.accounts.seller.key();
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let seeds = &[
+            b"vault".as_ref(),
+            seller.as_ref(),
+            nft_mint.as_ref(),
+            &[ctx.bumps.vault_nft_account],
+        ];
+        let signer = &[&seeds[..]];
 
-        // Transfer bid amount to escrow
-        let cpi_accounts = token::Transfer {
-            from: ctx.accounts.bidder_payment_account.to_account_info(),
-            to: ctx.accounts.escrow_payment_account.to_account_info(),
-            authority: ctx.accounts.bidder.to_account_info(),
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vault_nft_account.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, price)?;
-
-        emit!(BidPlaced {
-            bid_id: bid.key(),
-            bidder: ctx.accounts.bidder.key(),
-            nft_mint: ctx.accounts.nft_mint.key(),
-            price,
-            expiry,
-        });
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
 
         Ok(())
     }
 
-    // Cancel an existing bid
-    pub fn cancel_bid(ctx: Context<CancelBid>) -> Result<()> {
-        let bid = &ctx.accounts.bid;
+    // Mark expired listings as Expired without closing them, so other programs can
+    // read liveness straight from the account. Expects listings in remaining_accounts.
+    pub fn sweep_expired_listings<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SweepExpiredListings<'info>>,
+    ) -> Result<()> {
+        let listings = ctx.remaining_accounts;
+        require!(
+            !listings.is_empty() && listings.len() <= MAX_CRANK_BATCH,
+            ErrorCode::InvalidCrankBatch
+        );
 
-        // Transfer bid amount back to bidder
-        let seeds = &[
-            b"escrow".as_ref(),
-            bid.nft_mint.as_ref(),
-            bid.bidder.as_ref(),
-            &[ctx.bumps.escrow_payment_account],
-        ];
-        let signer = &[&seeds[..]];
+        let clock = Clock::get()?;
 
-        let cpi_accounts = token::Transfer {
-            from: ctx.accounts.escrow_payment_account.to_account_info(),
-            to: ctx.accounts.bidder_payment_account.to_account_info(),
-            authority: ctx.accounts.escrow_payment_account.to_account_info(),
+        for listing_info in listings {
+            let mut listing = Account::<Listing>::try_from(listing_info)?;
+
+            require!(listing.version == ACCOUNT_VERSION, ErrorCode::UnsupportedVersion);
+            require!(
+                listing_expired(listing.expiry, &ctx.accounts.config, clock.unix_timestamp),
+                ErrorCode::ListingNotExpired
+            );
+            require!(
+                listing.listing_status == ListingStatus::Active,
+                ErrorCode::ListingNotActive
+            );
+
+            listing.listing_status = ListingStatus::Expired;
+            listing.exit(ctx.program_id)?;
+
+            emit!(ListingMarkedExpired {
+                listing_id: listing.key(),
+                seller: listing.seller,
+                nft_mint: listing.nft_mint,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Update the price of an existing listing
+    pub fn update_listing_price(ctx: Context<UpdateListingPrice>, price: u64) -> Result<()> {
+        require!(price > 0, ErrorCode::InvalidPrice);
+
+        let listing = &mut ctx.accounts.listing;
+        let old_price = listing.price;
+        listing.price = price;
+
+        emit!(ListingPriceUpdated {
+            listing_id: listing.key(),
+            seller: ctx.accounts.seller.key(),
+            nft_mint: listing.nft_mint,
+            old_price,
+            new_price: price,
+        });
+
+        Ok(())
+    }
+
+    // Add more units of the listed mint to an existing listing
+    pub fn update_listing_quantity(
+        ctx: Context<UpdateListingQuantity>,
+        additional_quantity: u64,
+    ) -> Result<()> {
+        require!(additional_quantity > 0, ErrorCode::InvalidQuantity);
+        require!(
+            ctx.accounts.seller_nft_account.amount >= additional_quantity,
+            ErrorCode::InsufficientNftBalance
+        );
+
+        // Transfer the extra units to the vault
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.vault_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, bid.price)?;
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(
+            cpi_ctx,
+            additional_quantity,
+            ctx.accounts.nft_mint.decimals,
+        )?;
 
-        emit!(BidCancelled {
-            bid_id: bid.key(),
-            bidder: bid.bidder,
-            nft_mint: bid.nft_mint,
+        let listing = &mut ctx.accounts.listing;
+        let old_quantity = listing.quantity;
+        listing.quantity = old_quantity
+            .checked_add(additional_quantity)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(ListingQuantityUpdated {
+            listing_id: listing.key(),
+            seller: ctx.accounts.seller.key(),
+            nft_mint: listing.nft_mint,
+            old_quantity,
+            new_quantity: listing.quantity,
         });
 
         Ok(())
     }
 
-    // Accept a bid
-    pub fn accept_bid(ctx: Context<AcceptBid>, second_highest_bid: u64) -> Result<()> {
-        let bid = &ctx.accounts.bid;
-        let metadata = &ctx.accounts.metadata;
+    // Restrict a listing to one buyer, or open it to everyone with None
+    pub fn update_listing_buyer_allowlist(
+        ctx: Context<UpdateListingPrice>,
+        new_buyer: Option<Pubkey>,
+    ) -> Result<()> {
+        let listing = &mut ctx.accounts.listing;
+        listing.buyer_allowlist = new_buyer;
 
-        // Calculate royalties
-        let (creator_payments, remaining_payment) = calculate_creator_payments(
-            bid.price,
-            &metadata.data.creators,
+        emit!(ListingBuyerAllowlistUpdated {
+            listing_id: listing.key(),
+            seller: ctx.accounts.seller.key(),
+            nft_mint: listing.nft_mint,
+            buyer_allowlist: new_buyer,
+        });
+
+        Ok(())
+    }
+
+    // Replace the seller's URI or note on a listing; the escrowed NFT is untouched
+    pub fn update_listing_metadata(
+        ctx: Context<UpdateListingPrice>,
+        listing_metadata: String,
+    ) -> Result<()> {
+        require!(
+            listing_metadata.len() <= MAX_LISTING_METADATA_LEN,
+            ErrorCode::MetadataTooLong
+        );
+
+        let listing = &mut ctx.accounts.listing;
+        listing.listing_metadata = listing_metadata.clone();
+
+        emit!(ListingMetadataUpdated {
+            listing_id: listing.key(),
+            seller: ctx.accounts.seller.key(),
+            nft_mint: listing.nft_mint,
+            listing_metadata,
+        });
+
+        Ok(())
+    }
+
+    // Let bids at or above the threshold buy the listing immediately; zero turns this off
+    pub fn set_auto_accept_threshold(
+        ctx: Context<UpdateListingPrice>,
+        auto_accept_threshold: u64,
+    ) -> Result<()> {
+        let listing = &mut ctx.accounts.listing;
+        listing.auto_accept_threshold = auto_accept_threshold;
+
+        emit!(ListingAutoAcceptUpdated {
+            listing_id: listing.key(),
+            seller: ctx.accounts.seller.key(),
+            nft_mint: listing.nft_mint,
+            auto_accept_threshold,
+        });
+
+        Ok(())
+    }
+
+    // Lower the reserve price of a listing; raising it would strand existing bidders
+    pub fn update_reserve_price(
+        ctx: Context<UpdateListingPrice>,
+        reserve_price: u64,
+    ) -> Result<()> {
+        let listing = &mut ctx.accounts.listing;
+        require!(
+            reserve_price <= listing.reserve_price,
+            ErrorCode::ReservePriceIncrease
+        );
+
+        let old_reserve_price = listing.reserve_price;
+        listing.reserve_price = reserve_price;
+
+        emit!(ReservePriceUpdated {
+            listing_id: listing.key(),
+            seller: ctx.accounts.seller.key(),
+            nft_mint: listing.nft_mint,
+            old_reserve_price,
+            new_reserve_price: reserve_price,
+        });
+
+        Ok(())
+    }
+
+    // Execute a sale
+    pub fn execute_sale(
+        ctx: Context<ExecuteSale>,
+        max_price: u64,
+        second_highest_bid: u64,
+        whitelist_proof: Option<Vec<[u8; 32]>>,
+        buy_quantity: u64,
+    ) -> Result<()> {
+        log_compute_units("execute_sale: start");
+
+        let listing = &ctx.accounts.listing;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require!(buy_quantity > 0, ErrorCode::InvalidQuantity);
+        require!(buy_quantity <= listing.quantity, ErrorCode::InsufficientQuantity);
+
+        // Protect the buyer from paying more than the unit price they signed for
+        require!(listing.price <= max_price, ErrorCode::PriceExceedsMax);
+        let total_price = listing
+            .price
+            .checked_mul(buy_quantity)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Sellers cannot buy their own listing to fake volume
+        require_keys_neq!(ctx.accounts.buyer.key(), listing.seller, ErrorCode::SelfTrade);
+
+        require!(
+            listing.payment_mint == Some(ctx.accounts.payment_mint.key()),
+            ErrorCode::PaymentMintMismatch
+        );
+
+        // Cap how many units one wallet can take from the listing
+        let buyer_record = &mut ctx.accounts.buyer_record;
+        buyer_record.listing = listing.key();
+        buyer_record.buyer = ctx.accounts.buyer.key();
+        buyer_record.bump = ctx.bumps.buyer_record;
+        buyer_record.record_purchase(listing, buy_quantity)?;
+
+        // An expiry of zero means the listing never expires
+        require!(
+            !listing_expired(listing.expiry, config, clock.unix_timestamp),
+            ErrorCode::ListingExpired
+        );
+        require!(
+            clock.unix_timestamp >= listing.goes_live_at,
+            ErrorCode::ListingNotYetLive
+        );
+        if let Some(allowed_buyer) = listing.buyer_allowlist {
+            require_keys_eq!(
+                ctx.accounts.buyer.key(),
+                allowed_buyer,
+                ErrorCode::UnauthorizedBuyer
+            );
+        }
+
+        // Only trust creator shares from metadata that belongs to the traded mint
+        let metadata = load_metadata(&ctx.accounts.metadata, &listing.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &listing.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // Allowlisted listings only sell to buyers proven to be in the collection's merkle tree
+        if listing.whitelist_enabled {
+            check_whitelist(
+                ctx.accounts.whitelist.as_ref(),
+                &metadata,
+                &ctx.accounts.buyer.key(),
+                whitelist_proof.as_deref(),
+            )?;
+        }
+
+        // Calculate royalties according to the listing's enforcement mode
+        let royalty_enforcement = effective_royalty_enforcement(listing.royalty_enforcement, config);
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            royalty_enforcement,
+            &metadata,
+            &listing_creators(&listing.creator_cache, &metadata.creators),
+            total_price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties && royalty_enforcement == RoyaltyEnforcementMode::Mandatory {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        let second_highest_bid = validate_second_bid(
+            ctx.accounts.second_bid.as_ref(),
+            second_highest_bid,
+            listing.payment_mint,
+            &ctx.accounts.second_bidder_account.owner,
+            clock.unix_timestamp,
         )?;
 
-        // Calculate platform fee and distribute it
-        let (marketplace_fee, second_bidder_fee, seller_payment) = calculate_and_distribute_fee(
+        // The seller's listing was resting, so they pay the maker rate
+        let (marketplace_fee, second_bidder_fee, seller_payment) = calculate_and_distribute_fee_at(
             remaining_payment,
+            config.seller_fee_bps(Maker::Seller),
+            config,
             second_highest_bid,
         )?;
+        // The buyer took the listing, so they pay the taker rate on top of the price
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+        let buyer_fee = calculate_buyer_fee(total_price, buyer_fee_bps)?;
+        record_fee_tier_volume(&ctx.accounts.fee_tier, listing.payment_mint, total_price)?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
 
         // Transfer payments
+        let source = PaymentSource {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: &[],
+        };
+        let total_creator_royalty: u64 = creator_payments.iter().map(|(_, amount)| amount).sum();
+        let lazy_royalties = listing.royalty_enforcement == RoyaltyEnforcementMode::Lazy;
+        let direct_creator_payments: &[(Pubkey, u64)] = if lazy_royalties {
+            &[]
+        } else {
+            &creator_payments
+        };
+        if lazy_royalties {
+            // One transfer into the royalty vault instead of one per creator
+            source.transfer(
+                ctx.accounts.royalty_vault.to_account_info(),
+                total_creator_royalty,
+            )?;
+            escrow_royalties(
+                ctx.remaining_accounts,
+                &creator_payments,
+                &listing.nft_mint,
+                &ctx.accounts.payment_mint.key(),
+                ctx.accounts.collection_stats.sale_count,
+                &ctx.accounts.buyer.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+        }
         transfer_payments(
-            ctx,
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            Some(ctx.accounts.second_bidder_account.to_account_info()),
             seller_payment,
-            &creator_payments,
+            direct_creator_payments,
             marketplace_fee,
             second_bidder_fee,
         )?;
 
-        // Transfer NFT to bidder
-        let cpi_accounts = token::Transfer {
-            from: ctx.accounts.seller_nft_account.to_account_info(),
-            to: ctx.accounts.bidder_nft_account.to_account_info(),
-            authority: ctx.accounts.seller.to_account_info(),
+        // Transfer NFT from vault to buyer
+        let seeds = &[
+            b"vault".as_ref(),
+            listing.seller.as_ref(),
+            listing.nft_mint.as_ref(),
+            &[ctx.bumps.vault_nft_account],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.buyer_nft_account.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, 1)?;
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, buy_quantity, ctx.accounts.nft_mint.decimals)?;
 
-        emit!(BidAccepted {
-            bid_id: bid.key(),
-            seller: ctx.accounts.seller.key(),
-            bidder: bid.bidder,
-            nft_mint: bid.nft_mint,
-            price: bid.price,
+        // Record the sale in the on-chain price history
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = listing.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: listing.price,
+            buyer: ctx.accounts.buyer.key(),
+            timestamp: clock.unix_timestamp,
         });
 
-        Ok(())
-    }
+        // Roll the sale into the collection's stats
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(total_price, clock.unix_timestamp)?;
 
-    // Helper Functions
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(listing.seller, ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(total_price)?;
 
-    pub fn calculate_creator_payments(
-        ctx: Context<ExecuteSale>,
-        price: u64,
-        creators: &Option<Vec<Creator>>,
-    ) -> Result<(Vec<(Pubkey, u64)>, u64)> {
-        let mut creator_payments = Vec::new();
-        let mut remaining_payment = price;
+        if let Some(market_stats) = ctx.accounts.market_stats.as_mut() {
+            market_stats.record_sale(total_price, marketplace_fee)?;
+        }
 
-        if let Some(creators) = creators {
-            for creator in creators {
-                if creator.verified {
-                    let creator_fee = (price as u128)
-                        .checked_mul(creator.share as u128)
-                        .unwrap()
-                        .checked_div(100)
-                        .unwrap() as u64;
-                    creator_payments.push((creator.address, creator_fee));
-                    remaining_payment = remaining_payment.checked_sub(creator_fee).unwrap();
-                }
-            }
+        emit!(SaleExecuted {
+            listing_id: listing.key(),
+            buyer: ctx.accounts.buyer.key(),
+            seller: listing.seller,
+            nft_mint: listing.nft_mint,
+            payment_mint: listing.payment_mint,
+            price: total_price,
+            marketplace_fee,
+            second_bidder: ctx.accounts.second_bidder_account.owner,
+            second_bidder_fee,
+            total_creator_royalty,
+            seller_payment,
+        });
+
+        // Update or close the listing
+        if ctx.accounts.listing.quantity == buy_quantity {
+            // Only close out once the vault is drained, and reclaim its rent along with the listing's
+            ctx.accounts.vault_nft_account.reload()?;
+            require!(
+                ctx.accounts.vault_nft_account.amount == 0,
+                ErrorCode::VaultNotEmpty
+            );
+            let cpi_accounts = token_interface::CloseAccount {
+                account: ctx.accounts.vault_nft_account.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: ctx.accounts.vault_nft_account.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+
+            ctx.accounts.buyer_record.close(ctx.accounts.buyer.to_account_info())?;
+            close_listing(
+                &mut ctx.accounts.listing,
+                ListingStatus::SoldOut,
+                ctx.accounts.seller.to_account_info(),
+            )?;
+        } else {
+            ctx.accounts.listing.quantity -= buy_quantity;
         }
 
-        Ok((creator_payments, remaining_payment))
-    }
+        log_compute_units("execute_sale: end");
 
-    pub fn calculate_and_distribute_fee(
-        ctx: Context<ExecuteSale>,
-        amount: u64,
+        Ok(())
+    } 
+
+    // Execute a sale of a listing priced in native SOL
+    pub fn execute_sale_sol(
+        ctx: Context<ExecuteSaleSol>,
+        max_price: u64,
         second_highest_bid: u64,
-    ) -> Result<(u64, u64, u64)> {
-        let platform_fee_bps = 250; // 2.5%
-        let total_fee = (amount as u128)
-            .checked_mul(platform_fee_bps as u128)
-            .unwrap()
-            .checked_div(FEE_DENOMINATOR as u128)
-            .unwrap() as u64;
+        whitelist_proof: Option<Vec<[u8; 32]>>,
+        buy_quantity: u64,
+    ) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
 
-        let marketplace_fee = (total_fee as u128)
-            .checked_mul(MARKETPLACE_FEE_SHARE as u128)
-            .unwrap()
-            .checked_div(FEE_DENOMINATOR as u128)
-            .unwrap() as u64;
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require!(buy_quantity > 0, ErrorCode::InvalidQuantity);
+        require!(buy_quantity <= listing.quantity, ErrorCode::InsufficientQuantity);
 
-        let second_bidder_fee = (total_fee as u128)
-            .checked_mul(SECOND_BIDDER_FEE_SHARE as u128)
-            .unwrap()
-            .checked_div(FEE_DENOMINATOR as u128)
-            .unwrap() as u64;
+        // Protect the buyer from paying more than the unit price they signed for
+        require!(listing.price <= max_price, ErrorCode::PriceExceedsMax);
+        let total_price = listing
+            .price
+            .checked_mul(buy_quantity)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
 
-        let adjusted_second_bidder_fee = std::cmp::min(second_bidder_fee, second_highest_bid);
-        let adjusted_marketplace_fee = marketplace_fee + (second_bidder_fee - adjusted_second_bidder_fee);
+        require_keys_neq!(ctx.accounts.buyer.key(), listing.seller, ErrorCode::SelfTrade);
+        require!(
+            !listing_expired(listing.expiry, config, clock.unix_timestamp),
+            ErrorCode::ListingExpired
+        );
+        require!(
+            clock.unix_timestamp >= listing.goes_live_at,
+            ErrorCode::ListingNotYetLive
+        );
+        if let Some(allowed_buyer) = listing.buyer_allowlist {
+            require_keys_eq!(
+                ctx.accounts.buyer.key(),
+                allowed_buyer,
+                ErrorCode::UnauthorizedBuyer
+            );
+        }
 
-        let seller_payment = amount.checked_sub(total_fee).unwrap();
+        // Cap how many units one wallet can take from the listing
+        let buyer_record = &mut ctx.accounts.buyer_record;
+        buyer_record.listing = listing.key();
+        buyer_record.buyer = ctx.accounts.buyer.key();
+        buyer_record.bump = ctx.bumps.buyer_record;
+        buyer_record.record_purchase(listing, buy_quantity)?;
 
-        Ok((adjusted_marketplace_fee, adjusted_second_bidder_fee, seller_payment))
-    }
+        let metadata = load_metadata(&ctx.accounts.metadata, &listing.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &listing.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
 
-    pub fn transfer_payments(
-        ctx: Context<ExecuteSale>,
-        seller_payment: u64,
-        creator_payments: &[(Pubkey, u64)],
-        marketplace_fee: u64,
-        second_bidder_fee: u64,
-    ) -> Result<()> {
-        // Transfer to seller
-        if seller_payment > 0 {
-            let cpi_accounts = token::Transfer {
-                from: ctx.accounts.buyer_payment_account.to_account_info(),
-                to: ctx.accounts.seller_payment_account.to_account_info(),
-                authority: ctx.accounts.buyer.to_account_info(),
-            };
-            let cpi_program = ctx.accounts.token_program.to_account_info();
-            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-            token::transfer(cpi_ctx, seller_payment)?;
+        if listing.whitelist_enabled {
+            check_whitelist(
+                ctx.accounts.whitelist.as_ref(),
+                &metadata,
+                &ctx.accounts.buyer.key(),
+                whitelist_proof.as_deref(),
+            )?;
         }
 
-        // Transfer to creators
-        for (creator, amount) in creator_payments {
-            if *amount > 0 {
-                let creator_account = next_account_info(ctx.remaining_accounts.iter())?;
-                let cpi_accounts = token::Transfer {
-                    from: ctx.accounts.buyer_payment_account.to_account_info(),
-                    to: creator_account.to_account_info(),
-                    authority: ctx.accounts.buyer.to_account_info(),
-                };
-                let cpi_program = ctx.accounts.token_program.to_account_info();
-                let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-                token::transfer(cpi_ctx, *amount)?;
-            }
+        // Calculate royalties according to the listing's enforcement mode. Creators are
+        // paid directly, since the lazy royalty vault only holds tokens.
+        let royalty_enforcement = effective_royalty_enforcement(listing.royalty_enforcement, config);
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            royalty_enforcement,
+            &metadata,
+            &listing_creators(&listing.creator_cache, &metadata.creators),
+            total_price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties && royalty_enforcement == RoyaltyEnforcementMode::Mandatory {
+            check_creator_wallets(&creator_payments, ctx.remaining_accounts)?;
         }
 
-        // Transfer marketplace fee
-        if marketplace_fee > 0 {
-            let cpi_accounts = token::Transfer {
-                from: ctx.accounts.buyer_payment_account.to_account_info(),
-                to: ctx.accounts.marketplace_fee_account.to_account_info(),
-                authority: ctx.accounts.buyer.to_account_info(),
-            };
-            let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, marketplace_fee)?;
-    }
+        let second_highest_bid = validate_second_bid(
+            ctx.accounts.second_bid.as_ref(),
+            second_highest_bid,
+            None,
+            &ctx.accounts.second_bidder_account.key(),
+            clock.unix_timestamp,
+        )?;
 
-    // Transfer fee to second highest bidder
-    if second_bidder_fee > 0 {
-        let cpi_accounts = token::Transfer {
-            from: ctx.accounts.buyer_payment_account.to_account_info(),
-            to: ctx.accounts.second_bidder_account.to_account_info(),
-            authority: ctx.accounts.buyer.to_account_info(),
+        // The seller's listing was resting, so they pay the maker rate
+        let (marketplace_fee, second_bidder_fee, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Seller),
+            config,
+            second_highest_bid,
+        )?;
+        // The buyer took the listing, so they pay the taker rate on top of the price
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+        let buyer_fee = calculate_buyer_fee(total_price, buyer_fee_bps)?;
+        record_fee_tier_volume(&ctx.accounts.fee_tier, listing.payment_mint, total_price)?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Pay everyone in lamports straight from the buyer's wallet
+        let source = LamportSource::Wallet {
+            from: ctx.accounts.buyer.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
         };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, second_bidder_fee)?;
-    }
+        transfer_payments(
+            &source,
+            ctx.accounts.seller.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.fee_vault.to_account_info(),
+            Some(ctx.accounts.second_bidder_account.to_account_info()),
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            second_bidder_fee,
+        )?;
 
-    Ok(())
-}
+        // Transfer NFT from vault to buyer
+        let seeds = &[
+            b"vault".as_ref(),
+            listing.seller.as_ref(),
+            listing.nft_mint.as_ref(),
+            &[ctx.bumps.vault_nft_account],
+        ];
+        let signer = &[&seeds[..]];
 
-// Account structures
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.buyer_nft_account.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, buy_quantity, ctx.accounts.nft_mint.decimals)?;
 
-#[derive(Accounts)]
+        // Record the sale in the on-chain price history
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = listing.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: listing.price,
+            buyer: ctx.accounts.buyer.key(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        // Roll the sale into the collection's stats
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(total_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(listing.seller, ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(total_price)?;
+
+        if let Some(market_stats) = ctx.accounts.market_stats.as_mut() {
+            market_stats.record_sale(total_price, marketplace_fee)?;
+        }
+
+        emit!(SaleExecuted {
+            listing_id: listing.key(),
+            buyer: ctx.accounts.buyer.key(),
+            seller: listing.seller,
+            nft_mint: listing.nft_mint,
+            payment_mint: listing.payment_mint,
+            price: total_price,
+            marketplace_fee,
+            second_bidder: ctx.accounts.second_bidder_account.key(),
+            second_bidder_fee,
+            total_creator_royalty: creator_payments.iter().map(|(_, amount)| amount).sum(),
+            seller_payment,
+        });
+
+        // Update or close the listing
+        if ctx.accounts.listing.quantity == buy_quantity {
+            // Only close out once the vault is drained, and reclaim its rent along with the listing's
+            ctx.accounts.vault_nft_account.reload()?;
+            require!(
+                ctx.accounts.vault_nft_account.amount == 0,
+                ErrorCode::VaultNotEmpty
+            );
+            let cpi_accounts = token_interface::CloseAccount {
+                account: ctx.accounts.vault_nft_account.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: ctx.accounts.vault_nft_account.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+
+            ctx.accounts.buyer_record.close(ctx.accounts.buyer.to_account_info())?;
+            close_listing(
+                &mut ctx.accounts.listing,
+                ListingStatus::SoldOut,
+                ctx.accounts.seller.to_account_info(),
+            )?;
+        } else {
+            ctx.accounts.listing.quantity -= buy_quantity;
+        }
+
+        Ok(())
+    }
+
+    // Execute a sale of a programmable NFT. The NFT leaves the vault through the token
+    // metadata program so the collection's authorization rules are enforced.
+    pub fn execute_sale_pnft(
+        ctx: Context<ExecuteSalePnft>,
+        max_price: u64,
+        second_highest_bid: u64,
+        whitelist_proof: Option<Vec<[u8; 32]>>,
+    ) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        // Programmable NFTs are one of one, so the sale always takes the whole listing
+        require!(listing.quantity == 1, ErrorCode::InvalidQuantity);
+
+        // Protect the buyer from paying more than the price they signed for
+        require!(listing.price <= max_price, ErrorCode::PriceExceedsMax);
+
+        // Sellers cannot buy their own listing to fake volume
+        require_keys_neq!(ctx.accounts.buyer.key(), listing.seller, ErrorCode::SelfTrade);
+
+        require!(
+            listing.payment_mint == Some(ctx.accounts.payment_mint.key()),
+            ErrorCode::PaymentMintMismatch
+        );
+
+        // An expiry of zero means the listing never expires
+        require!(
+            !listing_expired(listing.expiry, config, clock.unix_timestamp),
+            ErrorCode::ListingExpired
+        );
+        require!(
+            clock.unix_timestamp >= listing.goes_live_at,
+            ErrorCode::ListingNotYetLive
+        );
+        if let Some(allowed_buyer) = listing.buyer_allowlist {
+            require_keys_eq!(
+                ctx.accounts.buyer.key(),
+                allowed_buyer,
+                ErrorCode::UnauthorizedBuyer
+            );
+        }
+
+        // Only trust creator shares from metadata that belongs to the traded mint
+        let metadata = load_metadata(&ctx.accounts.metadata, &listing.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &listing.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // Allowlisted listings only sell to buyers proven to be in the collection's merkle tree
+        if listing.whitelist_enabled {
+            check_whitelist(
+                ctx.accounts.whitelist.as_ref(),
+                &metadata,
+                &ctx.accounts.buyer.key(),
+                whitelist_proof.as_deref(),
+            )?;
+        }
+
+        // Calculate royalties according to the listing's enforcement mode
+        let royalty_enforcement = effective_royalty_enforcement(listing.royalty_enforcement, config);
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            royalty_enforcement,
+            &metadata,
+            &listing_creators(&listing.creator_cache, &metadata.creators),
+            listing.price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties && royalty_enforcement == RoyaltyEnforcementMode::Mandatory {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        let second_highest_bid = validate_second_bid(
+            ctx.accounts.second_bid.as_ref(),
+            second_highest_bid,
+            listing.payment_mint,
+            &ctx.accounts.second_bidder_account.owner,
+            clock.unix_timestamp,
+        )?;
+
+        // The seller's listing was resting, so they pay the maker rate
+        let (marketplace_fee, second_bidder_fee, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Seller),
+            config,
+            second_highest_bid,
+        )?;
+        // The buyer took the listing, so they pay the taker rate on top of the price
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+        let buyer_fee = calculate_buyer_fee(listing.price, buyer_fee_bps)?;
+        record_fee_tier_volume(&ctx.accounts.fee_tier, listing.payment_mint, listing.price)?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments
+        let source = PaymentSource {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: &[],
+        };
+        let total_creator_royalty: u64 = creator_payments.iter().map(|(_, amount)| amount).sum();
+        let lazy_royalties = listing.royalty_enforcement == RoyaltyEnforcementMode::Lazy;
+        let direct_creator_payments: &[(Pubkey, u64)] = if lazy_royalties {
+            &[]
+        } else {
+            &creator_payments
+        };
+        if lazy_royalties {
+            // One transfer into the royalty vault instead of one per creator
+            source.transfer(
+                ctx.accounts.royalty_vault.to_account_info(),
+                total_creator_royalty,
+            )?;
+            escrow_royalties(
+                ctx.remaining_accounts,
+                &creator_payments,
+                &listing.nft_mint,
+                &ctx.accounts.payment_mint.key(),
+                ctx.accounts.collection_stats.sale_count,
+                &ctx.accounts.buyer.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+        }
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            Some(ctx.accounts.second_bidder_account.to_account_info()),
+            seller_payment,
+            direct_creator_payments,
+            marketplace_fee,
+            second_bidder_fee,
+        )?;
+
+        // Transfer the pNFT from the vault to the buyer through the token metadata program
+        let seeds = &[
+            b"vault".as_ref(),
+            listing.seller.as_ref(),
+            listing.nft_mint.as_ref(),
+            &[ctx.bumps.vault_nft_account],
+        ];
+        let vault = ctx.accounts.vault_nft_account.to_account_info();
+        let buyer = ctx.accounts.buyer.to_account_info();
+        TransferV1CpiBuilder::new(&ctx.accounts.mpl_token_metadata_program)
+            .token(&vault)
+            .token_owner(&vault)
+            .destination_token(&ctx.accounts.buyer_nft_account)
+            .destination_owner(&buyer)
+            .mint(&ctx.accounts.nft_mint.to_account_info())
+            .metadata(&ctx.accounts.metadata)
+            .edition(Some(&ctx.accounts.edition))
+            .token_record(Some(&ctx.accounts.token_record))
+            .destination_token_record(Some(&ctx.accounts.destination_token_record))
+            .authority(&vault)
+            .payer(&buyer)
+            .system_program(&ctx.accounts.system_program.to_account_info())
+            .sysvar_instructions(&ctx.accounts.sysvar_instructions)
+            .spl_token_program(&ctx.accounts.token_program.to_account_info())
+            .spl_ata_program(&ctx.accounts.associated_token_program.to_account_info())
+            .authorization_rules_program(Some(&ctx.accounts.authorization_rules_program))
+            .authorization_rules(Some(&ctx.accounts.authorization_rules))
+            .amount(1)
+            .invoke_signed(&[&seeds[..]])?;
+
+        // Record the sale in the on-chain price history
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = listing.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: listing.price,
+            buyer: ctx.accounts.buyer.key(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        // Roll the sale into the collection's stats
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(listing.price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(listing.seller, ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(listing.price)?;
+
+        if let Some(market_stats) = ctx.accounts.market_stats.as_mut() {
+            market_stats.record_sale(listing.price, marketplace_fee)?;
+        }
+
+        emit!(SaleExecuted {
+            listing_id: listing.key(),
+            buyer: ctx.accounts.buyer.key(),
+            seller: listing.seller,
+            nft_mint: listing.nft_mint,
+            payment_mint: listing.payment_mint,
+            price: listing.price,
+            marketplace_fee,
+            second_bidder: ctx.accounts.second_bidder_account.owner,
+            second_bidder_fee,
+            total_creator_royalty,
+            seller_payment,
+        });
+
+        close_listing(
+            &mut ctx.accounts.listing,
+            ListingStatus::SoldOut,
+            ctx.accounts.seller.to_account_info(),
+        )?;
+
+        Ok(())
+    }
+
+    // Buy one unit from each of up to five listings in a collection, cheapest first.
+    // Expects (listing, seller, nft_mint, vault_nft_account, buyer_nft_account,
+    // seller_payment_account, metadata, buyer_record) groups in remaining_accounts sorted by
+    // ascending price, followed by the verified creator payment accounts of each listing in order.
+    pub fn floor_sweep<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FloorSweep<'info>>,
+        quantity: u8,
+        max_total_price: u64,
+    ) -> Result<()> {
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        let quantity = quantity as usize;
+        require!(
+            quantity > 0
+                && quantity <= MAX_SWEEP_SIZE
+                && ctx.remaining_accounts.len() >= quantity * 8,
+            ErrorCode::InvalidSweep
+        );
+        let (groups, mut creator_accounts) = ctx.remaining_accounts.split_at(quantity * 8);
+
+        let buyer = ctx.accounts.buyer.to_account_info();
+        let collection_mint = ctx.accounts.collection_mint.key();
+        let source = PaymentSource {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            authority: buyer.clone(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: &[],
+        };
+
+        let mut mints = Vec::with_capacity(quantity);
+        let mut total_price: u64 = 0;
+        let mut previous_price: u64 = 0;
+
+        let system_program = ctx.accounts.system_program.to_account_info();
+
+        for group in groups.chunks(8) {
+            let mut listing = Account::<Listing>::try_from(&group[0])?;
+            let seller = &group[1];
+            let nft_mint = &group[2];
+            let vault_nft_account = &group[3];
+            let buyer_nft_account = &group[4];
+            let seller_payment_account = &group[5];
+            let metadata_info = &group[6];
+            let buyer_record_info = &group[7];
+
+            require!(listing.version == ACCOUNT_VERSION, ErrorCode::UnsupportedVersion);
+            require_keys_eq!(
+                listing.key(),
+                Pubkey::create_program_address(
+                    &[
+                        b"listing",
+                        listing.seller.as_ref(),
+                        listing.nft_mint.as_ref(),
+                        &[listing.bump],
+                    ],
+                    ctx.program_id,
+                )
+                .map_err(|_| ErrorCode::InvalidSweep)?,
+                ErrorCode::InvalidSweep
+            );
+            require_keys_eq!(seller.key(), listing.seller, ErrorCode::InvalidSweep);
+            require_keys_eq!(nft_mint.key(), listing.nft_mint, ErrorCode::InvalidSweep);
+
+            // Listings must come cheapest first, matching the caller's view of the floor
+            require!(listing.price >= previous_price, ErrorCode::SweepNotSorted);
+            previous_price = listing.price;
+
+            require_keys_neq!(buyer.key(), listing.seller, ErrorCode::SelfTrade);
+            require!(
+                listing.payment_mint == Some(ctx.accounts.payment_mint.key()),
+                ErrorCode::PaymentMintMismatch
+            );
+            require!(
+                !listing_expired(listing.expiry, config, clock.unix_timestamp),
+                ErrorCode::ListingExpired
+            );
+            require!(
+                clock.unix_timestamp >= listing.goes_live_at,
+                ErrorCode::ListingNotYetLive
+            );
+            if let Some(allowed_buyer) = listing.buyer_allowlist {
+                require_keys_eq!(buyer.key(), allowed_buyer, ErrorCode::UnauthorizedBuyer);
+            }
+            // Allowlisted listings need a merkle proof, which sweeps do not carry
+            require!(!listing.whitelist_enabled, ErrorCode::MissingWhitelist);
+
+            // Count the unit against the listing's per-buyer limit, as execute_sale does
+            let (buyer_record_key, buyer_record_bump) = Pubkey::find_program_address(
+                &[b"buyer_record", listing.key().as_ref(), buyer.key.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(buyer_record_info.key(), buyer_record_key, ErrorCode::InvalidSweep);
+            let mut buyer_record = if buyer_record_info.data_is_empty() {
+                let listing_key = listing.key();
+                let seeds = &[
+                    b"buyer_record".as_ref(),
+                    listing_key.as_ref(),
+                    buyer.key.as_ref(),
+                    &[buyer_record_bump],
+                ];
+                create_program_account(
+                    buyer_record_info,
+                    8 + BuyerPurchaseRecord::INIT_SPACE,
+                    &buyer,
+                    &system_program,
+                    &[&seeds[..]],
+                )?;
+                BuyerPurchaseRecord {
+                    listing: listing.key(),
+                    buyer: buyer.key(),
+                    purchased: 0,
+                    bump: buyer_record_bump,
+                }
+            } else {
+                Account::<BuyerPurchaseRecord>::try_from(buyer_record_info)?.into_inner()
+            };
+            buyer_record.record_purchase(&listing, 1)?;
+            buyer_record.try_serialize(&mut &mut buyer_record_info.try_borrow_mut_data()?[..])?;
+
+            let metadata = load_metadata(metadata_info, &listing.nft_mint)?;
+            require!(
+                in_collection(&metadata, &collection_mint),
+                ErrorCode::NotInCollection
+            );
+
+            // Calculate royalties according to the listing's enforcement mode
+            let royalty_enforcement =
+                effective_royalty_enforcement(listing.royalty_enforcement, config);
+            let (creator_payments, remaining_payment) = sale_royalties(
+                config,
+                royalty_enforcement,
+                &metadata,
+                &listing_creators(&listing.creator_cache, &metadata.creators),
+                listing.price,
+                creator_accounts,
+            )?;
+            let creator_count = creator_payments
+                .iter()
+                .filter(|(_, amount)| *amount > 0)
+                .count();
+            require!(
+                creator_accounts.len() >= creator_count,
+                ErrorCode::MissingCreatorAccount
+            );
+            let (listing_creator_accounts, rest) = creator_accounts.split_at(creator_count);
+            creator_accounts = rest;
+            if config.enforce_royalties {
+                check_creator_accounts(&creator_payments, listing_creator_accounts)?;
+            }
+
+            // Sweeps have no second bidder, so its share of the fee is unused. The seller's
+            // listing was resting and pays the maker rate; the buyer pays the taker rate on top
+            let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+                remaining_payment,
+                config.seller_fee_bps(Maker::Seller),
+                config,
+                0,
+            )?;
+            let buyer_fee_bps =
+                tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+            let buyer_fee = calculate_buyer_fee(listing.price, buyer_fee_bps)?;
+            record_fee_tier_volume(&ctx.accounts.fee_tier, listing.payment_mint, listing.price)?;
+            let marketplace_fee = marketplace_fee
+                .checked_add(buyer_fee)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+            let seller_token_account =
+                TokenAccount::try_deserialize(&mut &seller_payment_account.try_borrow_data()?[..])?;
+            require!(
+                seller_token_account.owner == listing.seller
+                    && seller_token_account.mint == ctx.accounts.payment_mint.key(),
+                ErrorCode::MissingPaymentAccount
+            );
+
+            transfer_payments(
+                &source,
+                seller_payment_account.clone(),
+                listing_creator_accounts,
+                ctx.accounts.marketplace_fee_account.to_account_info(),
+                None,
+                seller_payment,
+                &creator_payments,
+                marketplace_fee,
+                0,
+            )?;
+
+            // Transfer NFT from vault to buyer
+            let (vault_key, vault_bump) = Pubkey::find_program_address(
+                &[b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(vault_nft_account.key(), vault_key, ErrorCode::InvalidSweep);
+            require_keys_eq!(
+                buyer_nft_account.key(),
+                get_associated_token_address_with_program_id(
+                    &buyer.key(),
+                    &listing.nft_mint,
+                    &ctx.accounts.token_program.key(),
+                ),
+                ErrorCode::InvalidSweep
+            );
+
+            let cpi_accounts = associated_token::Create {
+                payer: buyer.clone(),
+                associated_token: buyer_nft_account.clone(),
+                authority: buyer.clone(),
+                mint: nft_mint.clone(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.associated_token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            associated_token::create_idempotent(cpi_ctx)?;
+
+            let mint = Mint::try_deserialize(&mut &nft_mint.try_borrow_data()?[..])?;
+            let seeds = &[
+                b"vault".as_ref(),
+                listing.seller.as_ref(),
+                listing.nft_mint.as_ref(),
+                &[vault_bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = token_interface::TransferChecked {
+                from: vault_nft_account.clone(),
+                mint: nft_mint.clone(),
+                to: buyer_nft_account.clone(),
+                authority: vault_nft_account.clone(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::transfer_checked(cpi_ctx, 1, mint.decimals)?;
+
+            ctx.accounts
+                .collection_stats
+                .record_sale(listing.price, clock.unix_timestamp)?;
+            total_price = total_price
+                .checked_add(listing.price)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            mints.push(listing.nft_mint);
+
+            // Update or close the listing, along with the buyer's record for it
+            if listing.quantity == 1 {
+                Account::<BuyerPurchaseRecord>::try_from(buyer_record_info)?.close(buyer.clone())?;
+                close_listing(&mut listing, ListingStatus::SoldOut, seller.clone())?;
+            } else {
+                listing.quantity -= 1;
+                listing.exit(ctx.program_id)?;
+            }
+        }
+
+        // Protect the buyer from paying more in total than they signed for
+        require!(total_price <= max_total_price, ErrorCode::PriceExceedsMax);
+
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = collection_mint;
+        collection_stats.bump = ctx.bumps.collection_stats;
+
+        emit!(FloorSweepExecuted {
+            buyer: buyer.key(),
+            collection_mint,
+            mints,
+            total_price,
+        });
+
+        Ok(())
+    }
+
+    // Floor sweep with a price cap per listing instead of one cap on the total.
+    // Expects the same remaining_accounts layout as floor_sweep.
+    pub fn sweep_listings<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FloorSweep<'info>>,
+        max_prices: Vec<u64>,
+    ) -> Result<()> {
+        let quantity = max_prices.len();
+        require!(
+            quantity > 0
+                && quantity <= MAX_SWEEP_SIZE
+                && ctx.remaining_accounts.len() >= quantity * 8,
+            ErrorCode::InvalidSweep
+        );
+
+        // Any overpriced listing reverts the whole batch; floor_sweep verifies the listings themselves
+        for (group, max_price) in ctx.remaining_accounts.chunks(8).zip(&max_prices) {
+            let listing = Account::<Listing>::try_from(&group[0])?;
+            require!(listing.price <= *max_price, ErrorCode::PriceExceedsMax);
+        }
+        let max_total_price = max_prices
+            .iter()
+            .try_fold(0u64, |total, max_price| total.checked_add(*max_price))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        floor_sweep(ctx, quantity as u8, max_total_price)
+    }
+
+    // Create a bundle listing that sells several NFTs as a single unit.
+    // Expects (nft_mint, seller_nft_account, vault_nft_account) triplets in remaining_accounts.
+    pub fn create_bundle_listing<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateBundleListing<'info>>,
+        bundle_id: u64,
+        price: u64,
+        expiry: i64,
+        royalty_enforcement: RoyaltyEnforcementMode,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(price > 0, ErrorCode::InvalidPrice);
+
+        let triplets = ctx.remaining_accounts;
+        require!(
+            !triplets.is_empty()
+                && triplets.len() % 3 == 0
+                && triplets.len() / 3 <= MAX_BUNDLE_SIZE,
+            ErrorCode::InvalidBundleSize
+        );
+
+        let bundle_listing = &mut ctx.accounts.bundle_listing;
+        let clock = Clock::get()?;
+
+        // An expiry of zero means the bundle never expires
+        require!(
+            expiry == 0 || expiry > clock.unix_timestamp,
+            ErrorCode::ListingExpired
+        );
+
+        let mut mints = Vec::with_capacity(triplets.len() / 3);
+
+        for triplet in triplets.chunks(3) {
+            let nft_mint = &triplet[0];
+            let seller_nft_account = &triplet[1];
+            let vault_nft_account = &triplet[2];
+            let mint = Mint::try_deserialize(&mut &nft_mint.try_borrow_data()?[..])?;
+
+            require!(!mints.contains(&nft_mint.key()), ErrorCode::DuplicateBundleMint);
+            require_keys_eq!(
+                vault_nft_account.key(),
+                get_associated_token_address_with_program_id(
+                    &bundle_listing.key(),
+                    &nft_mint.key(),
+                    &ctx.accounts.token_program.key(),
+                ),
+                ErrorCode::InvalidBundleVault
+            );
+
+            // Create the vault owned by the bundle listing
+            let cpi_accounts = associated_token::Create {
+                payer: ctx.accounts.seller.to_account_info(),
+                associated_token: vault_nft_account.clone(),
+                authority: bundle_listing.to_account_info(),
+                mint: nft_mint.clone(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.associated_token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            associated_token::create_idempotent(cpi_ctx)?;
+
+            // Transfer NFT to the bundle vault
+            let cpi_accounts = token_interface::TransferChecked {
+                from: seller_nft_account.clone(),
+                mint: nft_mint.clone(),
+                to: vault_nft_account.clone(),
+                authority: ctx.accounts.seller.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token_interface::transfer_checked(cpi_ctx, 1, mint.decimals)?;
+
+            mints.push(nft_mint.key());
+        }
+
+        bundle_listing.seller = ctx.accounts.seller.key();
+        bundle_listing.bundle_id = bundle_id;
+        bundle_listing.payment_mint = ctx.accounts.payment_mint.key();
+        bundle_listing.mints = mints.clone();
+        bundle_listing.price = price;
+        bundle_listing.expiry = expiry;
+        bundle_listing.royalty_enforcement = royalty_enforcement;
+        bundle_listing.created_at = clock.unix_timestamp;
+        bundle_listing.bump = ctx.bumps.bundle_listing;
+
+        emit!(BundleListingCreated {
+            bundle_listing_id: bundle_listing.key(),
+            seller: ctx.accounts.seller.key(),
+            mints,
+            price,
+            expiry,
+        });
+
+        Ok(())
+    }
+
+    // Execute a bundle sale.
+    // Expects (nft_mint, vault_nft_account, buyer_nft_account, metadata) groups in
+    // remaining_accounts, in the same order as the bundle mints, followed by the
+    // verified creator payment accounts.
+    pub fn execute_bundle_sale<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteBundleSale<'info>>,
+    ) -> Result<()> {
+        let bundle_listing = &ctx.accounts.bundle_listing;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require_keys_neq!(
+            ctx.accounts.buyer.key(),
+            bundle_listing.seller,
+            ErrorCode::SelfTrade
+        );
+
+        require!(
+            !listing_expired(bundle_listing.expiry, config, clock.unix_timestamp),
+            ErrorCode::ListingExpired
+        );
+
+        let mint_count = bundle_listing.mints.len();
+        require!(
+            ctx.remaining_accounts.len() >= mint_count * 4,
+            ErrorCode::InvalidBundleSize
+        );
+        let (groups, creator_accounts) = ctx.remaining_accounts.split_at(mint_count * 4);
+
+        // Royalties are charged on an equal share of the bundle price for each NFT, according to
+        // the bundle's enforcement mode
+        let royalty_enforcement =
+            effective_royalty_enforcement(bundle_listing.royalty_enforcement, config);
+        let item_price = bundle_listing.price / mint_count as u64;
+        let mut creator_payments = Vec::new();
+        let mut total_royalties: u64 = 0;
+        for (i, (group, nft_mint)) in groups
+            .chunks(4)
+            .zip(bundle_listing.mints.iter())
+            .enumerate()
+        {
+            let price = if i == mint_count - 1 {
+                bundle_listing.price - item_price * (mint_count as u64 - 1)
+            } else {
+                item_price
+            };
+            let metadata = load_metadata(&group[3], nft_mint)?;
+            let (payments, remaining_payment) = sale_royalties(
+                config,
+                royalty_enforcement,
+                &metadata,
+                &metadata.creators,
+                price,
+                creator_accounts,
+            )?;
+            let royalties = price
+                .checked_sub(remaining_payment)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            total_royalties = total_royalties
+                .checked_add(royalties)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            creator_payments.extend(payments);
+        }
+        let remaining_payment = bundle_listing
+            .price
+            .checked_sub(total_royalties)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        if config.enforce_royalties && royalty_enforcement == RoyaltyEnforcementMode::Mandatory {
+            check_creator_accounts(&creator_payments, creator_accounts)?;
+        }
+
+        // Bundles have no second bidder, so its share of the fee is unused. The seller's
+        // listing was resting and pays the maker rate; the buyer pays the taker rate on top
+        let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Seller),
+            config,
+            0,
+        )?;
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+        let buyer_fee = calculate_buyer_fee(bundle_listing.price, buyer_fee_bps)?;
+        record_fee_tier_volume(
+            &ctx.accounts.fee_tier,
+            Some(ctx.accounts.payment_mint.key()),
+            bundle_listing.price,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments from the buyer
+        let source = PaymentSource {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: &[],
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            creator_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            None,
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            0,
+        )?;
+
+        // Transfer every NFT from its vault to the buyer and reclaim the vault rent
+        let bundle_id = bundle_listing.bundle_id.to_le_bytes();
+        let seeds = &[
+            b"bundle_listing".as_ref(),
+            bundle_listing.seller.as_ref(),
+            bundle_id.as_ref(),
+            &[bundle_listing.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        for (group, mint_key) in groups.chunks(4).zip(bundle_listing.mints.iter()) {
+            let nft_mint = &group[0];
+            let vault_nft_account = &group[1];
+            let buyer_nft_account = &group[2];
+            let mint = Mint::try_deserialize(&mut &nft_mint.try_borrow_data()?[..])?;
+
+            require_keys_eq!(nft_mint.key(), *mint_key, ErrorCode::InvalidBundleVault);
+            require_keys_eq!(
+                vault_nft_account.key(),
+                get_associated_token_address_with_program_id(
+                    &bundle_listing.key(),
+                    mint_key,
+                    &ctx.accounts.token_program.key(),
+                ),
+                ErrorCode::InvalidBundleVault
+            );
+
+            let cpi_accounts = token_interface::TransferChecked {
+                from: vault_nft_account.clone(),
+                mint: nft_mint.clone(),
+                to: buyer_nft_account.clone(),
+                authority: bundle_listing.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::transfer_checked(cpi_ctx, 1, mint.decimals)?;
+
+            let cpi_accounts = token_interface::CloseAccount {
+                account: vault_nft_account.clone(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: bundle_listing.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+        }
+
+        emit!(BundleSaleExecuted {
+            bundle_listing_id: bundle_listing.key(),
+            buyer: ctx.accounts.buyer.key(),
+            seller: bundle_listing.seller,
+            mints: bundle_listing.mints.clone(),
+            price: bundle_listing.price,
+        });
+
+        Ok(())
+    }
+
+    // Cancel a bundle listing and return every NFT to the seller.
+    // Expects (nft_mint, vault_nft_account, seller_nft_account) triplets in
+    // remaining_accounts, in the same order as the bundle mints.
+    pub fn cancel_bundle_listing<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelBundleListing<'info>>,
+    ) -> Result<()> {
+        let bundle_listing = &ctx.accounts.bundle_listing;
+        let triplets = ctx.remaining_accounts;
+        require!(
+            triplets.len() == bundle_listing.mints.len() * 3,
+            ErrorCode::InvalidBundleSize
+        );
+
+        let bundle_id = bundle_listing.bundle_id.to_le_bytes();
+        let seeds = &[
+            b"bundle_listing".as_ref(),
+            bundle_listing.seller.as_ref(),
+            bundle_id.as_ref(),
+            &[bundle_listing.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        for (triplet, mint_key) in triplets.chunks(3).zip(bundle_listing.mints.iter()) {
+            let nft_mint = &triplet[0];
+            let vault_nft_account = &triplet[1];
+            let seller_nft_account = &triplet[2];
+            let mint = Mint::try_deserialize(&mut &nft_mint.try_borrow_data()?[..])?;
+
+            require_keys_eq!(nft_mint.key(), *mint_key, ErrorCode::InvalidBundleVault);
+            require_keys_eq!(
+                vault_nft_account.key(),
+                get_associated_token_address_with_program_id(
+                    &bundle_listing.key(),
+                    mint_key,
+                    &ctx.accounts.token_program.key(),
+                ),
+                ErrorCode::InvalidBundleVault
+            );
+
+            // Transfer NFT back to seller and reclaim the vault rent
+            let cpi_accounts = token_interface::TransferChecked {
+                from: vault_nft_account.clone(),
+                mint: nft_mint.clone(),
+                to: seller_nft_account.clone(),
+                authority: bundle_listing.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::transfer_checked(cpi_ctx, 1, mint.decimals)?;
+
+            let cpi_accounts = token_interface::CloseAccount {
+                account: vault_nft_account.clone(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: bundle_listing.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+        }
+
+        emit!(BundleListingCancelled {
+            bundle_listing_id: bundle_listing.key(),
+            seller: bundle_listing.seller,
+            mints: bundle_listing.mints.clone(),
+        });
+
+        Ok(())
+    }
+
+    // List an NFT owned by several co-owners who split the proceeds by share.
+    // The primary seller deposits the NFT and is the only one who can cancel.
+    pub fn create_split_listing(
+        ctx: Context<CreateSplitListing>,
+        price: u64,
+        expiry: i64,
+        co_owners: Vec<CoOwner>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(price > 0, ErrorCode::InvalidPrice);
+        require!(
+            !co_owners.is_empty() && co_owners.len() <= MAX_CO_OWNERS,
+            ErrorCode::InvalidCoOwnerCount
+        );
+        let total_shares: u64 = co_owners.iter().map(|c| c.share_bps as u64).sum();
+        require!(total_shares == FEE_DENOMINATOR, ErrorCode::InvalidCoOwnerShares);
+
+        // Transfer NFT to the split listing's vault
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.vault_nft_account.to_account_info(),
+            authority: ctx.accounts.primary_seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        let split_listing = &mut ctx.accounts.split_listing;
+        split_listing.primary_seller = ctx.accounts.primary_seller.key();
+        split_listing.nft_mint = ctx.accounts.nft_mint.key();
+        split_listing.payment_mint = ctx.accounts.payment_mint.key();
+        split_listing.price = price;
+        split_listing.expiry = expiry;
+        split_listing.co_owners = co_owners.clone();
+        split_listing.created_at = Clock::get()?.unix_timestamp;
+        split_listing.bump = ctx.bumps.split_listing;
+
+        emit!(SplitListingCreated {
+            split_listing_id: split_listing.key(),
+            primary_seller: split_listing.primary_seller,
+            nft_mint: split_listing.nft_mint,
+            price,
+            expiry,
+            co_owners,
+        });
+
+        Ok(())
+    }
+
+    // Execute a split listing sale.
+    // Expects one payment account per co-owner in remaining_accounts, in co-owner order,
+    // followed by the verified creator payment accounts.
+    pub fn execute_split_listing_sale<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteSplitListingSale<'info>>,
+        max_price: u64,
+    ) -> Result<()> {
+        let split_listing = &ctx.accounts.split_listing;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require!(split_listing.price <= max_price, ErrorCode::PriceExceedsMax);
+
+        // Neither the primary seller nor any co-owner can buy the listing to fake volume
+        require_keys_neq!(
+            ctx.accounts.buyer.key(),
+            split_listing.primary_seller,
+            ErrorCode::SelfTrade
+        );
+        require!(
+            !split_listing
+                .co_owners
+                .iter()
+                .any(|c| c.owner == ctx.accounts.buyer.key()),
+            ErrorCode::SelfTrade
+        );
+        require!(
+            !listing_expired(split_listing.expiry, config, clock.unix_timestamp),
+            ErrorCode::ListingExpired
+        );
+
+        let co_owner_count = split_listing.co_owners.len();
+        require!(
+            ctx.remaining_accounts.len() >= co_owner_count,
+            ErrorCode::CoOwnerAccountMismatch
+        );
+        let (co_owner_accounts, creator_accounts) =
+            ctx.remaining_accounts.split_at(co_owner_count);
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &split_listing.nft_mint)?;
+
+        // Calculate royalties
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            RoyaltyEnforcementMode::Mandatory,
+            &metadata,
+            &metadata.creators,
+            split_listing.price,
+            creator_accounts,
+        )?;
+        if config.enforce_royalties {
+            check_creator_accounts(&creator_payments, creator_accounts)?;
+        }
+
+        // Split listings have no second bidder, so its share of the fee is unused. The seller's
+        // listing was resting and pays the maker rate; the buyer pays the taker rate on top
+        let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Seller),
+            config,
+            0,
+        )?;
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+        let buyer_fee = calculate_buyer_fee(split_listing.price, buyer_fee_bps)?;
+        record_fee_tier_volume(
+            &ctx.accounts.fee_tier,
+            Some(ctx.accounts.payment_mint.key()),
+            split_listing.price,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Co-owners stand in for the seller, each paid their share of the proceeds
+        let source = PaymentSource {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: &[],
+        };
+        let co_owner_payments = split_by_shares(seller_payment, &split_listing.co_owners)?;
+        for ((co_owner, co_owner_account), amount) in split_listing
+            .co_owners
+            .iter()
+            .zip(co_owner_accounts.iter())
+            .zip(co_owner_payments)
+        {
+            require_keys_eq!(
+                co_owner_account.key(),
+                get_associated_token_address_with_program_id(
+                    &co_owner.owner,
+                    &ctx.accounts.payment_mint.key(),
+                    &ctx.accounts.payment_token_program.key(),
+                ),
+                ErrorCode::CoOwnerAccountMismatch
+            );
+            source.transfer(co_owner_account.clone(), amount)?;
+        }
+
+        // The proceeds were split above, so only creators and the marketplace are paid here
+        transfer_payments(
+            &source,
+            co_owner_accounts[0].clone(),
+            creator_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            None,
+            0,
+            &creator_payments,
+            marketplace_fee,
+            0,
+        )?;
+
+        // Transfer NFT from the vault to the buyer and reclaim the vault rent
+        let seeds = &[
+            b"split_listing".as_ref(),
+            split_listing.nft_mint.as_ref(),
+            &[split_listing.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.buyer_nft_account.to_account_info(),
+            authority: split_listing.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vault_nft_account.to_account_info(),
+            destination: ctx.accounts.primary_seller.to_account_info(),
+            authority: split_listing.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(SplitListingSaleExecuted {
+            split_listing_id: split_listing.key(),
+            buyer: ctx.accounts.buyer.key(),
+            nft_mint: split_listing.nft_mint,
+            price: split_listing.price,
+            marketplace_fee,
+            total_creator_royalty: creator_payments.iter().map(|(_, amount)| amount).sum(),
+            seller_payment,
+        });
+
+        Ok(())
+    }
+
+    // Cancel a split listing and return the NFT to the primary seller
+    pub fn cancel_split_listing(ctx: Context<CancelSplitListing>) -> Result<()> {
+        let split_listing = &ctx.accounts.split_listing;
+        let seeds = &[
+            b"split_listing".as_ref(),
+            split_listing.nft_mint.as_ref(),
+            &[split_listing.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.seller_nft_account.to_account_info(),
+            authority: split_listing.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vault_nft_account.to_account_info(),
+            destination: ctx.accounts.primary_seller.to_account_info(),
+            authority: split_listing.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(ListingCancelled {
+            listing_id: split_listing.key(),
+            seller: split_listing.primary_seller,
+            nft_mint: split_listing.nft_mint,
+        });
+
+        Ok(())
+    }
+
+    // Create a dutch auction whose price declines linearly from start_price to end_price
+    pub fn create_dutch_auction(
+        ctx: Context<CreateDutchAuction>,
+        start_price: u64,
+        end_price: u64,
+        start_time: i64,
+        end_time: i64,
+        quantity: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(quantity > 0, ErrorCode::InvalidQuantity);
+        // A flat price isn't a dutch auction; use a listing instead
+        require!(start_price > end_price, ErrorCode::InvalidAuctionPrices);
+        require!(end_time > start_time, ErrorCode::InvalidAuctionTimes);
+
+        let dutch_auction = &mut ctx.accounts.dutch_auction;
+        dutch_auction.seller = ctx.accounts.seller.key();
+        dutch_auction.nft_mint = ctx.accounts.nft_mint.key();
+        dutch_auction.payment_mint = ctx.accounts.payment_mint.key();
+        dutch_auction.start_price = start_price;
+        dutch_auction.end_price = end_price;
+        dutch_auction.start_time = start_time;
+        dutch_auction.end_time = end_time;
+        dutch_auction.quantity = quantity;
+        dutch_auction.bump = ctx.bumps.dutch_auction;
+
+        // Transfer NFT to the auction vault
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.vault_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, quantity, ctx.accounts.nft_mint.decimals)?;
+
+        emit!(DutchAuctionCreated {
+            auction_id: dutch_auction.key(),
+            seller: ctx.accounts.seller.key(),
+            nft_mint: ctx.accounts.nft_mint.key(),
+            start_price,
+            end_price,
+            start_time,
+            end_time,
+            quantity,
+        });
+
+        Ok(())
+    }
+
+    // Buy one unit from a dutch auction at the current price, as long as it is
+    // no more than max_price
+    pub fn execute_dutch_auction_sale(
+        ctx: Context<ExecuteDutchAuctionSale>,
+        max_price: u64,
+    ) -> Result<()> {
+        let dutch_auction = &ctx.accounts.dutch_auction;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require_keys_neq!(
+            ctx.accounts.buyer.key(),
+            dutch_auction.seller,
+            ErrorCode::SelfTrade
+        );
+        require!(
+            clock.unix_timestamp >= dutch_auction.start_time,
+            ErrorCode::AuctionNotStarted
+        );
+
+        let price = current_dutch_auction_price(dutch_auction, clock.unix_timestamp)?;
+        require!(price <= max_price, ErrorCode::PriceExceedsMax);
+        let metadata = load_metadata(&ctx.accounts.metadata, &dutch_auction.nft_mint)?;
+
+        // Calculate royalties
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            RoyaltyEnforcementMode::Mandatory,
+            &metadata,
+            &metadata.creators,
+            price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        // Dutch auctions have no second bidder, so its share of the fee is unused. The seller's
+        // listing was resting and pays the maker rate; the buyer pays the taker rate on top
+        let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Seller),
+            config,
+            0,
+        )?;
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+        let buyer_fee = calculate_buyer_fee(price, buyer_fee_bps)?;
+        record_fee_tier_volume(
+            &ctx.accounts.fee_tier,
+            Some(ctx.accounts.payment_mint.key()),
+            price,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments from the buyer
+        let source = PaymentSource {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: &[],
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            None,
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            0,
+        )?;
+
+        // Transfer NFT from the auction vault to the buyer
+        let seeds = &[
+            b"dutch_auction".as_ref(),
+            dutch_auction.seller.as_ref(),
+            dutch_auction.nft_mint.as_ref(),
+            &[dutch_auction.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.buyer_nft_account.to_account_info(),
+            authority: dutch_auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        emit!(DutchAuctionSaleExecuted {
+            auction_id: dutch_auction.key(),
+            buyer: ctx.accounts.buyer.key(),
+            seller: dutch_auction.seller,
+            nft_mint: dutch_auction.nft_mint,
+            price,
+        });
+
+        // Close the vault and the auction once the last unit is sold
+        if dutch_auction.quantity == 1 {
+            let cpi_accounts = token_interface::CloseAccount {
+                account: ctx.accounts.vault_nft_account.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: dutch_auction.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+
+            dutch_auction.close(ctx.accounts.seller.to_account_info())?;
+        } else {
+            ctx.accounts.dutch_auction.quantity -= 1;
+        }
+
+        Ok(())
+    }
+
+    // Cancel a dutch auction and return the remaining NFTs to the seller
+    pub fn cancel_dutch_auction(ctx: Context<CancelDutchAuction>) -> Result<()> {
+        let dutch_auction = &ctx.accounts.dutch_auction;
+
+        let seeds = &[
+            b"dutch_auction".as_ref(),
+            dutch_auction.seller.as_ref(),
+            dutch_auction.nft_mint.as_ref(),
+            &[dutch_auction.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Transfer NFT back to seller
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.seller_nft_account.to_account_info(),
+            authority: dutch_auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(
+            cpi_ctx,
+            dutch_auction.quantity,
+            ctx.accounts.nft_mint.decimals,
+        )?;
+
+        // Reclaim the vault rent
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vault_nft_account.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: dutch_auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(DutchAuctionCancelled {
+            auction_id: dutch_auction.key(),
+            seller: dutch_auction.seller,
+            nft_mint: dutch_auction.nft_mint,
+        });
+
+        Ok(())
+    }
+
+    // List units whose price rises by increment_per_sale each time one sells
+    pub fn create_listing_with_price_curve(
+        ctx: Context<CreatePriceCurveListing>,
+        base_price: u64,
+        increment_per_sale: u64,
+        quantity: u64,
+        expiry: i64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(base_price > 0, ErrorCode::InvalidPrice);
+        require!(quantity > 0, ErrorCode::InvalidQuantity);
+
+        let clock = Clock::get()?;
+        // An expiry of zero means the listing never expires
+        require!(
+            expiry == 0 || expiry > clock.unix_timestamp,
+            ErrorCode::ListingExpired
+        );
+
+        let price_curve_listing = &mut ctx.accounts.price_curve_listing;
+        price_curve_listing.seller = ctx.accounts.seller.key();
+        price_curve_listing.nft_mint = ctx.accounts.nft_mint.key();
+        price_curve_listing.payment_mint = ctx.accounts.payment_mint.key();
+        price_curve_listing.base_price = base_price;
+        price_curve_listing.increment_per_sale = increment_per_sale;
+        price_curve_listing.units_sold = 0;
+        price_curve_listing.quantity = quantity;
+        price_curve_listing.created_at = clock.unix_timestamp;
+        price_curve_listing.expiry = expiry;
+        price_curve_listing.bump = ctx.bumps.price_curve_listing;
+
+        // Transfer NFTs to the listing vault
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.vault_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, quantity, ctx.accounts.nft_mint.decimals)?;
+
+        emit!(PriceCurveListingCreated {
+            listing_id: price_curve_listing.key(),
+            seller: price_curve_listing.seller,
+            nft_mint: price_curve_listing.nft_mint,
+            payment_mint: price_curve_listing.payment_mint,
+            base_price,
+            increment_per_sale,
+            quantity,
+            expiry,
+        });
+
+        Ok(())
+    }
+
+    // Buy one unit from a price curve listing at its current price
+    pub fn execute_price_curve_sale(
+        ctx: Context<ExecutePriceCurveSale>,
+        max_price: u64,
+    ) -> Result<()> {
+        let price_curve_listing = &ctx.accounts.price_curve_listing;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require!(
+            !listing_expired(price_curve_listing.expiry, config, clock.unix_timestamp),
+            ErrorCode::ListingExpired
+        );
+        require_keys_neq!(
+            ctx.accounts.buyer.key(),
+            price_curve_listing.seller,
+            ErrorCode::SelfTrade
+        );
+
+        // Another sale landing first raises the price, so the buyer caps what they'll pay
+        let price = price_curve_listing.current_price()?;
+        require!(price <= max_price, ErrorCode::PriceExceedsMax);
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &price_curve_listing.nft_mint)?;
+
+        // Calculate royalties
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            RoyaltyEnforcementMode::Mandatory,
+            &metadata,
+            &metadata.creators,
+            price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        // Price curve listings have no second bidder, so its share of the fee is unused. The seller's
+        // listing was resting and pays the maker rate; the buyer pays the taker rate on top
+        let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Seller),
+            config,
+            0,
+        )?;
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+        let buyer_fee = calculate_buyer_fee(price, buyer_fee_bps)?;
+        record_fee_tier_volume(
+            &ctx.accounts.fee_tier,
+            Some(ctx.accounts.payment_mint.key()),
+            price,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments from the buyer
+        let source = PaymentSource {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: &[],
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            None,
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            0,
+        )?;
+
+        // Transfer NFT from the listing vault to the buyer
+        let seeds = &[
+            b"price_curve".as_ref(),
+            price_curve_listing.seller.as_ref(),
+            price_curve_listing.nft_mint.as_ref(),
+            &[price_curve_listing.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.buyer_nft_account.to_account_info(),
+            authority: price_curve_listing.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        let units_sold = price_curve_listing
+            .units_sold
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        emit!(PriceCurveSaleExecuted {
+            listing_id: price_curve_listing.key(),
+            buyer: ctx.accounts.buyer.key(),
+            seller: price_curve_listing.seller,
+            nft_mint: price_curve_listing.nft_mint,
+            price_paid: price,
+            units_sold,
+        });
+
+        // Close the vault and the listing once the last unit is sold
+        if price_curve_listing.quantity == 1 {
+            let cpi_accounts = token_interface::CloseAccount {
+                account: ctx.accounts.vault_nft_account.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: price_curve_listing.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+
+            price_curve_listing.close(ctx.accounts.seller.to_account_info())?;
+        } else {
+            ctx.accounts.price_curve_listing.quantity -= 1;
+            ctx.accounts.price_curve_listing.units_sold = units_sold;
+        }
+
+        Ok(())
+    }
+
+    // Cancel a price curve listing and return the unsold NFTs to the seller
+    pub fn cancel_price_curve_listing(ctx: Context<CancelPriceCurveListing>) -> Result<()> {
+        let price_curve_listing = &ctx.accounts.price_curve_listing;
+
+        let seeds = &[
+            b"price_curve".as_ref(),
+            price_curve_listing.seller.as_ref(),
+            price_curve_listing.nft_mint.as_ref(),
+            &[price_curve_listing.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Transfer NFTs back to seller
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.seller_nft_account.to_account_info(),
+            authority: price_curve_listing.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(
+            cpi_ctx,
+            price_curve_listing.quantity,
+            ctx.accounts.nft_mint.decimals,
+        )?;
+
+        // Reclaim the vault rent
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vault_nft_account.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: price_curve_listing.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(PriceCurveListingCancelled {
+            listing_id: price_curve_listing.key(),
+            seller: price_curve_listing.seller,
+            nft_mint: price_curve_listing.nft_mint,
+            units_sold: price_curve_listing.units_sold,
+        });
+
+        Ok(())
+    }
+
+    // Bound listing prices in a collection, as the collection's update authority
+    pub fn set_collection_policy(
+        ctx: Context<SetCollectionPolicy>,
+        min_price: u64,
+        max_price: u64,
+    ) -> Result<()> {
+        require!(min_price <= max_price, ErrorCode::InvalidCollectionPolicy);
+
+        let authority = ctx.accounts.authority.key();
+        let metadata = load_metadata(
+            &ctx.accounts.collection_metadata,
+            &ctx.accounts.collection_mint.key(),
+        )?;
+        require_keys_eq!(
+            authority,
+            metadata.update_authority,
+            ErrorCode::UnauthorizedCollectionPolicy
+        );
+
+        let collection_policy = &mut ctx.accounts.collection_policy;
+        collection_policy.collection_mint = ctx.accounts.collection_mint.key();
+        collection_policy.min_price = min_price;
+        collection_policy.max_price = max_price;
+        collection_policy.authority = authority;
+        collection_policy.bump = ctx.bumps.collection_policy;
+
+        Ok(())
+    }
+
+    // Set a collection's auction rules, as its update authority or the marketplace authority
+    pub fn initialize_auction_config(
+        ctx: Context<InitializeAuctionConfig>,
+        min_bid_increment_bps: u64,
+        extension_window_seconds: i64,
+        max_duration_seconds: i64,
+    ) -> Result<()> {
+        require!(
+            min_bid_increment_bps <= FEE_DENOMINATOR
+                && extension_window_seconds >= 0
+                && max_duration_seconds >= 0,
+            ErrorCode::InvalidAuctionConfig
+        );
+
+        let authority = ctx.accounts.authority.key();
+        let metadata = load_metadata(
+            &ctx.accounts.collection_metadata,
+            &ctx.accounts.collection_mint.key(),
+        )?;
+        require!(
+            authority == metadata.update_authority || authority == ctx.accounts.config.authority,
+            ErrorCode::UnauthorizedAuctionConfig
+        );
+
+        let auction_config = &mut ctx.accounts.auction_config;
+        auction_config.collection_mint = ctx.accounts.collection_mint.key();
+        auction_config.min_bid_increment_bps = min_bid_increment_bps;
+        auction_config.extension_window_seconds = extension_window_seconds;
+        auction_config.max_duration_seconds = max_duration_seconds;
+        auction_config.config_authority = authority;
+        auction_config.bump = ctx.bumps.auction_config;
+
+        Ok(())
+    }
+
+    // Create an english auction where the highest bid at end_time wins
+    pub fn create_english_auction(
+        ctx: Context<CreateEnglishAuction>,
+        reserve_price: u64,
+        min_increment_bps: u64,
+        start_time: i64,
+        end_time: i64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(end_time > start_time, ErrorCode::InvalidAuctionTimes);
+        require!(
+            min_increment_bps <= FEE_DENOMINATOR,
+            ErrorCode::InvalidMinIncrement
+        );
+
+        // The collection's auction config applies if one was created, otherwise the marketplace defaults
+        let nft_mint = ctx.accounts.nft_mint.key();
+        let metadata = load_metadata(&ctx.accounts.metadata, &nft_mint)?;
+        let collection_mint = stats_collection_mint(&metadata, &nft_mint);
+        let (auction_config_key, _) = Pubkey::find_program_address(
+            &[b"auction_config", collection_mint.as_ref()],
+            ctx.program_id,
+        );
+        require_keys_eq!(
+            ctx.accounts.auction_config.key(),
+            auction_config_key,
+            ErrorCode::InvalidAuctionConfig
+        );
+        let (min_increment_floor, extension_window, max_duration) =
+            if ctx.accounts.auction_config.data_is_empty() {
+                (ctx.accounts.config.min_bid_increment_bps, AUCTION_EXTENSION_WINDOW, 0)
+            } else {
+                require_keys_eq!(
+                    *ctx.accounts.auction_config.owner,
+                    crate::ID,
+                    ErrorCode::InvalidAuctionConfig
+                );
+                let auction_config = AuctionConfig::try_deserialize(
+                    &mut &ctx.accounts.auction_config.try_borrow_data()?[..],
+                )?;
+                (
+                    auction_config.min_bid_increment_bps,
+                    auction_config.extension_window_seconds,
+                    auction_config.max_duration_seconds,
+                )
+            };
+        // A max duration of zero means auctions can run for any length of time
+        require!(
+            max_duration == 0 || end_time - start_time <= max_duration,
+            ErrorCode::AuctionTooLong
+        );
+        let min_increment_bps = min_increment_bps.max(min_increment_floor);
+
+        let english_auction = &mut ctx.accounts.english_auction;
+        english_auction.seller = ctx.accounts.seller.key();
+        english_auction.nft_mint = nft_mint;
+        english_auction.payment_mint = ctx.accounts.payment_mint.key();
+        english_auction.reserve_price = reserve_price;
+        english_auction.min_increment_bps = min_increment_bps;
+        english_auction.start_time = start_time;
+        english_auction.end_time = end_time;
+        english_auction.highest_bid = 0;
+        english_auction.highest_bidder = Pubkey::default();
+        english_auction.bid_count = 0;
+        english_auction.bump = ctx.bumps.english_auction;
+        english_auction.extension_window = extension_window;
+
+        // Transfer NFT to the auction vault
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.vault_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        emit!(EnglishAuctionCreated {
+            auction_id: english_auction.key(),
+            seller: ctx.accounts.seller.key(),
+            nft_mint: ctx.accounts.nft_mint.key(),
+            payment_mint: ctx.accounts.payment_mint.key(),
+            reserve_price,
+            min_increment_bps,
+            start_time,
+            end_time,
+        });
+
+        Ok(())
+    }
+
+    // Outbid the current highest bidder of an english auction
+    pub fn update_english_auction_bid(
+        ctx: Context<UpdateEnglishAuctionBid>,
+        amount: u64,
+    ) -> Result<()> {
+        let english_auction = &ctx.accounts.english_auction;
+        let clock = Clock::get()?;
+
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(
+            clock.unix_timestamp >= english_auction.start_time,
+            ErrorCode::AuctionNotStarted
+        );
+        require!(clock.unix_timestamp < english_auction.end_time, ErrorCode::AuctionEnded);
+        require!(amount >= english_auction.reserve_price, ErrorCode::BidTooLow);
+
+        let minimum_increment = (english_auction.highest_bid as u128)
+            .checked_mul(english_auction.min_increment_bps as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(FEE_DENOMINATOR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)? as u64;
+        let minimum_bid = english_auction
+            .highest_bid
+            .checked_add(minimum_increment)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            amount > english_auction.highest_bid && amount >= minimum_bid,
+            ErrorCode::BidTooLow
+        );
+
+        // Escrow the new bid
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.bidder_payment_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: ctx.accounts.bidder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.payment_mint.decimals)?;
+
+        // Refund the previous highest bidder
+        if english_auction.bid_count > 0 {
+            let previous_bidder_payment_account = ctx
+                .accounts
+                .previous_bidder_payment_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidPreviousBidder)?;
+            require_keys_eq!(
+                previous_bidder_payment_account.owner,
+                english_auction.highest_bidder,
+                ErrorCode::InvalidPreviousBidder
+            );
+
+            let seeds = &[
+                b"english_auction".as_ref(),
+                english_auction.seller.as_ref(),
+                english_auction.nft_mint.as_ref(),
+                &[english_auction.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = token_interface::TransferChecked {
+                from: ctx.accounts.escrow_payment_account.to_account_info(),
+                mint: ctx.accounts.payment_mint.to_account_info(),
+                to: previous_bidder_payment_account.to_account_info(),
+                authority: english_auction.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::transfer_checked(
+                cpi_ctx,
+                english_auction.highest_bid,
+                ctx.accounts.payment_mint.decimals,
+            )?;
+        }
+
+        let english_auction = &mut ctx.accounts.english_auction;
+        english_auction.highest_bid = amount;
+        english_auction.highest_bidder = ctx.accounts.bidder.key();
+        english_auction.bid_count = english_auction
+            .bid_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Extend the auction when a bid lands in its final window to stop sniping
+        if english_auction.end_time - clock.unix_timestamp <= english_auction.extension_window {
+            english_auction.end_time = english_auction
+                .end_time
+                .checked_add(english_auction.extension_window)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+
+        emit!(EnglishAuctionBidPlaced {
+            auction_id: english_auction.key(),
+            bidder: ctx.accounts.bidder.key(),
+            amount,
+            bid_count: english_auction.bid_count,
+            end_time: english_auction.end_time,
+        });
+
+        Ok(())
+    }
+
+    // Settle an english auction after it ends, paying the seller and delivering the NFT
+    pub fn settle_english_auction(ctx: Context<SettleEnglishAuction>) -> Result<()> {
+        let english_auction = &ctx.accounts.english_auction;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(clock.unix_timestamp >= english_auction.end_time, ErrorCode::AuctionNotEnded);
+
+        let seeds = &[
+            b"english_auction".as_ref(),
+            english_auction.seller.as_ref(),
+            english_auction.nft_mint.as_ref(),
+            &[english_auction.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        if english_auction.bid_count > 0 {
+            let escrow_payment_account = ctx
+                .accounts
+                .escrow_payment_account
+                .as_ref()
+                .ok_or(ErrorCode::MissingPaymentAccount)?;
+            let seller_payment_account = ctx
+                .accounts
+                .seller_payment_account
+                .as_ref()
+                .ok_or(ErrorCode::MissingPaymentAccount)?;
+            require_keys_eq!(
+                seller_payment_account.owner,
+                english_auction.seller,
+                ErrorCode::MissingPaymentAccount
+            );
+
+            let metadata = load_metadata(&ctx.accounts.metadata, &english_auction.nft_mint)?;
+
+            // The winner took the seller's resting auction, so their taker fee comes out of
+            // the escrowed bid and the rest is the sale price
+            let buyer_fee_bps =
+                tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+            let (sale_price, buyer_fee) =
+                split_escrowed_price(english_auction.highest_bid, buyer_fee_bps)?;
+            record_fee_tier_volume(
+                &ctx.accounts.fee_tier,
+                Some(english_auction.payment_mint),
+                sale_price,
+            )?;
+
+            // Calculate royalties
+            let (creator_payments, remaining_payment) = sale_royalties(
+                config,
+                RoyaltyEnforcementMode::Mandatory,
+                &metadata,
+                &metadata.creators,
+                sale_price,
+                ctx.remaining_accounts,
+            )?;
+
+            // English auctions have no second bidder, so its share of the fee is unused.
+            // The seller pays the maker rate
+            let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+                remaining_payment,
+                config.seller_fee_bps(Maker::Seller),
+                config,
+                0,
+            )?;
+            let marketplace_fee = marketplace_fee
+                .checked_add(buyer_fee)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+            // Transfer to seller
+            if seller_payment > 0 {
+                let cpi_accounts = token_interface::TransferChecked {
+                    from: escrow_payment_account.to_account_info(),
+                    mint: ctx.accounts.payment_mint.to_account_info(),
+                    to: seller_payment_account.to_account_info(),
+                    authority: english_auction.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+                token_interface::transfer_checked(
+                    cpi_ctx,
+                    seller_payment,
+                    ctx.accounts.payment_mint.decimals,
+                )?;
+            }
+
+            // Transfer to creators
+            let mut creator_accounts = ctx.remaining_accounts.iter();
+            for (creator, amount) in creator_payments.iter() {
+                if *amount > 0 {
+                    let creator_account = next_account_info(&mut creator_accounts)?;
+                    require_keys_eq!(
+                        creator_account.key(),
+                        get_associated_token_address_with_program_id(
+                            creator,
+                            &ctx.accounts.payment_mint.key(),
+                            &ctx.accounts.payment_token_program.key(),
+                        ),
+                        ErrorCode::CreatorAccountMismatch
+                    );
+                    let cpi_accounts = token_interface::TransferChecked {
+                        from: escrow_payment_account.to_account_info(),
+                        mint: ctx.accounts.payment_mint.to_account_info(),
+                        to: creator_account.to_account_info(),
+                        authority: english_auction.to_account_info(),
+                    };
+                    let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+                    token_interface::transfer_checked(
+                        cpi_ctx,
+                        *amount,
+                        ctx.accounts.payment_mint.decimals,
+                    )?;
+                }
+            }
+
+            // Transfer marketplace fee
+            if marketplace_fee > 0 {
+                let cpi_accounts = token_interface::TransferChecked {
+                    from: escrow_payment_account.to_account_info(),
+                    mint: ctx.accounts.payment_mint.to_account_info(),
+                    to: ctx.accounts.marketplace_fee_account.to_account_info(),
+                    authority: english_auction.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+                token_interface::transfer_checked(
+                    cpi_ctx,
+                    marketplace_fee,
+                    ctx.accounts.payment_mint.decimals,
+                )?;
+            }
+        }
+
+        // Deliver the NFT to the winner, or back to the seller when nobody bid
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.recipient_nft_account.to_account_info(),
+            authority: english_auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Reclaim the vault rent
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vault_nft_account.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: english_auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(EnglishAuctionSettled {
+            auction_id: english_auction.key(),
+            seller: english_auction.seller,
+            winner: ctx.accounts.recipient.key(),
+            nft_mint: english_auction.nft_mint,
+            price: english_auction.highest_bid,
+        });
+
+        Ok(())
+    }
+
+    // Place a bid on an NFT. The deadline bounds when this transaction may land, while expiry
+    // bounds how long the resting bid stays valid. Passing a listing's (listing, seller,
+    // vault_nft_account, bidder_nft_account, seller_payment_account, marketplace_fee_account,
+    // metadata, nft_token_program, buyer_record, price_history, collection_stats, seller_stats,
+    // market_stats) followed by its creator accounts in remaining_accounts settles the bid at
+    // once if it meets the listing's auto-accept threshold. Pass the program id as market_stats
+    // to leave it out.
+    pub fn place_bid<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PlaceBid<'info>>,
+        price: u64,
+        expiry: i64,
+        deadline: i64,
+    ) -> Result<()> {
+        log_compute_units("place_bid: start");
+
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+
+        let bid = &mut ctx.accounts.bid;
+        let clock = Clock::get()?;
+
+        require!(clock.unix_timestamp <= deadline, ErrorCode::TransactionExpired);
+        require!(
+            expiry == 0 || clock.unix_timestamp <= expiry,
+            ErrorCode::BidExpired
+        );
+
+        // Competing bids on the same NFT must beat the best bid by the configured increment
+        let bid_book = &mut ctx.accounts.bid_book;
+        bid_book.nft_mint = ctx.accounts.nft_mint.key();
+        bid_book.bump = ctx.bumps.bid_book;
+        bid_book.record_bid(ctx.accounts.bidder.key(), price, &ctx.accounts.config)?;
+
+        bid.bidder = ctx.accounts.bidder.key();
+        bid.nft_mint = ctx.accounts.nft_mint.key();
+        bid.payment_mint = Some(ctx.accounts.payment_mint.key());
+        bid.price = price;
+        bid.created_at = clock.unix_timestamp;
+        bid.expiry = expiry;
+        bid.bump = ctx.bumps.bid;
+        bid.version = ACCOUNT_VERSION;
+        bid.nonce = ctx.accounts.bid_counter.next_nonce;
+        ctx.accounts
+            .bid_counter
+            .advance(ctx.accounts.bidder.key(), ctx.bumps.bid_counter)?;
+
+        // Transfer bid amount to escrow
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.bidder_payment_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: ctx.accounts.bidder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, price, ctx.accounts.payment_mint.decimals)?;
+
+        emit!(BidPlaced {
+            bid_id: bid.key(),
+            bidder: ctx.accounts.bidder.key(),
+            nft_mint: ctx.accounts.nft_mint.key(),
+            price,
+            expiry,
+        });
+
+        if !ctx.remaining_accounts.is_empty() {
+            require!(
+                ctx.remaining_accounts.len() >= 13,
+                ErrorCode::InvalidAutoAcceptAccounts
+            );
+            let (group, creator_accounts) = ctx.remaining_accounts.split_at(13);
+            let mut listing = Account::<Listing>::try_from(&group[0])?;
+            let seller = &group[1];
+            let vault_nft_account = &group[2];
+            let bidder_nft_account = &group[3];
+            let seller_payment_account = &group[4];
+            let marketplace_fee_account = &group[5];
+            let metadata_info = &group[6];
+            let nft_token_program = &group[7];
+            let buyer_record_info = &group[8];
+            let price_history_info = &group[9];
+            let collection_stats_info = &group[10];
+            let seller_stats_info = &group[11];
+            let market_stats_info = &group[12];
+
+            require!(listing.version == ACCOUNT_VERSION, ErrorCode::UnsupportedVersion);
+            require_keys_eq!(
+                listing.key(),
+                Pubkey::create_program_address(
+                    &[
+                        b"listing",
+                        listing.seller.as_ref(),
+                        listing.nft_mint.as_ref(),
+                        &[listing.bump],
+                    ],
+                    ctx.program_id,
+                )
+                .map_err(|_| ErrorCode::InvalidAutoAcceptAccounts)?,
+                ErrorCode::InvalidAutoAcceptAccounts
+            );
+            require_keys_eq!(
+                listing.nft_mint,
+                ctx.accounts.nft_mint.key(),
+                ErrorCode::BidListingMismatch
+            );
+            require_keys_eq!(seller.key(), listing.seller, ErrorCode::InvalidAutoAcceptAccounts);
+            require_keys_eq!(
+                nft_token_program.key(),
+                *ctx.accounts.nft_mint.to_account_info().owner,
+                ErrorCode::InvalidAutoAcceptAccounts
+            );
+
+            // Below the threshold, or with auto-accept off, the bid simply stays open
+            if listing.auto_accept_threshold > 0 && price >= listing.auto_accept_threshold {
+                let config = &ctx.accounts.config;
+                let bidder = ctx.accounts.bidder.to_account_info();
+
+                require_keys_neq!(bidder.key(), listing.seller, ErrorCode::SelfTrade);
+                require!(
+                    listing.payment_mint == Some(ctx.accounts.payment_mint.key()),
+                    ErrorCode::PaymentMintMismatch
+                );
+                require!(
+                    listing.listing_status == ListingStatus::Active,
+                    ErrorCode::ListingNotActive
+                );
+                require!(
+                    !listing_expired(listing.expiry, config, clock.unix_timestamp),
+                    ErrorCode::ListingExpired
+                );
+                require!(
+                    clock.unix_timestamp >= listing.goes_live_at,
+                    ErrorCode::ListingNotYetLive
+                );
+                if let Some(allowed_buyer) = listing.buyer_allowlist {
+                    require_keys_eq!(bidder.key(), allowed_buyer, ErrorCode::UnauthorizedBuyer);
+                }
+                // Allowlisted listings need a merkle proof, which bids do not carry
+                require!(!listing.whitelist_enabled, ErrorCode::MissingWhitelist);
+
+                // Count the unit against the listing's per-buyer limit, as execute_sale does
+                let system_program = ctx.accounts.system_program.to_account_info();
+                let listing_key = listing.key();
+                let (mut buyer_record, buyer_record_bump) = load_or_init_pda::<BuyerPurchaseRecord>(
+                    buyer_record_info,
+                    &[b"buyer_record", listing_key.as_ref(), bidder.key.as_ref()],
+                    8 + BuyerPurchaseRecord::INIT_SPACE,
+                    &bidder,
+                    &system_program,
+                )?;
+                buyer_record.listing = listing_key;
+                buyer_record.buyer = bidder.key();
+                buyer_record.bump = buyer_record_bump;
+                buyer_record.record_purchase(&listing, 1)?;
+
+                let (fee_vault, _) = Pubkey::find_program_address(&[b"fee_vault"], ctx.program_id);
+                require_keys_eq!(
+                    marketplace_fee_account.key(),
+                    get_associated_token_address_with_program_id(
+                        &fee_vault,
+                        &ctx.accounts.payment_mint.key(),
+                        &ctx.accounts.token_program.key(),
+                    ),
+                    ErrorCode::InvalidFeeAccount
+                );
+                let seller_token_account =
+                    TokenAccount::try_deserialize(&mut &seller_payment_account.try_borrow_data()?[..])?;
+                require!(
+                    seller_token_account.owner == listing.seller
+                        && seller_token_account.mint == ctx.accounts.payment_mint.key(),
+                    ErrorCode::MissingPaymentAccount
+                );
+
+                // The bid crossed the resting listing, so the bidder pays the taker rate out of
+                // their escrow and the rest is the sale price
+                let buyer_fee_bps =
+                    tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+                let (sale_price, buyer_fee) = split_escrowed_price(price, buyer_fee_bps)?;
+                record_fee_tier_volume(
+                    &ctx.accounts.fee_tier,
+                    Some(ctx.accounts.payment_mint.key()),
+                    sale_price,
+                )?;
+
+                // Calculate royalties according to the listing's enforcement mode
+                let metadata = load_metadata(metadata_info, &listing.nft_mint)?;
+                let royalty_enforcement =
+                    effective_royalty_enforcement(listing.royalty_enforcement, config);
+                let (creator_payments, remaining_payment) = sale_royalties(
+                    config,
+                    royalty_enforcement,
+                    &metadata,
+                    &listing_creators(&listing.creator_cache, &metadata.creators),
+                    sale_price,
+                    creator_accounts,
+                )?;
+                if config.enforce_royalties
+                    && royalty_enforcement == RoyaltyEnforcementMode::Mandatory
+                {
+                    check_creator_accounts(&creator_payments, creator_accounts)?;
+                }
+
+                // The listing was resting, so the seller pays the maker rate. No second bidder is
+                // involved, so its share of the fee is unused
+                let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+                    remaining_payment,
+                    config.seller_fee_bps(Maker::Seller),
+                    config,
+                    0,
+                )?;
+                let marketplace_fee = marketplace_fee
+                    .checked_add(buyer_fee)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+                // Pay out of the escrow that was just funded
+                let bid = &ctx.accounts.bid;
+                let nonce = bid.nonce.to_le_bytes();
+                let bid_seeds = &[
+                    b"bid".as_ref(),
+                    bid.bidder.as_ref(),
+                    bid.nft_mint.as_ref(),
+                    nonce.as_ref(),
+                    &[bid.bump],
+                ];
+                let bid_signer = &[&bid_seeds[..]];
+                let source = PaymentSource {
+                    from: ctx.accounts.escrow_payment_account.to_account_info(),
+                    authority: bid.to_account_info(),
+                    mint: &ctx.accounts.payment_mint,
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    signer_seeds: bid_signer,
+                };
+                transfer_payments(
+                    &source,
+                    seller_payment_account.clone(),
+                    creator_accounts,
+                    marketplace_fee_account.clone(),
+                    None,
+                    seller_payment,
+                    &creator_payments,
+                    marketplace_fee,
+                    0,
+                )?;
+
+                // Transfer NFT from vault to bidder
+                let (vault_key, vault_bump) = Pubkey::find_program_address(
+                    &[b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+                    ctx.program_id,
+                );
+                require_keys_eq!(
+                    vault_nft_account.key(),
+                    vault_key,
+                    ErrorCode::InvalidAutoAcceptAccounts
+                );
+                require_keys_eq!(
+                    bidder_nft_account.key(),
+                    get_associated_token_address_with_program_id(
+                        &bidder.key(),
+                        &listing.nft_mint,
+                        &nft_token_program.key(),
+                    ),
+                    ErrorCode::InvalidAutoAcceptAccounts
+                );
+
+                let cpi_accounts = associated_token::Create {
+                    payer: bidder.clone(),
+                    associated_token: bidder_nft_account.clone(),
+                    authority: bidder.clone(),
+                    mint: ctx.accounts.nft_mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: nft_token_program.clone(),
+                };
+                let cpi_program = ctx.accounts.associated_token_program.to_account_info();
+                let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+                associated_token::create_idempotent(cpi_ctx)?;
+
+                let vault_seeds = &[
+                    b"vault".as_ref(),
+                    listing.seller.as_ref(),
+                    listing.nft_mint.as_ref(),
+                    &[vault_bump],
+                ];
+                let vault_signer = &[&vault_seeds[..]];
+                let cpi_accounts = token_interface::TransferChecked {
+                    from: vault_nft_account.clone(),
+                    mint: ctx.accounts.nft_mint.to_account_info(),
+                    to: bidder_nft_account.clone(),
+                    authority: vault_nft_account.clone(),
+                };
+                let cpi_program = nft_token_program.clone();
+                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, vault_signer);
+                token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+                // The escrow is drained, so close it and the bid back to the bidder
+                let cpi_accounts = token_interface::CloseAccount {
+                    account: ctx.accounts.escrow_payment_account.to_account_info(),
+                    destination: bidder.clone(),
+                    authority: bid.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, bid_signer);
+                token_interface::close_account(cpi_ctx)?;
+
+                ctx.accounts.bid_book.remove_bid(bidder.key(), price);
+
+                // Record the sale the way execute_sale does
+                let (mut price_history, price_history_bump) = load_or_init_pda::<PriceHistory>(
+                    price_history_info,
+                    &[b"price_history", listing.nft_mint.as_ref()],
+                    8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+                    &bidder,
+                    &system_program,
+                )?;
+                price_history.nft_mint = listing.nft_mint;
+                price_history.bump = price_history_bump;
+                price_history.push(SaleRecord {
+                    price: sale_price,
+                    buyer: bidder.key(),
+                    timestamp: clock.unix_timestamp,
+                });
+                price_history.exit(ctx.program_id)?;
+
+                let collection_mint = stats_collection_mint(&metadata, &listing.nft_mint);
+                let (mut collection_stats, collection_stats_bump) =
+                    load_or_init_pda::<CollectionStats>(
+                        collection_stats_info,
+                        &[b"stats", collection_mint.as_ref()],
+                        8 + CollectionStats::INIT_SPACE,
+                        &bidder,
+                        &system_program,
+                    )?;
+                collection_stats.collection_mint = collection_mint;
+                collection_stats.bump = collection_stats_bump;
+                collection_stats.record_sale(sale_price, clock.unix_timestamp)?;
+                collection_stats.exit(ctx.program_id)?;
+
+                let (mut seller_stats, seller_stats_bump) = load_or_init_pda::<SellerStats>(
+                    seller_stats_info,
+                    &[b"seller_stats", listing.seller.as_ref()],
+                    8 + SellerStats::INIT_SPACE,
+                    &bidder,
+                    &system_program,
+                )?;
+                seller_stats.init_if_new(listing.seller, seller_stats_bump)?;
+                seller_stats.record_sale(sale_price)?;
+                seller_stats.exit(ctx.program_id)?;
+
+                if market_stats_info.key() != crate::ID {
+                    let mut market_stats = Account::<MarketStats>::try_from(market_stats_info)?;
+                    require_keys_eq!(
+                        market_stats.key(),
+                        Pubkey::create_program_address(
+                            &[b"stats", &[market_stats.bump]],
+                            ctx.program_id,
+                        )
+                        .map_err(|_| ErrorCode::InvalidAutoAcceptAccounts)?,
+                        ErrorCode::InvalidAutoAcceptAccounts
+                    );
+                    market_stats.record_sale(sale_price, marketplace_fee)?;
+                    market_stats.exit(ctx.program_id)?;
+                }
+
+                emit!(BidAccepted {
+                    bid_id: bid.key(),
+                    seller: listing.seller,
+                    bidder: bidder.key(),
+                    nft_mint: listing.nft_mint,
+                    price,
+                    marketplace_fee,
+                    second_bidder: Pubkey::default(),
+                    second_bidder_fee: 0,
+                    total_creator_royalty: creator_payments.iter().map(|(_, amount)| amount).sum(),
+                    seller_payment,
+                });
+
+                ctx.accounts.bid.close(bidder)?;
+
+                // Update or close the listing, reclaiming the vault rent once it is drained, along
+                // with the bidder's record for it
+                if listing.quantity == 1 {
+                    let cpi_accounts = token_interface::CloseAccount {
+                        account: vault_nft_account.clone(),
+                        destination: seller.clone(),
+                        authority: vault_nft_account.clone(),
+                    };
+                    let cpi_program = nft_token_program.clone();
+                    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, vault_signer);
+                    token_interface::close_account(cpi_ctx)?;
+
+                    buyer_record.close(bidder.clone())?;
+                    close_listing(&mut listing, ListingStatus::SoldOut, seller.clone())?;
+                } else {
+                    buyer_record.exit(ctx.program_id)?;
+                    listing.quantity -= 1;
+                    listing.exit(ctx.program_id)?;
+                }
+            }
+        }
+
+        log_compute_units("place_bid: end");
+
+        Ok(())
+    }
+
+    // Place bids on several NFTs at once. Expects (nft_mint, bid, bid_book,
+    // escrow_payment_account) groups in remaining_accounts, one per entry in bids.
+    pub fn bulk_place_bids<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BulkPlaceBids<'info>>,
+        bids: Vec<BidParams>,
+    ) -> Result<()> {
+        log_compute_units("bulk_place_bids: start");
+
+        let config = &ctx.accounts.config;
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require!(!bids.is_empty(), ErrorCode::InvalidBulkBid);
+        require!(bids.len() <= MAX_BULK_BIDS, ErrorCode::BatchTooLarge);
+        require!(
+            ctx.remaining_accounts.len() == bids.len() * 4,
+            ErrorCode::InvalidBulkBid
+        );
+
+        let clock = Clock::get()?;
+        let bidder = ctx.accounts.bidder.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let payment_mint = ctx.accounts.payment_mint.key();
+        let source = PaymentSource {
+            from: ctx.accounts.bidder_payment_account.to_account_info(),
+            authority: bidder.clone(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.token_program.to_account_info(),
+            signer_seeds: &[],
+        };
+
+        let bid_counter = &mut ctx.accounts.bid_counter;
+        let mut bid_ids = Vec::with_capacity(bids.len());
+
+        for (params, group) in bids.iter().zip(ctx.remaining_accounts.chunks(4)) {
+            let nft_mint = &group[0];
+            let bid_info = &group[1];
+            let bid_book_info = &group[2];
+            let escrow_payment_account = &group[3];
+
+            require_keys_eq!(nft_mint.key(), params.nft_mint, ErrorCode::InvalidBulkBid);
+            require!(
+                params.expiry == 0 || clock.unix_timestamp <= params.expiry,
+                ErrorCode::BidExpired
+            );
+            // Every bid needs a real mint, just as place_bid's Mint account would require
+            Mint::try_deserialize(&mut &nft_mint.try_borrow_data()?[..])?;
+
+            // Competing bids on the same NFT must beat the best bid by the configured increment
+            let (bid_book_key, bid_book_bump) = Pubkey::find_program_address(
+                &[b"bid_book", params.nft_mint.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(bid_book_info.key(), bid_book_key, ErrorCode::InvalidBulkBid);
+            let mut bid_book = if bid_book_info.data_is_empty() {
+                let seeds = &[b"bid_book".as_ref(), params.nft_mint.as_ref(), &[bid_book_bump]];
+                create_program_account(
+                    bid_book_info,
+                    8 + BidBook::INIT_SPACE,
+                    &bidder,
+                    &system_program,
+                    &[&seeds[..]],
+                )?;
+                BidBook {
+                    nft_mint: params.nft_mint,
+                    best_bid: 0,
+                    best_bidder: Pubkey::default(),
+                    bump: bid_book_bump,
+                }
+            } else {
+                Account::<BidBook>::try_from(bid_book_info)?.into_inner()
+            };
+            bid_book.record_bid(bidder.key(), params.price, config)?;
+            bid_book.try_serialize(&mut &mut bid_book_info.try_borrow_mut_data()?[..])?;
+
+            let nonce = bid_counter.next_nonce.to_le_bytes();
+            let (bid_key, bid_bump) = Pubkey::find_program_address(
+                &[
+                    b"bid",
+                    bidder.key.as_ref(),
+                    params.nft_mint.as_ref(),
+                    nonce.as_ref(),
+                ],
+                ctx.program_id,
+            );
+            require_keys_eq!(bid_info.key(), bid_key, ErrorCode::InvalidBulkBid);
+            let seeds = &[
+                b"bid".as_ref(),
+                bidder.key.as_ref(),
+                params.nft_mint.as_ref(),
+                nonce.as_ref(),
+                &[bid_bump],
+            ];
+            create_program_account(
+                bid_info,
+                8 + Bid::INIT_SPACE,
+                &bidder,
+                &system_program,
+                &[&seeds[..]],
+            )?;
+            let bid = Bid {
+                bidder: bidder.key(),
+                nft_mint: params.nft_mint,
+                payment_mint: Some(payment_mint),
+                price: params.price,
+                created_at: clock.unix_timestamp,
+                expiry: params.expiry,
+                bump: bid_bump,
+                version: ACCOUNT_VERSION,
+                nonce: bid_counter.next_nonce,
+            };
+            bid.try_serialize(&mut &mut bid_info.try_borrow_mut_data()?[..])?;
+            bid_counter.advance(bidder.key(), ctx.bumps.bid_counter)?;
+
+            // Escrow the bid amount in the bid's associated token account
+            let cpi_accounts = associated_token::Create {
+                payer: bidder.clone(),
+                associated_token: escrow_payment_account.clone(),
+                authority: bid_info.clone(),
+                mint: ctx.accounts.payment_mint.to_account_info(),
+                system_program: system_program.clone(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.associated_token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            associated_token::create_idempotent(cpi_ctx)?;
+            source.transfer(escrow_payment_account.clone(), params.price)?;
+
+            bid_ids.push(bid_key);
+        }
+
+        emit!(BulkBidsPlaced {
+            bidder: bidder.key(),
+            bid_ids,
+        });
+
+        log_compute_units("bulk_place_bids: end");
+
+        Ok(())
+    }
+
+    // Cancel an existing bid
+    pub fn cancel_bid(ctx: Context<CancelBid>) -> Result<()> {
+        let bid = &ctx.accounts.bid;
+
+        refund_bid_escrow(
+            bid,
+            ctx.accounts.escrow_payment_account.to_account_info(),
+            ctx.accounts.payment_mint.to_account_info(),
+            ctx.accounts.bidder_payment_account.to_account_info(),
+            ctx.accounts.bidder.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        ctx.accounts.bid_book.remove_bid(bid.bidder, bid.price);
+
+        emit!(BidCancelled {
+            bid_id: bid.key(),
+            bidder: bid.bidder,
+            nft_mint: bid.nft_mint,
+        });
+
+        Ok(())
+    }
+
+    // Let anyone return an expired bid's escrow to the bidder. The keeper is tipped
+    // up to crank_reward_lamports out of the bid rent and the bidder gets the rest.
+    pub fn refund_expired_bid(ctx: Context<RefundExpiredBid>) -> Result<()> {
+        let bid = &ctx.accounts.bid;
+        let clock = Clock::get()?;
+
+        require!(
+            bid.expiry != 0 && clock.unix_timestamp > bid.expiry,
+            ErrorCode::BidNotExpired
+        );
+
+        match bid.payment_mint {
+            Some(mint) => {
+                let payment_mint = ctx
+                    .accounts
+                    .payment_mint
+                    .as_ref()
+                    .ok_or(ErrorCode::MissingPaymentAccount)?;
+                let bidder_payment_account = ctx
+                    .accounts
+                    .bidder_payment_account
+                    .as_ref()
+                    .ok_or(ErrorCode::MissingPaymentAccount)?;
+                let escrow_payment_account = ctx
+                    .accounts
+                    .escrow_payment_account
+                    .as_ref()
+                    .ok_or(ErrorCode::MissingPaymentAccount)?;
+                require_keys_eq!(payment_mint.key(), mint, ErrorCode::PaymentMintMismatch);
+
+                refund_bid_escrow(
+                    bid,
+                    escrow_payment_account.to_account_info(),
+                    payment_mint.to_account_info(),
+                    bidder_payment_account.to_account_info(),
+                    ctx.accounts.bidder.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                    payment_mint.decimals,
+                )?;
+            }
+            // A SOL bid escrows its price on top of the bid account's rent
+            None => LamportSource::Escrow(bid.to_account_info())
+                .transfer(ctx.accounts.bidder.to_account_info(), bid.price)?,
+        }
+
+        ctx.accounts.bid_book.remove_bid(bid.bidder, bid.price);
+
+        // Tip the keeper out of the bid rent, then close it to the bidder
+        let bid_info = bid.to_account_info();
+        let crank_reward = bid_info
+            .lamports()
+            .min(ctx.accounts.config.crank_reward_lamports);
+        LamportSource::Escrow(bid_info)
+            .transfer(ctx.accounts.keeper.to_account_info(), crank_reward)?;
+        bid.close(ctx.accounts.bidder.to_account_info())?;
+
+        emit!(BidExpiredRefunded {
+            bid_id: bid.key(),
+            bidder: bid.bidder,
+            nft_mint: bid.nft_mint,
+            amount: bid.price,
+            crank_operator: ctx.accounts.keeper.key(),
+            crank_reward,
+        });
+
+        Ok(())
+    }
+
+    // Refund expired bids in bulk. Expects (bid, bidder, bid_book, payment_mint,
+    // escrow_payment_account, bidder_payment_account) groups in remaining_accounts;
+    // SOL bids pass the program id for the last three, as Anchor does for a missing account.
+    // The crank is paid up to crank_reward_lamports out of each bid's rent.
+    pub fn batch_refund_expired_bids<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchRefundExpiredBids<'info>>,
+    ) -> Result<()> {
+        let groups = ctx.remaining_accounts;
+        require!(
+            !groups.is_empty() && groups.len() % 6 == 0 && groups.len() / 6 <= MAX_CRANK_BATCH,
+            ErrorCode::InvalidCrankBatch
+        );
+
+        let clock = Clock::get()?;
+        let crank_operator = ctx.accounts.crank_operator.to_account_info();
+        let token_program = ctx.accounts.token_program.to_account_info();
+
+        for group in groups.chunks(6) {
+            let bid = Account::<Bid>::try_from(&group[0])?;
+            let bidder = &group[1];
+            let mut bid_book = Account::<BidBook>::try_from(&group[2])?;
+            let payment_mint = &group[3];
+            let escrow_payment_account = &group[4];
+            let bidder_payment_account = &group[5];
+
+            require!(bid.version == ACCOUNT_VERSION, ErrorCode::UnsupportedVersion);
+            require!(
+                bid.expiry != 0 && clock.unix_timestamp > bid.expiry,
+                ErrorCode::BidNotExpired
+            );
+            require_keys_eq!(
+                bid.key(),
+                Pubkey::create_program_address(
+                    &[
+                        b"bid",
+                        bid.bidder.as_ref(),
+                        bid.nft_mint.as_ref(),
+                        bid.nonce.to_le_bytes().as_ref(),
+                        &[bid.bump],
+                    ],
+                    ctx.program_id,
+                )
+                .map_err(|_| ErrorCode::InvalidCrankBatch)?,
+                ErrorCode::InvalidCrankBatch
+            );
+            require_keys_eq!(bidder.key(), bid.bidder, ErrorCode::InvalidCrankBatch);
+            require_keys_eq!(
+                bid_book.key(),
+                Pubkey::create_program_address(
+                    &[b"bid_book", bid.nft_mint.as_ref(), &[bid_book.bump]],
+                    ctx.program_id,
+                )
+                .map_err(|_| ErrorCode::InvalidCrankBatch)?,
+                ErrorCode::InvalidCrankBatch
+            );
+            match bid.payment_mint {
+                Some(bid_payment_mint) => {
+                    require_keys_eq!(
+                        payment_mint.key(),
+                        bid_payment_mint,
+                        ErrorCode::PaymentMintMismatch
+                    );
+                    require_keys_eq!(
+                        escrow_payment_account.key(),
+                        get_associated_token_address_with_program_id(
+                            &bid.key(),
+                            &payment_mint.key(),
+                            &token_program.key(),
+                        ),
+                        ErrorCode::InvalidCrankBatch
+                    );
+                    let refund_account = TokenAccount::try_deserialize(
+                        &mut &bidder_payment_account.try_borrow_data()?[..],
+                    )?;
+                    require!(
+                        refund_account.owner == bid.bidder
+                            && refund_account.mint == payment_mint.key(),
+                        ErrorCode::InvalidCrankBatch
+                    );
+                    let mint = Mint::try_deserialize(&mut &payment_mint.try_borrow_data()?[..])?;
+
+                    refund_bid_escrow(
+                        &bid,
+                        escrow_payment_account.clone(),
+                        payment_mint.clone(),
+                        bidder_payment_account.clone(),
+                        bidder.clone(),
+                        token_program.clone(),
+                        mint.decimals,
+                    )?;
+                }
+                None => LamportSource::Escrow(bid.to_account_info())
+                    .transfer(bidder.clone(), bid.price)?,
+            }
+
+            bid_book.remove_bid(bid.bidder, bid.price);
+            bid_book.exit(ctx.program_id)?;
+
+            // Pay the crank out of the bid rent, then close it to the bidder
+            let bid_info = bid.to_account_info();
+            let crank_reward = bid_info
+                .lamports()
+                .min(ctx.accounts.config.crank_reward_lamports);
+            LamportSource::Escrow(bid_info).transfer(crank_operator.clone(), crank_reward)?;
+            bid.close(bidder.clone())?;
+
+            emit!(BidExpiredRefunded {
+                bid_id: bid.key(),
+                bidder: bid.bidder,
+                nft_mint: bid.nft_mint,
+                amount: bid.price,
+                crank_operator: crank_operator.key(),
+                crank_reward,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Raise or lower an existing bid, moving only the difference in or out of escrow
+    pub fn update_bid(ctx: Context<UpdateBid>, new_price: u64, new_expiry: i64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(new_price > 0, ErrorCode::InvalidPrice);
+
+        let bid = &ctx.accounts.bid;
+        let clock = Clock::get()?;
+
+        require!(
+            new_expiry == 0 || clock.unix_timestamp <= new_expiry,
+            ErrorCode::BidExpired
+        );
+
+        let old_price = bid.price;
+        if new_price > old_price {
+            // Top up the escrow with the difference
+            let cpi_accounts = token_interface::TransferChecked {
+                from: ctx.accounts.bidder_payment_account.to_account_info(),
+                mint: ctx.accounts.payment_mint.to_account_info(),
+                to: ctx.accounts.escrow_payment_account.to_account_info(),
+                authority: ctx.accounts.bidder.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token_interface::transfer_checked(
+                cpi_ctx,
+                new_price - old_price,
+                ctx.accounts.payment_mint.decimals,
+            )?;
+        } else if new_price < old_price {
+            // Refund the difference from escrow
+            let nonce = bid.nonce.to_le_bytes();
+            let seeds = &[
+                b"bid".as_ref(),
+                bid.bidder.as_ref(),
+                bid.nft_mint.as_ref(),
+                nonce.as_ref(),
+                &[bid.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            let cpi_accounts = token_interface::TransferChecked {
+                from: ctx.accounts.escrow_payment_account.to_account_info(),
+                mint: ctx.accounts.payment_mint.to_account_info(),
+                to: ctx.accounts.bidder_payment_account.to_account_info(),
+                authority: bid.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::transfer_checked(
+                cpi_ctx,
+                old_price - new_price,
+                ctx.accounts.payment_mint.decimals,
+            )?;
+        }
+
+        let bid = &mut ctx.accounts.bid;
+        bid.price = new_price;
+        bid.expiry = new_expiry;
+
+        emit!(BidUpdated {
+            bid_id: bid.key(),
+            bidder: bid.bidder,
+            nft_mint: bid.nft_mint,
+            old_price,
+            new_price,
+            expiry: new_expiry,
+        });
+
+        Ok(())
+    }
+
+    // Change only the price of a bid, keeping its current expiry
+    pub fn update_bid_price(ctx: Context<UpdateBid>, new_price: u64) -> Result<()> {
+        let expiry = ctx.accounts.bid.expiry;
+        update_bid(ctx, new_price, expiry)
+    }
+
+    // Accept a bid
+    pub fn accept_bid(ctx: Context<AcceptBid>, second_highest_bid: u64) -> Result<()> {
+        log_compute_units("accept_bid: start");
+
+        let bid = &ctx.accounts.bid;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require_keys_neq!(ctx.accounts.seller.key(), bid.bidder, ErrorCode::SelfTrade);
+
+        require!(
+            bid.expiry == 0 || clock.unix_timestamp <= bid.expiry,
+            ErrorCode::BidExpired
+        );
+
+        // Fail with a clear error instead of an opaque token error when the seller lacks the NFT
+        let seller_nft_account = &ctx.accounts.seller_nft_account;
+        require!(
+            seller_nft_account.mint == bid.nft_mint && seller_nft_account.amount >= 1,
+            ErrorCode::SellerDoesNotOwnNft
+        );
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &bid.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &bid.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // The bid was resting, so the bidder's maker fee comes out of their escrow
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Buyer))?;
+        let (sale_price, buyer_fee) = split_escrowed_price(bid.price, buyer_fee_bps)?;
+        record_fee_tier_volume(&ctx.accounts.fee_tier, bid.payment_mint, sale_price)?;
+
+        // Calculate royalties
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            RoyaltyEnforcementMode::Mandatory,
+            &metadata,
+            &metadata.creators,
+            sale_price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        // The seller filled a resting bid, so they pay the taker rate
+        let (marketplace_fee, second_bidder_fee, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Buyer),
+            config,
+            second_highest_bid,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments out of the bid escrow
+        let nonce = bid.nonce.to_le_bytes();
+        let seeds = &[
+            b"bid".as_ref(),
+            bid.bidder.as_ref(),
+            bid.nft_mint.as_ref(),
+            nonce.as_ref(),
+            &[bid.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let source = PaymentSource {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: ctx.accounts.bid.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: signer,
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            Some(ctx.accounts.second_bidder_account.to_account_info()),
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            second_bidder_fee,
+        )?;
+
+        // Transfer NFT to bidder
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.bidder_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Record the sale in the on-chain price history
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = bid.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: sale_price,
+            buyer: bid.bidder,
+            timestamp: clock.unix_timestamp,
+        });
+
+        // Roll the sale into the collection's stats
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(sale_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(ctx.accounts.seller.key(), ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(sale_price)?;
+
+        if let Some(market_stats) = ctx.accounts.market_stats.as_mut() {
+            market_stats.record_sale(sale_price, marketplace_fee)?;
+        }
+
+        ctx.accounts.bid_book.remove_bid(bid.bidder, bid.price);
+
+        emit!(BidAccepted {
+            bid_id: bid.key(),
+            seller: ctx.accounts.seller.key(),
+            bidder: bid.bidder,
+            nft_mint: bid.nft_mint,
+            price: bid.price,
+            marketplace_fee,
+            second_bidder: ctx.accounts.second_bidder_account.owner,
+            second_bidder_fee,
+            total_creator_royalty: creator_payments.iter().map(|(_, amount)| amount).sum(),
+            seller_payment,
+        });
+
+        log_compute_units("accept_bid: end");
+
+        Ok(())
+    }
+
+    // Settle a listing against a standalone bid, as long as the bid meets the reserve price
+    pub fn accept_highest_bid(
+        ctx: Context<AcceptHighestBid>,
+        second_highest_bid: u64,
+    ) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let bid = &ctx.accounts.bid;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require_keys_neq!(ctx.accounts.seller.key(), bid.bidder, ErrorCode::SelfTrade);
+        require!(
+            !listing_expired(listing.expiry, config, clock.unix_timestamp),
+            ErrorCode::ListingExpired
+        );
+        require!(
+            bid.expiry == 0 || clock.unix_timestamp <= bid.expiry,
+            ErrorCode::BidExpired
+        );
+        require!(bid.price >= listing.reserve_price, ErrorCode::ReserveNotMet);
+        if let Some(allowed_buyer) = listing.buyer_allowlist {
+            require_keys_eq!(bid.bidder, allowed_buyer, ErrorCode::UnauthorizedBuyer);
+        }
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &listing.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &listing.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // The bid was resting, so the bidder's maker fee comes out of their escrow
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Buyer))?;
+        let (sale_price, buyer_fee) = split_escrowed_price(bid.price, buyer_fee_bps)?;
+        record_fee_tier_volume(&ctx.accounts.fee_tier, bid.payment_mint, sale_price)?;
+
+        // Calculate royalties according to the listing's enforcement mode
+        let royalty_enforcement = effective_royalty_enforcement(listing.royalty_enforcement, config);
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            royalty_enforcement,
+            &metadata,
+            &listing_creators(&listing.creator_cache, &metadata.creators),
+            sale_price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties && royalty_enforcement == RoyaltyEnforcementMode::Mandatory {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        let second_highest_bid = validate_second_bid(
+            ctx.accounts.second_bid.as_ref(),
+            second_highest_bid,
+            listing.payment_mint,
+            &ctx.accounts.second_bidder_account.owner,
+            clock.unix_timestamp,
+        )?;
+
+        // The seller filled a resting bid, so they pay the taker rate
+        let (marketplace_fee, second_bidder_fee, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Buyer),
+            config,
+            second_highest_bid,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments out of the bid escrow
+        let nonce = bid.nonce.to_le_bytes();
+        let bid_seeds = &[
+            b"bid".as_ref(),
+            bid.bidder.as_ref(),
+            bid.nft_mint.as_ref(),
+            nonce.as_ref(),
+            &[bid.bump],
+        ];
+        let bid_signer = &[&bid_seeds[..]];
+        let source = PaymentSource {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: bid.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: bid_signer,
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            Some(ctx.accounts.second_bidder_account.to_account_info()),
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            second_bidder_fee,
+        )?;
+
+        // Transfer NFT from vault to bidder
+        let seeds = &[
+            b"vault".as_ref(),
+            listing.seller.as_ref(),
+            listing.nft_mint.as_ref(),
+            &[ctx.bumps.vault_nft_account],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.bidder_nft_account.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Record the sale in the on-chain price history
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = listing.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: sale_price,
+            buyer: bid.bidder,
+            timestamp: clock.unix_timestamp,
+        });
+
+        // Roll the sale into the collection's stats
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(sale_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(ctx.accounts.seller.key(), ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(sale_price)?;
+
+        if let Some(market_stats) = ctx.accounts.market_stats.as_mut() {
+            market_stats.record_sale(sale_price, marketplace_fee)?;
+        }
+
+        ctx.accounts.bid_book.remove_bid(bid.bidder, bid.price);
+
+        emit!(BidAccepted {
+            bid_id: bid.key(),
+            seller: ctx.accounts.seller.key(),
+            bidder: bid.bidder,
+            nft_mint: bid.nft_mint,
+            price: bid.price,
+            marketplace_fee,
+            second_bidder: ctx.accounts.second_bidder_account.owner,
+            second_bidder_fee,
+            total_creator_royalty: creator_payments.iter().map(|(_, amount)| amount).sum(),
+            seller_payment,
+        });
+
+        // Update or close the listing, reclaiming the vault rent once it is drained
+        if listing.quantity == 1 {
+            ctx.accounts.vault_nft_account.reload()?;
+            require!(
+                ctx.accounts.vault_nft_account.amount == 0,
+                ErrorCode::VaultNotEmpty
+            );
+            let cpi_accounts = token_interface::CloseAccount {
+                account: ctx.accounts.vault_nft_account.to_account_info(),
+                destination: ctx.accounts.seller.to_account_info(),
+                authority: ctx.accounts.vault_nft_account.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+
+            close_listing(
+                &mut ctx.accounts.listing,
+                ListingStatus::SoldOut,
+                ctx.accounts.seller.to_account_info(),
+            )?;
+        } else {
+            ctx.accounts.listing.quantity -= 1;
+        }
+
+        Ok(())
+    }
+
+    // Accept a bid on an NFT the seller has listed. The NFT comes out of the listing's vault,
+    // any units left over go back to the seller, and both the listing and the bid are closed.
+    pub fn accept_bid_for_listing(
+        ctx: Context<AcceptBidForListing>,
+        second_highest_bid: u64,
+    ) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let bid = &ctx.accounts.bid;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require_keys_neq!(ctx.accounts.seller.key(), bid.bidder, ErrorCode::SelfTrade);
+        require!(
+            bid.expiry == 0 || clock.unix_timestamp <= bid.expiry,
+            ErrorCode::BidExpired
+        );
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &listing.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &listing.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // The bid was resting, so the bidder's maker fee comes out of their escrow
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Buyer))?;
+        let (sale_price, buyer_fee) = split_escrowed_price(bid.price, buyer_fee_bps)?;
+        record_fee_tier_volume(&ctx.accounts.fee_tier, bid.payment_mint, sale_price)?;
+
+        // Calculate royalties according to the listing's enforcement mode
+        let royalty_enforcement = effective_royalty_enforcement(listing.royalty_enforcement, config);
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            royalty_enforcement,
+            &metadata,
+            &metadata.creators,
+            sale_price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties && royalty_enforcement == RoyaltyEnforcementMode::Mandatory {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        let second_highest_bid = validate_second_bid(
+            ctx.accounts.second_bid.as_ref(),
+            second_highest_bid,
+            listing.payment_mint,
+            &ctx.accounts.second_bidder_account.owner,
+            clock.unix_timestamp,
+        )?;
+
+        // The seller filled a resting bid, so they pay the taker rate
+        let (marketplace_fee, second_bidder_fee, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Buyer),
+            config,
+            second_highest_bid,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments out of the bid escrow
+        let nonce = bid.nonce.to_le_bytes();
+        let bid_seeds = &[
+            b"bid".as_ref(),
+            bid.bidder.as_ref(),
+            bid.nft_mint.as_ref(),
+            nonce.as_ref(),
+            &[bid.bump],
+        ];
+        let bid_signer = &[&bid_seeds[..]];
+        let source = PaymentSource {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: bid.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: bid_signer,
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            Some(ctx.accounts.second_bidder_account.to_account_info()),
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            second_bidder_fee,
+        )?;
+
+        // Transfer NFT from vault to bidder and return any remaining units to the seller
+        let seeds = &[
+            b"vault".as_ref(),
+            listing.seller.as_ref(),
+            listing.nft_mint.as_ref(),
+            &[ctx.bumps.vault_nft_account],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.bidder_nft_account.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        let leftover = listing.quantity - 1;
+        if leftover > 0 {
+            let cpi_accounts = token_interface::TransferChecked {
+                from: ctx.accounts.vault_nft_account.to_account_info(),
+                mint: ctx.accounts.nft_mint.to_account_info(),
+                to: ctx.accounts.seller_nft_account.to_account_info(),
+                authority: ctx.accounts.vault_nft_account.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::transfer_checked(cpi_ctx, leftover, ctx.accounts.nft_mint.decimals)?;
+        }
+
+        // The vault is drained now, so reclaim its rent along with the listing's
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vault_nft_account.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        // Record the sale in the on-chain price history
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = listing.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: sale_price,
+            buyer: bid.bidder,
+            timestamp: clock.unix_timestamp,
+        });
+
+        // Roll the sale into the collection's stats
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(sale_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(ctx.accounts.seller.key(), ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(sale_price)?;
+
+        ctx.accounts.bid_book.remove_bid(bid.bidder, bid.price);
+
+        emit!(BidAccepted {
+            bid_id: bid.key(),
+            seller: ctx.accounts.seller.key(),
+            bidder: bid.bidder,
+            nft_mint: bid.nft_mint,
+            price: bid.price,
+            marketplace_fee,
+            second_bidder: ctx.accounts.second_bidder_account.owner,
+            second_bidder_fee,
+            total_creator_royalty: creator_payments.iter().map(|(_, amount)| amount).sum(),
+            seller_payment,
+        });
+
+        Ok(())
+    }
+
+    // Place a bid on a specific listing rather than on any copy of the mint.
+    // The bid remembers when the listing was created, so it cannot be accepted
+    // against a later relisting that reuses the same listing address.
+    pub fn bid_on_listing(
+        ctx: Context<BidOnListing>,
+        price: u64,
+        expiry: i64,
+        deadline: i64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+
+        let listing = &ctx.accounts.listing;
+        let clock = Clock::get()?;
+
+        require!(clock.unix_timestamp <= deadline, ErrorCode::TransactionExpired);
+        require!(price > 0, ErrorCode::InvalidPrice);
+        require_keys_neq!(ctx.accounts.bidder.key(), listing.seller, ErrorCode::SelfTrade);
+        require!(
+            expiry == 0 || clock.unix_timestamp <= expiry,
+            ErrorCode::BidExpired
+        );
+        require!(
+            !listing_expired(listing.expiry, &ctx.accounts.config, clock.unix_timestamp),
+            ErrorCode::ListingExpired
+        );
+
+        let listing_bid = &mut ctx.accounts.listing_bid;
+        listing_bid.bidder = ctx.accounts.bidder.key();
+        listing_bid.listing = listing.key();
+        listing_bid.listing_created_at = listing.created_at;
+        listing_bid.nft_mint = listing.nft_mint;
+        listing_bid.payment_mint = ctx.accounts.payment_mint.key();
+        listing_bid.price = price;
+        listing_bid.created_at = clock.unix_timestamp;
+        listing_bid.expiry = expiry;
+        listing_bid.bump = ctx.bumps.listing_bid;
+        listing_bid.version = ACCOUNT_VERSION;
+
+        // Transfer bid amount to escrow
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.bidder_payment_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: ctx.accounts.bidder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, price, ctx.accounts.payment_mint.decimals)?;
+
+        emit!(ListingBidPlaced {
+            bid_id: listing_bid.key(),
+            listing: listing.key(),
+            bidder: listing_bid.bidder,
+            nft_mint: listing_bid.nft_mint,
+            price,
+            expiry,
+        });
+
+        Ok(())
+    }
+
+    // Cancel a listing bid and refund its escrow
+    pub fn cancel_listing_bid(ctx: Context<CancelListingBid>) -> Result<()> {
+        let listing_bid = &ctx.accounts.listing_bid;
+
+        // Transfer bid amount back to bidder
+        let seeds = &[
+            b"listing_bid".as_ref(),
+            listing_bid.listing.as_ref(),
+            listing_bid.bidder.as_ref(),
+            &[listing_bid.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.bidder_payment_account.to_account_info(),
+            authority: listing_bid.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(
+            cpi_ctx,
+            listing_bid.price,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        // Reclaim the escrow rent
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.escrow_payment_account.to_account_info(),
+            destination: ctx.accounts.bidder.to_account_info(),
+            authority: listing_bid.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(BidCancelled {
+            bid_id: listing_bid.key(),
+            bidder: listing_bid.bidder,
+            nft_mint: listing_bid.nft_mint,
+        });
+
+        Ok(())
+    }
+
+    // Accept a bid placed on this exact listing and sell the bidder one unit from the vault
+    pub fn accept_listing_bid(ctx: Context<AcceptListingBid>) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let listing_bid = &ctx.accounts.listing_bid;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+
+        // A relisting reuses the listing address, so the creation time tells them apart
+        require!(
+            listing_bid.listing_created_at == listing.created_at,
+            ErrorCode::BidListingMismatch
+        );
+        require!(
+            !listing_expired(listing.expiry, config, clock.unix_timestamp),
+            ErrorCode::ListingExpired
+        );
+        require!(
+            listing_bid.expiry == 0 || clock.unix_timestamp <= listing_bid.expiry,
+            ErrorCode::BidExpired
+        );
+        if let Some(allowed_buyer) = listing.buyer_allowlist {
+            require_keys_eq!(listing_bid.bidder, allowed_buyer, ErrorCode::UnauthorizedBuyer);
+        }
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &listing.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &listing.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // The bid was resting, so the bidder's maker fee comes out of their escrow
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Buyer))?;
+        let (sale_price, buyer_fee) = split_escrowed_price(listing_bid.price, buyer_fee_bps)?;
+        record_fee_tier_volume(
+            &ctx.accounts.fee_tier,
+            Some(ctx.accounts.payment_mint.key()),
+            sale_price,
+        )?;
+
+        // Calculate royalties according to the listing's enforcement mode
+        let royalty_enforcement = effective_royalty_enforcement(listing.royalty_enforcement, config);
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            royalty_enforcement,
+            &metadata,
+            &metadata.creators,
+            sale_price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties && royalty_enforcement == RoyaltyEnforcementMode::Mandatory {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        // Listing bids have no second bidder, so its share of the fee is unused. The seller
+        // filled a resting bid, so they pay the taker rate
+        let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Buyer),
+            config,
+            0,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments out of the bid escrow
+        let bid_seeds = &[
+            b"listing_bid".as_ref(),
+            listing_bid.listing.as_ref(),
+            listing_bid.bidder.as_ref(),
+            &[listing_bid.bump],
+        ];
+        let bid_signer = &[&bid_seeds[..]];
+        let source = PaymentSource {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: listing_bid.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: bid_signer,
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            None,
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            0,
+        )?;
+
+        // Return the emptied escrow's rent to the bidder
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.escrow_payment_account.to_account_info(),
+            destination: ctx.accounts.bidder.to_account_info(),
+            authority: listing_bid.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, bid_signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        // Transfer NFT from vault to bidder
+        let seeds = &[
+            b"vault".as_ref(),
+            listing.seller.as_ref(),
+            listing.nft_mint.as_ref(),
+            &[ctx.bumps.vault_nft_account],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.bidder_nft_account.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Record the sale in the on-chain price history
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = listing.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: sale_price,
+            buyer: listing_bid.bidder,
+            timestamp: clock.unix_timestamp,
+        });
+
+        // Roll the sale into the collection's stats
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(sale_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(listing.seller, ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(sale_price)?;
+
+        emit!(BidAccepted {
+            bid_id: listing_bid.key(),
+            seller: listing.seller,
+            bidder: listing_bid.bidder,
+            nft_mint: listing_bid.nft_mint,
+            price: listing_bid.price,
+            marketplace_fee,
+            second_bidder: Pubkey::default(),
+            second_bidder_fee: 0,
+            total_creator_royalty: creator_payments.iter().map(|(_, amount)| amount).sum(),
+            seller_payment,
+        });
+
+        // Update or close the listing
+        if listing.quantity == 1 {
+            close_listing(
+                &mut ctx.accounts.listing,
+                ListingStatus::SoldOut,
+                ctx.accounts.seller.to_account_info(),
+            )?;
+        } else {
+            ctx.accounts.listing.quantity -= 1;
+        }
+
+        Ok(())
+    }
+
+    // Place a bid without escrow by approving the bid PDA as a delegate over the bidder's
+    // payment account. A token account has a single delegate, so a newer delegated bid
+    // from the same account replaces the approval behind any older one.
+    pub fn place_bid_delegated(
+        ctx: Context<PlaceBidDelegated>,
+        price: u64,
+        expiry: i64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(price > 0, ErrorCode::InvalidPrice);
+
+        let clock = Clock::get()?;
+        require!(
+            expiry == 0 || clock.unix_timestamp <= expiry,
+            ErrorCode::BidExpired
+        );
+
+        let delegated_bid = &mut ctx.accounts.delegated_bid;
+        delegated_bid.bidder = ctx.accounts.bidder.key();
+        delegated_bid.nft_mint = ctx.accounts.nft_mint.key();
+        delegated_bid.payment_mint = ctx.accounts.payment_mint.key();
+        delegated_bid.bidder_payment_account = ctx.accounts.bidder_payment_account.key();
+        delegated_bid.price = price;
+        delegated_bid.created_at = clock.unix_timestamp;
+        delegated_bid.expiry = expiry;
+        delegated_bid.bump = ctx.bumps.delegated_bid;
+        delegated_bid.version = ACCOUNT_VERSION;
+
+        // Let the bid PDA pull up to the bid price when the bid is accepted
+        let cpi_accounts = token_interface::Approve {
+            to: ctx.accounts.bidder_payment_account.to_account_info(),
+            delegate: delegated_bid.to_account_info(),
+            authority: ctx.accounts.bidder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::approve(cpi_ctx, price)?;
+
+        emit!(BidPlaced {
+            bid_id: delegated_bid.key(),
+            bidder: delegated_bid.bidder,
+            nft_mint: delegated_bid.nft_mint,
+            price,
+            expiry,
+        });
+
+        Ok(())
+    }
+
+    // Cancel a delegated bid and revoke its approval
+    pub fn cancel_bid_delegated(ctx: Context<CancelBidDelegated>) -> Result<()> {
+        let delegated_bid = &ctx.accounts.delegated_bid;
+
+        // Only revoke the approval if it still belongs to this bid
+        let delegate: Option<Pubkey> = ctx.accounts.bidder_payment_account.delegate.into();
+        if delegate == Some(delegated_bid.key()) {
+            let cpi_accounts = token_interface::Revoke {
+                source: ctx.accounts.bidder_payment_account.to_account_info(),
+                authority: ctx.accounts.bidder.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            token_interface::revoke(cpi_ctx)?;
+        }
+
+        emit!(BidCancelled {
+            bid_id: delegated_bid.key(),
+            bidder: delegated_bid.bidder,
+            nft_mint: delegated_bid.nft_mint,
+        });
+
+        Ok(())
+    }
+
+    // Accept a delegated bid, pulling the payment straight from the bidder's account
+    pub fn accept_bid_delegated(ctx: Context<AcceptBidDelegated>) -> Result<()> {
+        let delegated_bid = &ctx.accounts.delegated_bid;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require_keys_neq!(
+            ctx.accounts.seller.key(),
+            delegated_bid.bidder,
+            ErrorCode::SelfTrade
+        );
+        require!(
+            delegated_bid.expiry == 0 || clock.unix_timestamp <= delegated_bid.expiry,
+            ErrorCode::BidExpired
+        );
+
+        // The bidder may have revoked or replaced the approval since bidding
+        let bidder_payment_account = &ctx.accounts.bidder_payment_account;
+        let delegate: Option<Pubkey> = bidder_payment_account.delegate.into();
+        require!(
+            delegate == Some(delegated_bid.key())
+                && bidder_payment_account.delegated_amount >= delegated_bid.price,
+            ErrorCode::DelegationRevoked
+        );
+
+        // Fail with a clear error instead of an opaque token error when the seller lacks the NFT
+        let seller_nft_account = &ctx.accounts.seller_nft_account;
+        require!(
+            seller_nft_account.mint == delegated_bid.nft_mint && seller_nft_account.amount >= 1,
+            ErrorCode::SellerDoesNotOwnNft
+        );
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &delegated_bid.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &delegated_bid.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // The bid was resting, so the bidder's maker fee comes out of the amount they approved
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Buyer))?;
+        let (sale_price, buyer_fee) = split_escrowed_price(delegated_bid.price, buyer_fee_bps)?;
+        record_fee_tier_volume(
+            &ctx.accounts.fee_tier,
+            Some(ctx.accounts.payment_mint.key()),
+            sale_price,
+        )?;
+
+        // Calculate royalties
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            RoyaltyEnforcementMode::Mandatory,
+            &metadata,
+            &metadata.creators,
+            sale_price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        // Delegated bids have no second bidder, so its share of the fee is unused
+        let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Buyer),
+            config,
+            0,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments from the bidder's account under the delegation
+        let seeds = &[
+            b"delegated_bid".as_ref(),
+            delegated_bid.bidder.as_ref(),
+            delegated_bid.nft_mint.as_ref(),
+            &[delegated_bid.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let source = PaymentSource {
+            from: ctx.accounts.bidder_payment_account.to_account_info(),
+            authority: delegated_bid.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: signer,
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            None,
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            0,
+        )?;
+
+        // Transfer NFT to bidder
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.bidder_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Record the sale in the on-chain price history
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = delegated_bid.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: sale_price,
+            buyer: delegated_bid.bidder,
+            timestamp: clock.unix_timestamp,
+        });
+
+        // Roll the sale into the collection's stats
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(sale_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(ctx.accounts.seller.key(), ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(sale_price)?;
+
+        emit!(BidAccepted {
+            bid_id: delegated_bid.key(),
+            seller: ctx.accounts.seller.key(),
+            bidder: delegated_bid.bidder,
+            nft_mint: delegated_bid.nft_mint,
+            price: delegated_bid.price,
+            marketplace_fee,
+            second_bidder: Pubkey::default(),
+            second_bidder_fee: 0,
+            total_creator_royalty: creator_payments.iter().map(|(_, amount)| amount).sum(),
+            seller_payment,
+        });
+
+        Ok(())
+    }
+
+    // Place a bid escrowed in native SOL inside the bid account itself
+    pub fn place_bid_sol(ctx: Context<PlaceBidSol>, price: u64, expiry: i64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(price > 0, ErrorCode::InvalidPrice);
+
+        let clock = Clock::get()?;
+        require!(
+            expiry == 0 || clock.unix_timestamp <= expiry,
+            ErrorCode::BidExpired
+        );
+
+        // Competing bids on the same NFT must beat the best bid by the configured increment
+        let bid_book = &mut ctx.accounts.bid_book;
+        bid_book.nft_mint = ctx.accounts.nft_mint.key();
+        bid_book.bump = ctx.bumps.bid_book;
+        bid_book.record_bid(ctx.accounts.bidder.key(), price, &ctx.accounts.config)?;
+
+        let bid = &mut ctx.accounts.bid;
+        bid.bidder = ctx.accounts.bidder.key();
+        bid.nft_mint = ctx.accounts.nft_mint.key();
+        bid.payment_mint = None;
+        bid.price = price;
+        bid.created_at = clock.unix_timestamp;
+        bid.expiry = expiry;
+        bid.bump = ctx.bumps.bid;
+        bid.version = ACCOUNT_VERSION;
+        bid.nonce = ctx.accounts.bid_counter.next_nonce;
+        ctx.accounts
+            .bid_counter
+            .advance(ctx.accounts.bidder.key(), ctx.bumps.bid_counter)?;
+
+        // Escrow the bid lamports on top of the bid account's rent
+        let cpi_accounts = system_program::Transfer {
+            from: ctx.accounts.bidder.to_account_info(),
+            to: ctx.accounts.bid.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.system_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        system_program::transfer(cpi_ctx, price)?;
+
+        emit!(BidPlaced {
+            bid_id: ctx.accounts.bid.key(),
+            bidder: ctx.accounts.bidder.key(),
+            nft_mint: ctx.accounts.nft_mint.key(),
+            price,
+            expiry,
+        });
+
+        Ok(())
+    }
+
+    // Cancel a SOL bid; closing the bid account refunds the escrow and the rent
+    pub fn cancel_bid_sol(ctx: Context<CancelBidSol>) -> Result<()> {
+        let bid = &ctx.accounts.bid;
+
+        ctx.accounts.bid_book.remove_bid(bid.bidder, bid.price);
+
+        emit!(BidCancelled {
+            bid_id: bid.key(),
+            bidder: bid.bidder,
+            nft_mint: bid.nft_mint,
+        });
+
+        Ok(())
+    }
+
+    // Accept a SOL bid, paying out of the lamports escrowed in the bid account
+    pub fn accept_bid_sol(ctx: Context<AcceptBidSol>, second_highest_bid: u64) -> Result<()> {
+        let bid = &ctx.accounts.bid;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require_keys_neq!(ctx.accounts.seller.key(), bid.bidder, ErrorCode::SelfTrade);
+        require!(
+            bid.expiry == 0 || clock.unix_timestamp <= bid.expiry,
+            ErrorCode::BidExpired
+        );
+
+        // Fail with a clear error instead of an opaque token error when the seller lacks the NFT
+        require!(
+            ctx.accounts.seller_nft_account.amount >= 1,
+            ErrorCode::SellerDoesNotOwnNft
+        );
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &bid.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &bid.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // The bid was resting, so the bidder's maker fee comes out of their escrow
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Buyer))?;
+        let (sale_price, buyer_fee) = split_escrowed_price(bid.price, buyer_fee_bps)?;
+        record_fee_tier_volume(&ctx.accounts.fee_tier, None, sale_price)?;
+
+        // Calculate royalties
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            RoyaltyEnforcementMode::Mandatory,
+            &metadata,
+            &metadata.creators,
+            sale_price,
+            ctx.remaining_accounts,
+        )?;
+        if config.enforce_royalties {
+            check_creator_wallets(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        let second_highest_bid = validate_second_bid(
+            ctx.accounts.second_bid.as_ref(),
+            second_highest_bid,
+            None,
+            &ctx.accounts.second_bidder_account.key(),
+            clock.unix_timestamp,
+        )?;
+
+        // The seller filled a resting bid, so they pay the taker rate
+        let (marketplace_fee, second_bidder_fee, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Buyer),
+            config,
+            second_highest_bid,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments out of the bid escrow
+        let source = LamportSource::Escrow(ctx.accounts.bid.to_account_info());
+        transfer_payments(
+            &source,
+            ctx.accounts.seller.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.fee_vault.to_account_info(),
+            Some(ctx.accounts.second_bidder_account.to_account_info()),
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            second_bidder_fee,
+        )?;
+
+        // Transfer NFT to bidder
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.bidder_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Record the sale in the on-chain price history
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = bid.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: sale_price,
+            buyer: bid.bidder,
+            timestamp: clock.unix_timestamp,
+        });
+
+        // Roll the sale into the collection's stats
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(sale_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(ctx.accounts.seller.key(), ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(sale_price)?;
+
+        if let Some(market_stats) = ctx.accounts.market_stats.as_mut() {
+            market_stats.record_sale(sale_price, marketplace_fee)?;
+        }
+
+        ctx.accounts.bid_book.remove_bid(bid.bidder, bid.price);
+
+        emit!(BidAccepted {
+            bid_id: bid.key(),
+            seller: ctx.accounts.seller.key(),
+            bidder: bid.bidder,
+            nft_mint: bid.nft_mint,
+            price: bid.price,
+            marketplace_fee,
+            second_bidder: ctx.accounts.second_bidder_account.key(),
+            second_bidder_fee,
+            total_creator_royalty: creator_payments.iter().map(|(_, amount)| amount).sum(),
+            seller_payment,
+        });
+
+        Ok(())
+    }
+
+    // Return the recent sales of an NFT, oldest first
+    pub fn get_price_history(ctx: Context<GetPriceHistory>) -> Result<Vec<SaleRecord>> {
+        Ok(ctx.accounts.price_history.records_oldest_first())
+    }
+
+    // Reclaim the rent of a purchase record once its listing has been closed
+    pub fn close_buyer_record(_ctx: Context<CloseBuyerRecord>) -> Result<()> {
+        Ok(())
+    }
+
+    // Return a seller's lifetime sale and cancellation counters
+    pub fn get_seller_stats(ctx: Context<GetSellerStats>) -> Result<SellerStats> {
+        Ok((*ctx.accounts.seller_stats).clone())
+    }
+
+    // Create the buyer allowlist for a collection
+    pub fn initialize_whitelist(
+        ctx: Context<InitializeWhitelist>,
+        merkle_root: [u8; 32],
+    ) -> Result<()> {
+        let whitelist = &mut ctx.accounts.whitelist;
+        whitelist.authority = ctx.accounts.authority.key();
+        whitelist.collection_mint = ctx.accounts.collection_mint.key();
+        whitelist.merkle_root = merkle_root;
+        whitelist.bump = ctx.bumps.whitelist;
+
+        Ok(())
+    }
+
+    // Replace the merkle root of a collection's buyer allowlist
+    pub fn update_whitelist_root(
+        ctx: Context<UpdateWhitelistRoot>,
+        merkle_root: [u8; 32],
+    ) -> Result<()> {
+        ctx.accounts.whitelist.merkle_root = merkle_root;
+
+        Ok(())
+    }
+
+    // Pay a creator the royalty escrowed for them by a lazy-royalty sale
+    pub fn claim_royalties(ctx: Context<ClaimRoyalties>) -> Result<()> {
+        let royalty_escrow = &mut ctx.accounts.royalty_escrow;
+        require!(!royalty_escrow.claimed, ErrorCode::RoyaltiesAlreadyClaimed);
+        royalty_escrow.claimed = true;
+
+        let payment_mint = ctx.accounts.payment_mint.key();
+        let seeds = &[
+            b"royalty_vault".as_ref(),
+            payment_mint.as_ref(),
+            &[ctx.bumps.royalty_vault],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.royalty_vault.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.creator_payment_account.to_account_info(),
+            authority: ctx.accounts.royalty_vault.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(
+            cpi_ctx,
+            royalty_escrow.amount,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        emit!(RoyaltiesClaimed {
+            creator: royalty_escrow.creator,
+            nft_mint: royalty_escrow.nft_mint,
+            payment_mint,
+            sale_id: royalty_escrow.sale_id,
+            amount: royalty_escrow.amount,
+        });
+
+        Ok(())
+    }
+
+    // Set a collection's floor price from an off-chain feed
+    pub fn update_floor_price(ctx: Context<UpdateFloorPrice>, floor_price: u64) -> Result<()> {
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.floor_price = floor_price;
+        collection_stats.last_updated = Clock::get()?.unix_timestamp;
+
+        Ok(())
+    }
+
+    // Offer a fixed price for up to `quantity` NFTs from a verified collection
+    pub fn create_collection_offer(
+        ctx: Context<CreateCollectionOffer>,
+        price: u64,
+        quantity: u64,
+        expiry: i64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(price > 0, ErrorCode::InvalidPrice);
+        require!(quantity > 0, ErrorCode::InvalidQuantity);
+
+        let clock = Clock::get()?;
+        require!(
+            expiry == 0 || clock.unix_timestamp <= expiry,
+            ErrorCode::BidExpired
+        );
+
+        let total = price
+            .checked_mul(quantity)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let collection_offer = &mut ctx.accounts.collection_offer;
+        collection_offer.bidder = ctx.accounts.bidder.key();
+        collection_offer.collection_mint = ctx.accounts.collection_mint.key();
+        collection_offer.payment_mint = ctx.accounts.payment_mint.key();
+        collection_offer.price = price;
+        collection_offer.expiry = expiry;
+        collection_offer.quantity = quantity;
+        collection_offer.bump = ctx.bumps.collection_offer;
+
+        // Escrow the payment for every NFT the bidder wants to buy
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.bidder_payment_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: ctx.accounts.bidder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, total, ctx.accounts.payment_mint.decimals)?;
+
+        emit!(CollectionOfferCreated {
+            offer_id: collection_offer.key(),
+            bidder: collection_offer.bidder,
+            collection_mint: collection_offer.collection_mint,
+            price,
+            quantity,
+            expiry,
+        });
+
+        Ok(())
+    }
+
+    // Sell an NFT from the collection straight into an open collection offer. No
+    // listing is needed: the trade settles inline from the seller's wallet.
+    pub fn take_collection_offer(ctx: Context<TakeCollectionOffer>) -> Result<()> {
+        let collection_offer = &ctx.accounts.collection_offer;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require!(
+            collection_offer.expiry == 0 || clock.unix_timestamp <= collection_offer.expiry,
+            ErrorCode::BidExpired
+        );
+
+        // The NFT must be a verified member of the offered collection
+        let metadata = load_metadata(&ctx.accounts.metadata, &ctx.accounts.nft_mint.key())?;
+        require!(
+            in_collection(&metadata, &collection_offer.collection_mint),
+            ErrorCode::NotInCollection
+        );
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &ctx.accounts.nft_mint.key()),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // The offer was resting, so the bidder's maker fee comes out of their escrow
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Buyer))?;
+        let (sale_price, buyer_fee) = split_escrowed_price(collection_offer.price, buyer_fee_bps)?;
+        record_fee_tier_volume(
+            &ctx.accounts.fee_tier,
+            Some(collection_offer.payment_mint),
+            sale_price,
+        )?;
+
+        // Calculate royalties
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            RoyaltyEnforcementMode::Mandatory,
+            &metadata,
+            &metadata.creators,
+            sale_price,
+            ctx.remaining_accounts,
+        )?;
+
+        if config.enforce_royalties {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        // Collection offers have no second bidder, so its share of the fee is unused. The seller
+        // filled a resting offer, so they pay the taker rate
+        let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Buyer),
+            config,
+            0,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments out of the offer escrow
+        let seeds = &[
+            b"collection_offer".as_ref(),
+            collection_offer.bidder.as_ref(),
+            collection_offer.collection_mint.as_ref(),
+            &[collection_offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let source = PaymentSource {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: collection_offer.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: signer,
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            None,
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            0,
+        )?;
+
+        // Transfer NFT to bidder
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.bidder_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Record the sale in the on-chain price history and stats
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = ctx.accounts.nft_mint.key();
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: sale_price,
+            buyer: collection_offer.bidder,
+            timestamp: clock.unix_timestamp,
+        });
+
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(sale_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(ctx.accounts.seller.key(), ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(sale_price)?;
+
+        if let Some(market_stats) = ctx.accounts.market_stats.as_mut() {
+            market_stats.record_sale(sale_price, marketplace_fee)?;
+        }
+
+        let remaining_quantity = collection_offer.quantity - 1;
+
+        emit!(CollectionOfferAccepted {
+            offer_id: collection_offer.key(),
+            seller: ctx.accounts.seller.key(),
+            bidder: collection_offer.bidder,
+            nft_mint: ctx.accounts.nft_mint.key(),
+            price: collection_offer.price,
+            remaining_quantity,
+        });
+
+        // Close the escrow and the offer once it is completely filled, refunding
+        // anything left over so stray deposits can't block the close
+        if remaining_quantity == 0 {
+            ctx.accounts.escrow_payment_account.reload()?;
+            let leftover = ctx.accounts.escrow_payment_account.amount;
+            if leftover > 0 {
+                source.transfer(ctx.accounts.bidder_payment_account.to_account_info(), leftover)?;
+            }
+
+            let cpi_accounts = token_interface::CloseAccount {
+                account: ctx.accounts.escrow_payment_account.to_account_info(),
+                destination: ctx.accounts.bidder.to_account_info(),
+                authority: collection_offer.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+
+            collection_offer.close(ctx.accounts.bidder.to_account_info())?;
+        } else {
+            ctx.accounts.collection_offer.quantity = remaining_quantity;
+        }
+
+        Ok(())
+    }
+
+    // Cancel a collection offer and refund whatever is left in escrow
+    pub fn cancel_collection_offer(ctx: Context<CancelCollectionOffer>) -> Result<()> {
+        let collection_offer = &ctx.accounts.collection_offer;
+
+        let seeds = &[
+            b"collection_offer".as_ref(),
+            collection_offer.bidder.as_ref(),
+            collection_offer.collection_mint.as_ref(),
+            &[collection_offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.bidder_payment_account.to_account_info(),
+            authority: collection_offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(
+            cpi_ctx,
+            ctx.accounts.escrow_payment_account.amount,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        // Reclaim the escrow rent
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.escrow_payment_account.to_account_info(),
+            destination: ctx.accounts.bidder.to_account_info(),
+            authority: collection_offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(CollectionOfferCancelled {
+            offer_id: collection_offer.key(),
+            bidder: collection_offer.bidder,
+            collection_mint: collection_offer.collection_mint,
+        });
+
+        Ok(())
+    }
+
+    // Escrow an offer on a specific NFT. Only target_owner, the holder the buyer
+    // is addressing, can accept or counter it.
+    pub fn make_offer(
+        ctx: Context<MakeOffer>,
+        offer_price: u64,
+        expiry: i64,
+        target_owner: Pubkey,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(offer_price > 0, ErrorCode::InvalidPrice);
+
+        let offer = &mut ctx.accounts.offer;
+        let clock = Clock::get()?;
+
+        require!(
+            expiry == 0 || clock.unix_timestamp <= expiry,
+            ErrorCode::BidExpired
+        );
+
+        require_keys_neq!(target_owner, ctx.accounts.buyer.key(), ErrorCode::SelfTrade);
+
+        offer.buyer = ctx.accounts.buyer.key();
+        offer.seller = Pubkey::default();
+        offer.target_owner = target_owner;
+        offer.nft_mint = ctx.accounts.nft_mint.key();
+        offer.payment_mint = ctx.accounts.payment_mint.key();
+        offer.offer_price = offer_price;
+        offer.counter_price = 0;
+        offer.state = OfferState::Pending;
+        offer.created_at = clock.unix_timestamp;
+        offer.expiry = expiry;
+        offer.bump = ctx.bumps.offer;
+
+        // Transfer offer amount to escrow
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(
+            cpi_ctx,
+            offer_price,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        emit!(OfferMade {
+            offer_id: offer.key(),
+            buyer: offer.buyer,
+            target_owner,
+            nft_mint: offer.nft_mint,
+            payment_mint: offer.payment_mint,
+            offer_price,
+            expiry,
+        });
+
+        Ok(())
+    }
+
+    // The holder answers an offer with their own price and escrows the NFT until the buyer decides
+    pub fn counter_offer(ctx: Context<CounterOffer>, counter_price: u64) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+
+        let offer = &mut ctx.accounts.offer;
+        let clock = Clock::get()?;
+
+        require!(offer.state == OfferState::Pending, ErrorCode::InvalidOfferState);
+        require!(counter_price > offer.offer_price, ErrorCode::InvalidPrice);
+        require!(
+            offer.expiry == 0 || clock.unix_timestamp <= offer.expiry,
+            ErrorCode::BidExpired
+        );
+        require_keys_eq!(
+            ctx.accounts.seller.key(),
+            offer.target_owner,
+            ErrorCode::NotOfferTarget
+        );
+
+        offer.seller = ctx.accounts.seller.key();
+        offer.counter_price = counter_price;
+        offer.state = OfferState::Countered;
+
+        // Escrow the NFT under the offer
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.offer_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        emit!(OfferCountered {
+            offer_id: offer.key(),
+            buyer: offer.buyer,
+            seller: offer.seller,
+            nft_mint: offer.nft_mint,
+            offer_price: offer.offer_price,
+            counter_price,
+        });
+
+        Ok(())
+    }
+
+    // The targeted holder takes the offer as made, selling the NFT straight to the buyer
+    pub fn accept_offer(ctx: Context<AcceptOffer>) -> Result<()> {
+        let config = &ctx.accounts.config;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+
+        let offer = &mut ctx.accounts.offer;
+        let clock = Clock::get()?;
+
+        require!(offer.state == OfferState::Pending, ErrorCode::InvalidOfferState);
+        require!(
+            offer.expiry == 0 || clock.unix_timestamp <= offer.expiry,
+            ErrorCode::BidExpired
+        );
+        // Whoever holds the token now is not necessarily the holder the buyer made the offer to
+        require_keys_eq!(
+            ctx.accounts.seller.key(),
+            offer.target_owner,
+            ErrorCode::NotOfferTarget
+        );
+
+        offer.seller = ctx.accounts.seller.key();
+        offer.state = OfferState::Accepted;
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &offer.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &offer.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // The offer was resting, so the bidder's maker fee comes out of their escrow
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Buyer))?;
+        let (sale_price, buyer_fee) = split_escrowed_price(offer.offer_price, buyer_fee_bps)?;
+        record_fee_tier_volume(
+            &ctx.accounts.fee_tier,
+            Some(ctx.accounts.payment_mint.key()),
+            sale_price,
+        )?;
+
+        // Calculate royalties
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            RoyaltyEnforcementMode::Mandatory,
+            &metadata,
+            &metadata.creators,
+            sale_price,
+            ctx.remaining_accounts,
+        )?;
+
+        if config.enforce_royalties {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        // Offers have no second bidder, so its share of the fee is unused. The seller
+        // filled a resting offer, so they pay the taker rate
+        let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Buyer),
+            config,
+            0,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments out of the offer escrow
+        let seeds = &[
+            b"offer".as_ref(),
+            offer.buyer.as_ref(),
+            offer.nft_mint.as_ref(),
+            &[offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let source = PaymentSource {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: offer.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: signer,
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            None,
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            0,
+        )?;
+
+        // Transfer the NFT from the holder to the buyer
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.seller_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.buyer_nft_account.to_account_info(),
+            authority: ctx.accounts.seller.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Record the sale in the on-chain price history and stats
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = offer.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: sale_price,
+            buyer: offer.buyer,
+            timestamp: clock.unix_timestamp,
+        });
+
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(sale_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(ctx.accounts.seller.key(), ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(sale_price)?;
+
+        if let Some(market_stats) = ctx.accounts.market_stats.as_mut() {
+            market_stats.record_sale(sale_price, marketplace_fee)?;
+        }
+
+        // Reclaim the escrow rent
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.escrow_payment_account.to_account_info(),
+            destination: ctx.accounts.buyer.to_account_info(),
+            authority: offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(OfferClosed {
+            offer_id: offer.key(),
+            buyer: offer.buyer,
+            seller: offer.seller,
+            nft_mint: offer.nft_mint,
+            state: OfferState::Accepted,
+            price: offer.offer_price,
+        });
+
+        Ok(())
+    }
+
+    // The buyer takes the counter price, topping up the escrow before settling
+    pub fn accept_counter_offer(ctx: Context<AcceptCounterOffer>) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        let config = &ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        require!(!config.paused, ErrorCode::MarketplacePaused);
+        require!(offer.state == OfferState::Countered, ErrorCode::InvalidOfferState);
+
+        // The seller's counter was resting, so the buyer takes it at the taker rate, paid on
+        // top of the counter price
+        let buyer_fee_bps =
+            tiered_fee_bps(&ctx.accounts.fee_tier, config.buyer_fee_bps(Maker::Seller))?;
+        let buyer_fee = calculate_buyer_fee(offer.counter_price, buyer_fee_bps)?;
+        record_fee_tier_volume(
+            &ctx.accounts.fee_tier,
+            Some(ctx.accounts.payment_mint.key()),
+            offer.counter_price,
+        )?;
+
+        // Top up the escrow with the difference and the buyer's fee
+        let top_up = offer
+            .counter_price
+            .checked_sub(offer.offer_price)
+            .and_then(|difference| difference.checked_add(buyer_fee))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, top_up, ctx.accounts.payment_mint.decimals)?;
+
+        let metadata = load_metadata(&ctx.accounts.metadata, &offer.nft_mint)?;
+        require_keys_eq!(
+            ctx.accounts.collection_mint.key(),
+            stats_collection_mint(&metadata, &offer.nft_mint),
+            ErrorCode::InvalidCollectionStats
+        );
+
+        // Calculate royalties
+        let (creator_payments, remaining_payment) = sale_royalties(
+            config,
+            RoyaltyEnforcementMode::Mandatory,
+            &metadata,
+            &metadata.creators,
+            offer.counter_price,
+            ctx.remaining_accounts,
+        )?;
+
+        if config.enforce_royalties {
+            check_creator_accounts(&creator_payments, ctx.remaining_accounts)?;
+        }
+
+        // Offers have no second bidder, so its share of the fee is unused. The seller pays
+        // the maker rate
+        let (marketplace_fee, _, seller_payment) = calculate_and_distribute_fee_at(
+            remaining_payment,
+            config.seller_fee_bps(Maker::Seller),
+            config,
+            0,
+        )?;
+        let marketplace_fee = marketplace_fee
+            .checked_add(buyer_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Transfer payments out of the offer escrow
+        let seeds = &[
+            b"offer".as_ref(),
+            offer.buyer.as_ref(),
+            offer.nft_mint.as_ref(),
+            &[offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let source = PaymentSource {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            authority: offer.to_account_info(),
+            mint: &ctx.accounts.payment_mint,
+            token_program: ctx.accounts.payment_token_program.to_account_info(),
+            signer_seeds: signer,
+        };
+        transfer_payments(
+            &source,
+            ctx.accounts.seller_payment_account.to_account_info(),
+            ctx.remaining_accounts,
+            ctx.accounts.marketplace_fee_account.to_account_info(),
+            None,
+            seller_payment,
+            &creator_payments,
+            marketplace_fee,
+            0,
+        )?;
+
+        // Release the NFT to the buyer
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.offer_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.buyer_nft_account.to_account_info(),
+            authority: offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Record the sale in the on-chain price history and stats
+        let price_history = &mut ctx.accounts.price_history;
+        price_history.nft_mint = offer.nft_mint;
+        price_history.bump = ctx.bumps.price_history;
+        price_history.push(SaleRecord {
+            price: offer.counter_price,
+            buyer: offer.buyer,
+            timestamp: clock.unix_timestamp,
+        });
+
+        let collection_stats = &mut ctx.accounts.collection_stats;
+        collection_stats.collection_mint = ctx.accounts.collection_mint.key();
+        collection_stats.bump = ctx.bumps.collection_stats;
+        collection_stats.record_sale(offer.counter_price, clock.unix_timestamp)?;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        seller_stats.init_if_new(offer.seller, ctx.bumps.seller_stats)?;
+        seller_stats.record_sale(offer.counter_price)?;
+
+        if let Some(market_stats) = ctx.accounts.market_stats.as_mut() {
+            market_stats.record_sale(offer.counter_price, marketplace_fee)?;
+        }
+
+        // Reclaim the escrow rent
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.offer_nft_account.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.escrow_payment_account.to_account_info(),
+            destination: ctx.accounts.buyer.to_account_info(),
+            authority: offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(OfferClosed {
+            offer_id: offer.key(),
+            buyer: offer.buyer,
+            seller: offer.seller,
+            nft_mint: offer.nft_mint,
+            state: OfferState::Accepted,
+            price: offer.counter_price,
+        });
+
+        Ok(())
+    }
+
+    // The buyer declines the counter price: the offer is refunded and the NFT returned
+    pub fn reject_counter_offer(ctx: Context<RejectCounterOffer>) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+
+        require!(offer.state == OfferState::Countered, ErrorCode::InvalidOfferState);
+
+        let seeds = &[
+            b"offer".as_ref(),
+            offer.buyer.as_ref(),
+            offer.nft_mint.as_ref(),
+            &[offer.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Refund the offer
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.escrow_payment_account.to_account_info(),
+            mint: ctx.accounts.payment_mint.to_account_info(),
+            to: ctx.accounts.buyer_payment_account.to_account_info(),
+            authority: offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(
+            cpi_ctx,
+            offer.offer_price,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        // Return the NFT to the seller
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.offer_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.seller_nft_account.to_account_info(),
+            authority: offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, 1, ctx.accounts.nft_mint.decimals)?;
+
+        // Reclaim the escrow rent
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.offer_nft_account.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.escrow_payment_account.to_account_info(),
+            destination: ctx.accounts.buyer.to_account_info(),
+            authority: offer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.payment_token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(OfferClosed {
+            offer_id: offer.key(),
+            buyer: offer.buyer,
+            seller: offer.seller,
+            nft_mint: offer.nft_mint,
+            state: OfferState::Rejected,
+            price: offer.counter_price,
+        });
+
+        Ok(())
+    }
+
+    // Lend a listed NFT out of its vault against SOL collateral. Both the seller
+    // (as lender) and the borrower sign so the terms are agreed on-chain.
+    pub fn request_nft_loan(
+        ctx: Context<RequestNftLoan>,
+        amount: u64,
+        duration_seconds: i64,
+        interest_rate_bps: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, ErrorCode::MarketplacePaused);
+        require!(
+            amount > 0 && duration_seconds > 0 && interest_rate_bps <= FEE_DENOMINATOR,
+            ErrorCode::InvalidLoanTerms
+        );
+        require_keys_neq!(
+            ctx.accounts.borrower.key(),
+            ctx.accounts.seller.key(),
+            ErrorCode::SelfTrade
+        );
+
+        // The escrow is reused across loans on the same listing, so only an active loan blocks a new one
+        let loan_escrow = &ctx.accounts.loan_escrow;
+        require!(
+            loan_escrow.borrower == Pubkey::default() || loan_escrow.status != LoanStatus::Active,
+            ErrorCode::LoanAlreadyActive
+        );
+
+        let clock = Clock::get()?;
+        let listing = &ctx.accounts.listing;
+        let due_at = clock
+            .unix_timestamp
+            .checked_add(duration_seconds)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // Release the NFT from the vault to the borrower
+        let seeds = &[
+            b"vault".as_ref(),
+            listing.seller.as_ref(),
+            listing.nft_mint.as_ref(),
+            &[ctx.bumps.vault_nft_account],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.vault_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.borrower_nft_account.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, listing.quantity, ctx.accounts.nft_mint.decimals)?;
+
+        // Lock the collateral on top of the escrow account's rent
+        LamportSource::Wallet {
+            from: ctx.accounts.borrower.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        }
+        .transfer(ctx.accounts.loan_escrow.to_account_info(), amount)?;
+
+        let loan_escrow = &mut ctx.accounts.loan_escrow;
+        loan_escrow.borrower = ctx.accounts.borrower.key();
+        loan_escrow.lender = ctx.accounts.seller.key();
+        loan_escrow.listing = listing.key();
+        loan_escrow.nft_mint = listing.nft_mint;
+        loan_escrow.quantity = listing.quantity;
+        loan_escrow.loan_amount = amount;
+        loan_escrow.interest_rate_bps = interest_rate_bps;
+        loan_escrow.due_at = due_at;
+        loan_escrow.status = LoanStatus::Active;
+        loan_escrow.bump = ctx.bumps.loan_escrow;
+
+        emit!(NftLoanRequested {
+            loan_id: loan_escrow.key(),
+            borrower: loan_escrow.borrower,
+            lender: loan_escrow.lender,
+            nft_mint: loan_escrow.nft_mint,
+            loan_amount: amount,
+            interest_rate_bps,
+            due_at,
+        });
+
+        Ok(())
+    }
+
+    // Return a borrowed NFT to its vault, pay the lender interest and take back the collateral
+    pub fn repay_nft_loan(ctx: Context<RepayNftLoan>) -> Result<()> {
+        let loan_escrow = &ctx.accounts.loan_escrow;
+        require!(loan_escrow.status == LoanStatus::Active, ErrorCode::LoanNotActive);
+
+        let interest: u64 = (loan_escrow.loan_amount as u128)
+            .checked_mul(loan_escrow.interest_rate_bps as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(FEE_DENOMINATOR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .try_into()
+            .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: ctx.accounts.borrower_nft_account.to_account_info(),
+            mint: ctx.accounts.nft_mint.to_account_info(),
+            to: ctx.accounts.vault_nft_account.to_account_info(),
+            authority: ctx.accounts.borrower.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token_interface::transfer_checked(
+            cpi_ctx,
+            loan_escrow.quantity,
+            ctx.accounts.nft_mint.decimals,
+        )?;
+
+        LamportSource::Wallet {
+            from: ctx.accounts.borrower.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        }
+        .transfer(ctx.accounts.lender.to_account_info(), interest)?;
+        LamportSource::Escrow(ctx.accounts.loan_escrow.to_account_info())
+            .transfer(ctx.accounts.borrower.to_account_info(), loan_escrow.loan_amount)?;
+
+        let loan_escrow = &mut ctx.accounts.loan_escrow;
+        loan_escrow.status = LoanStatus::Repaid;
+
+        emit!(NftLoanRepaid {
+            loan_id: loan_escrow.key(),
+            borrower: loan_escrow.borrower,
+            lender: loan_escrow.lender,
+            nft_mint: loan_escrow.nft_mint,
+            interest,
+        });
+
+        Ok(())
+    }
+
+    // Past the due date the lender keeps the collateral in place of the NFT,
+    // and the now-empty listing and vault are closed
+    pub fn liquidate_defaulted_loan(ctx: Context<LiquidateDefaultedLoan>) -> Result<()> {
+        let loan_escrow = &ctx.accounts.loan_escrow;
+        require!(loan_escrow.status == LoanStatus::Active, ErrorCode::LoanNotActive);
+        require!(
+            Clock::get()?.unix_timestamp > loan_escrow.due_at,
+            ErrorCode::LoanNotDue
+        );
+
+        LamportSource::Escrow(ctx.accounts.loan_escrow.to_account_info())
+            .transfer(ctx.accounts.seller.to_account_info(), loan_escrow.loan_amount)?;
+
+        let seeds = &[
+            b"vault".as_ref(),
+            loan_escrow.lender.as_ref(),
+            loan_escrow.nft_mint.as_ref(),
+            &[ctx.bumps.vault_nft_account],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vault_nft_account.to_account_info(),
+            destination: ctx.accounts.seller.to_account_info(),
+            authority: ctx.accounts.vault_nft_account.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        let loan_escrow = &mut ctx.accounts.loan_escrow;
+        loan_escrow.status = LoanStatus::Liquidated;
+
+        emit!(NftLoanLiquidated {
+            loan_id: loan_escrow.key(),
+            borrower: loan_escrow.borrower,
+            lender: loan_escrow.lender,
+            nft_mint: loan_escrow.nft_mint,
+            loan_amount: loan_escrow.loan_amount,
+        });
+
+        Ok(())
+    }
+}
+
+mod helpers {
+    use super::*;
+
+    // Payment tokens leave either the buyer's account or a program-owned escrow
+    pub struct PaymentSource<'a, 'info> {
+        pub from: AccountInfo<'info>,
+        pub authority: AccountInfo<'info>,
+        pub mint: &'a InterfaceAccount<'info, Mint>,
+        pub token_program: AccountInfo<'info>,
+        pub signer_seeds: &'a [&'a [&'a [u8]]],
+    }
+
+    pub trait PaymentTransfer<'info> {
+        fn transfer(&self, to: AccountInfo<'info>, amount: u64) -> Result<()>;
+        // The only account a creator's royalty from this source may be sent to
+        fn creator_account(&self, creator: &Pubkey) -> Pubkey;
+    }
+
+    impl<'a, 'info> PaymentTransfer<'info> for PaymentSource<'a, 'info> {
+        fn transfer(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+            if amount == 0 {
+                return Ok(());
+            }
+
+            let cpi_accounts = token_interface::TransferChecked {
+                from: self.from.clone(),
+                mint: self.mint.to_account_info(),
+                to,
+                authority: self.authority.clone(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                self.token_program.clone(),
+                cpi_accounts,
+                self.signer_seeds,
+            );
+            token_interface::transfer_checked(cpi_ctx, amount, self.mint.decimals)
+        }
+
+        fn creator_account(&self, creator: &Pubkey) -> Pubkey {
+            get_associated_token_address_with_program_id(
+                creator,
+                &self.mint.key(),
+                self.token_program.key,
+            )
+        }
+    }
+
+    // Native SOL leaves either a signing wallet or a program-owned escrow account
+    pub enum LamportSource<'info> {
+        Wallet {
+            from: AccountInfo<'info>,
+            system_program: AccountInfo<'info>,
+        },
+        Escrow(AccountInfo<'info>),
+    }
+
+    impl<'info> PaymentTransfer<'info> for LamportSource<'info> {
+        fn transfer(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+            if amount == 0 {
+                return Ok(());
+            }
+
+            match self {
+                LamportSource::Wallet {
+                    from,
+                    system_program,
+                } => {
+                    let cpi_accounts = system_program::Transfer {
+                        from: from.clone(),
+                        to,
+                    };
+                    let cpi_ctx = CpiContext::new(system_program.clone(), cpi_accounts);
+                    system_program::transfer(cpi_ctx, amount)
+                }
+                LamportSource::Escrow(escrow) => {
+                    // The program owns the escrow, so lamports can be moved directly
+                    let escrow_lamports = escrow
+                        .lamports()
+                        .checked_sub(amount)
+                        .ok_or(ErrorCode::ArithmeticOverflow)?;
+                    let to_lamports = to
+                        .lamports()
+                        .checked_add(amount)
+                        .ok_or(ErrorCode::ArithmeticOverflow)?;
+                    **escrow.try_borrow_mut_lamports()? = escrow_lamports;
+                    **to.try_borrow_mut_lamports()? = to_lamports;
+                    Ok(())
+                }
+            }
+        }
+
+        fn creator_account(&self, creator: &Pubkey) -> Pubkey {
+            *creator
+        }
+    }
+
+    pub fn current_dutch_auction_price(dutch_auction: &DutchAuction, now: i64) -> Result<u64> {
+        if now >= dutch_auction.end_time {
+            return Ok(dutch_auction.end_price);
+        }
+        if now <= dutch_auction.start_time {
+            return Ok(dutch_auction.start_price);
+        }
+
+        let elapsed = (now - dutch_auction.start_time) as u128;
+        let duration = (dutch_auction.end_time - dutch_auction.start_time) as u128;
+        let decline = ((dutch_auction.start_price - dutch_auction.end_price) as u128)
+            .checked_mul(elapsed)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(duration)
+            .ok_or(ErrorCode::ArithmeticOverflow)? as u64;
+
+        Ok(dutch_auction.start_price - decline)
+    }
+
+    // Send a bid's escrowed price back to the bidder and close the escrow to them
+    pub fn refund_bid_escrow<'info>(
+        bid: &Account<'info, Bid>,
+        escrow_payment_account: AccountInfo<'info>,
+        payment_mint: AccountInfo<'info>,
+        bidder_payment_account: AccountInfo<'info>,
+        bidder: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+        decimals: u8,
+    ) -> Result<()> {
+        let nonce = bid.nonce.to_le_bytes();
+        let seeds = &[
+            b"bid".as_ref(),
+            bid.bidder.as_ref(),
+            bid.nft_mint.as_ref(),
+            nonce.as_ref(),
+            &[bid.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = token_interface::TransferChecked {
+            from: escrow_payment_account.clone(),
+            mint: payment_mint,
+            to: bidder_payment_account,
+            authority: bid.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, bid.price, decimals)?;
+
+        // Reclaim the escrow rent
+        let cpi_accounts = token_interface::CloseAccount {
+            account: escrow_payment_account,
+            destination: bidder,
+            authority: bid.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program, cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)
+    }
+
+    pub fn load_metadata(metadata_info: &AccountInfo, nft_mint: &Pubkey) -> Result<Metadata> {
+        require_keys_eq!(
+            *metadata_info.owner,
+            mpl_token_metadata::ID,
+            ErrorCode::InvalidMetadata
+        );
+
+        let metadata = Metadata::try_from(metadata_info)
+            .map_err(|_| error!(ErrorCode::InvalidMetadata))?;
+        require_keys_eq!(metadata.mint, *nft_mint, ErrorCode::InvalidMetadata);
+
+        Ok(metadata)
+    }
+
+    // Listings stay buyable through the configured grace period after their expiry
+    pub fn listing_expired(expiry: i64, config: &MarketplaceConfig, now: i64) -> bool {
+        expiry != 0 && now > expiry.saturating_add(config.expiry_grace_secs)
+    }
+
+    // Closing wipes the listing, so its final status is published in a ListingClosed event
+    pub fn close_listing<'info>(
+        listing: &mut Account<'info, Listing>,
+        status: ListingStatus,
+        destination: AccountInfo<'info>,
+    ) -> Result<()> {
+        listing.listing_status = status;
+        emit!(ListingClosed {
+            listing_id: listing.key(),
+            seller: listing.seller,
+            nft_mint: listing.nft_mint,
+            status,
+        });
+        listing.close(destination)
+    }
+
+    pub fn in_collection(metadata: &Metadata, collection_mint: &Pubkey) -> bool {
+        metadata
+            .collection
+            .as_ref()
+            .is_some_and(|collection| collection.verified && collection.key == *collection_mint)
+    }
+
+    // NFTs without a verified collection are tracked as a collection of one
+    pub fn stats_collection_mint(metadata: &Metadata, nft_mint: &Pubkey) -> Pubkey {
+        metadata
+            .collection
+            .as_ref()
+            .filter(|collection| collection.verified)
+            .map_or(*nft_mint, |collection| collection.key)
+    }
+
+    // Logs the remaining compute budget; compiled out unless compute-logging is enabled
+    pub fn log_compute_units(_label: &str) {
+        #[cfg(feature = "compute-logging")]
+        {
+            msg!(_label);
+            anchor_lang::solana_program::log::sol_log_compute_units();
+        }
+    }
+
+    // Leaves are keccak(buyer) and each level hashes the sorted pair of nodes
+    pub fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+        let computed = proof.iter().fold(leaf, |node, sibling| {
+            if node <= *sibling {
+                keccak::hashv(&[&node, sibling]).0
+            } else {
+                keccak::hashv(&[sibling, &node]).0
+            }
+        });
+        computed == root
+    }
+
+    pub fn check_whitelist(
+        whitelist: Option<&Account<Whitelist>>,
+        metadata: &Metadata,
+        buyer: &Pubkey,
+        proof: Option<&[[u8; 32]]>,
+    ) -> Result<()> {
+        let whitelist = whitelist.ok_or(ErrorCode::MissingWhitelist)?;
+        require!(
+            in_collection(metadata, &whitelist.collection_mint),
+            ErrorCode::NotInCollection
+        );
+
+        let proof = proof.ok_or(ErrorCode::NotWhitelisted)?;
+        let leaf = keccak::hashv(&[buyer.as_ref()]).0;
+        require!(
+            verify_merkle_proof(proof, whitelist.merkle_root, leaf),
+            ErrorCode::NotWhitelisted
+        );
+
+        Ok(())
+    }
+
+    pub fn calculate_creator_payments(
+        price: u64,
+        creators: &Option<Vec<Creator>>,
+    ) -> Result<(Vec<(Pubkey, u64)>, u64)> {
+        check_creator_count(creators)?;
+
+        let mut creator_payments = Vec::new();
+        let mut remaining_payment = price;
+
+        if let Some(creators) = creators {
+            let total_share: u16 = creators
+                .iter()
+                .filter(|creator| creator.verified)
+                .map(|creator| creator.share as u16)
+                .sum();
+            require!(total_share <= 100, ErrorCode::InvalidCreatorShares);
+
+            for creator in creators {
+                if creator.verified {
+                    let creator_fee: u64 = (price as u128)
+                        .checked_mul(creator.share as u128)
+                        .ok_or(ErrorCode::ArithmeticOverflow)?
+                        .checked_div(100)
+                        .ok_or(ErrorCode::ArithmeticOverflow)?
+                        .try_into()
+                        .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+                    creator_payments.push((creator.address, creator_fee));
+                    remaining_payment = remaining_payment
+                        .checked_sub(creator_fee)
+                        .ok_or(ErrorCode::ArithmeticOverflow)?;
+                }
+            }
+        }
+
+        Ok((creator_payments, remaining_payment))
+    }
+
+    // The marketplace-wide switch turns optional and disabled listings into mandatory ones
+    pub fn effective_royalty_enforcement(
+        mode: RoyaltyEnforcementMode,
+        config: &MarketplaceConfig,
+    ) -> RoyaltyEnforcementMode {
+        match mode {
+            RoyaltyEnforcementMode::Optional | RoyaltyEnforcementMode::Disabled
+                if config.enforce_royalties =>
+            {
+                RoyaltyEnforcementMode::Mandatory
+            }
+            mode => mode,
+        }
+    }
+
+    // Only a live escrowed bid on this NFT can claim the second-bidder fee
+    pub fn validate_second_bid(
+        second_bid: Option<&Account<Bid>>,
+        second_highest_bid: u64,
+        payment_mint: Option<Pubkey>,
+        second_bidder: &Pubkey,
+        now: i64,
+    ) -> Result<u64> {
+        let Some(second_bid) = second_bid else {
+            return Ok(0);
+        };
+        require!(
+            second_bid.price == second_highest_bid
+                && second_bid.payment_mint == payment_mint
+                && (second_bid.expiry == 0 || now <= second_bid.expiry),
+            ErrorCode::InvalidSecondBid
+        );
+        require_keys_eq!(*second_bidder, second_bid.bidder, ErrorCode::InvalidSecondBid);
+
+        Ok(second_highest_bid)
+    }
+
+    // Every creator owed a royalty needs one of their own token accounts, in creator order
+    pub fn check_creator_accounts(
+        creator_payments: &[(Pubkey, u64)],
+        creator_accounts: &[AccountInfo],
+    ) -> Result<()> {
+        let mut creator_accounts = creator_accounts.iter();
+        for (creator, _) in creator_payments.iter().filter(|(_, amount)| *amount > 0) {
+            let creator_account = creator_accounts
+                .next()
+                .ok_or(ErrorCode::MissingCreatorAccount)?;
+            let token_account =
+                TokenAccount::try_deserialize(&mut &creator_account.try_borrow_data()?[..])
+                    .map_err(|_| error!(ErrorCode::MissingCreatorAccount))?;
+            require_keys_eq!(token_account.owner, *creator, ErrorCode::MissingCreatorAccount);
+        }
+
+        Ok(())
+    }
+
+    // The lamport version of check_creator_accounts: each creator's own wallet, in creator order
+    pub fn check_creator_wallets(
+        creator_payments: &[(Pubkey, u64)],
+        creator_accounts: &[AccountInfo],
+    ) -> Result<()> {
+        let mut creator_accounts = creator_accounts.iter();
+        for (creator, _) in creator_payments.iter().filter(|(_, amount)| *amount > 0) {
+            let creator_account = creator_accounts
+                .next()
+                .ok_or(ErrorCode::MissingCreatorAccount)?;
+            require_keys_eq!(creator_account.key(), *creator, ErrorCode::MissingCreatorAccount);
+        }
+
+        Ok(())
+    }
+
+    // Every verified creator costs a royalty transfer, so metadata declaring more than
+    // MAX_CREATORS is rejected up front rather than failing mid-sale on compute
+    pub fn check_creator_count(creators: &Option<Vec<Creator>>) -> Result<()> {
+        let verified = creators
+            .iter()
+            .flatten()
+            .filter(|creator| creator.verified)
+            .count();
+        require!(verified <= MAX_CREATORS, ErrorCode::TooManyCreators);
+
+        Ok(())
+    }
+
+    // Only verified creators are paid royalties, so they're the only ones worth keeping
+    pub fn cache_creators(creators: &Option<Vec<Creator>>) -> Result<Vec<CachedCreator>> {
+        check_creator_count(creators)?;
+
+        Ok(creators
+            .iter()
+            .flatten()
+            .filter(|creator| creator.verified)
+            .take(MAX_CACHED_CREATORS)
+            .map(|creator| CachedCreator {
+                address: creator.address,
+                share: creator.share,
+            })
+            .collect())
+    }
+
+    // Prefers the creators cached at listing time, falling back to live metadata for old listings
+    pub fn listing_creators(
+        cache: &[CachedCreator],
+        live: &Option<Vec<Creator>>,
+    ) -> Option<Vec<Creator>> {
+        if cache.is_empty() {
+            return live.clone();
+        }
+
+        Some(
+            cache
+                .iter()
+                .map(|creator| Creator {
+                    address: creator.address,
+                    verified: true,
+                    share: creator.share,
+                })
+                .collect(),
+        )
+    }
+
+    // The configured collection pays override_bps of the price in royalties instead of
+    // the metadata rate, split between creators in the same proportions. Dust goes to the seller.
+    pub fn apply_royalty_override(
+        config: &MarketplaceConfig,
+        metadata: &Metadata,
+        price: u64,
+        creator_payments: Vec<(Pubkey, u64)>,
+        remaining_payment: u64,
+    ) -> Result<(Vec<(Pubkey, u64)>, u64)> {
+        let (Some(collection), Some(override_bps)) =
+            (config.royalty_override_collection, config.royalty_override_bps)
+        else {
+            return Ok((creator_payments, remaining_payment));
+        };
+        let original_total: u64 = creator_payments.iter().map(|(_, amount)| amount).sum();
+        if !in_collection(metadata, &collection) || original_total == 0 {
+            return Ok((creator_payments, remaining_payment));
+        }
+
+        let override_total = (price as u128)
+            .checked_mul(override_bps as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(FEE_DENOMINATOR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let mut remaining_payment = price;
+        let mut overridden = Vec::with_capacity(creator_payments.len());
+        for (creator, amount) in creator_payments {
+            let creator_fee: u64 = override_total
+                .checked_mul(amount as u128)
+                .ok_or(ErrorCode::ArithmeticOverflow)?
+                .checked_div(original_total as u128)
+                .ok_or(ErrorCode::ArithmeticOverflow)?
+                .try_into()
+                .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+            remaining_payment = remaining_payment
+                .checked_sub(creator_fee)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            overridden.push((creator, creator_fee));
+        }
+
+        Ok((overridden, remaining_payment))
+    }
+
+    // Mandatory-royalty sales whose metadata pays nothing, e.g. no verified creators, fall back
+    // to default_royalty_bps of the price, paid to the default royalty treasury
+    pub fn apply_default_royalty(
+        config: &MarketplaceConfig,
+        royalty_enforcement: RoyaltyEnforcementMode,
+        price: u64,
+        creator_payments: Vec<(Pubkey, u64)>,
+        remaining_payment: u64,
+    ) -> Result<(Vec<(Pubkey, u64)>, u64)> {
+        let metadata_total: u64 = creator_payments.iter().map(|(_, amount)| amount).sum();
+        if royalty_enforcement != RoyaltyEnforcementMode::Mandatory
+            || config.default_royalty_bps == 0
+            || metadata_total > 0
+        {
+            return Ok((creator_payments, remaining_payment));
+        }
+
+        let royalty: u64 = (price as u128)
+            .checked_mul(config.default_royalty_bps as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(FEE_DENOMINATOR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .try_into()
+            .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+        let remaining_payment = remaining_payment
+            .checked_sub(royalty)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok((vec![(config.default_royalty_treasury, royalty)], remaining_payment))
+    }
+
+    // Royalties for a sale under the listing's enforcement mode, with the configured collection
+    // override and the default royalty applied on top. Every fill prices royalties through here.
+    pub fn sale_royalties(
+        config: &MarketplaceConfig,
+        mode: RoyaltyEnforcementMode,
+        metadata: &Metadata,
+        creators: &Option<Vec<Creator>>,
+        price: u64,
+        creator_accounts: &[AccountInfo],
+    ) -> Result<(Vec<(Pubkey, u64)>, u64)> {
+        let (creator_payments, remaining_payment) =
+            calculate_listing_royalties(mode, price, creators, creator_accounts)?;
+        let (creator_payments, remaining_payment) =
+            apply_royalty_override(config, metadata, price, creator_payments, remaining_payment)?;
+        apply_default_royalty(config, mode, price, creator_payments, remaining_payment)
+    }
+
+    // Disabled skips royalties entirely; Optional skips them when creator accounts are missing
+    pub fn calculate_listing_royalties(
+        mode: RoyaltyEnforcementMode,
+        price: u64,
+        creators: &Option<Vec<Creator>>,
+        creator_accounts: &[AccountInfo],
+    ) -> Result<(Vec<(Pubkey, u64)>, u64)> {
+        match mode {
+            RoyaltyEnforcementMode::Mandatory | RoyaltyEnforcementMode::Lazy => {
+                calculate_creator_payments(price, creators)
+            }
+            RoyaltyEnforcementMode::Optional => {
+                let (creator_payments, remaining_payment) =
+                    calculate_creator_payments(price, creators)?;
+                let required = creator_payments
+                    .iter()
+                    .filter(|(_, amount)| *amount > 0)
+                    .count();
+                if creator_accounts.len() < required {
+                    Ok((Vec::new(), price))
+                } else {
+                    Ok((creator_payments, remaining_payment))
+                }
+            }
+            RoyaltyEnforcementMode::Disabled => Ok((Vec::new(), price)),
+        }
+    }
+
+    // Splits the seller's proceeds by co-owner share; rounding dust goes to the last co-owner
+    pub fn split_by_shares(amount: u64, co_owners: &[CoOwner]) -> Result<Vec<u64>> {
+        let mut payments = Vec::with_capacity(co_owners.len());
+        let mut distributed: u64 = 0;
+        for (i, co_owner) in co_owners.iter().enumerate() {
+            let payment = if i == co_owners.len() - 1 {
+                amount
+                    .checked_sub(distributed)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?
+            } else {
+                (amount as u128)
+                    .checked_mul(co_owner.share_bps as u128)
+                    .ok_or(ErrorCode::ArithmeticOverflow)?
+                    .checked_div(FEE_DENOMINATOR as u128)
+                    .ok_or(ErrorCode::ArithmeticOverflow)? as u64
+            };
+            distributed = distributed
+                .checked_add(payment)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            payments.push(payment);
+        }
+
+        Ok(payments)
+    }
+
+    // Splits the seller's leg of the fee at their maker or taker rate
+    pub fn calculate_and_distribute_fee_at(
+        amount: u64,
+        fee_bps: u64,
+        config: &MarketplaceConfig,
+        second_highest_bid: u64,
+    ) -> Result<(u64, u64, u64)> {
+        let total_fee: u64 = (amount as u128)
+            .checked_mul(fee_bps as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(FEE_DENOMINATOR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .try_into()
+            .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+
+        let marketplace_fee: u64 = (total_fee as u128)
+            .checked_mul(MARKETPLACE_FEE_SHARE as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(FEE_DENOMINATOR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .try_into()
+            .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+
+        let second_bidder_fee: u64 = (total_fee as u128)
+            .checked_mul(SECOND_BIDDER_FEE_SHARE as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(FEE_DENOMINATOR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .try_into()
+            .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+
+        let adjusted_second_bidder_fee = std::cmp::min(second_bidder_fee, second_highest_bid);
+        let unused_second_bidder_fee = second_bidder_fee
+            .checked_sub(adjusted_second_bidder_fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let seller_payment = amount.checked_sub(total_fee).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        // The unused part of the second bidder's share goes to the seller or the marketplace
+        if config.rebate_unused_second_bidder_fee {
+            let seller_payment = seller_payment
+                .checked_add(unused_second_bidder_fee)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            Ok((marketplace_fee, adjusted_second_bidder_fee, seller_payment))
+        } else {
+            let adjusted_marketplace_fee = marketplace_fee
+                .checked_add(unused_second_bidder_fee)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            Ok((adjusted_marketplace_fee, adjusted_second_bidder_fee, seller_payment))
+        }
+    }
+
+    // The buyer's own leg of the fee, paid on top of the sale price
+    pub fn calculate_buyer_fee(price: u64, fee_bps: u64) -> Result<u64> {
+        let fee: u64 = (price as u128)
+            .checked_mul(fee_bps as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div(FEE_DENOMINATOR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .try_into()
+            .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+        Ok(fee)
+    }
+
+    // An escrowed bid is everything the bidder pays, so split it into the sale price and the
+    // bidder's fee at their rate (price + fee == escrowed)
+    pub fn split_escrowed_price(escrowed: u64, fee_bps: u64) -> Result<(u64, u64)> {
+        let price: u64 = (escrowed as u128)
+            .checked_mul(FEE_DENOMINATOR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .checked_div((FEE_DENOMINATOR + fee_bps) as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .try_into()
+            .map_err(|_| ErrorCode::ArithmeticOverflow)?;
+        let fee = escrowed.checked_sub(price).ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok((price, fee))
+    }
+
+    // Platform fee in basis points for a trader's fee tier
+    pub fn calculate_fee_bps(tier: u8) -> u64 {
+        match tier {
+            0 => 250,
+            1 => 200,
+            2 => 150,
+            _ => 100,
+        }
+    }
+
+    // The buyer's rate for their own leg of the fee, lowered by their fee tier once they've
+    // opened one
+    pub fn tiered_fee_bps(fee_tier: &AccountInfo, fee_bps: u64) -> Result<u64> {
+        if fee_tier.data_is_empty() {
+            return Ok(fee_bps);
+        }
+        let fee_tier = FeeTier::try_deserialize(&mut &fee_tier.try_borrow_data()?[..])?;
+        Ok(std::cmp::min(fee_bps, calculate_fee_bps(fee_tier.tier)))
+    }
+
+    // Adds a purchase to the buyer's volume once they've opened a fee tier. The tier thresholds
+    // are in lamports, so only purchases paid in SOL or wrapped SOL count
+    pub fn record_fee_tier_volume(
+        fee_tier: &AccountInfo,
+        payment_mint: Option<Pubkey>,
+        price: u64,
+    ) -> Result<()> {
+        if fee_tier.data_is_empty() || payment_mint.map_or(false, |mint| mint != native_mint::ID) {
+            return Ok(());
+        }
+        let mut tier = FeeTier::try_deserialize(&mut &fee_tier.try_borrow_data()?[..])?;
+        tier.record_volume(price)?;
+        tier.try_serialize(&mut &mut fee_tier.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+
+    // Creates a program-owned PDA from remaining_accounts, which Anchor can't init for us
+    pub fn create_program_account<'info>(
+        account: &AccountInfo<'info>,
+        space: usize,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let cpi_accounts = system_program::CreateAccount {
+            from: payer.clone(),
+            to: account.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(system_program.clone(), cpi_accounts, signer_seeds);
+        system_program::create_account(
+            cpi_ctx,
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            &crate::ID,
+        )
+    }
+
+    // Loads a PDA from remaining_accounts, creating it zeroed behind its discriminator first
+    // the way init_if_needed would. Returns the account and its bump
+    pub fn load_or_init_pda<'info, T>(
+        account: &AccountInfo<'info>,
+        seeds: &[&[u8]],
+        space: usize,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+    ) -> Result<(Account<'info, T>, u8)>
+    where
+        T: AccountSerialize + AccountDeserialize + Owner + Clone + Discriminator,
+    {
+        let (expected, bump) = Pubkey::find_program_address(seeds, &crate::ID);
+        require_keys_eq!(account.key(), expected, ErrorCode::InvalidAutoAcceptAccounts);
+
+        if account.data_is_empty() {
+            let bump_seed = [bump];
+            let mut signer_seeds = seeds.to_vec();
+            signer_seeds.push(&bump_seed);
+            create_program_account(account, space, payer, system_program, &[&signer_seeds[..]])?;
+            account.try_borrow_mut_data()?[..8].copy_from_slice(T::DISCRIMINATOR.as_ref());
+        }
+
+        Ok((Account::try_from(account)?, bump))
+    }
+
+    // Writes a RoyaltyEscrow for every creator owed a royalty. Expects the escrow
+    // PDAs in creator order, skipping creators whose royalty rounds to zero.
+    pub fn escrow_royalties<'info>(
+        escrow_accounts: &[AccountInfo<'info>],
+        creator_payments: &[(Pubkey, u64)],
+        nft_mint: &Pubkey,
+        payment_mint: &Pubkey,
+        sale_id: u64,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+    ) -> Result<()> {
+        let sale_id_bytes = sale_id.to_le_bytes();
+        let mut escrow_accounts = escrow_accounts.iter();
+        for (creator, amount) in creator_payments {
+            if *amount == 0 {
+                continue;
+            }
+
+            let royalty_escrow = next_account_info(&mut escrow_accounts)?;
+            let (expected, bump) = Pubkey::find_program_address(
+                &[
+                    b"royalty_escrow",
+                    creator.as_ref(),
+                    nft_mint.as_ref(),
+                    sale_id_bytes.as_ref(),
+                ],
+                &crate::ID,
+            );
+            require_keys_eq!(royalty_escrow.key(), expected, ErrorCode::InvalidRoyaltyEscrow);
+
+            let seeds = &[
+                b"royalty_escrow".as_ref(),
+                creator.as_ref(),
+                nft_mint.as_ref(),
+                sale_id_bytes.as_ref(),
+                &[bump],
+            ];
+            let signer = &[&seeds[..]];
+            let space = 8 + RoyaltyEscrow::INIT_SPACE;
+            let cpi_accounts = system_program::CreateAccount {
+                from: payer.clone(),
+                to: royalty_escrow.clone(),
+            };
+            let cpi_ctx =
+                CpiContext::new_with_signer(system_program.clone(), cpi_accounts, signer);
+            system_program::create_account(
+                cpi_ctx,
+                Rent::get()?.minimum_balance(space),
+                space as u64,
+                &crate::ID,
+            )?;
+
+            let escrow = RoyaltyEscrow {
+                creator: *creator,
+                nft_mint: *nft_mint,
+                payment_mint: *payment_mint,
+                sale_id,
+                amount: *amount,
+                claimed: false,
+                bump,
+            };
+            escrow.try_serialize(&mut &mut royalty_escrow.try_borrow_mut_data()?[..])?;
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn transfer_payments<'info>(
+        source: &impl PaymentTransfer<'info>,
+        seller_payment_account: AccountInfo<'info>,
+        creator_accounts: &[AccountInfo<'info>],
+        marketplace_fee_account: AccountInfo<'info>,
+        second_bidder_account: Option<AccountInfo<'info>>,
+        seller_payment: u64,
+        creator_payments: &[(Pubkey, u64)],
+        marketplace_fee: u64,
+        second_bidder_fee: u64,
+    ) -> Result<()> {
+        // Transfer to seller
+        source.transfer(seller_payment_account, seller_payment)?;
+
+        // Transfer to creators
+        let mut creator_accounts = creator_accounts.iter();
+        for (creator, amount) in creator_payments {
+            if *amount > 0 {
+                let creator_account = next_account_info(&mut creator_accounts)?;
+                require_keys_eq!(
+                    creator_account.key(),
+                    source.creator_account(creator),
+                    ErrorCode::CreatorAccountMismatch
+                );
+                source.transfer(creator_account.clone(), *amount)?;
+            }
+        }
+
+        // Transfer marketplace fee
+        source.transfer(marketplace_fee_account, marketplace_fee)?;
+
+        // Transfer fee to second highest bidder
+        if let Some(second_bidder_account) = second_bidder_account {
+            source.transfer(second_bidder_account, second_bidder_fee)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Account structures
+
+#[derive(Accounts)]
+pub struct InitializeMarketplace<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MarketplaceConfig::INIT_SPACE,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    /// CHECK: Only the address is stored, as the wallet fee withdrawals are paid to
+    pub fee_recipient: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MarketplaceFeeVault::INIT_SPACE,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarketplaceFee<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthorityTransfer<'info> {
+    pub new_authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeMarketStats<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MarketStats::INIT_SPACE,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub market_stats: Account<'info, MarketStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeFeeTier<'info> {
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = trader,
+        space = 8 + FeeTier::INIT_SPACE,
+        seeds = [b"fee_tier", trader.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: Account<'info, FeeTier>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = fee_vault,
+        associated_token::token_program = token_program
+    )]
+    pub fee_vault_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        constraint = destination.owner == config.fee_recipient @ ErrorCode::InvalidFeeRecipient
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSolFees<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the configured fee recipient
+    #[account(mut, address = config.fee_recipient @ ErrorCode::InvalidFeeRecipient)]
+    pub destination: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PromoteFeeTier<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        mut,
+        seeds = [b"fee_tier", fee_tier.trader.as_ref()],
+        bump = fee_tier.bump
+    )]
+    pub fee_tier: Account<'info, FeeTier>,
+}
+
+#[derive(Accounts)]
+pub struct AdminRecover<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub stuck_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: The Listing or Bid that owned stuck_account, verified closed in the handler
+    pub record: UncheckedAccount<'info>,
+    #[account(mut, token::mint = mint, token::token_program = token_program)]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+}
+
+#[derive(Accounts)]
 pub struct CreateListing<'info> {
     #[account(mut)]
     pub seller: Signer<'info>,
-    pub nft_mint: Account<'info, Mint>,
     #[account(
-        init,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + Listing::INIT_SPACE,
+        seeds = [b"listing", seller.key().as_ref(), nft_mint.key().as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = seller,
+        seeds = [b"vault", seller.key().as_ref(), nft_mint.key().as_ref()],
+        bump,
+        token::mint = nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: Loaded and checked against the mint in the handler
+    pub metadata: UncheckedAccount<'info>,
+    /// CHECK: The policy PDA of the NFT's verified collection, which need not be initialized.
+    /// Derived from the metadata and checked in the handler
+    pub collection_policy: Option<UncheckedAccount<'info>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelListing<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = listing.nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseEmptyVault<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    /// CHECK: The seller's listing for this mint, which must already be closed
+    #[account(
+        seeds = [b"listing", seller.key().as_ref(), nft_mint.key().as_ref()],
+        bump
+    )]
+    pub listing: UncheckedAccount<'info>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"vault", seller.key().as_ref(), nft_mint.key().as_ref()],
+        bump,
+        token::mint = nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program,
+        constraint = vault_nft_account.amount == 0 @ ErrorCode::VaultNotEmpty
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CloseExpiredListing<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+    /// CHECK: Receives the NFT and the remaining listing rent
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = listing.nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct BatchCancelExpiredListings<'info> {
+    #[account(mut)]
+    pub crank_operator: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SweepExpiredListings<'info> {
+    pub crank_operator: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+}
+
+#[derive(Accounts)]
+pub struct BatchCancelListings<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateListingPrice<'info> {
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller
+    )]
+    pub listing: Account<'info, Listing>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateListingQuantity<'info> {
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = listing.nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteSale<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: We're reading data from this account
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + BuyerPurchaseRecord::INIT_SPACE,
+        seeds = [b"buyer_record", listing.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_record: Account<'info, BuyerPurchaseRecord>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = listing.nft_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = seller,
+        token::token_program = payment_token_program
+    )]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        seeds = [b"royalty_vault", payment_mint.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = royalty_vault,
+        token::token_program = payment_token_program
+    )]
+    pub royalty_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    #[account(mut)]
+    pub second_bidder_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [
+            b"bid",
+            second_bid.bidder.as_ref(),
+            second_bid.nft_mint.as_ref(),
+            second_bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = second_bid.bump,
+        constraint = second_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = second_bid.nft_mint == listing.nft_mint @ ErrorCode::BidListingMismatch
+    )]
+    pub second_bid: Option<Account<'info, Bid>>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        seeds = [b"whitelist", whitelist.collection_mint.as_ref()],
+        bump = whitelist.bump
+    )]
+    pub whitelist: Option<Account<'info, Whitelist>>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = market_stats.bump
+    )]
+    pub market_stats: Option<Account<'info, MarketStats>>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteSaleSol<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: Receives the sale proceeds in lamports
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller,
+        constraint = listing.payment_mint.is_none() @ ErrorCode::PaymentMintMismatch
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + BuyerPurchaseRecord::INIT_SPACE,
+        seeds = [b"buyer_record", listing.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_record: Account<'info, BuyerPurchaseRecord>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = listing.nft_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the second bid's bidder, checked in the handler
+    #[account(mut)]
+    pub second_bidder_account: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            b"bid",
+            second_bid.bidder.as_ref(),
+            second_bid.nft_mint.as_ref(),
+            second_bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = second_bid.bump,
+        constraint = second_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = second_bid.nft_mint == listing.nft_mint @ ErrorCode::BidListingMismatch
+    )]
+    pub second_bid: Option<Account<'info, Bid>>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        seeds = [b"whitelist", whitelist.collection_mint.as_ref()],
+        bump = whitelist.bump
+    )]
+    pub whitelist: Option<Account<'info, Whitelist>>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = market_stats.bump
+    )]
+    pub market_stats: Option<Account<'info, MarketStats>>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteSalePnft<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: We're reading data from this account
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: The buyer's ATA, created by the token metadata program if it does not exist
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &buyer.key(),
+            &listing.nft_mint,
+            &token_program.key()
+        )
+    )]
+    pub buyer_nft_account: AccountInfo<'info>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = seller,
+        token::token_program = payment_token_program
+    )]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        seeds = [b"royalty_vault", payment_mint.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = royalty_vault,
+        token::token_program = payment_token_program
+    )]
+    pub royalty_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    #[account(mut)]
+    pub second_bidder_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [
+            b"bid",
+            second_bid.bidder.as_ref(),
+            second_bid.nft_mint.as_ref(),
+            second_bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = second_bid.bump,
+        constraint = second_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = second_bid.nft_mint == listing.nft_mint @ ErrorCode::BidListingMismatch
+    )]
+    pub second_bid: Option<Account<'info, Bid>>,
+    /// CHECK: Loaded and checked against the mint in the handler, then written by the token metadata program
+    #[account(mut)]
+    pub metadata: AccountInfo<'info>,
+    /// CHECK: The NFT's master edition, validated by the token metadata program
+    pub edition: AccountInfo<'info>,
+    /// CHECK: The vault's token record, validated by the token metadata program
+    #[account(mut)]
+    pub token_record: AccountInfo<'info>,
+    /// CHECK: The buyer's token record, created by the token metadata program
+    #[account(mut)]
+    pub destination_token_record: AccountInfo<'info>,
+    /// CHECK: The collection's rule set, validated by the token metadata program
+    pub authorization_rules: AccountInfo<'info>,
+    /// CHECK: Must be the Metaplex token authorization rules program
+    #[account(address = MPL_TOKEN_AUTH_RULES_ID)]
+    pub authorization_rules_program: AccountInfo<'info>,
+    /// CHECK: Must be the Metaplex token metadata program
+    #[account(address = mpl_token_metadata::ID)]
+    pub mpl_token_metadata_program: AccountInfo<'info>,
+    /// CHECK: Must be the instructions sysvar
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub sysvar_instructions: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = market_stats.bump
+    )]
+    pub market_stats: Option<Account<'info, MarketStats>>,
+    #[account(
+        seeds = [b"whitelist", whitelist.collection_mint.as_ref()],
+        bump = whitelist.bump
+    )]
+    pub whitelist: Option<Account<'info, Whitelist>>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FloorSweep<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    /// CHECK: Every swept NFT must be a verified member of this collection
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: u64)]
+pub struct CreateBundleListing<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + BundleListing::INIT_SPACE,
+        seeds = [b"bundle_listing", seller.key().as_ref(), bundle_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub bundle_listing: Account<'info, BundleListing>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteBundleSale<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: Receives the rent of the closed bundle listing and vaults
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [
+            b"bundle_listing",
+            seller.key().as_ref(),
+            bundle_listing.bundle_id.to_le_bytes().as_ref()
+        ],
+        bump = bundle_listing.bump,
+        has_one = seller,
+        has_one = payment_mint
+    )]
+    pub bundle_listing: Account<'info, BundleListing>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = buyer,
+        token::token_program = payment_token_program
+    )]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = seller,
+        token::token_program = payment_token_program
+    )]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBundleListing<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [
+            b"bundle_listing",
+            seller.key().as_ref(),
+            bundle_listing.bundle_id.to_le_bytes().as_ref()
+        ],
+        bump = bundle_listing.bump,
+        has_one = seller
+    )]
+    pub bundle_listing: Account<'info, BundleListing>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSplitListing<'info> {
+    #[account(mut)]
+    pub primary_seller: Signer<'info>,
+    #[account(
+        init,
+        payer = primary_seller,
+        space = 8 + SplitListing::INIT_SPACE,
+        seeds = [b"split_listing", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub split_listing: Account<'info, SplitListing>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = primary_seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = primary_seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = split_listing,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteSplitListingSale<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: Receives the rent of the closed split listing and vault
+    #[account(mut)]
+    pub primary_seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = primary_seller,
+        seeds = [b"split_listing", split_listing.nft_mint.as_ref()],
+        bump = split_listing.bump,
+        has_one = primary_seller,
+        has_one = nft_mint,
+        has_one = payment_mint @ ErrorCode::PaymentMintMismatch
+    )]
+    pub split_listing: Account<'info, SplitListing>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = split_listing,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = nft_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSplitListing<'info> {
+    #[account(mut)]
+    pub primary_seller: Signer<'info>,
+    #[account(
+        mut,
+        close = primary_seller,
+        seeds = [b"split_listing", split_listing.nft_mint.as_ref()],
+        bump = split_listing.bump,
+        has_one = primary_seller,
+        has_one = nft_mint
+    )]
+    pub split_listing: Account<'info, SplitListing>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = primary_seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = split_listing,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreateDutchAuction<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + DutchAuction::INIT_SPACE,
+        seeds = [b"dutch_auction", seller.key().as_ref(), nft_mint.key().as_ref()],
+        bump
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = dutch_auction,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteDutchAuctionSale<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: Receives the rent of the auction once it sells out
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"dutch_auction", seller.key().as_ref(), dutch_auction.nft_mint.as_ref()],
+        bump = dutch_auction.bump,
+        has_one = seller,
+        has_one = nft_mint,
+        has_one = payment_mint
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = dutch_auction,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = nft_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = seller)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelDutchAuction<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"dutch_auction", seller.key().as_ref(), dutch_auction.nft_mint.as_ref()],
+        bump = dutch_auction.bump,
+        has_one = seller,
+        has_one = nft_mint
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = dutch_auction,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreatePriceCurveListing<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + PriceCurveListing::INIT_SPACE,
+        seeds = [b"price_curve", seller.key().as_ref(), nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_curve_listing: Account<'info, PriceCurveListing>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = price_curve_listing,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecutePriceCurveSale<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: Receives the rent of the listing once it sells out
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"price_curve", seller.key().as_ref(), price_curve_listing.nft_mint.as_ref()],
+        bump = price_curve_listing.bump,
+        has_one = seller,
+        has_one = nft_mint,
+        has_one = payment_mint
+    )]
+    pub price_curve_listing: Account<'info, PriceCurveListing>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = price_curve_listing,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = nft_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint, token::authority = buyer)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = seller)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPriceCurveListing<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"price_curve", seller.key().as_ref(), price_curve_listing.nft_mint.as_ref()],
+        bump = price_curve_listing.bump,
+        has_one = seller,
+        has_one = nft_mint
+    )]
+    pub price_curve_listing: Account<'info, PriceCurveListing>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = price_curve_listing,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetCollectionPolicy<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: Loaded and checked against the collection mint in the handler
+    pub collection_metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + CollectionPolicy::INIT_SPACE,
+        seeds = [b"policy", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_policy: Account<'info, CollectionPolicy>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAuctionConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: Loaded and checked against the collection mint in the handler
+    pub collection_metadata: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + AuctionConfig::INIT_SPACE,
+        seeds = [b"auction_config", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub auction_config: Account<'info, AuctionConfig>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateEnglishAuction<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + EnglishAuction::INIT_SPACE,
+        seeds = [b"english_auction", seller.key().as_ref(), nft_mint.key().as_ref()],
+        bump
+    )]
+    pub english_auction: Account<'info, EnglishAuction>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = english_auction,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    /// CHECK: Checked against the collection's auction config PDA in the handler; may be uninitialized
+    pub auction_config: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateEnglishAuctionBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        mut,
+        seeds = [
+            b"english_auction",
+            english_auction.seller.as_ref(),
+            english_auction.nft_mint.as_ref()
+        ],
+        bump = english_auction.bump,
+        has_one = payment_mint
+    )]
+    pub english_auction: Account<'info, EnglishAuction>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        associated_token::mint = payment_mint,
+        associated_token::authority = english_auction,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub previous_bidder_payment_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleEnglishAuction<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Receives the auction proceeds and rent
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"english_auction", seller.key().as_ref(), english_auction.nft_mint.as_ref()],
+        bump = english_auction.bump,
+        has_one = seller,
+        has_one = nft_mint,
+        has_one = payment_mint
+    )]
+    pub english_auction: Account<'info, EnglishAuction>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: Must be the highest bidder, or the seller when the auction had no bids
+    #[account(
+        constraint = recipient.key() == english_auction.winner()
+            @ ErrorCode::InvalidAuctionRecipient
+    )]
+    pub recipient: AccountInfo<'info>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = english_auction,
+        associated_token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = nft_mint,
+        associated_token::authority = recipient,
+        associated_token::token_program = token_program
+    )]
+    pub recipient_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub escrow_payment_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub seller_payment_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    /// CHECK: The winning bidder's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", english_auction.highest_bidder.as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + BidCounter::INIT_SPACE,
+        seeds = [b"bid_counter", bidder.key().as_ref()],
+        bump
+    )]
+    pub bid_counter: Account<'info, BidCounter>,
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + Bid::INIT_SPACE,
+        seeds = [
+            b"bid",
+            bidder.key().as_ref(),
+            nft_mint.key().as_ref(),
+            bid_counter.next_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + BidBook::INIT_SPACE,
+        seeds = [b"bid_book", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub bid_book: Account<'info, BidBook>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        associated_token::mint = payment_mint,
+        associated_token::authority = bid,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: The bidder's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", bidder.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct BulkPlaceBids<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + BidCounter::INIT_SPACE,
+        seeds = [b"bid_counter", bidder.key().as_ref()],
+        bump
+    )]
+    pub bid_counter: Account<'info, BidCounter>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = bidder,
+        token::token_program = token_program
+    )]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefundExpiredBid<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+    /// CHECK: Receives the refund and the remaining bid and escrow rent
+    #[account(mut)]
+    pub bidder: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"bid",
+            bidder.key().as_ref(),
+            bid.nft_mint.as_ref(),
+            bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = bid.bump,
+        constraint = bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = bidder
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        mut,
+        seeds = [b"bid_book", bid.nft_mint.as_ref()],
+        bump = bid_book.bump
+    )]
+    pub bid_book: Account<'info, BidBook>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    // The token accounts are only passed for SPL bids; SOL bids escrow in the bid account
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = bidder,
+        token::token_program = token_program
+    )]
+    pub bidder_payment_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = bid,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct BatchRefundExpiredBids<'info> {
+    #[account(mut)]
+    pub crank_operator: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        close = bidder,
+        seeds = [
+            b"bid",
+            bidder.key().as_ref(),
+            bid.nft_mint.as_ref(),
+            bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = bid.bump,
+        constraint = bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = bidder,
+        constraint = bid.payment_mint == Some(payment_mint.key()) @ ErrorCode::PaymentMintMismatch
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        mut,
+        seeds = [b"bid_book", bid.nft_mint.as_ref()],
+        bump = bid_book.bump
+    )]
+    pub bid_book: Account<'info, BidBook>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = bid,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateBid<'info> {
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"bid",
+            bidder.key().as_ref(),
+            bid.nft_mint.as_ref(),
+            bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = bid.bump,
+        constraint = bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = bidder,
+        constraint = bid.payment_mint == Some(payment_mint.key()) @ ErrorCode::PaymentMintMismatch
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = bid,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptBid<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [
+            b"bid",
+            bid.bidder.as_ref(),
+            bid.nft_mint.as_ref(),
+            bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = bid.bump,
+        constraint = bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = bid.payment_mint == Some(payment_mint.key()) @ ErrorCode::PaymentMintMismatch
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        mut,
+        seeds = [b"bid_book", bid.nft_mint.as_ref()],
+        bump = bid_book.bump
+    )]
+    pub bid_book: Account<'info, BidBook>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(constraint = bid.nft_mint == nft_mint.key() @ ErrorCode::MintMismatch)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = bid.bidder,
+        associated_token::token_program = token_program
+    )]
+    pub bidder_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = bid,
+        associated_token::token_program = payment_token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    #[account(mut)]
+    pub second_bidder_account: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = market_stats.bump
+    )]
+    pub market_stats: Option<Account<'info, MarketStats>>,
+    /// CHECK: The bidder's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", bid.bidder.as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptHighestBid<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller,
+        constraint = listing.payment_mint == Some(payment_mint.key())
+            @ ErrorCode::PaymentMintMismatch
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [
+            b"bid",
+            bid.bidder.as_ref(),
+            bid.nft_mint.as_ref(),
+            bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = bid.bump,
+        constraint = bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = bid.nft_mint == listing.nft_mint @ ErrorCode::BidListingMismatch,
+        constraint = bid.payment_mint == Some(payment_mint.key()) @ ErrorCode::PaymentMintMismatch
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        mut,
+        seeds = [b"bid_book", bid.nft_mint.as_ref()],
+        bump = bid_book.bump
+    )]
+    pub bid_book: Account<'info, BidBook>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = bid.bidder,
+        associated_token::token_program = token_program
+    )]
+    pub bidder_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = bid,
+        associated_token::token_program = payment_token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub second_bidder_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [
+            b"bid",
+            second_bid.bidder.as_ref(),
+            second_bid.nft_mint.as_ref(),
+            second_bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = second_bid.bump,
+        constraint = second_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = second_bid.nft_mint == listing.nft_mint @ ErrorCode::BidListingMismatch
+    )]
+    pub second_bid: Option<Account<'info, Bid>>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = market_stats.bump
+    )]
+    pub market_stats: Option<Account<'info, MarketStats>>,
+    /// CHECK: The bidder's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", bid.bidder.as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptBidForListing<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller,
+        constraint = listing.payment_mint == Some(payment_mint.key())
+            @ ErrorCode::PaymentMintMismatch
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [
+            b"bid",
+            bid.bidder.as_ref(),
+            bid.nft_mint.as_ref(),
+            bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = bid.bump,
+        constraint = bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = bid.nft_mint == listing.nft_mint @ ErrorCode::BidListingMismatch,
+        constraint = bid.payment_mint == Some(payment_mint.key()) @ ErrorCode::PaymentMintMismatch
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        mut,
+        seeds = [b"bid_book", bid.nft_mint.as_ref()],
+        bump = bid_book.bump
+    )]
+    pub bid_book: Account<'info, BidBook>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = bid.bidder,
+        associated_token::token_program = token_program
+    )]
+    pub bidder_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = bid,
+        associated_token::token_program = payment_token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub second_bidder_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [
+            b"bid",
+            second_bid.bidder.as_ref(),
+            second_bid.nft_mint.as_ref(),
+            second_bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = second_bid.bump,
+        constraint = second_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = second_bid.nft_mint == listing.nft_mint @ ErrorCode::BidListingMismatch
+    )]
+    pub second_bid: Option<Account<'info, Bid>>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    /// CHECK: The bidder's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", bid.bidder.as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BidOnListing<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        seeds = [b"listing", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = listing.payment_mint == Some(payment_mint.key())
+            @ ErrorCode::PaymentMintMismatch
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + ListingBid::INIT_SPACE,
+        seeds = [b"listing_bid", listing.key().as_ref(), bidder.key().as_ref()],
+        bump
+    )]
+    pub listing_bid: Account<'info, ListingBid>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        associated_token::mint = payment_mint,
+        associated_token::authority = listing_bid,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelListingBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        close = bidder,
+        seeds = [b"listing_bid", listing_bid.listing.as_ref(), bidder.key().as_ref()],
+        bump = listing_bid.bump,
+        constraint = listing_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = bidder,
+        has_one = payment_mint @ ErrorCode::PaymentMintMismatch
+    )]
+    pub listing_bid: Account<'info, ListingBid>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = listing_bid,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptListingBid<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        mut,
+        close = bidder,
+        seeds = [b"listing_bid", listing.key().as_ref(), bidder.key().as_ref()],
+        bump = listing_bid.bump,
+        constraint = listing_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = listing @ ErrorCode::BidListingMismatch,
+        has_one = bidder,
+        has_one = payment_mint @ ErrorCode::PaymentMintMismatch
+    )]
+    pub listing_bid: Account<'info, ListingBid>,
+    /// CHECK: Receives the rent of the closed bid and escrow
+    #[account(mut)]
+    pub bidder: AccountInfo<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = bidder,
+        associated_token::token_program = token_program
+    )]
+    pub bidder_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = listing_bid,
+        associated_token::token_program = payment_token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    /// CHECK: The bidder's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", bidder.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBidDelegated<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + DelegatedBid::INIT_SPACE,
+        seeds = [b"delegated_bid", bidder.key().as_ref(), nft_mint.key().as_ref()],
+        bump
+    )]
+    pub delegated_bid: Account<'info, DelegatedBid>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = bidder,
+        token::token_program = token_program
+    )]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBidDelegated<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        close = bidder,
+        seeds = [b"delegated_bid", bidder.key().as_ref(), delegated_bid.nft_mint.as_ref()],
+        bump = delegated_bid.bump,
+        constraint = delegated_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = bidder,
+        has_one = bidder_payment_account
+    )]
+    pub delegated_bid: Account<'info, DelegatedBid>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptBidDelegated<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = bidder,
+        seeds = [b"delegated_bid", bidder.key().as_ref(), delegated_bid.nft_mint.as_ref()],
+        bump = delegated_bid.bump,
+        constraint = delegated_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = bidder,
+        has_one = nft_mint,
+        has_one = payment_mint @ ErrorCode::PaymentMintMismatch,
+        has_one = bidder_payment_account
+    )]
+    pub delegated_bid: Account<'info, DelegatedBid>,
+    /// CHECK: Receives the rent of the closed bid
+    #[account(mut)]
+    pub bidder: AccountInfo<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = bidder,
+        associated_token::token_program = token_program
+    )]
+    pub bidder_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    /// CHECK: The bidder's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", bidder.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBidSol<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + BidCounter::INIT_SPACE,
+        seeds = [b"bid_counter", bidder.key().as_ref()],
+        bump
+    )]
+    pub bid_counter: Account<'info, BidCounter>,
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + Bid::INIT_SPACE,
+        seeds = [
+            b"bid",
+            bidder.key().as_ref(),
+            nft_mint.key().as_ref(),
+            bid_counter.next_nonce.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = 8 + BidBook::INIT_SPACE,
+        seeds = [b"bid_book", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub bid_book: Account<'info, BidBook>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBidSol<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        close = bidder,
+        seeds = [
+            b"bid",
+            bidder.key().as_ref(),
+            bid.nft_mint.as_ref(),
+            bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = bid.bump,
+        constraint = bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = bidder,
+        constraint = bid.payment_mint.is_none() @ ErrorCode::PaymentMintMismatch
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        mut,
+        seeds = [b"bid_book", bid.nft_mint.as_ref()],
+        bump = bid_book.bump
+    )]
+    pub bid_book: Account<'info, BidBook>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptBidSol<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [
+            b"bid",
+            bid.bidder.as_ref(),
+            bid.nft_mint.as_ref(),
+            bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = bid.bump,
+        constraint = bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = bid.payment_mint.is_none() @ ErrorCode::PaymentMintMismatch
+    )]
+    pub bid: Account<'info, Bid>,
+    #[account(
+        mut,
+        seeds = [b"bid_book", bid.nft_mint.as_ref()],
+        bump = bid_book.bump
+    )]
+    pub bid_book: Account<'info, BidBook>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(address = bid.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = bid.bidder,
+        associated_token::token_program = token_program
+    )]
+    pub bidder_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the second bid's bidder, checked in the handler
+    #[account(mut)]
+    pub second_bidder_account: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            b"bid",
+            second_bid.bidder.as_ref(),
+            second_bid.nft_mint.as_ref(),
+            second_bid.nonce.to_le_bytes().as_ref()
+        ],
+        bump = second_bid.bump,
+        constraint = second_bid.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        constraint = second_bid.nft_mint == bid.nft_mint @ ErrorCode::BidListingMismatch,
+        constraint = second_bid.key() != bid.key() @ ErrorCode::InvalidSecondBid
+    )]
+    pub second_bid: Option<Account<'info, Bid>>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = market_stats.bump
+    )]
+    pub market_stats: Option<Account<'info, MarketStats>>,
+    /// CHECK: The bidder's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", bid.bidder.as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBuyerRecord<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: Only the address is used, and it must no longer hold a listing
+    #[account(constraint = listing.data_is_empty() @ ErrorCode::ListingStillActive)]
+    pub listing: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [b"buyer_record", listing.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_record.bump
+    )]
+    pub buyer_record: Account<'info, BuyerPurchaseRecord>,
+}
+
+#[derive(Accounts)]
+pub struct GetSellerStats<'info> {
+    #[account(
+        seeds = [b"seller_stats", seller_stats.seller.as_ref()],
+        bump = seller_stats.bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+}
+
+#[derive(Accounts)]
+pub struct GetPriceHistory<'info> {
+    #[account(
+        seeds = [b"price_history", price_history.nft_mint.as_ref()],
+        bump = price_history.bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeWhitelist<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 32 + 32 + 1,
+        seeds = [b"whitelist", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub whitelist: Account<'info, Whitelist>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRoyalties<'info> {
+    pub creator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"royalty_escrow",
+            creator.key().as_ref(),
+            royalty_escrow.nft_mint.as_ref(),
+            royalty_escrow.sale_id.to_le_bytes().as_ref()
+        ],
+        bump = royalty_escrow.bump,
+        has_one = creator,
+        has_one = payment_mint
+    )]
+    pub royalty_escrow: Account<'info, RoyaltyEscrow>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"royalty_vault", payment_mint.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = royalty_vault,
+        token::token_program = payment_token_program
+    )]
+    pub royalty_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint)]
+    pub creator_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFloorPrice<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        mut,
+        seeds = [b"stats", collection_stats.collection_mint.as_ref()],
+        bump = collection_stats.bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateWhitelistRoot<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"whitelist", whitelist.collection_mint.as_ref()],
+        bump = whitelist.bump,
+        has_one = authority
+    )]
+    pub whitelist: Account<'info, Whitelist>,
+}
+
+#[derive(Accounts)]
+pub struct CreateCollectionOffer<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = bidder,
+        space = 8 + 32 + 32 + 32 + 8 + 8 + 8 + 1,
+        seeds = [b"collection_offer", bidder.key().as_ref(), collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_offer: Account<'info, CollectionOffer>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = bidder,
+        associated_token::mint = payment_mint,
+        associated_token::authority = collection_offer,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TakeCollectionOffer<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    /// CHECK: Receives the NFT and the offer rent once it is filled
+    #[account(mut)]
+    pub bidder: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"collection_offer",
+            bidder.key().as_ref(),
+            collection_offer.collection_mint.as_ref()
+        ],
+        bump = collection_offer.bump,
+        has_one = bidder,
+        has_one = payment_mint
+    )]
+    pub collection_offer: Account<'info, CollectionOffer>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = bidder,
+        associated_token::token_program = token_program
+    )]
+    pub bidder_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = collection_offer,
+        associated_token::token_program = payment_token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    // Receives whatever is left in escrow when the offer fills
+    #[account(mut, token::mint = payment_mint, token::authority = bidder)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = market_stats.bump
+    )]
+    pub market_stats: Option<Account<'info, MarketStats>>,
+    /// CHECK: The bidder's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", bidder.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelCollectionOffer<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        close = bidder,
+        seeds = [
+            b"collection_offer",
+            bidder.key().as_ref(),
+            collection_offer.collection_mint.as_ref()
+        ],
+        bump = collection_offer.bump,
+        has_one = bidder,
+        has_one = payment_mint
+    )]
+    pub collection_offer: Account<'info, CollectionOffer>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub bidder_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = collection_offer,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct MakeOffer<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + Offer::INIT_SPACE,
+        seeds = [b"offer", buyer.key().as_ref(), nft_mint.key().as_ref()],
+        bump
+    )]
+    pub offer: Account<'info, Offer>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = payment_mint,
+        associated_token::authority = offer,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CounterOffer<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        mut,
+        seeds = [b"offer", offer.buyer.as_ref(), offer.nft_mint.as_ref()],
+        bump = offer.bump
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(address = offer.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = nft_mint,
+        associated_token::authority = offer,
+        associated_token::token_program = token_program
+    )]
+    pub offer_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    /// CHECK: The buyer who made the offer, receives the escrow rent
+    #[account(mut)]
+    pub buyer: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [b"offer", buyer.key().as_ref(), offer.nft_mint.as_ref()],
+        bump = offer.bump,
+        has_one = buyer,
+        has_one = payment_mint
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(address = offer.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
         payer = seller,
-        space = 8 + 32 + 32 + 8 + 8 + 8 + 8,
-        seeds = [b"listing", seller.key().as_ref(), nft_mint.key().as_ref()],
+        associated_token::mint = nft_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = offer,
+        associated_token::token_program = payment_token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = seller)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
         bump
     )]
-    pub listing: Account<'info, Listing>,
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = seller,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = market_stats.bump
+    )]
+    pub market_stats: Option<Account<'info, MarketStats>>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
+    )]
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptCounterOffer<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: The seller who countered, receives the NFT escrow rent
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [b"offer", buyer.key().as_ref(), offer.nft_mint.as_ref()],
+        bump = offer.bump,
+        has_one = buyer,
+        has_one = seller,
+        has_one = payment_mint
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(address = offer.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
         associated_token::mint = nft_mint,
-        associated_token::authority = seller
+        associated_token::authority = offer,
+        associated_token::token_program = token_program
     )]
-    pub seller_nft_account: Account<'info, TokenAccount>,
+    pub offer_nft_account: InterfaceAccount<'info, TokenAccount>,
     #[account(
         init_if_needed,
-        payer = seller,
+        payer = buyer,
         associated_token::mint = nft_mint,
-        associated_token::authority = vault_nft_account
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = offer,
+        associated_token::token_program = payment_token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = seller)]
+    pub seller_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"fee_vault"],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, MarketplaceFeeVault>,
+    /// CHECK: Must be the fee vault's token account for the payment mint
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &fee_vault.key(),
+            &payment_mint.key(),
+            &payment_token_program.key()
+        ) @ ErrorCode::InvalidFeeAccount
+    )]
+    pub marketplace_fee_account: AccountInfo<'info>,
+    /// CHECK: We're reading data from this account
+    pub metadata: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + 32 + (8 + 32 + 8) * PRICE_HISTORY_LEN + 1 + 1,
+        seeds = [b"price_history", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub price_history: Account<'info, PriceHistory>,
+    /// CHECK: Checked against the NFT's verified collection in the handler
+    pub collection_mint: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + CollectionStats::INIT_SPACE,
+        seeds = [b"stats", collection_mint.key().as_ref()],
+        bump
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + SellerStats::INIT_SPACE,
+        seeds = [b"seller_stats", seller.key().as_ref()],
+        bump
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = market_stats.bump
+    )]
+    pub market_stats: Option<Account<'info, MarketStats>>,
+    /// CHECK: The buyer's fee tier PDA, which stays empty until they open one.
+    /// Read and updated in the handler
+    #[account(
+        mut,
+        seeds = [b"fee_tier", buyer.key().as_ref()],
+        bump
     )]
-    pub vault_nft_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+    pub fee_tier: UncheckedAccount<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
-#[derive(Accounts)]
-pub struct CancelListing<'info> {
-    #[account(mut)]
-    pub seller: Signer<'info>,
-    #[account(
-        mut,
-        close = seller,
-        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
-        bump,
-        has_one = seller
-    )]
-    pub listing: Account<'info, Listing>,
-    #[account(
-        mut,
-        associated_token::mint = listing.nft_mint,
-        associated_token::authority = seller
-    )]
-    pub seller_nft_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        associated_token::mint = listing.nft_mint,
-        associated_token::authority = vault_nft_account
-    )]
-    pub vault_nft_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+#[derive(Accounts)]
+pub struct RejectCounterOffer<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    /// CHECK: The seller who countered, receives the NFT escrow rent
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [b"offer", buyer.key().as_ref(), offer.nft_mint.as_ref()],
+        bump = offer.bump,
+        has_one = buyer,
+        has_one = seller,
+        has_one = payment_mint
+    )]
+    pub offer: Account<'info, Offer>,
+    #[account(address = offer.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = offer,
+        associated_token::token_program = token_program
+    )]
+    pub offer_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint)]
+    pub buyer_payment_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = offer,
+        associated_token::token_program = payment_token_program
+    )]
+    pub escrow_payment_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Interface<'info, TokenInterface>,
+}
+
+// Data structures
+
+#[account]
+#[derive(InitSpace)]
+pub struct MarketplaceConfig {
+    pub fee_bps: u64,
+    pub fee_recipient: Pubkey,
+    pub authority: Pubkey,
+    pub paused: bool,
+    pub bump: u8,
+    // Rebate the unused second-bidder fee to the seller instead of keeping it
+    pub rebate_unused_second_bidder_fee: bool,
+    // Set by propose_authority_transfer until the new authority accepts
+    pub pending_authority: Option<Pubkey>,
+    // Share of each expired listing's rent paid to the crank that closes it
+    pub crank_reward_lamports: u64,
+    // Overrides optional and disabled listings so every verified creator is paid
+    pub enforce_royalties: bool,
+    // How much a new bid must beat the best bid on the same NFT by; zero disables the check
+    pub min_bid_increment: u64,
+    // The same requirement as a share of the best bid, in basis points
+    pub min_bid_increment_bps: u64,
+    // Royalty rate that replaces the metadata rate for royalty_override_collection
+    pub royalty_override_bps: Option<u64>,
+    pub royalty_override_collection: Option<Pubkey>,
+    // Only allow listing mints with zero decimals and a supply of one
+    pub enforce_nft: bool,
+    // Royalty charged when metadata pays none; zero disables the fallback
+    pub default_royalty_bps: u64,
+    pub default_royalty_treasury: Pubkey,
+    // Expired listings stay buyable, and can't be cranked closed, for this many seconds
+    pub expiry_grace_secs: i64,
+    // Fee charged to whoever's listing, bid or offer was resting when the trade filled
+    pub maker_fee_bps: u64,
+    // Fee charged to whoever filled a resting listing, bid or offer
+    pub taker_fee_bps: u64,
+    // Set by set_maker_taker_fees. Until then only the seller pays, at the flat fee_bps
+    pub maker_taker_fees: bool,
+}
+
+// Whose listing, bid or offer was resting when a trade filled
+#[derive(Clone, Copy)]
+pub enum Maker {
+    Seller,
+    Buyer,
+}
+
+impl MarketplaceConfig {
+    // The seller's rate for their leg of the fee, taken out of the proceeds
+    pub fn seller_fee_bps(&self, maker: Maker) -> u64 {
+        match (self.maker_taker_fees, maker) {
+            (false, _) => self.fee_bps,
+            (true, Maker::Seller) => self.maker_fee_bps,
+            (true, Maker::Buyer) => self.taker_fee_bps,
+        }
+    }
+
+    // The buyer's rate for their leg of the fee, paid on top of the sale price. The flat fee
+    // leaves the buyer's leg at zero
+    pub fn buyer_fee_bps(&self, maker: Maker) -> u64 {
+        match (self.maker_taker_fees, maker) {
+            (false, _) => 0,
+            (true, Maker::Seller) => self.taker_fee_bps,
+            (true, Maker::Buyer) => self.maker_fee_bps,
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct RequestNftLoan<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    // The listing's seller lends the NFT
+    pub seller: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, MarketplaceConfig>,
+    #[account(
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        constraint = listing.version == ACCOUNT_VERSION @ ErrorCode::UnsupportedVersion,
+        has_one = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(address = listing.nft_mint)]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = borrower,
+        associated_token::mint = nft_mint,
+        associated_token::authority = borrower,
+        associated_token::token_program = token_program
+    )]
+    pub borrower_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = borrower,
+        space = 8 + LoanEscrow::INIT_SPACE,
+        seeds = [b"loan_escrow", listing.key().as_ref()],
+        bump
+    )]
+    pub loan_escrow: Account<'info, LoanEscrow>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RepayNftLoan<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    /// CHECK: Only receives the interest; matched against the loan's lender
+    #[account(mut)]
+    pub lender: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"loan_escrow", loan_escrow.listing.as_ref()],
+        bump = loan_escrow.bump,
+        has_one = borrower,
+        has_one = lender,
+        has_one = nft_mint
+    )]
+    pub loan_escrow: Account<'info, LoanEscrow>,
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"vault", lender.key().as_ref(), nft_mint.key().as_ref()],
+        bump,
+        token::mint = nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = borrower,
+        associated_token::token_program = token_program
+    )]
+    pub borrower_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidateDefaultedLoan<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
+        bump = listing.bump,
+        has_one = seller
+    )]
+    pub listing: Account<'info, Listing>,
+    #[account(
+        mut,
+        seeds = [b"loan_escrow", listing.key().as_ref()],
+        bump = loan_escrow.bump
+    )]
+    pub loan_escrow: Account<'info, LoanEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", listing.seller.as_ref(), listing.nft_mint.as_ref()],
+        bump,
+        token::mint = listing.nft_mint,
+        token::authority = vault_nft_account,
+        token::token_program = token_program
+    )]
+    pub vault_nft_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Listing {
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub price: u64,
+    pub quantity: u64,
+    pub listing_status: ListingStatus,
+    pub created_at: i64,
+    pub expiry: i64,
+    pub token_program_type: TokenProgramType,
+    pub whitelist_enabled: bool,
+    // None means the listing is priced in native SOL
+    pub payment_mint: Option<Pubkey>,
+    pub royalty_enforcement: RoyaltyEnforcementMode,
+    // Lowest bid the seller will accept through accept_highest_bid
+    pub reserve_price: u64,
+    // Zero means a single buyer can take every unit
+    pub max_quantity_per_buyer: u64,
+    // Purchases are rejected before this timestamp
+    pub goes_live_at: i64,
+    pub bump: u8,
+    pub version: u8,
+    // Private listings can only be bought by this wallet
+    pub buyer_allowlist: Option<Pubkey>,
+    // Verified creators read at listing time; empty means royalties use live metadata
+    #[max_len(MAX_CACHED_CREATORS)]
+    pub creator_cache: Vec<CachedCreator>,
+    // Seller-supplied URI or note, independent of the NFT's own metadata
+    #[max_len(MAX_LISTING_METADATA_LEN)]
+    pub listing_metadata: String,
+    // Bids at or above this price settle as soon as they are placed; zero disables it
+    pub auto_accept_threshold: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct BundleListing {
+    pub seller: Pubkey,
+    pub bundle_id: u64,
+    pub payment_mint: Pubkey,
+    #[max_len(MAX_BUNDLE_SIZE)]
+    pub mints: Vec<Pubkey>,
+    pub price: u64,
+    pub expiry: i64,
+    pub royalty_enforcement: RoyaltyEnforcementMode,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct SplitListing {
+    pub primary_seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub price: u64,
+    pub expiry: i64,
+    #[max_len(MAX_CO_OWNERS)]
+    pub co_owners: Vec<CoOwner>,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct DutchAuction {
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub start_price: u64,
+    pub end_price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub quantity: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct PriceCurveListing {
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub base_price: u64,
+    pub increment_per_sale: u64,
+    pub units_sold: u64,
+    // Units still in the vault
+    pub quantity: u64,
+    pub created_at: i64,
+    pub expiry: i64,
+    pub bump: u8,
+}
+
+impl PriceCurveListing {
+    // The next unit costs base_price plus one increment for every unit already sold
+    pub fn current_price(&self) -> Result<u64> {
+        self.increment_per_sale
+            .checked_mul(self.units_sold)
+            .and_then(|increase| self.base_price.checked_add(increase))
+            .ok_or(error!(ErrorCode::ArithmeticOverflow))
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct EnglishAuction {
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub reserve_price: u64,
+    pub min_increment_bps: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub highest_bid: u64,
+    pub highest_bidder: Pubkey,
+    pub bid_count: u64,
+    pub bump: u8,
+    // Bids this close to the end push it back by the same amount
+    pub extension_window: i64,
+}
+
+impl EnglishAuction {
+    pub fn winner(&self) -> Pubkey {
+        if self.bid_count > 0 {
+            self.highest_bidder
+        } else {
+            self.seller
+        }
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct CollectionPolicy {
+    pub collection_mint: Pubkey,
+    pub min_price: u64,
+    pub max_price: u64,
+    pub authority: Pubkey,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct AuctionConfig {
+    pub collection_mint: Pubkey,
+    pub min_bid_increment_bps: u64,
+    pub extension_window_seconds: i64,
+    // Zero means auctions in the collection can run for any length of time
+    pub max_duration_seconds: i64,
+    pub config_authority: Pubkey,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Bid {
+    pub bidder: Pubkey,
+    pub nft_mint: Pubkey,
+    // None means the bid is escrowed in native SOL
+    pub payment_mint: Option<Pubkey>,
+    pub price: u64,
+    pub created_at: i64,
+    pub expiry: i64,
+    pub bump: u8,
+    pub version: u8,
+    // Lets a bidder hold several bids on the same NFT
+    pub nonce: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct BidCounter {
+    pub bidder: Pubkey,
+    pub next_nonce: u64,
+    pub bump: u8,
+}
+
+impl BidCounter {
+    // Accounts created by init_if_needed start zeroed
+    pub fn advance(&mut self, bidder: Pubkey, bump: u8) -> Result<()> {
+        self.bidder = bidder;
+        self.bump = bump;
+        self.next_nonce = self
+            .next_nonce
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct ListingBid {
+    pub bidder: Pubkey,
+    pub listing: Pubkey,
+    // Distinguishes the listing this bid was placed on from a later relisting at the same address
+    pub listing_created_at: i64,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub price: u64,
+    pub created_at: i64,
+    pub expiry: i64,
+    pub bump: u8,
+    pub version: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct DelegatedBid {
+    pub bidder: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    // The account the bid PDA is approved to spend from
+    pub bidder_payment_account: Pubkey,
+    pub price: u64,
+    pub created_at: i64,
+    pub expiry: i64,
+    pub bump: u8,
+    pub version: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct BidBook {
+    pub nft_mint: Pubkey,
+    pub best_bid: u64,
+    pub best_bidder: Pubkey,
+    pub bump: u8,
+}
+
+impl BidBook {
+    pub fn record_bid(
+        &mut self,
+        bidder: Pubkey,
+        price: u64,
+        config: &MarketplaceConfig,
+    ) -> Result<()> {
+        let enforced = config.min_bid_increment > 0 || config.min_bid_increment_bps > 0;
+        if enforced && self.best_bid > 0 {
+            let flat_min = self
+                .best_bid
+                .checked_add(config.min_bid_increment)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            let bps_min = (self.best_bid as u128)
+                .checked_mul((FEE_DENOMINATOR as u128) + (config.min_bid_increment_bps as u128))
+                .ok_or(ErrorCode::ArithmeticOverflow)?
+                .checked_div(FEE_DENOMINATOR as u128)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            let bps_min = u64::try_from(bps_min).map_err(|_| ErrorCode::ArithmeticOverflow)?;
+            require!(price >= flat_min.max(bps_min), ErrorCode::BidIncrementTooSmall);
+        }
+        if price > self.best_bid {
+            self.best_bid = price;
+            self.best_bidder = bidder;
+        }
+
+        Ok(())
+    }
+
+    // The runner-up isn't tracked, so the book restarts once the best bid is gone
+    pub fn remove_bid(&mut self, bidder: Pubkey, price: u64) {
+        if self.best_bidder == bidder && self.best_bid == price {
+            self.best_bid = 0;
+            self.best_bidder = Pubkey::default();
+        }
+    }
+}
+
+#[account]
+pub struct PriceHistory {
+    pub nft_mint: Pubkey,
+    pub records: [SaleRecord; PRICE_HISTORY_LEN],
+    pub head: u8,
+    pub bump: u8,
+}
+
+impl PriceHistory {
+    // Overwrite the oldest slot with the newest sale
+    pub fn push(&mut self, record: SaleRecord) {
+        let head = self.head as usize % PRICE_HISTORY_LEN;
+        self.records[head] = record;
+        self.head = ((head + 1) % PRICE_HISTORY_LEN) as u8;
+    }
+
+    pub fn records_oldest_first(&self) -> Vec<SaleRecord> {
+        let head = self.head as usize % PRICE_HISTORY_LEN;
+        self.records[head..]
+            .iter()
+            .chain(self.records[..head].iter())
+            .copied()
+            .collect()
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct SaleRecord {
+    pub price: u64,
+    pub buyer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct RoyaltyEscrow {
+    pub creator: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    // The collection's sale count when the royalty was escrowed
+    pub sale_id: u64,
+    pub amount: u64,
+    pub claimed: bool,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct CollectionStats {
+    pub collection_mint: Pubkey,
+    pub total_volume: u128,
+    pub sale_count: u64,
+    pub floor_price: u64,
+    pub last_sale_price: u64,
+    pub last_updated: i64,
+    pub bump: u8,
+}
+
+impl CollectionStats {
+    pub fn record_sale(&mut self, price: u64, timestamp: i64) -> Result<()> {
+        self.total_volume = self
+            .total_volume
+            .checked_add(u128::from(price))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.sale_count = self
+            .sale_count
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.last_sale_price = price;
+        self.last_updated = timestamp;
+
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MarketStats {
+    pub total_volume: u128,
+    pub total_sales: u64,
+    // Only the marketplace's share of the fee; second-bidder fees are excluded
+    pub total_fees_collected: u128,
+    pub bump: u8,
+}
+
+impl MarketStats {
+    pub fn record_sale(&mut self, price: u64, marketplace_fee: u64) -> Result<()> {
+        self.total_volume = self
+            .total_volume
+            .checked_add(u128::from(price))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_sales = self
+            .total_sales
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_fees_collected = self
+            .total_fees_collected
+            .checked_add(u128::from(marketplace_fee))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+}
+
+// Collects every marketplace fee: SPL fees in one token account per payment mint it owns,
+// and SOL fees as lamports on the vault itself
+#[account]
+#[derive(InitSpace)]
+pub struct MarketplaceFeeVault {
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct FeeTier {
+    pub trader: Pubkey,
+    // Lamports spent on purchases paid in SOL or wrapped SOL
+    pub volume_traded: u128,
+    pub tier: u8,
+    pub bump: u8,
+}
+
+impl FeeTier {
+    // Adds a purchase to the trader's volume, advancing the tier once a threshold is crossed.
+    // Never demotes, so manual promotions stick.
+    pub fn record_volume(&mut self, price: u64) -> Result<()> {
+        self.volume_traded = self
+            .volume_traded
+            .checked_add(u128::from(price))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        let earned_tier = if self.volume_traded > FEE_TIER_3_VOLUME {
+            3
+        } else if self.volume_traded > FEE_TIER_2_VOLUME {
+            2
+        } else if self.volume_traded > FEE_TIER_1_VOLUME {
+            1
+        } else {
+            0
+        };
+        self.tier = std::cmp::max(self.tier, earned_tier);
+
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct BuyerPurchaseRecord {
+    pub listing: Pubkey,
+    pub buyer: Pubkey,
+    pub purchased: u64,
+    pub bump: u8,
+}
+
+impl BuyerPurchaseRecord {
+    pub fn record_purchase(&mut self, listing: &Listing, quantity: u64) -> Result<()> {
+        let purchased = self
+            .purchased
+            .checked_add(quantity)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            listing.max_quantity_per_buyer == 0 || purchased <= listing.max_quantity_per_buyer,
+            ErrorCode::PurchaseLimitExceeded
+        );
+        self.purchased = purchased;
+
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct SellerStats {
+    pub seller: Pubkey,
+    pub total_sales: u64,
+    pub total_volume: u128,
+    pub total_cancellations: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl SellerStats {
+    // Accounts created by init_if_needed start zeroed
+    pub fn init_if_new(&mut self, seller: Pubkey, bump: u8) -> Result<()> {
+        if self.seller == Pubkey::default() {
+            self.seller = seller;
+            self.bump = bump;
+            self.created_at = Clock::get()?.unix_timestamp;
+        }
+
+        Ok(())
+    }
+
+    pub fn record_sale(&mut self, price: u64) -> Result<()> {
+        self.total_sales = self
+            .total_sales
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.total_volume = self
+            .total_volume
+            .checked_add(u128::from(price))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Whitelist {
+    pub authority: Pubkey,
+    pub collection_mint: Pubkey,
+    pub merkle_root: [u8; 32],
+    pub bump: u8,
+}
+
+// A collection-wide bid: escrows `price` for each of up to `quantity` NFTs, and fills with
+// any NFT whose metadata names `collection_mint` as its verified collection
+#[account]
+pub struct CollectionOffer {
+    pub bidder: Pubkey,
+    pub collection_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub price: u64,
+    pub expiry: i64,
+    pub quantity: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Offer {
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    // The holder the offer is addressed to; only they may accept or counter it
+    pub target_owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub offer_price: u64,
+    pub counter_price: u64,
+    pub state: OfferState,
+    pub created_at: i64,
+    pub expiry: i64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct LoanEscrow {
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub listing: Pubkey,
+    pub nft_mint: Pubkey,
+    pub quantity: u64,
+    // SOL collateral held in this account on top of its rent
+    pub loan_amount: u64,
+    // Interest on the collateral paid to the lender on repayment
+    pub interest_rate_bps: u64,
+    pub due_at: i64,
+    pub status: LoanStatus,
+    pub bump: u8,
+}
+
+// Sold-out and cancelled listings are closed, so those statuses are read from ListingClosed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum ListingStatus {
+    Active,
+    SoldOut,
+    Expired,
+    Cancelled,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum TokenProgramType {
+    Legacy,
+    Token2022,
+}
+
+// What admin_recover is sweeping and whose closed account proves it is stuck
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryTarget {
+    // The seller's vault for the mint, left behind by their listing
+    Vault { seller: Pubkey },
+    // The escrow ATA of a bid, in the mint passed to admin_recover
+    BidEscrow {
+        bidder: Pubkey,
+        nft_mint: Pubkey,
+        nonce: u64,
+    },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum RoyaltyEnforcementMode {
+    Mandatory,
+    Optional,
+    Disabled,
+    // Royalties are escrowed at sale time and pulled by each creator later
+    Lazy,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum OfferState {
+    Pending,
+    Countered,
+    Accepted,
+    Rejected,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum LoanStatus {
+    Active,
+    Repaid,
+    Liquidated,
+}
+
+// One entry of a bulk_place_bids call
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct BidParams {
+    pub nft_mint: Pubkey,
+    pub price: u64,
+    pub expiry: i64,
+}
+
+// A verified creator copied from metadata when the listing was created
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct CachedCreator {
+    pub address: Pubkey,
+    pub share: u8,
+}
+
+// A co-owner of a split listing and their share of the proceeds in basis points
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct CoOwner {
+    pub owner: Pubkey,
+    pub share_bps: u16,
+}
+
+// Event structures
+
+#[event]
+pub struct ListingCreated {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub price: u64,
+    pub quantity: u64,
+    pub expiry: i64,
+    pub reserve_price: u64,
+    pub goes_live_at: i64,
+    pub buyer_allowlist: Option<Pubkey>,
+    // Where the listed NFT is escrowed, so indexers need not re-derive the PDA
+    pub vault: Pubkey,
+    pub vault_bump: u8,
+    pub listing_metadata: String,
+}
+
+#[event]
+pub struct ListingCancelled {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+}
+
+#[event]
+pub struct FundsRecovered {
+    pub account: Pubkey,
+    pub mint: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ListingMarkedExpired {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+}
+
+#[event]
+pub struct ListingExpiredClosed {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub crank_operator: Pubkey,
+    pub crank_reward: u64,
+}
+
+#[event]
+pub struct ListingClosed {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub status: ListingStatus,
+}
+
+#[event]
+pub struct ListingQuantityUpdated {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub old_quantity: u64,
+    pub new_quantity: u64,
+}
+
+#[event]
+pub struct ListingAutoAcceptUpdated {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub auto_accept_threshold: u64,
+}
+
+#[event]
+pub struct ListingMetadataUpdated {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub listing_metadata: String,
+}
+
+#[event]
+pub struct ListingBuyerAllowlistUpdated {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub buyer_allowlist: Option<Pubkey>,
+}
+
+#[event]
+pub struct ListingPriceUpdated {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub old_price: u64,
+    pub new_price: u64,
+}
+
+#[event]
+pub struct RoyaltiesClaimed {
+    pub creator: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub sale_id: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub authority: Pubkey,
+    pub old_fee_bps: u64,
+    pub new_fee_bps: u64,
+    pub old_fee_recipient: Pubkey,
+    pub new_fee_recipient: Pubkey,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub authority: Pubkey,
+    pub payment_mint: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct FeeTierPromoted {
+    pub trader: Pubkey,
+    pub tier: u8,
+    pub volume_traded: u128,
+}
+
+#[event]
+pub struct AuthorityTransferProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct ExecuteSale<'info> {
-    #[account(mut)]
-    pub buyer: Signer<'info>,
-    /// CHECK: We're reading data from this account
-    #[account(mut)]
-    pub seller: AccountInfo<'info>,
-    #[account(
-        mut,
-        seeds = [b"listing", seller.key().as_ref(), listing.nft_mint.as_ref()],
-        bump,
-        has_one = seller
-    )]
-    pub listing: Account<'info, Listing>,
-    pub nft_mint: Account<'info, Mint>,
-    #[account(
-        mut,
-        associated_token::mint = listing.nft_mint,
-        associated_token::authority = vault_nft_account
-    )]
-    pub vault_nft_account: Account<'info, TokenAccount>,
-    #[account(
-        init_if_needed,
-        payer = buyer,
-        associated_token::mint = listing.nft_mint,
-        associated_token::authority = buyer
-    )]
-    pub buyer_nft_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub buyer_payment_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub seller_payment_account: Account<'info, TokenAccount>,
-    /// CHECK: We're reading data from this account
-    #[account(mut)]
-    pub marketplace_fee_account: AccountInfo<'info>,
-    /// CHECK: We're reading data from this account
-    #[account(mut)]
-    pub second_bidder_account: Account<'info, TokenAccount>,
-    /// CHECK: We're reading data from this account
-    pub metadata: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+#[event]
+pub struct AuthorityTransferCompleted {
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct PlaceBid<'info> {
-    #[account(mut)]
-    pub bidder: Signer<'info>,
-    pub nft_mint: Account<'info, Mint>,
-    #[account(
-        init,
-        payer = bidder,
-        space = 8 + 32 + 32 + 8 + 8 + 8,
-        seeds = [b"bid", bidder.key().as_ref(), nft_mint.key().as_ref()],
-        bump
-    )]
-    pub bid: Account<'info, Bid>,
-    #[account(mut)]
-    pub bidder_payment_account: Account<'info, TokenAccount>,
-    #[account(
-        init_if_needed,
-        payer = bidder,
-        associated_token::mint = nft_mint,
-        associated_token::authority = escrow_payment_account
-    )]
-    pub escrow_payment_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+#[event]
+pub struct ReservePriceUpdated {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub old_reserve_price: u64,
+    pub new_reserve_price: u64,
 }
 
-#[derive(Accounts)]
-pub struct CancelBid<'info> {
-    #[account(mut)]
-    pub bidder: Signer<'info>,
-    #[account(
-        mut,
-        close = bidder,
-        seeds = [b"bid", bidder.key().as_ref(), bid.nft_mint.as_ref()],
-        bump,
-        has_one = bidder
-    )]
-    pub bid: Account<'info, Bid>,
-    #[account(mut)]
-    pub bidder_payment_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        associated_token::mint = bid.nft_mint,
-        associated_token::authority = escrow_payment_account
-    )]
-    pub escrow_payment_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+#[event]
+pub struct SaleExecuted {
+    pub listing_id: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Option<Pubkey>,
+    pub price: u64,
+    pub marketplace_fee: u64,
+    // Owner of the account the second-bidder fee went to; the default key where the instruction has none
+    pub second_bidder: Pubkey,
+    pub second_bidder_fee: u64,
+    pub total_creator_royalty: u64,
+    pub seller_payment: u64,
 }
 
-#[derive(Accounts)]
-pub struct AcceptBid<'info> {
-    #[account(mut)]
-    pub seller: Signer<'info>,
-    #[account(
-        mut,
-        close = seller,
-        seeds = [b"bid", bid.bidder.as_ref(), bid.nft_mint.as_ref()],
-        bump
-    )]
-    pub bid: Account<'info, Bid>,
-    pub nft_mint: Account<'info, Mint>,
-    #[account(
-        mut,
-        associated_token::mint = nft_mint,
-        associated_token::authority = seller
-    )]
-    pub seller_nft_account: Account<'info, TokenAccount>,
-    #[account(
-        init_if_needed,
-        payer = seller,
-        associated_token::mint = nft_mint,
-        associated_token::authority = bid.bidder
-    )]
-    pub bidder_nft_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub escrow_payment_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub seller_payment_account: Account<'info, TokenAccount>,
-    /// CHECK: We're reading data from this account
-    #[account(mut)]
-    pub marketplace_fee_account: AccountInfo<'info>,
-    /// CHECK: We're reading data from this account
-    #[account(mut)]
-    pub second_bidder_account: Account<'info, TokenAccount>,
-    /// CHECK: We're reading data from this account
-    pub metadata: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+#[event]
+pub struct FloorSweepExecuted {
+    pub buyer: Pubkey,
+    pub collection_mint: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub total_price: u64,
 }
 
-// Data structures
+#[event]
+pub struct BundleListingCreated {
+    pub bundle_listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub price: u64,
+    pub expiry: i64,
+}
 
-#[account]
-pub struct Listing {
+#[event]
+pub struct BundleListingCancelled {
+    pub bundle_listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub mints: Vec<Pubkey>,
+}
+
+#[event]
+pub struct BundleSaleExecuted {
+    pub bundle_listing_id: Pubkey,
+    pub buyer: Pubkey,
     pub seller: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub price: u64,
+}
+
+#[event]
+pub struct SplitListingCreated {
+    pub split_listing_id: Pubkey,
+    pub primary_seller: Pubkey,
     pub nft_mint: Pubkey,
     pub price: u64,
-    pub quantity: u64,
-    pub created_at: i64,
     pub expiry: i64,
+    pub co_owners: Vec<CoOwner>,
 }
 
-#[account]
-pub struct Bid {
-    pub bidder: Pubkey,
+#[event]
+pub struct SplitListingSaleExecuted {
+    pub split_listing_id: Pubkey,
+    pub buyer: Pubkey,
     pub nft_mint: Pubkey,
     pub price: u64,
-    pub created_at: i64,
-    pub expiry: i64,
+    pub marketplace_fee: u64,
+    pub total_creator_royalty: u64,
+    pub seller_payment: u64,
 }
 
-// Event structures
+#[event]
+pub struct DutchAuctionCreated {
+    pub auction_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub start_price: u64,
+    pub end_price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub quantity: u64,
+}
 
 #[event]
-pub struct ListingCreated {
-    pub listing_id: Pubkey,
+pub struct DutchAuctionSaleExecuted {
+    pub auction_id: Pubkey,
+    pub buyer: Pubkey,
     pub seller: Pubkey,
     pub nft_mint: Pubkey,
     pub price: u64,
+}
+
+#[event]
+pub struct DutchAuctionCancelled {
+    pub auction_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+}
+
+#[event]
+pub struct PriceCurveListingCreated {
+    pub listing_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub base_price: u64,
+    pub increment_per_sale: u64,
     pub quantity: u64,
     pub expiry: i64,
 }
 
 #[event]
-pub struct ListingCancelled {
+pub struct PriceCurveSaleExecuted {
     pub listing_id: Pubkey,
+    pub buyer: Pubkey,
     pub seller: Pubkey,
     pub nft_mint: Pubkey,
+    pub price_paid: u64,
+    pub units_sold: u64,
 }
 
 #[event]
-pub struct SaleExecuted {
+pub struct PriceCurveListingCancelled {
     pub listing_id: Pubkey,
-    pub buyer: Pubkey,
     pub seller: Pubkey,
     pub nft_mint: Pubkey,
+    pub units_sold: u64,
+}
+
+#[event]
+pub struct EnglishAuctionCreated {
+    pub auction_id: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub reserve_price: u64,
+    pub min_increment_bps: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+#[event]
+pub struct EnglishAuctionBidPlaced {
+    pub auction_id: Pubkey,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub bid_count: u64,
+    pub end_time: i64,
+}
+
+#[event]
+pub struct EnglishAuctionSettled {
+    pub auction_id: Pubkey,
+    pub seller: Pubkey,
+    pub winner: Pubkey,
+    pub nft_mint: Pubkey,
     pub price: u64,
 }
 
@@ -643,6 +11992,22 @@ pub struct BidPlaced {
     pub expiry: i64,
 }
 
+#[event]
+pub struct BulkBidsPlaced {
+    pub bidder: Pubkey,
+    pub bid_ids: Vec<Pubkey>,
+}
+
+#[event]
+pub struct ListingBidPlaced {
+    pub bid_id: Pubkey,
+    pub listing: Pubkey,
+    pub bidder: Pubkey,
+    pub nft_mint: Pubkey,
+    pub price: u64,
+    pub expiry: i64,
+}
+
 #[event]
 pub struct BidCancelled {
     pub bid_id: Pubkey,
@@ -650,6 +12015,26 @@ pub struct BidCancelled {
     pub nft_mint: Pubkey,
 }
 
+#[event]
+pub struct BidExpiredRefunded {
+    pub bid_id: Pubkey,
+    pub bidder: Pubkey,
+    pub nft_mint: Pubkey,
+    pub amount: u64,
+    pub crank_operator: Pubkey,
+    pub crank_reward: u64,
+}
+
+#[event]
+pub struct BidUpdated {
+    pub bid_id: Pubkey,
+    pub bidder: Pubkey,
+    pub nft_mint: Pubkey,
+    pub old_price: u64,
+    pub new_price: u64,
+    pub expiry: i64,
+}
+
 #[event]
 pub struct BidAccepted {
     pub bid_id: Pubkey,
@@ -657,5 +12042,285 @@ pub struct BidAccepted {
     pub bidder: Pubkey,
     pub nft_mint: Pubkey,
     pub price: u64,
+    pub marketplace_fee: u64,
+    // Owner of the account the second-bidder fee went to; the default key where the instruction has none
+    pub second_bidder: Pubkey,
+    pub second_bidder_fee: u64,
+    pub total_creator_royalty: u64,
+    pub seller_payment: u64,
+}
+
+#[event]
+pub struct CollectionOfferCreated {
+    pub offer_id: Pubkey,
+    pub bidder: Pubkey,
+    pub collection_mint: Pubkey,
+    pub price: u64,
+    pub quantity: u64,
+    pub expiry: i64,
+}
+
+#[event]
+pub struct CollectionOfferAccepted {
+    pub offer_id: Pubkey,
+    pub seller: Pubkey,
+    pub bidder: Pubkey,
+    pub nft_mint: Pubkey,
+    pub price: u64,
+    pub remaining_quantity: u64,
+}
+
+#[event]
+pub struct CollectionOfferCancelled {
+    pub offer_id: Pubkey,
+    pub bidder: Pubkey,
+    pub collection_mint: Pubkey,
+}
+
+#[event]
+pub struct OfferMade {
+    pub offer_id: Pubkey,
+    pub buyer: Pubkey,
+    pub target_owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub payment_mint: Pubkey,
+    pub offer_price: u64,
+    pub expiry: i64,
+}
+
+#[event]
+pub struct OfferCountered {
+    pub offer_id: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub offer_price: u64,
+    pub counter_price: u64,
+}
+
+#[event]
+pub struct OfferClosed {
+    pub offer_id: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub nft_mint: Pubkey,
+    pub state: OfferState,
+    pub price: u64,
+}
+
+#[event]
+pub struct NftLoanRequested {
+    pub loan_id: Pubkey,
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub nft_mint: Pubkey,
+    pub loan_amount: u64,
+    pub interest_rate_bps: u64,
+    pub due_at: i64,
+}
+
+#[event]
+pub struct NftLoanRepaid {
+    pub loan_id: Pubkey,
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub nft_mint: Pubkey,
+    pub interest: u64,
+}
+
+#[event]
+pub struct NftLoanLiquidated {
+    pub loan_id: Pubkey,
+    pub borrower: Pubkey,
+    pub lender: Pubkey,
+    pub nft_mint: Pubkey,
+    pub loan_amount: u64,
+}
+
+// Error codes
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Platform fee exceeds the maximum allowed")]
+    FeeTooHigh,
+    #[msg("Listing has expired")]
+    ListingExpired,
+    #[msg("Bid has expired")]
+    BidExpired,
+    #[msg("Bid has not expired")]
+    BidNotExpired,
+    #[msg("Metadata account does not belong to the traded mint")]
+    InvalidMetadata,
+    #[msg("Price must be greater than zero")]
+    InvalidPrice,
+    #[msg("Bundle must contain between 1 and 10 NFTs")]
+    InvalidBundleSize,
+    #[msg("Bundle contains the same mint more than once")]
+    DuplicateBundleMint,
+    #[msg("Vault account does not match the bundle listing")]
+    InvalidBundleVault,
+    #[msg("Marketplace is paused")]
+    MarketplacePaused,
+    #[msg("Auction start price must not be below its end price")]
+    InvalidAuctionPrices,
+    #[msg("Auction end time must be after its start time")]
+    InvalidAuctionTimes,
+    #[msg("Auction has not started yet")]
+    AuctionNotStarted,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    #[msg("Verified creator shares exceed 100%")]
+    InvalidCreatorShares,
+    #[msg("Auction has already ended")]
+    AuctionEnded,
+    #[msg("Auction has not ended yet")]
+    AuctionNotEnded,
+    #[msg("Bid is below the reserve price or minimum increment")]
+    BidTooLow,
+    #[msg("Previous highest bidder's payment account is missing or invalid")]
+    InvalidPreviousBidder,
+    #[msg("Recipient is not the auction winner")]
+    InvalidAuctionRecipient,
+    #[msg("Payment account is missing or invalid")]
+    MissingPaymentAccount,
+    #[msg("Listing price exceeds the buyer's maximum price")]
+    PriceExceedsMax,
+    #[msg("Quantity must be greater than zero")]
+    InvalidQuantity,
+    #[msg("NFT is not a verified member of the collection")]
+    NotInCollection,
+    #[msg("Whitelist account is required for this listing")]
+    MissingWhitelist,
+    #[msg("Buyer is not on the whitelist")]
+    NotWhitelisted,
+    #[msg("Buyer and seller must be different accounts")]
+    SelfTrade,
+    #[msg("Payment mint does not match the listing or bid")]
+    PaymentMintMismatch,
+    #[msg("Bid is below the listing's reserve price")]
+    ReserveNotMet,
+    #[msg("Bid is not for the listed NFT")]
+    BidListingMismatch,
+    #[msg("Listing does not have enough units left")]
+    InsufficientQuantity,
+    #[msg("Reserve price can only be lowered")]
+    ReservePriceIncrease,
+    #[msg("Collection stats account does not match the NFT's collection")]
+    InvalidCollectionStats,
+    #[msg("Fee account is not the configured fee recipient")]
+    InvalidFeeAccount,
+    #[msg("Offer is not in a state that allows this action")]
+    InvalidOfferState,
+    #[msg("Buyer has reached the listing's purchase limit")]
+    PurchaseLimitExceeded,
+    #[msg("Listing is still active")]
+    ListingStillActive,
+    #[msg("Listing is not live yet")]
+    ListingNotYetLive,
+    #[msg("Listing must go live before it expires")]
+    InvalidGoLiveTime,
+    #[msg("Seller does not own the NFT")]
+    SellerDoesNotOwnNft,
+    #[msg("Second highest bid does not match a live escrowed bid")]
+    InvalidSecondBid,
+    #[msg("No authority transfer is pending")]
+    NoPendingAuthority,
+    #[msg("Signer is not the pending authority")]
+    InvalidPendingAuthority,
+    #[msg("Listing has not expired")]
+    ListingNotExpired,
+    #[msg("Crank batch accounts are invalid")]
+    InvalidCrankBatch,
+    #[msg("Account version is not supported")]
+    UnsupportedVersion,
+    #[msg("Royalty escrow account does not match the creator and sale")]
+    InvalidRoyaltyEscrow,
+    #[msg("Royalties have already been claimed")]
+    RoyaltiesAlreadyClaimed,
+    #[msg("Seller does not hold enough NFT units")]
+    InsufficientNftBalance,
+    #[msg("A verified creator's payment account is missing")]
+    MissingCreatorAccount,
+    #[msg("Creator payment account is not the creator's associated token account")]
+    CreatorAccountMismatch,
+    #[msg("Listing is reserved for another buyer")]
+    UnauthorizedBuyer,
+    #[msg("Bid does not beat the best bid by the minimum increment")]
+    BidIncrementTooSmall,
+    #[msg("Split listing must have between 1 and 5 co-owners")]
+    InvalidCoOwnerCount,
+    #[msg("Co-owner shares must sum to 100%")]
+    InvalidCoOwnerShares,
+    #[msg("Co-owner payment account is missing or not the co-owner's associated token account")]
+    CoOwnerAccountMismatch,
+    #[msg("Transaction landed after its deadline")]
+    TransactionExpired,
+    #[msg("Cancel batch accounts are invalid")]
+    InvalidCancelBatch,
+    #[msg("Batch exceeds the maximum number of listings")]
+    BatchTooLarge,
+    #[msg("Bid's token delegation was revoked or replaced")]
+    DelegationRevoked,
+    #[msg("Floor sweep accounts are invalid")]
+    InvalidSweep,
+    #[msg("Floor sweep listings must be sorted by ascending price")]
+    SweepNotSorted,
+    #[msg("Auction config account or parameters are invalid")]
+    InvalidAuctionConfig,
+    #[msg("Only the collection's update authority or the marketplace authority can configure its auctions")]
+    UnauthorizedAuctionConfig,
+    #[msg("Auction runs longer than its collection allows")]
+    AuctionTooLong,
+    #[msg("Loan amount, duration or interest rate is invalid")]
+    InvalidLoanTerms,
+    #[msg("Listing already has an active loan")]
+    LoanAlreadyActive,
+    #[msg("Loan is not active")]
+    LoanNotActive,
+    #[msg("Loan is not past its due date")]
+    LoanNotDue,
+    #[msg("Vault still holds tokens")]
+    VaultNotEmpty,
+    #[msg("Bulk bid accounts or parameters are invalid")]
+    InvalidBulkBid,
+    #[msg("Only the holder the offer was made to can answer it")]
+    NotOfferTarget,
+    #[msg("Listing is not active")]
+    ListingNotActive,
+    #[msg("Metadata declares more verified creators than a sale can pay")]
+    TooManyCreators,
+    #[msg("Account does not match the recovery target")]
+    InvalidRecoveryTarget,
+    #[msg("The listing or bid owning this account still exists")]
+    EscrowStillOwned,
+    #[msg("Royalty override must not exceed 10000 basis points")]
+    InvalidRoyaltyOverride,
+    #[msg("Collection policy is invalid or does not cover this NFT")]
+    InvalidCollectionPolicy,
+    #[msg("Only the collection's update authority can set its policy")]
+    UnauthorizedCollectionPolicy,
+    #[msg("Price is outside the collection's allowed range")]
+    PriceOutOfPolicyRange,
+    #[msg("The listing for this vault is still open")]
+    ListingStillOpen,
+    #[msg("Fee tier must be above the trader's current tier and at most 3")]
+    InvalidFeeTier,
+    #[msg("NFT mint does not match the bid")]
+    MintMismatch,
+    #[msg("Mint must have zero decimals and a supply of one")]
+    NotAnNft,
+    #[msg("Listing metadata exceeds 200 bytes")]
+    MetadataTooLong,
+    #[msg("Accounts for auto-accepting the bid are missing or invalid")]
+    InvalidAutoAcceptAccounts,
+    #[msg("Default royalty must not exceed 1500 basis points")]
+    InvalidDefaultRoyalty,
+    #[msg("Expiry grace period cannot be negative")]
+    InvalidExpiryGrace,
+    #[msg("Withdrawal would leave the fee vault below its rent-exempt balance")]
+    InsufficientFeeBalance,
+    #[msg("Fees can only be withdrawn to the configured fee recipient")]
+    InvalidFeeRecipient,
+    #[msg("Minimum bid increment must not exceed 10000 basis points")]
+    InvalidMinIncrement,
 }
-}
\ No newline at end of file
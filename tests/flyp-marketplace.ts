@@ -1,16 +1,4450 @@
 import * as anchor from "@coral-xyz/anchor";
-import { Program } from "@coral-xyz/anchor";
-import { FlypMarketplace } from "../target/types/flyp_marketplace";
+import { assert } from "chai";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import {
+  NATIVE_MINT,
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  getAssociatedTokenAddressSync,
+} from "@solana/spl-token";
+import {
+  BidFixture,
+  Buyer,
+  Env,
+  ListingFixture,
+  Market,
+  TOKEN_METADATA_PROGRAM_ID,
+  U64_MAX,
+  accountExists,
+  bidPda,
+  nextBidNonce,
+  cancelBid,
+  configPda,
+  feeVaultPda,
+  createAta,
+  createListing,
+  createMint,
+  createTransferFeeMint,
+  executeSale,
+  executeSaleMethod,
+  expectError,
+  fund,
+  initializeMarketplace,
+  lamports,
+  lookupTable,
+  merkleTree,
+  mintTo,
+  newBuyer,
+  newUser,
+  now,
+  pda,
+  placeBid,
+  setMetadata,
+  tokenBalance,
+  setup,
+  statsPda,
+  tokenAccount,
+  vaultPda,
+  warpTo,
+} from "./helpers";
 
 describe("flyp-marketplace", () => {
-  // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.AnchorProvider.env());
+  let env: Env;
+  let market: Market;
 
-  const program = anchor.workspace.FlypMarketplace as Program<FlypMarketplace>;
+  beforeEach(async () => {
+    env = await setup();
+    market = await initializeMarketplace(env);
+  });
 
   it("Is initialized!", async () => {
-    // Add your test here.
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+    const config = await env.program.account.marketplaceConfig.fetch(configPda(env.program));
+    assert.equal(config.feeBps.toNumber(), 250);
+    assert.ok(config.authority.equals(env.payer.publicKey));
+  });
+
+  describe("expiry", () => {
+    it("allows a purchase at exactly the listing expiry", async () => {
+      const expiry = (await now(env)) + BigInt(60);
+      const fixture = await createListing(env, market, { expiry });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await warpTo(env, expiry);
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(1));
+    });
+
+    it("rejects a purchase one second after the listing expiry", async () => {
+      const expiry = (await now(env)) + BigInt(60);
+      const fixture = await createListing(env, market, { expiry });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await warpTo(env, expiry + BigInt(1));
+      await expectError(executeSale(env, market, fixture, buyer), "ListingExpired");
+    });
+
+    it("never expires a listing with zero expiry", async () => {
+      const fixture = await createListing(env, market, { expiry: 0 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await warpTo(env, (await now(env)) + BigInt(365 * 24 * 60 * 60));
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(1));
+    });
+
+    it("rejects a bid whose expiry is already in the past", async () => {
+      const bidder = await newUser(env);
+      const nftMint = await createMint(env, 0);
+      const bidderPaymentAccount = await mintTo(env, market.paymentMint, bidder.publicKey, 1_000_000);
+      const expiry = (await now(env)) - BigInt(1);
+
+      await expectError(
+        env.program.methods
+          .placeBid(
+            new anchor.BN(1_000_000),
+            new anchor.BN(expiry.toString()),
+            new anchor.BN(((await now(env)) + BigInt(60)).toString())
+          )
+          .accountsPartial({
+            bidder: bidder.publicKey,
+            nftMint,
+            bid: bidPda(env.program, bidder.publicKey, nftMint),
+            paymentMint: market.paymentMint,
+            bidderPaymentAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([bidder])
+          .rpc(),
+        "BidExpired"
+      );
+    });
+
+    it("rejects a bid transaction that lands after its deadline", async () => {
+      const bidder = await newUser(env);
+      const nftMint = await createMint(env, 0);
+      const deadline = (await now(env)) - BigInt(1);
+
+      await expectError(
+        placeBid(env, market, nftMint, 1_000_000, 10_000_000, bidder, deadline),
+        "TransactionExpired"
+      );
+
+      const bid = bidPda(env.program, bidder.publicKey, nftMint);
+      assert.isFalse(await accountExists(env, bid));
+      assert.equal(
+        await tokenBalance(env, getAssociatedTokenAddressSync(market.paymentMint, bidder.publicKey)),
+        BigInt(10_000_000)
+      );
+    });
+  });
+
+  describe("metadata", () => {
+    it("rejects metadata that belongs to a different mint", async () => {
+      const fixture = await createListing(env, market);
+      const other = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, { accounts: { metadata: other.metadata } }),
+        "InvalidMetadata"
+      );
+    });
+  });
+
+  describe("update_listing_quantity", () => {
+    const addQuantity = (fixture: ListingFixture, additionalQuantity: number) =>
+      env.program.methods
+        .updateListingQuantity(new anchor.BN(additionalQuantity))
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          sellerNftAccount: fixture.sellerNftAccount,
+          vaultNftAccount: fixture.vault,
+          tokenProgram: fixture.tokenProgram,
+        })
+        .signers([fixture.seller])
+        .rpc();
+
+    it("adds a newly minted unit to a single-edition listing", async () => {
+      const fixture = await createListing(env, market);
+      await mintTo(env, fixture.nftMint, fixture.seller.publicKey, 1);
+
+      await addQuantity(fixture, 1);
+
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.equal(listing.quantity.toNumber(), 2);
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(2));
+      assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(0));
+    });
+
+    it("tops up a multi-edition listing", async () => {
+      const fixture = await createListing(env, market, { quantity: 5 });
+      await mintTo(env, fixture.nftMint, fixture.seller.publicKey, 3);
+
+      await addQuantity(fixture, 3);
+
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.equal(listing.quantity.toNumber(), 8);
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(8));
+    });
+
+    it("rejects more units than the seller holds", async () => {
+      const fixture = await createListing(env, market, { quantity: 5 });
+      await mintTo(env, fixture.nftMint, fixture.seller.publicKey, 1);
+
+      await expectError(addQuantity(fixture, 2), "InsufficientNftBalance");
+      await expectError(addQuantity(fixture, 0), "InvalidQuantity");
+    });
+  });
+
+  describe("update_listing_price", () => {
+    it("reprices a listing without touching the vault", async () => {
+      const fixture = await createListing(env, market, { price: 1_000_000 });
+
+      await env.program.methods
+        .updateListingPrice(new anchor.BN(2_000_000))
+        .accountsPartial({ seller: fixture.seller.publicKey, listing: fixture.listing })
+        .signers([fixture.seller])
+        .rpc();
+
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.equal(listing.price.toNumber(), 2_000_000);
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(1));
+    });
+
+    it("rejects a zero price", async () => {
+      const fixture = await createListing(env, market);
+
+      await expectError(
+        env.program.methods
+          .updateListingPrice(new anchor.BN(0))
+          .accountsPartial({ seller: fixture.seller.publicKey, listing: fixture.listing })
+          .signers([fixture.seller])
+          .rpc(),
+        "InvalidPrice"
+      );
+    });
+
+    it("aborts a sale when the price was raised above the buyer's max", async () => {
+      const fixture = await createListing(env, market, { price: 1_000_000 });
+      const buyer = await newBuyer(env, market, fixture.nftMint, 5_000_000);
+
+      await env.program.methods
+        .updateListingPrice(new anchor.BN(2_000_000))
+        .accountsPartial({ seller: fixture.seller.publicKey, listing: fixture.listing })
+        .signers([fixture.seller])
+        .rpc();
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, { maxPrice: BigInt(1_000_000) }),
+        "PriceExceedsMax"
+      );
+      assert.equal(await tokenBalance(env, buyer.buyerPaymentAccount), BigInt(5_000_000));
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(1));
+    });
+  });
+
+  describe("batch_cancel_listings", () => {
+    it("cancels several listings atomically and returns every NFT", async () => {
+      const first = await createListing(env, market);
+      const fixtures = [
+        first,
+        await createListing(env, market, { seller: first.seller }),
+        await createListing(env, market, { seller: first.seller }),
+      ];
+
+      await env.program.methods
+        .batchCancelListings()
+        .accountsPartial({ seller: first.seller.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+        .remainingAccounts(
+          fixtures.flatMap((f) =>
+            [f.listing, f.nftMint, f.vault, f.sellerNftAccount].map((pubkey) => ({
+              pubkey,
+              isSigner: false,
+              isWritable: true,
+            }))
+          )
+        )
+        .signers([first.seller])
+        .rpc();
+
+      for (const fixture of fixtures) {
+        assert.isFalse(await accountExists(env, fixture.listing));
+        assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(1));
+        assert.isFalse(await accountExists(env, fixture.vault));
+      }
+    });
+
+    it("rejects a listing that belongs to another seller", async () => {
+      const mine = await createListing(env, market);
+      const theirs = await createListing(env, market);
+
+      await expectError(
+        env.program.methods
+          .batchCancelListings()
+          .accountsPartial({ seller: mine.seller.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+          .remainingAccounts(
+            [mine, theirs].flatMap((f) =>
+              [f.listing, f.nftMint, f.vault, f.sellerNftAccount].map((pubkey) => ({
+                pubkey,
+                isSigner: false,
+                isWritable: true,
+              }))
+            )
+          )
+          .signers([mine.seller])
+          .rpc(),
+        "InvalidCancelBatch"
+      );
+    });
+  });
+
+  describe("authority transfer", () => {
+    it("hands over the authority once the nominee accepts", async () => {
+      const config = configPda(env.program);
+      const nominee = await newUser(env);
+
+      await expectError(
+        env.program.methods
+          .acceptAuthorityTransfer()
+          .accountsPartial({ newAuthority: nominee.publicKey, config })
+          .signers([nominee])
+          .rpc(),
+        "NoPendingAuthority"
+      );
+
+      await env.program.methods
+        .proposeAuthorityTransfer(nominee.publicKey)
+        .accountsPartial({ authority: env.payer.publicKey, config })
+        .rpc();
+
+      const outsider = await newUser(env);
+      await expectError(
+        env.program.methods
+          .acceptAuthorityTransfer()
+          .accountsPartial({ newAuthority: outsider.publicKey, config })
+          .signers([outsider])
+          .rpc(),
+        "InvalidPendingAuthority"
+      );
+
+      await env.program.methods
+        .acceptAuthorityTransfer()
+        .accountsPartial({ newAuthority: nominee.publicKey, config })
+        .signers([nominee])
+        .rpc();
+
+      const account = await env.program.account.marketplaceConfig.fetch(config);
+      assert.ok(account.authority.equals(nominee.publicKey));
+      assert.isNull(account.pendingAuthority);
+
+      await expectError(
+        env.program.methods
+          .setPaused(true)
+          .accountsPartial({ authority: env.payer.publicKey, config })
+          .rpc(),
+        "ConstraintHasOne"
+      );
+    });
+  });
+
+  describe("update_config", () => {
+    it("applies the configured fee to the seller payout", async () => {
+      await env.program.methods
+        .updateConfig(new anchor.BN(500), env.payer.publicKey)
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+
+      const fixture = await createListing(env, market, { price: 1_000_000 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      await executeSale(env, market, fixture, buyer);
+
+      // A flat fee is charged to the seller alone
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(950_000));
+      assert.equal(await tokenBalance(env, buyer.buyerPaymentAccount), BigInt(999_000_000));
+      assert.equal(await tokenBalance(env, market.feeAccount), BigInt(50_000));
+    });
+
+    it("emits the old and new fee schedule", async () => {
+      const newRecipient = (await newUser(env)).publicKey;
+      const { events } = await env.program.methods
+        .updateConfig(new anchor.BN(500), newRecipient)
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .simulate();
+      const update = events.find((event) => event.name === "configUpdated").data;
+
+      assert.ok(update.authority.equals(env.payer.publicKey));
+      assert.equal(update.oldFeeBps.toNumber(), 250);
+      assert.equal(update.newFeeBps.toNumber(), 500);
+      assert.ok(update.oldFeeRecipient.equals(env.payer.publicKey));
+      assert.ok(update.newFeeRecipient.equals(newRecipient));
+    });
+
+    it("rejects fees above the cap", async () => {
+      await expectError(
+        env.program.methods
+          .updateConfig(new anchor.BN(1_001), env.payer.publicKey)
+          .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+          .rpc(),
+        "FeeTooHigh"
+      );
+    });
+  });
+
+  describe("execute_sale", () => {
+    it("closes a single-item listing and its vault and returns their rent to the seller", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const listingRent = await lamports(env, fixture.listing);
+      const vaultRent = await lamports(env, fixture.vault);
+      const sellerLamports = await lamports(env, fixture.seller.publicKey);
+
+      await executeSale(env, market, fixture, buyer);
+
+      assert.isFalse(await accountExists(env, fixture.listing));
+      assert.isFalse(await accountExists(env, fixture.vault));
+      assert.equal(
+        await lamports(env, fixture.seller.publicKey),
+        sellerLamports + listingRent + vaultRent
+      );
+    });
+
+    it("rejects a seller payment account the seller doesn't own", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, {
+          accounts: { sellerPaymentAccount: buyer.buyerPaymentAccount },
+        }),
+        "ConstraintTokenOwner"
+      );
+    });
+  });
+
+  describe("execute_sale_pnft", () => {
+    const AUTH_RULES_PROGRAM_ID = new PublicKey("auth9SigNpDKz4sJJ1DfCTuZrZNSAgh9sFD3rboVmgg");
+
+    function executeSalePnft(
+      fixture: ListingFixture,
+      buyer: Buyer,
+      accounts: Record<string, PublicKey> = {}
+    ) {
+      return env.program.methods
+        .executeSalePnft(new anchor.BN(U64_MAX.toString()), new anchor.BN(0), null)
+        .accountsPartial({
+          buyer: buyer.buyer.publicKey,
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          config: configPda(env.program),
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          buyerNftAccount: buyer.buyerNftAccount,
+          paymentMint: market.paymentMint,
+          buyerPaymentAccount: buyer.buyerPaymentAccount,
+          sellerPaymentAccount: fixture.sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          secondBidderAccount: buyer.secondBidderAccount,
+          secondBid: null,
+          metadata: fixture.metadata,
+          edition: PublicKey.unique(),
+          tokenRecord: PublicKey.unique(),
+          destinationTokenRecord: PublicKey.unique(),
+          authorizationRules: PublicKey.unique(),
+          authorizationRulesProgram: AUTH_RULES_PROGRAM_ID,
+          mplTokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          collectionMint: fixture.collectionMint,
+          marketStats: null,
+          whitelist: null,
+          tokenProgram: fixture.tokenProgram,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+          ...accounts,
+        })
+        .signers([buyer.buyer])
+        .rpc();
+    }
+
+    it("only sells one-of-one listings", async () => {
+      const fixture = await createListing(env, market, { quantity: 2 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(executeSalePnft(fixture, buyer), "InvalidQuantity");
+    });
+
+    it("rejects a token metadata program other than Metaplex's", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(
+        executeSalePnft(fixture, buyer, { mplTokenMetadataProgram: PublicKey.unique() }),
+        "ConstraintAddress"
+      );
+    });
+
+    it("rejects a seller payment account the seller doesn't own", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(
+        executeSalePnft(fixture, buyer, { sellerPaymentAccount: buyer.buyerPaymentAccount }),
+        "ConstraintTokenOwner"
+      );
+    });
+  });
+
+  describe("second bidder fee", () => {
+    it("pays no second bidder fee without an escrowed bid", async () => {
+      const fixture = await createListing(env, market, { price: 1_000_000 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      await executeSale(env, market, fixture, buyer, { secondHighestBid: 1_000_000 });
+
+      assert.equal(await tokenBalance(env, buyer.secondBidderAccount), BigInt(0));
+      assert.equal(await tokenBalance(env, market.feeAccount), BigInt(25_000));
+    });
+
+    it("rejects a second highest bid that differs from the escrowed bid", async () => {
+      const fixture = await createListing(env, market, { price: 1_000_000 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const secondBid = await placeBid(env, market, fixture.nftMint, 1_000);
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, {
+          secondHighestBid: 1_000_000,
+          accounts: { secondBid: secondBid.bid, secondBidderAccount: secondBid.bidderPaymentAccount },
+        }),
+        "InvalidSecondBid"
+      );
+    });
+  });
+
+  describe("second bidder rebate", () => {
+    async function saleWithSmallSecondBid() {
+      const fixture = await createListing(env, market, { price: 1_000_000 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const secondBid = await placeBid(env, market, fixture.nftMint, 1_000);
+      await executeSale(env, market, fixture, buyer, {
+        secondHighestBid: 1_000,
+        accounts: { secondBid: secondBid.bid, secondBidderAccount: secondBid.bidderPaymentAccount },
+      });
+      return { fixture, secondBid };
+    }
+
+    it("folds the unused second bidder fee into the marketplace fee by default", async () => {
+      const { fixture, secondBid } = await saleWithSmallSecondBid();
+
+      assert.equal(await tokenBalance(env, secondBid.bidderPaymentAccount), BigInt(10_000_000));
+      assert.equal(await tokenBalance(env, market.feeAccount), BigInt(24_000));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+    });
+
+    it("rebates the unused second bidder fee to the seller when enabled", async () => {
+      await env.program.methods
+        .setSecondBidderRebate(true)
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+
+      const { fixture, secondBid } = await saleWithSmallSecondBid();
+
+      assert.equal(await tokenBalance(env, secondBid.bidderPaymentAccount), BigInt(10_000_000));
+      assert.equal(await tokenBalance(env, market.feeAccount), BigInt(22_500));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(976_500));
+    });
+
+    it("splits the fee on a near u64::MAX sale without overflowing", async () => {
+      // A fresh payment mint, so the buyer can hold u64::MAX without overflowing the shared mint's supply
+      const paymentMint = await createMint(env, 6);
+      const bigMarket = {
+        paymentMint,
+        feeAccount: await createAta(env, paymentMint, feeVaultPda(env.program)),
+      };
+
+      const fixture = await createListing(env, bigMarket, { price: U64_MAX });
+      const buyer = await newBuyer(env, bigMarket, fixture.nftMint, U64_MAX);
+      await executeSale(env, bigMarket, fixture, buyer);
+
+      // The unused second bidder share is folded into the marketplace fee
+      assert.equal(await tokenBalance(env, bigMarket.feeAccount), BigInt("461168601842738790"));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt("17985575471866812825"));
+    });
+  });
+
+  describe("fee tiers", () => {
+    // Tiers lower the buyer's leg, which only exists once maker and taker rates are set
+    beforeEach(async () => {
+      await env.program.methods
+        .setMakerTakerFees(new anchor.BN(250), new anchor.BN(250))
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+    });
+
+    async function openFeeTier(trader: Keypair) {
+      const feeTier = pda(env.program, Buffer.from("fee_tier"), trader.publicKey.toBuffer());
+      await env.program.methods
+        .initializeFeeTier()
+        .accountsPartial({ trader: trader.publicKey, feeTier })
+        .signers([trader])
+        .rpc();
+      return feeTier;
+    }
+
+    async function buySol(fixture: ListingFixture, buyer: Keypair, price: number) {
+      await env.program.methods
+        .executeSaleSol(new anchor.BN(price), new anchor.BN(0), null, new anchor.BN(1))
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          buyerNftAccount: getAssociatedTokenAddressSync(fixture.nftMint, buyer.publicKey),
+          secondBidderAccount: (await newUser(env)).publicKey,
+          secondBid: null,
+          metadata: fixture.metadata,
+          collectionMint: fixture.nftMint,
+          whitelist: null,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+    }
+
+    it("lowers the buyer's fee once their SOL volume crosses a tier threshold", async () => {
+      const fixture = await createListing(env, market, { price: 10_000_000_001, paymentMint: null });
+      const buyer = await newUser(env);
+      await fund(env, buyer.publicKey, 20_000_000_000);
+      const feeTier = await openFeeTier(buyer);
+      const feeVault = feeVaultPda(env.program);
+
+      const feeBefore = await lamports(env, feeVault);
+      await buySol(fixture, buyer, 10_000_000_001);
+      // The first purchase is still charged at the tier 0 rate on both legs
+      assert.equal((await lamports(env, feeVault)) - feeBefore, BigInt(500_000_000));
+      const tier = await env.program.account.feeTier.fetch(feeTier);
+      assert.equal(tier.tier, 1);
+      assert.equal(tier.volumeTraded.toString(), "10000000001");
+
+      // Tier 1 only lowers the buyer's own leg to 2%, the seller still pays the maker rate
+      const next = await createListing(env, market, { price: 1_000_000_000, paymentMint: null });
+      const nextFeeBefore = await lamports(env, feeVault);
+      await buySol(next, buyer, 1_000_000_000);
+      assert.equal((await lamports(env, feeVault)) - nextFeeBefore, BigInt(45_000_000));
+    });
+
+    it("doesn't count purchases paid in other mints toward the volume", async () => {
+      const fixture = await createListing(env, market, { price: 10_000_000_001 });
+      const buyer = await newBuyer(env, market, fixture.nftMint, 20_000_000_000);
+      const feeTier = await openFeeTier(buyer.buyer);
+
+      await executeSale(env, market, fixture, buyer);
+      const tier = await env.program.account.feeTier.fetch(feeTier);
+      assert.equal(tier.tier, 0);
+      assert.equal(tier.volumeTraded.toString(), "0");
+    });
+
+    it("lets the authority promote a trader but not demote them", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const feeTier = await openFeeTier(buyer.buyer);
+      const promote = (tier: number) =>
+        env.program.methods
+          .promoteFeeTier(tier)
+          .accountsPartial({ authority: env.payer.publicKey, feeTier })
+          .rpc();
+
+      await promote(3);
+      await expectError(promote(2), "InvalidFeeTier");
+
+      await executeSale(env, market, fixture, buyer);
+      // Tier 3 drops the buyer's leg to 1%, the seller's leg stays at 2.5%
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+      assert.equal(await tokenBalance(env, buyer.buyerPaymentAccount), BigInt(998_990_000));
+    });
+  });
+
+  describe("maker and taker fees", () => {
+    beforeEach(async () => {
+      await env.program.methods
+        .setMakerTakerFees(new anchor.BN(100), new anchor.BN(400))
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+    });
+
+    it("charges the seller of a filled listing the maker rate and the buyer the taker rate", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(990_000));
+      assert.equal(await tokenBalance(env, buyer.buyerPaymentAccount), BigInt(998_960_000));
+      assert.equal(await tokenBalance(env, market.feeAccount), BigInt(50_000));
+    });
+
+    it("charges a seller who accepts a resting bid the taker rate and the bidder the maker rate", async () => {
+      const seller = await newUser(env);
+      const nftMint = await createMint(env);
+      const sellerNftAccount = await mintTo(env, nftMint, seller.publicKey, 1);
+      const sellerPaymentAccount = await createAta(env, market.paymentMint, seller.publicKey);
+      const fixture = await placeBid(env, market, nftMint, 1_000_000);
+
+      await env.program.methods
+        .acceptBid(new anchor.BN(0))
+        .accountsPartial({
+          seller: seller.publicKey,
+          bid: fixture.bid,
+          nftMint,
+          sellerNftAccount,
+          bidderNftAccount: getAssociatedTokenAddressSync(nftMint, fixture.bidder.publicKey),
+          paymentMint: market.paymentMint,
+          escrowPaymentAccount: fixture.escrowPaymentAccount,
+          sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          secondBidderAccount: await createAta(env, market.paymentMint, (await newUser(env)).publicKey),
+          metadata: setMetadata(env, nftMint),
+          collectionMint: nftMint,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc();
+
+      // The escrowed 1M covers the bidder's maker fee: 990_099 sale price plus 9_901
+      assert.equal(await tokenBalance(env, sellerPaymentAccount), BigInt(950_496));
+      assert.equal(await tokenBalance(env, fixture.escrowPaymentAccount), BigInt(0));
+      assert.equal(await tokenBalance(env, market.feeAccount), BigInt(49_504));
+    });
+
+    it("keeps the maker and taker rates when update_config changes the flat fee", async () => {
+      await env.program.methods
+        .updateConfig(new anchor.BN(500), env.payer.publicKey)
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(990_000));
+      assert.equal(await tokenBalance(env, buyer.buyerPaymentAccount), BigInt(998_960_000));
+    });
+
+    it("goes back to a seller-only fee when a flat fee is set", async () => {
+      await env.program.methods
+        .updateMarketplaceFee(new anchor.BN(250))
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+      assert.equal(await tokenBalance(env, buyer.buyerPaymentAccount), BigInt(999_000_000));
+    });
+  });
+
+  describe("floor sweep", () => {
+    function floorSweep(collection: PublicKey, fixtures: ListingFixture[], buyer: Buyer, maxPrices?: number[]) {
+      const method = maxPrices
+        ? env.program.methods.sweepListings(maxPrices.map((price) => new anchor.BN(price)))
+        : env.program.methods.floorSweep(fixtures.length, new anchor.BN(U64_MAX.toString()));
+      return method
+        .accountsPartial({
+          buyer: buyer.buyer.publicKey,
+          collectionMint: collection,
+          collectionStats: statsPda(env.program, collection),
+          paymentMint: market.paymentMint,
+          buyerPaymentAccount: buyer.buyerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          fixtures.flatMap((f) =>
+            [
+              f.listing,
+              f.seller.publicKey,
+              f.nftMint,
+              f.vault,
+              getAssociatedTokenAddressSync(f.nftMint, buyer.buyer.publicKey),
+              f.sellerPaymentAccount,
+              f.metadata,
+              pda(env.program, Buffer.from("buyer_record"), f.listing.toBuffer(), buyer.buyer.publicKey.toBuffer()),
+            ].map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+          )
+        )
+        .signers([buyer.buyer])
+        .rpc();
+    }
+
+    it("buys the cheapest listings in a collection in one transaction", async () => {
+      const collection = await createMint(env);
+      const fixtures = [];
+      for (const price of [1_000_000, 1_100_000, 1_200_000]) {
+        fixtures.push(await createListing(env, market, { price, collection }));
+      }
+      const buyer = await newBuyer(env, market, fixtures[0].nftMint);
+
+      await floorSweep(collection, fixtures, buyer);
+
+      for (const fixture of fixtures) {
+        const nftAccount = getAssociatedTokenAddressSync(fixture.nftMint, buyer.buyer.publicKey);
+        assert.equal(await tokenBalance(env, nftAccount), BigInt(1));
+        assert.isFalse(await accountExists(env, fixture.listing));
+      }
+      assert.equal(await tokenBalance(env, fixtures[2].sellerPaymentAccount), BigInt(1_170_000));
+      assert.equal(await tokenBalance(env, buyer.buyerPaymentAccount), BigInt(996_700_000));
+      const stats = await env.program.account.collectionStats.fetch(statsPda(env.program, collection));
+      assert.equal(stats.saleCount.toNumber(), 3);
+    });
+
+    it("sweeps listings under a price cap for each one", async () => {
+      const collection = await createMint(env);
+      const fixtures = [];
+      for (const price of [1_000_000, 1_100_000, 1_200_000]) {
+        fixtures.push(await createListing(env, market, { price, collection }));
+      }
+      const buyer = await newBuyer(env, market, fixtures[0].nftMint);
+
+      await floorSweep(collection, fixtures, buyer, [1_000_000, 1_100_000, 1_200_000]);
+
+      for (const fixture of fixtures) {
+        const nftAccount = getAssociatedTokenAddressSync(fixture.nftMint, buyer.buyer.publicKey);
+        assert.equal(await tokenBalance(env, nftAccount), BigInt(1));
+      }
+    });
+
+    it("reverts the sweep when one listing is above its cap", async () => {
+      const collection = await createMint(env);
+      const cheap = await createListing(env, market, { price: 1_000_000, collection });
+      const pricey = await createListing(env, market, { price: 1_500_000, collection });
+      const buyer = await newBuyer(env, market, cheap.nftMint);
+
+      await expectError(
+        floorSweep(collection, [cheap, pricey], buyer, [1_000_000, 1_400_000]),
+        "PriceExceedsMax"
+      );
+      assert.isTrue(await accountExists(env, cheap.listing));
+    });
+
+    it("counts swept units against the per-buyer limit", async () => {
+      const collection = await createMint(env);
+      const fixture = await createListing(env, market, { collection, quantity: 3, maxQuantityPerBuyer: 1 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(floorSweep(collection, [fixture, fixture], buyer), "PurchaseLimitExceeded");
+      await floorSweep(collection, [fixture], buyer);
+
+      const record = await env.program.account.buyerPurchaseRecord.fetch(
+        pda(env.program, Buffer.from("buyer_record"), fixture.listing.toBuffer(), buyer.buyer.publicKey.toBuffer())
+      );
+      assert.equal(record.purchased.toNumber(), 1);
+    });
+
+    it("rejects listings that are not sorted by price", async () => {
+      const collection = await createMint(env);
+      const expensive = await createListing(env, market, { price: 2_000_000, collection });
+      const cheap = await createListing(env, market, { price: 1_000_000, collection });
+      const buyer = await newBuyer(env, market, cheap.nftMint);
+
+      await expectError(floorSweep(collection, [expensive, cheap], buyer), "SweepNotSorted");
+    });
+
+    it("rejects an NFT from another collection", async () => {
+      const collection = await createMint(env);
+      const member = await createListing(env, market, { collection });
+      const outsider = await createListing(env, market, { price: 2_000_000 });
+      const buyer = await newBuyer(env, market, member.nftMint);
+
+      await expectError(floorSweep(collection, [member, outsider], buyer), "NotInCollection");
+    });
+  });
+
+  describe("bundle listings", () => {
+    const bundleId = new anchor.BN(1);
+
+    async function createBundle(size = 3, expiry = BigInt(0)) {
+      const seller = await newUser(env);
+      const bundleListing = pda(
+        env.program,
+        Buffer.from("bundle_listing"),
+        seller.publicKey.toBuffer(),
+        bundleId.toArrayLike(Buffer, "le", 8)
+      );
+
+      const mints: PublicKey[] = [];
+      const createAccounts = [];
+      for (let i = 0; i < size; i++) {
+        const nftMint = await createMint(env, 0);
+        const sellerNftAccount = await mintTo(env, nftMint, seller.publicKey, 1);
+        mints.push(nftMint);
+        createAccounts.push(
+          { pubkey: nftMint, isSigner: false, isWritable: false },
+          { pubkey: sellerNftAccount, isSigner: false, isWritable: true },
+          {
+            pubkey: getAssociatedTokenAddressSync(nftMint, bundleListing, true),
+            isSigner: false,
+            isWritable: true,
+          }
+        );
+      }
+
+      await env.program.methods
+        .createBundleListing(
+          bundleId,
+          new anchor.BN(3_000_000),
+          new anchor.BN(expiry.toString()),
+          { mandatory: {} } as any
+        )
+        .accountsPartial({
+          seller: seller.publicKey,
+          bundleListing,
+          paymentMint: market.paymentMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(createAccounts)
+        .signers([seller])
+        .rpc();
+
+      return { seller, bundleListing, mints };
+    }
+
+    async function buyBundle(
+      bundle: Awaited<ReturnType<typeof createBundle>>,
+      buyer: Buyer,
+      paymentMint = market.paymentMint,
+      sellerPaymentAccount?: PublicKey
+    ) {
+      const saleAccounts = [];
+      for (const nftMint of bundle.mints) {
+        saleAccounts.push(
+          { pubkey: nftMint, isSigner: false, isWritable: false },
+          {
+            pubkey: getAssociatedTokenAddressSync(nftMint, bundle.bundleListing, true),
+            isSigner: false,
+            isWritable: true,
+          },
+          {
+            pubkey: await createAta(env, nftMint, buyer.buyer.publicKey),
+            isSigner: false,
+            isWritable: true,
+          },
+          { pubkey: setMetadata(env, nftMint), isSigner: false, isWritable: false }
+        );
+      }
+
+      return env.program.methods
+        .executeBundleSale()
+        .accountsPartial({
+          buyer: buyer.buyer.publicKey,
+          seller: bundle.seller.publicKey,
+          bundleListing: bundle.bundleListing,
+          config: configPda(env.program),
+          paymentMint,
+          buyerPaymentAccount: buyer.buyerPaymentAccount,
+          sellerPaymentAccount:
+            sellerPaymentAccount ?? (await createAta(env, paymentMint, bundle.seller.publicKey)),
+          marketplaceFeeAccount: market.feeAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(saleAccounts)
+        .signers([buyer.buyer])
+        .rpc();
+    }
+
+    it("sells every NFT in a bundle atomically", async () => {
+      const bundle = await createBundle();
+      const buyer = await newBuyer(env, market, bundle.mints[0]);
+
+      await buyBundle(bundle, buyer);
+
+      for (const nftMint of bundle.mints) {
+        const ata = getAssociatedTokenAddressSync(nftMint, buyer.buyer.publicKey);
+        assert.equal(await tokenBalance(env, ata), BigInt(1));
+      }
+      const sellerPaymentAccount = getAssociatedTokenAddressSync(market.paymentMint, bundle.seller.publicKey);
+      assert.equal(await tokenBalance(env, sellerPaymentAccount), BigInt(2_925_000));
+    });
+
+    it("rejects a bundle that has already expired", async () => {
+      await expectError(createBundle(3, (await now(env)) - BigInt(1)), "ListingExpired");
+    });
+
+    it("sells a bundle within the expiry grace period", async () => {
+      await env.program.methods
+        .setExpiryGrace(new anchor.BN(300))
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+      const expiry = (await now(env)) + BigInt(60);
+      const bundle = await createBundle(3, expiry);
+      const buyer = await newBuyer(env, market, bundle.mints[0]);
+
+      await warpTo(env, expiry + BigInt(300));
+      await buyBundle(bundle, buyer);
+
+      const ata = getAssociatedTokenAddressSync(bundle.mints[0], buyer.buyer.publicKey);
+      assert.equal(await tokenBalance(env, ata), BigInt(1));
+    });
+
+    it("rejects payment in a mint other than the bundle's", async () => {
+      const bundle = await createBundle();
+      const buyer = await newBuyer(env, market, bundle.mints[0]);
+      const worthlessMint = await createMint(env, 6);
+      const worthlessBuyer = {
+        ...buyer,
+        buyerPaymentAccount: await mintTo(env, worthlessMint, buyer.buyer.publicKey, 5_000_000),
+      };
+
+      await expectError(buyBundle(bundle, worthlessBuyer, worthlessMint), "ConstraintHasOne");
+    });
+
+    it("rejects a seller payment account the seller doesn't own", async () => {
+      const bundle = await createBundle();
+      const buyer = await newBuyer(env, market, bundle.mints[0]);
+      const buyerOwned = getAssociatedTokenAddressSync(market.paymentMint, buyer.buyer.publicKey);
+      await expectError(
+        buyBundle(bundle, buyer, market.paymentMint, buyerOwned),
+        "ConstraintTokenOwner"
+      );
+    });
+
+    it("returns every NFT to the seller on cancel", async () => {
+      const bundle = await createBundle();
+      const sellerNftAccounts = bundle.mints.map((nftMint) =>
+        getAssociatedTokenAddressSync(nftMint, bundle.seller.publicKey)
+      );
+
+      await env.program.methods
+        .cancelBundleListing()
+        .accountsPartial({
+          seller: bundle.seller.publicKey,
+          bundleListing: bundle.bundleListing,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          bundle.mints.flatMap((nftMint, i) => [
+            { pubkey: nftMint, isSigner: false, isWritable: false },
+            {
+              pubkey: getAssociatedTokenAddressSync(nftMint, bundle.bundleListing, true),
+              isSigner: false,
+              isWritable: true,
+            },
+            { pubkey: sellerNftAccounts[i], isSigner: false, isWritable: true },
+          ])
+        )
+        .signers([bundle.seller])
+        .rpc();
+
+      for (const sellerNftAccount of sellerNftAccounts) {
+        assert.equal(await tokenBalance(env, sellerNftAccount), BigInt(1));
+      }
+      assert.isFalse(await accountExists(env, bundle.bundleListing));
+    });
+  });
+
+  describe("split listings", () => {
+    async function createSplitListing(shares: number[]) {
+      const primarySeller = await newUser(env);
+      const coOwners = [primarySeller, ...(await Promise.all(shares.slice(1).map(() => newUser(env))))];
+      const nftMint = await createMint(env);
+      await mintTo(env, nftMint, primarySeller.publicKey, 1);
+      const metadata = setMetadata(env, nftMint);
+      const splitListing = pda(env.program, Buffer.from("split_listing"), nftMint.toBuffer());
+
+      await env.program.methods
+        .createSplitListing(
+          new anchor.BN(1_000_000),
+          new anchor.BN(0),
+          coOwners.map((c, i) => ({ owner: c.publicKey, shareBps: shares[i] }))
+        )
+        .accountsPartial({
+          primarySeller: primarySeller.publicKey,
+          splitListing,
+          nftMint,
+          paymentMint: market.paymentMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([primarySeller])
+        .rpc();
+
+      return { primarySeller, coOwners, nftMint, metadata, splitListing };
+    }
+
+    it("splits the seller proceeds across co-owners by share", async () => {
+      const split = await createSplitListing([7_000, 3_000]);
+      const coOwnerAccounts = await Promise.all(
+        split.coOwners.map((c) => createAta(env, market.paymentMint, c.publicKey))
+      );
+      const buyer = await newBuyer(env, market, split.nftMint);
+
+      await env.program.methods
+        .executeSplitListingSale(new anchor.BN(1_000_000))
+        .accountsPartial({
+          buyer: buyer.buyer.publicKey,
+          primarySeller: split.primarySeller.publicKey,
+          splitListing: split.splitListing,
+          nftMint: split.nftMint,
+          paymentMint: market.paymentMint,
+          buyerPaymentAccount: buyer.buyerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          metadata: split.metadata,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          coOwnerAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+        )
+        .signers([buyer.buyer])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, coOwnerAccounts[0]), BigInt(682_500));
+      assert.equal(await tokenBalance(env, coOwnerAccounts[1]), BigInt(292_500));
+      assert.equal(await tokenBalance(env, market.feeAccount), BigInt(25_000));
+      assert.isFalse(await accountExists(env, split.splitListing));
+    });
+
+    it("rejects shares that do not sum to 100%", async () => {
+      await expectError(createSplitListing([7_000, 2_000]), "InvalidCoOwnerShares");
+    });
+
+    it("rejects more than five co-owners", async () => {
+      await expectError(
+        createSplitListing([2_000, 2_000, 2_000, 2_000, 1_000, 1_000]),
+        "InvalidCoOwnerCount"
+      );
+    });
+  });
+
+  describe("admin_recover", () => {
+    const recover = (fixture: BidFixture, authority = env.payer) =>
+      env.program.methods
+        .adminRecover({
+          bidEscrow: { bidder: fixture.bidder.publicKey, nftMint: fixture.nftMint, nonce: new anchor.BN(0) },
+        })
+        .accountsPartial({
+          authority: authority.publicKey,
+          mint: market.paymentMint,
+          stuckAccount: fixture.escrowPaymentAccount,
+          record: fixture.bid,
+          destination: fixture.bidderPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+    it("sweeps an orphaned bid escrow back to the bidder", async () => {
+      const fixture = await placeBid(env, market, await createMint(env), 1_000_000);
+
+      await expectError(recover(fixture), "EscrowStillOwned");
+      await expectError(recover(fixture, await newUser(env)), "ConstraintHasOne");
+
+      // Drop the bid account while leaving its escrow behind
+      env.context.setAccount(fixture.bid, {
+        lamports: 0,
+        data: Buffer.alloc(0),
+        owner: anchor.web3.SystemProgram.programId,
+        executable: false,
+      });
+      await recover(fixture);
+
+      assert.equal(await tokenBalance(env, fixture.bidderPaymentAccount), BigInt(10_000_000));
+      assert.isFalse(await accountExists(env, fixture.escrowPaymentAccount));
+    });
+  });
+
+  describe("pause", () => {
+    const setPaused = (paused: boolean) =>
+      env.program.methods
+        .setPaused(paused)
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+
+    it("blocks new trades while paused", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      await setPaused(true);
+
+      await expectError(executeSale(env, market, fixture, buyer), "MarketplacePaused");
+      await expectError(createListing(env, market), "MarketplacePaused");
+
+      await setPaused(false);
+      await executeSale(env, market, fixture, buyer);
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(1));
+    });
+
+    it("still allows cancellations while paused", async () => {
+      const fixture = await createListing(env, market);
+      await setPaused(true);
+
+      await env.program.methods
+        .cancelListing()
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          sellerNftAccount: fixture.sellerNftAccount,
+          vaultNftAccount: fixture.vault,
+          tokenProgram: fixture.tokenProgram,
+        })
+        .signers([fixture.seller])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(1));
+    });
+
+    it("blocks new bids but lets bidders withdraw while paused", async () => {
+      const fixture = await placeBid(env, market, await createMint(env), 1_000_000);
+      await setPaused(true);
+
+      await expectError(
+        placeBid(env, market, await createMint(env), 1_000_000),
+        "MarketplacePaused"
+      );
+
+      await cancelBid(env, market, fixture);
+      assert.equal(await tokenBalance(env, fixture.bidderPaymentAccount), BigInt(10_000_000));
+    });
+  });
+
+  describe("token-2022", () => {
+    it("lists and sells a Token-2022 NFT", async () => {
+      const fixture = await createListing(env, market, { tokenProgram: TOKEN_2022_PROGRAM_ID });
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.deepEqual(listing.tokenProgramType, { token2022: {} });
+
+      const buyer = await newBuyer(env, market, fixture.nftMint, 1_000_000_000, TOKEN_2022_PROGRAM_ID);
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+    });
+
+    it("sells a Token-2022 NFT with a transfer fee extension", async () => {
+      // A zero maximum fee keeps the single unit whole while the mint still carries the extension
+      const nftMint = await createTransferFeeMint(env, 500, BigInt(0));
+      const fixture = await createListing(env, market, { tokenProgram: TOKEN_2022_PROGRAM_ID, nftMint });
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(1));
+
+      const buyer = await newBuyer(env, market, nftMint, 1_000_000_000, TOKEN_2022_PROGRAM_ID);
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+      assert.equal(await tokenBalance(env, market.feeAccount), BigInt(25_000));
+    });
+  });
+
+  describe("dutch auction", () => {
+    it("declines the price linearly and pins it at the end price", async () => {
+      const seller = await newUser(env);
+      const nftMint = await createMint(env, 0);
+      await mintTo(env, nftMint, seller.publicKey, 4);
+      const metadata = setMetadata(env, nftMint);
+      const sellerPaymentAccount = await createAta(env, market.paymentMint, seller.publicKey);
+      const dutchAuction = pda(
+        env.program,
+        Buffer.from("dutch_auction"),
+        seller.publicKey.toBuffer(),
+        nftMint.toBuffer()
+      );
+      const startTime = (await now(env)) + BigInt(10);
+      const endTime = startTime + BigInt(1_000);
+
+      await env.program.methods
+        .createDutchAuction(
+          new anchor.BN(2_000_000),
+          new anchor.BN(1_000_000),
+          new anchor.BN(startTime.toString()),
+          new anchor.BN(endTime.toString()),
+          new anchor.BN(4)
+        )
+        .accountsPartial({
+          seller: seller.publicKey,
+          nftMint,
+          paymentMint: market.paymentMint,
+          dutchAuction,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc();
+
+      const buyAt = async (timestamp: bigint, maxPrice = U64_MAX) => {
+        await warpTo(env, timestamp);
+        const buyer = await newBuyer(env, market, nftMint);
+        const before = await tokenBalance(env, sellerPaymentAccount);
+        await env.program.methods
+          .executeDutchAuctionSale(new anchor.BN(maxPrice.toString()))
+          .accountsPartial({
+            buyer: buyer.buyer.publicKey,
+            seller: seller.publicKey,
+            dutchAuction,
+            nftMint,
+            buyerNftAccount: buyer.buyerNftAccount,
+            paymentMint: market.paymentMint,
+            buyerPaymentAccount: buyer.buyerPaymentAccount,
+            sellerPaymentAccount,
+            marketplaceFeeAccount: market.feeAccount,
+            metadata,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([buyer.buyer])
+          .rpc();
+        // The seller receives the price minus the 2.5% platform fee
+        return ((await tokenBalance(env, sellerPaymentAccount)) - before) * BigInt(40) / BigInt(39);
+      };
+
+      assert.equal(await buyAt(startTime), BigInt(2_000_000));
+      // The price is above what this buyer is willing to pay
+      await expectError(buyAt(startTime + BigInt(500), BigInt(1_400_000)), "PriceExceedsMax");
+      assert.equal(await buyAt(startTime + BigInt(500)), BigInt(1_500_000));
+      assert.equal(await buyAt(endTime), BigInt(1_000_000));
+      assert.equal(await buyAt(endTime + BigInt(1_000)), BigInt(1_000_000));
+    });
+
+    async function createAuction(startPrice: number, endPrice: number, quantity: number) {
+      const seller = await newUser(env);
+      const nftMint = await createMint(env, 0);
+      await mintTo(env, nftMint, seller.publicKey, 1);
+      const dutchAuction = pda(
+        env.program,
+        Buffer.from("dutch_auction"),
+        seller.publicKey.toBuffer(),
+        nftMint.toBuffer()
+      );
+      const startTime = await now(env);
+
+      return env.program.methods
+        .createDutchAuction(
+          new anchor.BN(startPrice),
+          new anchor.BN(endPrice),
+          new anchor.BN(startTime.toString()),
+          new anchor.BN((startTime + BigInt(1_000)).toString()),
+          new anchor.BN(quantity)
+        )
+        .accountsPartial({
+          seller: seller.publicKey,
+          nftMint,
+          paymentMint: market.paymentMint,
+          dutchAuction,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc();
+    }
+
+    it("rejects an auction whose price does not decline", async () => {
+      await expectError(createAuction(1_000_000, 1_000_000, 1), "InvalidAuctionPrices");
+    });
+
+    it("rejects an auction with nothing to sell", async () => {
+      await expectError(createAuction(2_000_000, 1_000_000, 0), "InvalidQuantity");
+    });
+  });
+
+  describe("price curve listing", () => {
+    it("raises the price by the increment after each sale", async () => {
+      const seller = await newUser(env);
+      const nftMint = await createMint(env, 0);
+      await mintTo(env, nftMint, seller.publicKey, 3);
+      const metadata = setMetadata(env, nftMint);
+      const sellerPaymentAccount = await createAta(env, market.paymentMint, seller.publicKey);
+      const priceCurveListing = pda(
+        env.program,
+        Buffer.from("price_curve"),
+        seller.publicKey.toBuffer(),
+        nftMint.toBuffer()
+      );
+
+      await env.program.methods
+        .createListingWithPriceCurve(new anchor.BN(1_000_000), new anchor.BN(200_000), new anchor.BN(3), new anchor.BN(0))
+        .accountsPartial({
+          seller: seller.publicKey,
+          nftMint,
+          paymentMint: market.paymentMint,
+          priceCurveListing,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc();
+
+      const buy = async (maxPrice: number) => {
+        const buyer = await newBuyer(env, market, nftMint);
+        const before = await tokenBalance(env, sellerPaymentAccount);
+        await env.program.methods
+          .executePriceCurveSale(new anchor.BN(maxPrice))
+          .accountsPartial({
+            buyer: buyer.buyer.publicKey,
+            seller: seller.publicKey,
+            priceCurveListing,
+            nftMint,
+            buyerNftAccount: buyer.buyerNftAccount,
+            paymentMint: market.paymentMint,
+            buyerPaymentAccount: buyer.buyerPaymentAccount,
+            sellerPaymentAccount,
+            marketplaceFeeAccount: market.feeAccount,
+            metadata,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([buyer.buyer])
+          .rpc();
+        // The seller receives the price minus the 2.5% platform fee
+        return ((await tokenBalance(env, sellerPaymentAccount)) - before) * BigInt(40) / BigInt(39);
+      };
+
+      assert.equal(await buy(1_000_000), BigInt(1_000_000));
+      // A buyer capped at the old price can't buy after the curve moved
+      await expectError(buy(1_000_000), "PriceExceedsMax");
+      assert.equal(await buy(1_200_000), BigInt(1_200_000));
+      assert.equal(await buy(1_400_000), BigInt(1_400_000));
+      assert.isFalse(await accountExists(env, priceCurveListing));
+    });
+  });
+
+  describe("royalties", () => {
+    it("returns an error when verified creator shares exceed 100%", async () => {
+      const creators = [
+        { address: anchor.web3.Keypair.generate().publicKey, share: 60, verified: true },
+        { address: anchor.web3.Keypair.generate().publicKey, share: 60, verified: true },
+      ];
+      const fixture = await createListing(env, market, { creators });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(executeSale(env, market, fixture, buyer), "InvalidCreatorShares");
+    });
+
+    it("bounds the number of verified creators instead of exhausting compute", async () => {
+      const creators = Array.from({ length: 6 }, () => ({
+        address: anchor.web3.Keypair.generate().publicKey,
+        share: 10,
+        verified: true,
+      }));
+
+      await expectError(createListing(env, market, { creators }), "TooManyCreators");
+
+      // A listing without cached creators reads them from metadata at sale time
+      const fixture = await createListing(env, market, { creators: creators.slice(0, 1), cacheCreators: false });
+      setMetadata(env, fixture.nftMint, creators);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      await expectError(executeSale(env, market, fixture, buyer), "TooManyCreators");
+    });
+
+    it("applies the royalty override to the configured collection only", async () => {
+      const collection = await createMint(env);
+      const setOverride = (authority: anchor.web3.Keypair, bps: number | null) =>
+        env.program.methods
+          .updateRoyaltyOverride(collection, bps === null ? null : new anchor.BN(bps))
+          .accountsPartial({ authority: authority.publicKey, config: configPda(env.program) })
+          .signers([authority])
+          .rpc();
+
+      await expectError(setOverride(await newUser(env), 100), "ConstraintHasOne");
+      await expectError(setOverride(env.payer, 10_001), "InvalidRoyaltyOverride");
+      await setOverride(env.payer, 100);
+
+      const creator = await newUser(env);
+      const creators = [{ address: creator.publicKey, share: 10, verified: true }];
+      const creatorAccount = await createAta(env, market.paymentMint, creator.publicKey);
+      const creatorAccounts = [{ pubkey: creatorAccount, isSigner: false, isWritable: true }];
+
+      const waived = await createListing(env, market, { creators, collection });
+      await executeSaleMethod(env, market, waived, await newBuyer(env, market, waived.nftMint))
+        .remainingAccounts(creatorAccounts)
+        .rpc();
+      assert.equal(await tokenBalance(env, creatorAccount), BigInt(10_000));
+      assert.equal(await tokenBalance(env, waived.sellerPaymentAccount), BigInt(965_250));
+
+      const other = await createListing(env, market, { creators, collection: await createMint(env) });
+      await executeSaleMethod(env, market, other, await newBuyer(env, market, other.nftMint))
+        .remainingAccounts(creatorAccounts)
+        .rpc();
+      assert.equal(await tokenBalance(env, creatorAccount), BigInt(110_000));
+    });
+
+    it("pays the default royalty to the treasury when metadata pays none", async () => {
+      const treasury = await newUser(env);
+      const setDefault = (bps: number) =>
+        env.program.methods
+          .setDefaultRoyalty(new anchor.BN(bps), treasury.publicKey)
+          .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+          .rpc();
+
+      await expectError(setDefault(1_501), "InvalidDefaultRoyalty");
+      await setDefault(500);
+
+      const treasuryAccount = await createAta(env, market.paymentMint, treasury.publicKey);
+      const fixture = await createListing(env, market);
+      await executeSaleMethod(env, market, fixture, await newBuyer(env, market, fixture.nftMint))
+        .remainingAccounts([{ pubkey: treasuryAccount, isSigner: false, isWritable: true }])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, treasuryAccount), BigInt(50_000));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(926_250));
+
+      // Accepting a bid prices royalties the same way
+      const seller = await newUser(env);
+      const nftMint = await createMint(env);
+      const sellerNftAccount = await mintTo(env, nftMint, seller.publicKey, 1);
+      const sellerPaymentAccount = await createAta(env, market.paymentMint, seller.publicKey);
+      const bid = await placeBid(env, market, nftMint, 1_000_000);
+      await env.program.methods
+        .acceptBid(new anchor.BN(0))
+        .accountsPartial({
+          seller: seller.publicKey,
+          bid: bid.bid,
+          nftMint,
+          sellerNftAccount,
+          bidderNftAccount: getAssociatedTokenAddressSync(nftMint, bid.bidder.publicKey),
+          paymentMint: market.paymentMint,
+          escrowPaymentAccount: bid.escrowPaymentAccount,
+          sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          secondBidderAccount: await createAta(env, market.paymentMint, (await newUser(env)).publicKey),
+          metadata: setMetadata(env, nftMint),
+          collectionMint: nftMint,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([{ pubkey: treasuryAccount, isSigner: false, isWritable: true }])
+        .signers([seller])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, treasuryAccount), BigInt(100_000));
+      assert.equal(await tokenBalance(env, sellerPaymentAccount), BigInt(926_250));
+    });
+
+    it("returns an overflow error for a u64::MAX price instead of panicking", async () => {
+      const creators = [{ address: (await newUser(env)).publicKey, share: 100, verified: true }];
+      const fixture = await createListing(env, market, { creators, price: U64_MAX, quantity: 2 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, { buyQuantity: 2 }),
+        "ArithmeticOverflow"
+      );
+    });
+
+    it("skips royalties when the listing disables them", async () => {
+      const creators = [{ address: (await newUser(env)).publicKey, share: 10, verified: true }];
+      const fixture = await createListing(env, market, { creators, royaltyEnforcement: "disabled" });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+    });
+
+    it("proceeds without creator accounts when royalties are optional", async () => {
+      const creators = [{ address: (await newUser(env)).publicKey, share: 10, verified: true }];
+      const fixture = await createListing(env, market, { creators, royaltyEnforcement: "optional" });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+    });
+
+    it("rejects creator accounts passed in the wrong order", async () => {
+      const creatorA = await newUser(env);
+      const creatorB = await newUser(env);
+      const creators = [
+        { address: creatorA.publicKey, share: 10, verified: true },
+        { address: creatorB.publicKey, share: 5, verified: true },
+      ];
+      const fixture = await createListing(env, market, { creators });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const creatorAccounts = [
+        await createAta(env, market.paymentMint, creatorB.publicKey),
+        await createAta(env, market.paymentMint, creatorA.publicKey),
+      ];
+
+      await expectError(
+        executeSaleMethod(env, market, fixture, buyer)
+          .remainingAccounts(
+            creatorAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+          )
+          .rpc(),
+        "CreatorAccountMismatch"
+      );
+    });
+
+    it("pays creators on optional listings when the marketplace enforces royalties", async () => {
+      await env.program.methods
+        .setEnforceRoyalties(true)
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+
+      const creator = await newUser(env);
+      const creators = [{ address: creator.publicKey, share: 10, verified: true }];
+      const fixture = await createListing(env, market, { creators, royaltyEnforcement: "optional" });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const creatorAccount = await createAta(env, market.paymentMint, creator.publicKey);
+      const sale = (creatorAccount: PublicKey) =>
+        executeSaleMethod(env, market, fixture, buyer)
+          .remainingAccounts([{ pubkey: creatorAccount, isSigner: false, isWritable: true }])
+          .rpc();
+
+      await expectError(executeSale(env, market, fixture, buyer), "MissingCreatorAccount");
+      await expectError(sale(fixture.sellerPaymentAccount), "MissingCreatorAccount");
+
+      await sale(creatorAccount);
+
+      assert.equal(await tokenBalance(env, creatorAccount), BigInt(100_000));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(877_500));
+    });
+
+    it("escrows lazy royalties for each creator to claim once", async () => {
+      const creatorA = await newUser(env);
+      const creatorB = await newUser(env);
+      const creators = [
+        { address: creatorA.publicKey, share: 10, verified: true },
+        { address: creatorB.publicKey, share: 5, verified: true },
+      ];
+      const fixture = await createListing(env, market, { creators, royaltyEnforcement: "lazy" });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const saleId = new anchor.BN(0).toArrayLike(Buffer, "le", 8);
+      const escrowFor = (creator: PublicKey) =>
+        pda(
+          env.program,
+          Buffer.from("royalty_escrow"),
+          creator.toBuffer(),
+          fixture.nftMint.toBuffer(),
+          saleId
+        );
+      const royaltyVault = pda(env.program, Buffer.from("royalty_vault"), market.paymentMint.toBuffer());
+
+      await executeSaleMethod(env, market, fixture, buyer)
+        .remainingAccounts(
+          [creatorA, creatorB].map((creator) => ({
+            pubkey: escrowFor(creator.publicKey),
+            isSigner: false,
+            isWritable: true,
+          }))
+        )
+        .rpc();
+
+      assert.equal(await tokenBalance(env, royaltyVault), BigInt(150_000));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(828_750));
+
+      const creatorAccount = await createAta(env, market.paymentMint, creatorA.publicKey);
+      const claim = () =>
+        env.program.methods
+          .claimRoyalties()
+          .accountsPartial({
+            creator: creatorA.publicKey,
+            royaltyEscrow: escrowFor(creatorA.publicKey),
+            paymentMint: market.paymentMint,
+            royaltyVault,
+            creatorPaymentAccount: creatorAccount,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([creatorA])
+          .rpc();
+
+      await claim();
+      assert.equal(await tokenBalance(env, creatorAccount), BigInt(100_000));
+      assert.equal(await tokenBalance(env, royaltyVault), BigInt(50_000));
+
+      await expectError(claim(), "RoyaltiesAlreadyClaimed");
+    });
+
+    it("pays the same royalties from cached and live creator shares", async () => {
+      const creatorA = await newUser(env);
+      const creatorB = await newUser(env);
+      const creators = [
+        { address: creatorA.publicKey, share: 10, verified: true },
+        { address: (await newUser(env)).publicKey, share: 20, verified: false },
+        { address: creatorB.publicKey, share: 5, verified: true },
+      ];
+      const creatorAccounts = [
+        await createAta(env, market.paymentMint, creatorA.publicKey),
+        await createAta(env, market.paymentMint, creatorB.publicKey),
+      ];
+      const cached = await createListing(env, market, { creators, cacheCreators: true });
+      const live = await createListing(env, market, { creators, cacheCreators: false });
+      const cache = (await env.program.account.listing.fetch(cached.listing)).creatorCache;
+      assert.deepEqual(
+        cache.map((creator) => [creator.address.toBase58(), creator.share]),
+        [
+          [creatorA.publicKey.toBase58(), 10],
+          [creatorB.publicKey.toBase58(), 5],
+        ]
+      );
+      assert.lengthOf((await env.program.account.listing.fetch(live.listing)).creatorCache, 0);
+
+      const sell = async (fixture: ListingFixture) => {
+        const buyer = await newBuyer(env, market, fixture.nftMint);
+        await executeSaleMethod(env, market, fixture, buyer)
+          .remainingAccounts(
+            creatorAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+          )
+          .rpc();
+        return Promise.all(creatorAccounts.map((account) => tokenBalance(env, account)));
+      };
+      const cachedPayouts = await sell(cached);
+      const totalPayouts = await sell(live);
+
+      assert.deepEqual(cachedPayouts, [BigInt(100_000), BigInt(50_000)]);
+      assert.deepEqual(totalPayouts, [BigInt(200_000), BigInt(100_000)]);
+      assert.equal(
+        await tokenBalance(env, cached.sellerPaymentAccount),
+        await tokenBalance(env, live.sellerPaymentAccount)
+      );
+    });
+  });
+
+  describe("english auction", () => {
+    const auctionConfigPda = (collectionMint: PublicKey) =>
+      pda(env.program, Buffer.from("auction_config"), collectionMint.toBuffer());
+
+    async function createAuction(
+      opts: { collection?: PublicKey; duration?: number; minIncrementBps?: number } = {}
+    ) {
+      const { collection, duration = 3_600, minIncrementBps = 500 } = opts;
+      const seller = await newUser(env);
+      const nftMint = await createMint(env, 0);
+      await mintTo(env, nftMint, seller.publicKey, 1);
+      const metadata = setMetadata(env, nftMint, [], collection);
+      const sellerPaymentAccount = await createAta(env, market.paymentMint, seller.publicKey);
+      const englishAuction = pda(
+        env.program,
+        Buffer.from("english_auction"),
+        seller.publicKey.toBuffer(),
+        nftMint.toBuffer()
+      );
+      const escrowPaymentAccount = getAssociatedTokenAddressSync(
+        market.paymentMint,
+        englishAuction,
+        true
+      );
+      const startTime = await now(env);
+      const endTime = startTime + BigInt(duration);
+
+      await env.program.methods
+        .createEnglishAuction(
+          new anchor.BN(1_000_000),
+          new anchor.BN(minIncrementBps),
+          new anchor.BN(startTime.toString()),
+          new anchor.BN(endTime.toString())
+        )
+        .accountsPartial({
+          seller: seller.publicKey,
+          nftMint,
+          paymentMint: market.paymentMint,
+          englishAuction,
+          metadata,
+          auctionConfig: auctionConfigPda(collection ?? nftMint),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc();
+
+      const bid = (bidder: Buyer, amount: number, previous: PublicKey | null) =>
+        env.program.methods
+          .updateEnglishAuctionBid(new anchor.BN(amount))
+          .accountsPartial({
+            bidder: bidder.buyer.publicKey,
+            englishAuction,
+            paymentMint: market.paymentMint,
+            bidderPaymentAccount: bidder.buyerPaymentAccount,
+            escrowPaymentAccount,
+            previousBidderPaymentAccount: previous,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([bidder.buyer])
+          .rpc();
+
+      return {
+        seller,
+        nftMint,
+        metadata,
+        sellerPaymentAccount,
+        englishAuction,
+        escrowPaymentAccount,
+        endTime,
+        bid,
+      };
+    }
+
+    it("rejects a minimum increment above 100%", async () => {
+      await expectError(createAuction({ minIncrementBps: 10_001 }), "InvalidMinIncrement");
+    });
+
+    it("refunds outbid bidders and settles to the highest bidder", async () => {
+      const auction = await createAuction();
+      const { nftMint, englishAuction, escrowPaymentAccount, bid } = auction;
+
+      const alice = await newBuyer(env, market, nftMint, 10_000_000);
+      const bob = await newBuyer(env, market, nftMint, 10_000_000);
+
+      await bid(alice, 1_000_000, null);
+      await expectError(bid(bob, 1_040_000, alice.buyerPaymentAccount), "BidTooLow");
+      await bid(bob, 1_050_000, alice.buyerPaymentAccount);
+
+      assert.equal(await tokenBalance(env, alice.buyerPaymentAccount), BigInt(10_000_000));
+      assert.equal(await tokenBalance(env, escrowPaymentAccount), BigInt(1_050_000));
+
+      await warpTo(env, auction.endTime);
+      await env.program.methods
+        .settleEnglishAuction()
+        .accountsPartial({
+          payer: env.payer.publicKey,
+          seller: auction.seller.publicKey,
+          englishAuction,
+          nftMint,
+          paymentMint: market.paymentMint,
+          recipient: bob.buyer.publicKey,
+          recipientNftAccount: bob.buyerNftAccount,
+          escrowPaymentAccount,
+          sellerPaymentAccount: auction.sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          metadata: auction.metadata,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      assert.equal(await tokenBalance(env, bob.buyerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, auction.sellerPaymentAccount), BigInt(1_023_750));
+    });
+
+    it("extends the auction when a bid lands in the final minute", async () => {
+      const auction = await createAuction();
+      const alice = await newBuyer(env, market, auction.nftMint, 10_000_000);
+      const bob = await newBuyer(env, market, auction.nftMint, 10_000_000);
+
+      await auction.bid(alice, 1_000_000, null);
+      let state = await env.program.account.englishAuction.fetch(auction.englishAuction);
+      assert.equal(state.endTime.toString(), auction.endTime.toString());
+
+      await warpTo(env, auction.endTime - BigInt(30));
+      await auction.bid(bob, 1_050_000, alice.buyerPaymentAccount);
+      state = await env.program.account.englishAuction.fetch(auction.englishAuction);
+      assert.equal(state.endTime.toString(), (auction.endTime + BigInt(60)).toString());
+    });
+
+    describe("collection auction config", () => {
+      async function initializeAuctionConfig(collection: PublicKey, authority = env.payer) {
+        await env.program.methods
+          .initializeAuctionConfig(new anchor.BN(1_000), new anchor.BN(300), new anchor.BN(7_200))
+          .accountsPartial({
+            authority: authority.publicKey,
+            collectionMint: collection,
+            collectionMetadata: setMetadata(env, collection),
+            auctionConfig: auctionConfigPda(collection),
+          })
+          .signers(authority === env.payer ? [] : [authority])
+          .rpc();
+      }
+
+      it("applies the collection's increment, extension window and max duration", async () => {
+        const collection = await createMint(env, 0);
+        await initializeAuctionConfig(collection);
+
+        await expectError(createAuction({ collection, duration: 7_201 }), "AuctionTooLong");
+
+        const auction = await createAuction({ collection });
+        let state = await env.program.account.englishAuction.fetch(auction.englishAuction);
+        assert.equal(state.minIncrementBps.toNumber(), 1_000);
+        assert.equal(state.extensionWindow.toNumber(), 300);
+
+        const alice = await newBuyer(env, market, auction.nftMint, 10_000_000);
+        const bob = await newBuyer(env, market, auction.nftMint, 10_000_000);
+        await auction.bid(alice, 1_000_000, null);
+        await expectError(auction.bid(bob, 1_050_000, alice.buyerPaymentAccount), "BidTooLow");
+
+        await warpTo(env, auction.endTime - BigInt(200));
+        await auction.bid(bob, 1_100_000, alice.buyerPaymentAccount);
+        state = await env.program.account.englishAuction.fetch(auction.englishAuction);
+        assert.equal(state.endTime.toString(), (auction.endTime + BigInt(300)).toString());
+      });
+
+      it("falls back to the marketplace defaults without a collection config", async () => {
+        const auction = await createAuction({ collection: await createMint(env, 0) });
+        const state = await env.program.account.englishAuction.fetch(auction.englishAuction);
+
+        assert.equal(state.minIncrementBps.toNumber(), 500);
+        assert.equal(state.extensionWindow.toNumber(), 60);
+      });
+
+      it("rejects configuration by anyone but the update or marketplace authority", async () => {
+        const collection = await createMint(env, 0);
+
+        await expectError(
+          initializeAuctionConfig(collection, await newUser(env)),
+          "UnauthorizedAuctionConfig"
+        );
+      });
+    });
+  });
+
+  describe("collection offers", () => {
+    async function mintCollectionNft(collection: PublicKey) {
+      const seller = await newUser(env);
+      const nftMint = await createMint(env);
+      const sellerNftAccount = await mintTo(env, nftMint, seller.publicKey, 1);
+      const sellerPaymentAccount = await createAta(env, market.paymentMint, seller.publicKey);
+      const metadata = setMetadata(env, nftMint, [], collection);
+      return { seller, nftMint, sellerNftAccount, sellerPaymentAccount, metadata };
+    }
+
+    async function createOffer(price: number, quantity: number) {
+      const bidder = await newUser(env);
+      const collectionMint = await createMint(env);
+      const bidderPaymentAccount = await mintTo(env, market.paymentMint, bidder.publicKey, 10_000_000);
+      const collectionOffer = pda(
+        env.program,
+        Buffer.from("collection_offer"),
+        bidder.publicKey.toBuffer(),
+        collectionMint.toBuffer()
+      );
+      const escrowPaymentAccount = getAssociatedTokenAddressSync(
+        market.paymentMint,
+        collectionOffer,
+        true
+      );
+
+      await env.program.methods
+        .createCollectionOffer(new anchor.BN(price), new anchor.BN(quantity), new anchor.BN(0))
+        .accountsPartial({
+          bidder: bidder.publicKey,
+          collectionMint,
+          collectionOffer,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount,
+          escrowPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([bidder])
+        .rpc();
+
+      return { bidder, collectionMint, collectionOffer, bidderPaymentAccount, escrowPaymentAccount };
+    }
+
+    function takeOffer(
+      offer: Awaited<ReturnType<typeof createOffer>>,
+      nft: Awaited<ReturnType<typeof mintCollectionNft>>
+    ) {
+      return env.program.methods
+        .takeCollectionOffer()
+        .accountsPartial({
+          seller: nft.seller.publicKey,
+          bidder: offer.bidder.publicKey,
+          collectionOffer: offer.collectionOffer,
+          nftMint: nft.nftMint,
+          sellerNftAccount: nft.sellerNftAccount,
+          paymentMint: market.paymentMint,
+          escrowPaymentAccount: offer.escrowPaymentAccount,
+          bidderPaymentAccount: offer.bidderPaymentAccount,
+          sellerPaymentAccount: nft.sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          metadata: nft.metadata,
+          collectionMint: offer.collectionMint,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([nft.seller])
+        .rpc();
+    }
+
+    it("fills an offer with any NFT from the collection", async () => {
+      const offer = await createOffer(1_000_000, 2);
+      assert.equal(await tokenBalance(env, offer.escrowPaymentAccount), BigInt(2_000_000));
+
+      const nft = await mintCollectionNft(offer.collectionMint);
+      await takeOffer(offer, nft);
+
+      const bidderNftAccount = getAssociatedTokenAddressSync(nft.nftMint, offer.bidder.publicKey);
+      assert.equal(await tokenBalance(env, bidderNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, nft.sellerPaymentAccount), BigInt(975_000));
+      assert.equal(await tokenBalance(env, offer.escrowPaymentAccount), BigInt(1_000_000));
+
+      const account = await env.program.account.collectionOffer.fetch(offer.collectionOffer);
+      assert.equal(account.quantity.toNumber(), 1);
+
+      const stats = await env.program.account.collectionStats.fetch(statsPda(env.program, offer.collectionMint));
+      assert.equal(stats.saleCount.toNumber(), 1);
+      assert.equal(stats.lastSalePrice.toNumber(), 1_000_000);
+    });
+
+    it("closes the offer and refunds leftover escrow once it is filled", async () => {
+      const offer = await createOffer(1_000_000, 1);
+      // A stray deposit must not block the escrow from closing
+      await mintTo(env, market.paymentMint, offer.collectionOffer, 5);
+      const nft = await mintCollectionNft(offer.collectionMint);
+
+      await takeOffer(offer, nft);
+
+      assert.equal(await tokenBalance(env, nft.sellerPaymentAccount), BigInt(975_000));
+      assert.equal(await tokenBalance(env, offer.bidderPaymentAccount), BigInt(9_000_005));
+      assert.isFalse(await accountExists(env, offer.escrowPaymentAccount));
+      assert.isFalse(await accountExists(env, offer.collectionOffer));
+      const listing = pda(
+        env.program,
+        Buffer.from("listing"),
+        nft.seller.publicKey.toBuffer(),
+        nft.nftMint.toBuffer()
+      );
+      assert.isFalse(await accountExists(env, listing));
+    });
+
+    it("rejects an NFT from another collection", async () => {
+      const offer = await createOffer(1_000_000, 1);
+      const nft = await mintCollectionNft(await createMint(env));
+
+      await expectError(takeOffer(offer, nft), "NotInCollection");
+    });
+
+    it("refunds the remaining escrow on cancel", async () => {
+      const offer = await createOffer(1_000_000, 2);
+      await takeOffer(offer, await mintCollectionNft(offer.collectionMint));
+
+      await env.program.methods
+        .cancelCollectionOffer()
+        .accountsPartial({
+          bidder: offer.bidder.publicKey,
+          collectionOffer: offer.collectionOffer,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount: offer.bidderPaymentAccount,
+          escrowPaymentAccount: offer.escrowPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([offer.bidder])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, offer.bidderPaymentAccount), BigInt(9_000_000));
+      assert.isFalse(await accountExists(env, offer.collectionOffer));
+    });
+  });
+
+  describe("offers", () => {
+    async function openOffer(offerPrice: number) {
+      const seller = await newUser(env);
+      const nftMint = await createMint(env);
+      const sellerNftAccount = await mintTo(env, nftMint, seller.publicKey, 1);
+      const sellerPaymentAccount = await createAta(env, market.paymentMint, seller.publicKey);
+      const metadata = setMetadata(env, nftMint);
+      const buyer = await newUser(env);
+      const buyerPaymentAccount = await mintTo(env, market.paymentMint, buyer.publicKey, 10_000_000);
+      const offer = pda(env.program, Buffer.from("offer"), buyer.publicKey.toBuffer(), nftMint.toBuffer());
+      const escrowPaymentAccount = getAssociatedTokenAddressSync(market.paymentMint, offer, true);
+      const offerNftAccount = getAssociatedTokenAddressSync(nftMint, offer, true);
+
+      await env.program.methods
+        .makeOffer(new anchor.BN(offerPrice), new anchor.BN(0), seller.publicKey)
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          nftMint,
+          offer,
+          paymentMint: market.paymentMint,
+          buyerPaymentAccount,
+          escrowPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      return {
+        seller,
+        nftMint,
+        sellerNftAccount,
+        sellerPaymentAccount,
+        metadata,
+        buyer,
+        buyerPaymentAccount,
+        offer,
+        escrowPaymentAccount,
+        offerNftAccount,
+      };
+    }
+
+    async function counteredOffer(offerPrice: number, counterPrice: number) {
+      const fixture = await openOffer(offerPrice);
+
+      await env.program.methods
+        .counterOffer(new anchor.BN(counterPrice))
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          offer: fixture.offer,
+          nftMint: fixture.nftMint,
+          sellerNftAccount: fixture.sellerNftAccount,
+          offerNftAccount: fixture.offerNftAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.seller])
+        .rpc();
+
+      return fixture;
+    }
+
+    function acceptOffer(
+      fixture: Awaited<ReturnType<typeof openOffer>>,
+      seller: anchor.web3.Keypair,
+      sellerNftAccount: PublicKey,
+      sellerPaymentAccount: PublicKey
+    ) {
+      return env.program.methods
+        .acceptOffer()
+        .accountsPartial({
+          seller: seller.publicKey,
+          buyer: fixture.buyer.publicKey,
+          offer: fixture.offer,
+          nftMint: fixture.nftMint,
+          sellerNftAccount,
+          paymentMint: market.paymentMint,
+          escrowPaymentAccount: fixture.escrowPaymentAccount,
+          sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          metadata: fixture.metadata,
+          collectionMint: fixture.nftMint,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc();
+    }
+
+    it("settles at the offer price when the targeted owner accepts", async () => {
+      const fixture = await openOffer(1_000_000);
+      const offer = await env.program.account.offer.fetch(fixture.offer);
+      assert.ok(offer.targetOwner.equals(fixture.seller.publicKey));
+
+      await acceptOffer(fixture, fixture.seller, fixture.sellerNftAccount, fixture.sellerPaymentAccount);
+
+      const buyerNftAccount = getAssociatedTokenAddressSync(fixture.nftMint, fixture.buyer.publicKey);
+      assert.equal(await tokenBalance(env, buyerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(0));
+      assert.equal(await tokenBalance(env, fixture.buyerPaymentAccount), BigInt(9_000_000));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+      assert.isFalse(await accountExists(env, fixture.offer));
+      assert.isFalse(await accountExists(env, fixture.escrowPaymentAccount));
+
+      const sellerStats = await env.program.account.sellerStats.fetch(
+        pda(env.program, Buffer.from("seller_stats"), fixture.seller.publicKey.toBuffer())
+      );
+      assert.equal(sellerStats.totalSales.toNumber(), 1);
+    });
+
+    it("rejects a holder the offer was not made to", async () => {
+      const fixture = await openOffer(1_000_000);
+      // The token has since moved to someone else, who tries to take the offer
+      const holder = await newUser(env);
+      const holderNftAccount = await mintTo(env, fixture.nftMint, holder.publicKey, 1);
+      const holderPaymentAccount = await createAta(env, market.paymentMint, holder.publicKey);
+
+      await expectError(
+        acceptOffer(fixture, holder, holderNftAccount, holderPaymentAccount),
+        "NotOfferTarget"
+      );
+
+      assert.equal(await tokenBalance(env, holderNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.escrowPaymentAccount), BigInt(1_000_000));
+    });
+
+    it("settles at the counter price when the buyer accepts", async () => {
+      const fixture = await counteredOffer(1_000_000, 1_500_000);
+      const offer = await env.program.account.offer.fetch(fixture.offer);
+      assert.ok(offer.state.countered);
+      assert.equal(await tokenBalance(env, fixture.offerNftAccount), BigInt(1));
+
+      await env.program.methods
+        .acceptCounterOffer()
+        .accountsPartial({
+          buyer: fixture.buyer.publicKey,
+          seller: fixture.seller.publicKey,
+          offer: fixture.offer,
+          nftMint: fixture.nftMint,
+          offerNftAccount: fixture.offerNftAccount,
+          paymentMint: market.paymentMint,
+          buyerPaymentAccount: fixture.buyerPaymentAccount,
+          escrowPaymentAccount: fixture.escrowPaymentAccount,
+          sellerPaymentAccount: fixture.sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          metadata: fixture.metadata,
+          collectionMint: fixture.nftMint,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.buyer])
+        .rpc();
+
+      const buyerNftAccount = getAssociatedTokenAddressSync(fixture.nftMint, fixture.buyer.publicKey);
+      assert.equal(await tokenBalance(env, buyerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.buyerPaymentAccount), BigInt(8_500_000));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(1_462_500));
+      assert.isFalse(await accountExists(env, fixture.offer));
+      assert.isFalse(await accountExists(env, fixture.escrowPaymentAccount));
+
+      const stats = await env.program.account.collectionStats.fetch(statsPda(env.program, fixture.nftMint));
+      assert.equal(stats.lastSalePrice.toNumber(), 1_500_000);
+    });
+
+    it("refunds the offer and returns the NFT when the buyer rejects", async () => {
+      const fixture = await counteredOffer(1_000_000, 1_500_000);
+
+      await env.program.methods
+        .rejectCounterOffer()
+        .accountsPartial({
+          buyer: fixture.buyer.publicKey,
+          seller: fixture.seller.publicKey,
+          offer: fixture.offer,
+          nftMint: fixture.nftMint,
+          offerNftAccount: fixture.offerNftAccount,
+          sellerNftAccount: fixture.sellerNftAccount,
+          paymentMint: market.paymentMint,
+          buyerPaymentAccount: fixture.buyerPaymentAccount,
+          escrowPaymentAccount: fixture.escrowPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.buyer])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.buyerPaymentAccount), BigInt(10_000_000));
+      assert.isFalse(await accountExists(env, fixture.offer));
+      assert.isFalse(await accountExists(env, fixture.offerNftAccount));
+    });
+  });
+
+  describe("price history", () => {
+    it("records each sale of an NFT", async () => {
+      const fixture = await createListing(env, market, { price: 1_000_000, quantity: 2 });
+      const first = await newBuyer(env, market, fixture.nftMint);
+      const second = await newBuyer(env, market, fixture.nftMint);
+      await executeSale(env, market, fixture, first);
+      await executeSale(env, market, fixture, second);
+
+      const priceHistory = pda(env.program, Buffer.from("price_history"), fixture.nftMint.toBuffer());
+      const records = await env.program.methods
+        .getPriceHistory()
+        .accountsPartial({ priceHistory })
+        .view();
+
+      assert.equal(records.length, 10);
+      const sales = records.slice(-2);
+      assert.ok(sales[0].buyer.equals(first.buyer.publicKey));
+      assert.ok(sales[1].buyer.equals(second.buyer.publicKey));
+      assert.equal(sales[1].price.toNumber(), 1_000_000);
+      assert.equal(records[0].timestamp.toNumber(), 0);
+    });
+  });
+
+  describe("collection stats", () => {
+    it("tracks volume across sales in a collection", async () => {
+      const collection = await createMint(env);
+      const first = await createListing(env, market, { price: 1_000_000, collection });
+      const second = await createListing(env, market, { price: 3_000_000, collection });
+      await executeSale(env, market, first, await newBuyer(env, market, first.nftMint));
+      await executeSale(env, market, second, await newBuyer(env, market, second.nftMint));
+
+      const stats = await env.program.account.collectionStats.fetch(statsPda(env.program, collection));
+      assert.ok(stats.collectionMint.equals(collection));
+      assert.equal(stats.totalVolume.toNumber(), 4_000_000);
+      assert.equal(stats.saleCount.toNumber(), 2);
+      assert.equal(stats.lastSalePrice.toNumber(), 3_000_000);
+    });
+
+    it("rejects stats for another collection", async () => {
+      const fixture = await createListing(env, market, { collection: await createMint(env) });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, { accounts: { collectionMint: fixture.nftMint } }),
+        "InvalidCollectionStats"
+      );
+    });
+
+    it("lets only the authority set the floor price", async () => {
+      const fixture = await createListing(env, market);
+      await executeSale(env, market, fixture, await newBuyer(env, market, fixture.nftMint));
+      const collectionStats = statsPda(env.program, fixture.nftMint);
+      const outsider = await newUser(env);
+
+      await expectError(
+        env.program.methods
+          .updateFloorPrice(new anchor.BN(500_000))
+          .accountsPartial({ authority: outsider.publicKey, collectionStats })
+          .signers([outsider])
+          .rpc(),
+        "ConstraintHasOne"
+      );
+
+      await env.program.methods
+        .updateFloorPrice(new anchor.BN(500_000))
+        .accountsPartial({ authority: env.payer.publicKey, collectionStats })
+        .rpc();
+      const stats = await env.program.account.collectionStats.fetch(collectionStats);
+      assert.equal(stats.floorPrice.toNumber(), 500_000);
+    });
+  });
+
+  describe("update_bid", () => {
+    function updateBid(fixture: BidFixture, newPrice: number) {
+      return env.program.methods
+        .updateBid(new anchor.BN(newPrice), new anchor.BN(0))
+        .accountsPartial({
+          bidder: fixture.bidder.publicKey,
+          bid: fixture.bid,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount: fixture.bidderPaymentAccount,
+          escrowPaymentAccount: fixture.escrowPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.bidder])
+        .rpc();
+    }
+
+    it("tops up the escrow when the bid is raised", async () => {
+      const fixture = await placeBid(env, market, await createMint(env), 1_000_000);
+      await updateBid(fixture, 1_500_000);
+
+      const bid = await env.program.account.bid.fetch(fixture.bid);
+      assert.equal(bid.price.toNumber(), 1_500_000);
+      assert.equal(await tokenBalance(env, fixture.escrowPaymentAccount), BigInt(1_500_000));
+      assert.equal(await tokenBalance(env, fixture.bidderPaymentAccount), BigInt(8_500_000));
+    });
+
+    it("refunds the difference when the bid is lowered", async () => {
+      const fixture = await placeBid(env, market, await createMint(env), 1_000_000);
+      await updateBid(fixture, 400_000);
+
+      const bid = await env.program.account.bid.fetch(fixture.bid);
+      assert.equal(bid.price.toNumber(), 400_000);
+      assert.equal(await tokenBalance(env, fixture.escrowPaymentAccount), BigInt(400_000));
+      assert.equal(await tokenBalance(env, fixture.bidderPaymentAccount), BigInt(9_600_000));
+    });
+
+    it("updates only the price with update_bid_price", async () => {
+      const fixture = await placeBid(env, market, await createMint(env), 1_000_000);
+
+      await env.program.methods
+        .updateBidPrice(new anchor.BN(1_200_000))
+        .accountsPartial({
+          bidder: fixture.bidder.publicKey,
+          bid: fixture.bid,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount: fixture.bidderPaymentAccount,
+          escrowPaymentAccount: fixture.escrowPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.bidder])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, fixture.escrowPaymentAccount), BigInt(1_200_000));
+      await expectError(updateBid(fixture, 0), "InvalidPrice");
+    });
+  });
+
+  describe("whitelist", () => {
+    it("only sells allowlisted listings to buyers with a valid proof", async () => {
+      const collectionMint = await createMint(env);
+      const fixture = await createListing(env, market, {
+        collection: collectionMint,
+        whitelistEnabled: true,
+      });
+      const allowed = await newBuyer(env, market, fixture.nftMint);
+      const outsider = await newBuyer(env, market, fixture.nftMint);
+      const tree = merkleTree([allowed.buyer.publicKey, (await newUser(env)).publicKey]);
+      const whitelist = pda(env.program, Buffer.from("whitelist"), collectionMint.toBuffer());
+
+      await env.program.methods
+        .initializeWhitelist(tree.root)
+        .accountsPartial({ authority: env.payer.publicKey, collectionMint, whitelist })
+        .rpc();
+
+      await expectError(
+        executeSale(env, market, fixture, outsider, {
+          whitelistProof: tree.proof(allowed.buyer.publicKey),
+          accounts: { whitelist },
+        }),
+        "NotWhitelisted"
+      );
+
+      await executeSale(env, market, fixture, allowed, {
+        whitelistProof: tree.proof(allowed.buyer.publicKey),
+        accounts: { whitelist },
+      });
+      assert.equal(await tokenBalance(env, allowed.buyerNftAccount), BigInt(1));
+    });
+  });
+
+  describe("bids", () => {
+    it("escrows a USDC bid and refunds it on cancel", async () => {
+      const fixture = await placeBid(env, market, await createMint(env), 1_000_000);
+
+      const bid = await env.program.account.bid.fetch(fixture.bid);
+      assert.ok(bid.paymentMint.equals(market.paymentMint));
+      assert.equal(await tokenBalance(env, fixture.escrowPaymentAccount), BigInt(1_000_000));
+      assert.equal(await tokenBalance(env, fixture.bidderPaymentAccount), BigInt(9_000_000));
+
+      await cancelBid(env, market, fixture);
+
+      assert.equal(await tokenBalance(env, fixture.bidderPaymentAccount), BigInt(10_000_000));
+      assert.isFalse(await accountExists(env, fixture.escrowPaymentAccount));
+      assert.isFalse(await accountExists(env, fixture.bid));
+    });
+
+    it("rejects a competing bid within the minimum increment", async () => {
+      await env.program.methods
+        .setMinBidIncrement(new anchor.BN(100_000))
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+      const nftMint = await createMint(env);
+
+      await placeBid(env, market, nftMint, 1_000_000);
+      await expectError(placeBid(env, market, nftMint, 1_050_000), "BidIncrementTooSmall");
+      await placeBid(env, market, nftMint, 1_100_000);
+
+      const bidBook = await env.program.account.bidBook.fetch(
+        pda(env.program, Buffer.from("bid_book"), nftMint.toBuffer())
+      );
+      assert.equal(bidBook.bestBid.toNumber(), 1_100_000);
+    });
+
+    it("requires a competing bid to beat the best bid by 1% by default", async () => {
+      const nftMint = await createMint(env);
+
+      await placeBid(env, market, nftMint, 1_000_000);
+      await expectError(placeBid(env, market, nftMint, 1_009_999), "BidIncrementTooSmall");
+      await placeBid(env, market, nftMint, 1_010_000);
+    });
+
+    it("lets one wallet hold several bids on the same NFT", async () => {
+      const nftMint = await createMint(env);
+      const first = await placeBid(env, market, nftMint, 1_000_000);
+      const second = await placeBid(env, market, nftMint, 1_100_000, 10_000_000, first.bidder);
+
+      assert.ok(first.bid.equals(bidPda(env.program, first.bidder.publicKey, nftMint, 0)));
+      assert.ok(second.bid.equals(bidPda(env.program, first.bidder.publicKey, nftMint, 1)));
+      assert.equal((await env.program.account.bid.fetch(second.bid)).nonce.toNumber(), 1);
+
+      await cancelBid(env, market, first);
+
+      assert.isFalse(await accountExists(env, first.bid));
+      assert.equal(await tokenBalance(env, first.escrowPaymentAccount), BigInt(0));
+      assert.equal(await tokenBalance(env, second.escrowPaymentAccount), BigInt(1_100_000));
+      const bidBook = await env.program.account.bidBook.fetch(
+        pda(env.program, Buffer.from("bid_book"), nftMint.toBuffer())
+      );
+      assert.equal(bidBook.bestBid.toNumber(), 1_100_000);
+    });
+  });
+
+  describe("bulk bids", () => {
+    async function bulkBidAccounts(bidder: PublicKey, nftMints: PublicKey[]) {
+      return nftMints.flatMap((nftMint, nonce) => {
+        const bid = bidPda(env.program, bidder, nftMint, nonce);
+        return [
+          { pubkey: nftMint, isSigner: false, isWritable: false },
+          { pubkey: bid, isSigner: false, isWritable: true },
+          {
+            pubkey: pda(env.program, Buffer.from("bid_book"), nftMint.toBuffer()),
+            isSigner: false,
+            isWritable: true,
+          },
+          {
+            pubkey: getAssociatedTokenAddressSync(market.paymentMint, bid, true),
+            isSigner: false,
+            isWritable: true,
+          },
+        ];
+      });
+    }
+
+    async function bulkPlaceBidsMethod(bidder: anchor.web3.Keypair, nftMints: PublicKey[], price: number) {
+      const bidderPaymentAccount = await mintTo(env, market.paymentMint, bidder.publicKey, 100_000_000);
+      const method = env.program.methods
+        .bulkPlaceBids(
+          nftMints.map((nftMint) => ({ nftMint, price: new anchor.BN(price), expiry: new anchor.BN(0) }))
+        )
+        .accountsPartial({
+          bidder: bidder.publicKey,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(await bulkBidAccounts(bidder.publicKey, nftMints))
+        .signers([bidder]);
+      return { method, bidderPaymentAccount };
+    }
+
+    it("places ten bids in one transaction under 1.4M compute units", async () => {
+      const bidder = await newUser(env);
+      const nftMints: PublicKey[] = [];
+      for (let i = 0; i < 10; i++) {
+        nftMints.push(await createMint(env));
+      }
+
+      const { method, bidderPaymentAccount } = await bulkPlaceBidsMethod(bidder, nftMints, 1_000_000);
+      // Ten bids reference more accounts than fit in a legacy transaction
+      const table = lookupTable(
+        env,
+        (await bulkBidAccounts(bidder.publicKey, nftMints)).map((meta) => meta.pubkey)
+      );
+      const [recentBlockhash] = await env.context.banksClient.getLatestBlockhash();
+      const message = new anchor.web3.TransactionMessage({
+        payerKey: bidder.publicKey,
+        recentBlockhash,
+        instructions: [
+          anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({ units: 1_400_000 }),
+          await method.instruction(),
+        ],
+      }).compileToV0Message([table]);
+      const tx = new anchor.web3.VersionedTransaction(message);
+      tx.sign([bidder]);
+      const meta = await env.context.banksClient.processTransaction(tx);
+
+      assert.isBelow(Number(meta.computeUnitsConsumed), 1_400_000);
+      assert.equal(await tokenBalance(env, bidderPaymentAccount), BigInt(90_000_000));
+      for (const [nonce, nftMint] of nftMints.entries()) {
+        const bid = bidPda(env.program, bidder.publicKey, nftMint, nonce);
+        const account = await env.program.account.bid.fetch(bid);
+        assert.ok(account.nftMint.equals(nftMint));
+        assert.equal(account.nonce.toNumber(), nonce);
+        const escrow = getAssociatedTokenAddressSync(market.paymentMint, bid, true);
+        assert.equal(await tokenBalance(env, escrow), BigInt(1_000_000));
+      }
+    });
+
+    it("reverts every bid when one of them fails", async () => {
+      const nftMint = await createMint(env);
+      await placeBid(env, market, nftMint, 2_000_000);
+      const bidder = await newUser(env);
+      const other = await createMint(env);
+
+      const { method } = await bulkPlaceBidsMethod(bidder, [other, nftMint], 1_000_000);
+
+      await expectError(method.rpc(), "BidIncrementTooSmall");
+
+      assert.isFalse(await accountExists(env, bidPda(env.program, bidder.publicKey, other, 0)));
+      assert.isFalse(
+        await accountExists(env, pda(env.program, Buffer.from("bid_book"), other.toBuffer()))
+      );
+    });
+  });
+
+  describe("delegated bids", () => {
+    async function placeBidDelegated(
+      nftMint: PublicKey,
+      price: number,
+      bidder?: anchor.web3.Keypair
+    ) {
+      bidder = bidder ?? (await newUser(env));
+      const bidderPaymentAccount = await mintTo(env, market.paymentMint, bidder.publicKey, 10_000_000);
+      const delegatedBid = pda(
+        env.program,
+        Buffer.from("delegated_bid"),
+        bidder.publicKey.toBuffer(),
+        nftMint.toBuffer()
+      );
+      await env.program.methods
+        .placeBidDelegated(new anchor.BN(price), new anchor.BN(0))
+        .accountsPartial({
+          bidder: bidder.publicKey,
+          nftMint,
+          delegatedBid,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([bidder])
+        .rpc();
+      return { bidder, bidderPaymentAccount, delegatedBid };
+    }
+
+    async function acceptBidDelegated(
+      nftMint: PublicKey,
+      seller: anchor.web3.Keypair,
+      bid: { bidder: anchor.web3.Keypair; bidderPaymentAccount: PublicKey; delegatedBid: PublicKey }
+    ) {
+      return env.program.methods
+        .acceptBidDelegated()
+        .accountsPartial({
+          seller: seller.publicKey,
+          delegatedBid: bid.delegatedBid,
+          bidder: bid.bidder.publicKey,
+          nftMint,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount: bid.bidderPaymentAccount,
+          sellerPaymentAccount: await createAta(env, market.paymentMint, seller.publicKey),
+          marketplaceFeeAccount: market.feeAccount,
+          metadata: setMetadata(env, nftMint),
+          collectionMint: nftMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .rpc();
+    }
+
+    it("approves the bid instead of escrowing and revokes on cancel", async () => {
+      const nftMint = await createMint(env);
+      const bid = await placeBidDelegated(nftMint, 1_000_000);
+
+      let account = await tokenAccount(env, bid.bidderPaymentAccount);
+      assert.equal(account.amount, BigInt(10_000_000));
+      assert.ok(account.delegate.equals(bid.delegatedBid));
+      assert.equal(account.delegatedAmount, BigInt(1_000_000));
+
+      await env.program.methods
+        .cancelBidDelegated()
+        .accountsPartial({
+          bidder: bid.bidder.publicKey,
+          delegatedBid: bid.delegatedBid,
+          bidderPaymentAccount: bid.bidderPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([bid.bidder])
+        .rpc();
+
+      account = await tokenAccount(env, bid.bidderPaymentAccount);
+      assert.isNull(account.delegate);
+      assert.isFalse(await accountExists(env, bid.delegatedBid));
+    });
+
+    it("pulls the payment from the bidder when the seller accepts", async () => {
+      const nftMint = await createMint(env);
+      const seller = await newUser(env);
+      await mintTo(env, nftMint, seller.publicKey, 1);
+      const bid = await placeBidDelegated(nftMint, 1_000_000);
+
+      await acceptBidDelegated(nftMint, seller, bid);
+
+      assert.equal(await tokenBalance(env, bid.bidderPaymentAccount), BigInt(9_000_000));
+      assert.equal(
+        await tokenBalance(env, getAssociatedTokenAddressSync(market.paymentMint, seller.publicKey)),
+        BigInt(975_000)
+      );
+      assert.equal(
+        await tokenBalance(env, getAssociatedTokenAddressSync(nftMint, bid.bidder.publicKey)),
+        BigInt(1)
+      );
+      assert.isFalse(await accountExists(env, bid.delegatedBid));
+    });
+
+    it("rejects a bid whose approval was replaced by a newer one", async () => {
+      const nftMint = await createMint(env);
+      const seller = await newUser(env);
+      await mintTo(env, nftMint, seller.publicKey, 1);
+      const bid = await placeBidDelegated(nftMint, 1_000_000);
+      await placeBidDelegated(await createMint(env), 500_000, bid.bidder);
+
+      await expectError(acceptBidDelegated(nftMint, seller, bid), "DelegationRevoked");
+    });
+  });
+
+  describe("account versions", () => {
+    it("stores the canonical bump and current version on listings and bids", async () => {
+      const listingFixture = await createListing(env, market);
+      const [, listingBump] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("listing"),
+          listingFixture.seller.publicKey.toBuffer(),
+          listingFixture.nftMint.toBuffer(),
+        ],
+        env.program.programId
+      );
+      const listing = await env.program.account.listing.fetch(listingFixture.listing);
+      assert.equal(listing.bump, listingBump);
+      assert.equal(listing.version, 1);
+
+      const bidFixture = await placeBid(env, market, listingFixture.nftMint, 1_000_000);
+      const [, bidBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("bid"), bidFixture.bidder.publicKey.toBuffer(), bidFixture.nftMint.toBuffer()],
+        env.program.programId
+      );
+      const bid = await env.program.account.bid.fetch(bidFixture.bid);
+      assert.equal(bid.bump, bidBump);
+      assert.equal(bid.version, 1);
+    });
+  });
+
+  describe("self trades", () => {
+    it("rejects a seller buying their own listing", async () => {
+      const fixture = await createListing(env, market);
+      const seller: Buyer = {
+        buyer: fixture.seller,
+        buyerPaymentAccount: await mintTo(env, market.paymentMint, fixture.seller.publicKey, 5_000_000),
+        buyerNftAccount: fixture.sellerNftAccount,
+        secondBidderAccount: await createAta(env, market.paymentMint, (await newUser(env)).publicKey),
+      };
+
+      await expectError(executeSale(env, market, fixture, seller), "SelfTrade");
+    });
+
+    it("rejects a bidder accepting their own bid", async () => {
+      const owner = await newUser(env);
+      const nftMint = await createMint(env);
+      const ownerNftAccount = await mintTo(env, nftMint, owner.publicKey, 1);
+      const metadata = setMetadata(env, nftMint);
+      const fixture = await placeBid(env, market, nftMint, 1_000_000, 10_000_000, owner);
+
+      await expectError(
+        env.program.methods
+          .acceptBid(new anchor.BN(0))
+          .accountsPartial({
+            seller: owner.publicKey,
+            bid: fixture.bid,
+            nftMint,
+            sellerNftAccount: ownerNftAccount,
+            bidderNftAccount: ownerNftAccount,
+            paymentMint: market.paymentMint,
+            escrowPaymentAccount: fixture.escrowPaymentAccount,
+            sellerPaymentAccount: fixture.bidderPaymentAccount,
+            marketplaceFeeAccount: market.feeAccount,
+            secondBidderAccount: await createAta(env, market.paymentMint, (await newUser(env)).publicKey),
+            metadata,
+            collectionMint: nftMint,
+            marketStats: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([owner])
+          .rpc(),
+        "SelfTrade"
+      );
+    });
+  });
+
+  describe("accept_bid", () => {
+    it("rejects a seller who does not hold the NFT", async () => {
+      const seller = await newUser(env);
+      const nftMint = await createMint(env);
+      await mintTo(env, nftMint, (await newUser(env)).publicKey, 1);
+      const sellerNftAccount = await createAta(env, nftMint, seller.publicKey);
+      const metadata = setMetadata(env, nftMint);
+      const fixture = await placeBid(env, market, nftMint, 1_000_000);
+
+      await expectError(
+        env.program.methods
+          .acceptBid(new anchor.BN(0))
+          .accountsPartial({
+            seller: seller.publicKey,
+            bid: fixture.bid,
+            nftMint,
+            sellerNftAccount,
+            bidderNftAccount: getAssociatedTokenAddressSync(nftMint, fixture.bidder.publicKey),
+            paymentMint: market.paymentMint,
+            escrowPaymentAccount: fixture.escrowPaymentAccount,
+            sellerPaymentAccount: await createAta(env, market.paymentMint, seller.publicKey),
+            marketplaceFeeAccount: market.feeAccount,
+            secondBidderAccount: await createAta(env, market.paymentMint, (await newUser(env)).publicKey),
+            metadata,
+            collectionMint: nftMint,
+            marketStats: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([seller])
+          .rpc(),
+        "SellerDoesNotOwnNft"
+      );
+    });
+
+    it("rejects a seller passing another NFT's mint to drain the bid escrow", async () => {
+      const seller = await newUser(env);
+      const nftMint = await createMint(env);
+      const cheapMint = await createMint(env);
+      const sellerNftAccount = await mintTo(env, cheapMint, seller.publicKey, 1);
+      const metadata = setMetadata(env, cheapMint);
+      const fixture = await placeBid(env, market, nftMint, 1_000_000);
+
+      await expectError(
+        env.program.methods
+          .acceptBid(new anchor.BN(0))
+          .accountsPartial({
+            seller: seller.publicKey,
+            bid: fixture.bid,
+            nftMint: cheapMint,
+            sellerNftAccount,
+            bidderNftAccount: getAssociatedTokenAddressSync(cheapMint, fixture.bidder.publicKey),
+            paymentMint: market.paymentMint,
+            escrowPaymentAccount: fixture.escrowPaymentAccount,
+            sellerPaymentAccount: await createAta(env, market.paymentMint, seller.publicKey),
+            marketplaceFeeAccount: market.feeAccount,
+            secondBidderAccount: await createAta(env, market.paymentMint, (await newUser(env)).publicKey),
+            metadata,
+            collectionMint: cheapMint,
+            marketStats: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            paymentTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([seller])
+          .rpc(),
+        "MintMismatch"
+      );
+      assert.equal(await tokenBalance(env, fixture.escrowPaymentAccount), BigInt(1_000_000));
+    });
+  });
+
+  describe("auto-accept bids", () => {
+    async function autoAcceptListing(threshold: number, opts: { quantity?: number; maxQuantityPerBuyer?: number } = {}) {
+      const fixture = await createListing(env, market, opts);
+      await env.program.methods
+        .setAutoAcceptThreshold(new anchor.BN(threshold))
+        .accountsPartial({ seller: fixture.seller.publicKey, listing: fixture.listing })
+        .signers([fixture.seller])
+        .rpc();
+      return fixture;
+    }
+
+    async function bidAgainst(fixture: ListingFixture, price: number, repeatBidder?: Keypair) {
+      const bidder = repeatBidder ?? (await newUser(env));
+      const bidderPaymentAccount = repeatBidder
+        ? getAssociatedTokenAddressSync(market.paymentMint, bidder.publicKey)
+        : await mintTo(env, market.paymentMint, bidder.publicKey, 10_000_000);
+      const bid = bidPda(env.program, bidder.publicKey, fixture.nftMint, await nextBidNonce(env, bidder.publicKey));
+      const bidderNftAccount = getAssociatedTokenAddressSync(fixture.nftMint, bidder.publicKey);
+
+      await env.program.methods
+        .placeBid(new anchor.BN(price), new anchor.BN(0), new anchor.BN(((await now(env)) + BigInt(60)).toString()))
+        .accountsPartial({
+          bidder: bidder.publicKey,
+          nftMint: fixture.nftMint,
+          bid,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount,
+          escrowPaymentAccount: getAssociatedTokenAddressSync(market.paymentMint, bid, true),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          [
+            fixture.listing,
+            fixture.seller.publicKey,
+            fixture.vault,
+            bidderNftAccount,
+            fixture.sellerPaymentAccount,
+            market.feeAccount,
+            fixture.metadata,
+            fixture.tokenProgram,
+            pda(env.program, Buffer.from("buyer_record"), fixture.listing.toBuffer(), bidder.publicKey.toBuffer()),
+            pda(env.program, Buffer.from("price_history"), fixture.nftMint.toBuffer()),
+            statsPda(env.program, fixture.collectionMint),
+            pda(env.program, Buffer.from("seller_stats"), fixture.seller.publicKey.toBuffer()),
+            env.program.programId,
+          ].map((pubkey) => ({
+            pubkey,
+            isSigner: false,
+            isWritable: !pubkey.equals(fixture.tokenProgram) && !pubkey.equals(env.program.programId),
+          }))
+        )
+        .signers([bidder])
+        .rpc();
+      return { bid, bidder, bidderNftAccount };
+    }
+
+    it("settles a bid at or above the threshold as soon as it is placed", async () => {
+      const fixture = await autoAcceptListing(900_000);
+
+      const { bid, bidderNftAccount } = await bidAgainst(fixture, 950_000);
+
+      assert.equal(await tokenBalance(env, bidderNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(926_250));
+      assert.isFalse(await accountExists(env, bid));
+      assert.isFalse(await accountExists(env, fixture.listing));
+    });
+
+    it("records the sale in the collection and seller stats", async () => {
+      const fixture = await autoAcceptListing(900_000);
+
+      await bidAgainst(fixture, 950_000);
+
+      const stats = await env.program.account.collectionStats.fetch(statsPda(env.program, fixture.collectionMint));
+      assert.equal(stats.saleCount.toNumber(), 1);
+      assert.equal(stats.lastSalePrice.toNumber(), 950_000);
+      const sellerStats = await env.program.account.sellerStats.fetch(
+        pda(env.program, Buffer.from("seller_stats"), fixture.seller.publicKey.toBuffer())
+      );
+      assert.equal(sellerStats.totalSales.toNumber(), 1);
+    });
+
+    it("holds an auto-accepting bidder to the per-buyer limit", async () => {
+      const fixture = await autoAcceptListing(900_000, { quantity: 3, maxQuantityPerBuyer: 1 });
+
+      const { bidder } = await bidAgainst(fixture, 950_000);
+      await expectError(bidAgainst(fixture, 960_000, bidder), "PurchaseLimitExceeded");
+
+      assert.equal((await env.program.account.listing.fetch(fixture.listing)).quantity.toNumber(), 2);
+    });
+
+    it("leaves a bid below the threshold open", async () => {
+      const fixture = await autoAcceptListing(900_000);
+
+      const { bid } = await bidAgainst(fixture, 800_000);
+
+      assert.isTrue(await accountExists(env, bid));
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(1));
+    });
+  });
+
+  describe("listing metadata", () => {
+    it("stores a seller note that can be changed after listing", async () => {
+      const uri = "ar://" + "a".repeat(43);
+      const fixture = await createListing(env, market, { listingMetadata: uri });
+      assert.equal((await env.program.account.listing.fetch(fixture.listing)).listingMetadata, uri);
+
+      const update = (note: string) =>
+        env.program.methods
+          .updateListingMetadata(note)
+          .accountsPartial({ seller: fixture.seller.publicKey, listing: fixture.listing })
+          .signers([fixture.seller])
+          .rpc();
+      await update("signed first edition");
+      await expectError(update("x".repeat(201)), "MetadataTooLong");
+
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.equal(listing.listingMetadata, "signed first edition");
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(1));
+    });
+
+    it("rejects a note longer than 200 bytes at listing time", async () => {
+      await expectError(createListing(env, market, { listingMetadata: "x".repeat(201) }), "MetadataTooLong");
+    });
+  });
+
+  describe("nft enforcement", () => {
+    it("rejects listing a fungible token once NFTs are enforced", async () => {
+      await env.program.methods
+        .setEnforceNft(true)
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+
+      const fungibleMint = await createMint(env, 6);
+      await expectError(createListing(env, market, { nftMint: fungibleMint }), "NotAnNft");
+
+      const fixture = await createListing(env, market);
+      assert.isTrue(await accountExists(env, fixture.listing));
+    });
+  });
+
+  describe("payment mint", () => {
+    it("rejects a buyer payment account in another mint", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const otherMint = await createMint(env, 6);
+      const wrongAccount = await mintTo(env, otherMint, buyer.buyer.publicKey, 5_000_000);
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, { accounts: { buyerPaymentAccount: wrongAccount } }),
+        "ConstraintTokenMint"
+      );
+    });
+  });
+
+  describe("fee account", () => {
+    it("lets only the authority withdraw fees from the fee vault", async () => {
+      const fixture = await createListing(env, market);
+      await executeSale(env, market, fixture, await newBuyer(env, market, fixture.nftMint));
+      const destination = await createAta(env, market.paymentMint, env.payer.publicKey);
+      const withdraw = (authority: anchor.web3.Keypair, to = destination) =>
+        env.program.methods
+          .withdrawFees(new anchor.BN(25_000))
+          .accountsPartial({
+            authority: authority.publicKey,
+            paymentMint: market.paymentMint,
+            feeVaultTokenAccount: market.feeAccount,
+            destination: to,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([authority])
+          .rpc();
+
+      await expectError(withdraw(await newUser(env)), "ConstraintHasOne");
+      const elsewhere = await createAta(env, market.paymentMint, (await newUser(env)).publicKey);
+      await expectError(withdraw(env.payer, elsewhere), "InvalidFeeRecipient");
+      await withdraw(env.payer);
+
+      assert.equal(await tokenBalance(env, destination), BigInt(25_000));
+    });
+
+    it("rejects a fee account other than the fee vault's", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, {
+          accounts: { marketplaceFeeAccount: buyer.buyerPaymentAccount },
+        }),
+        "InvalidFeeAccount"
+      );
+    });
+  });
+
+  describe("native SOL", () => {
+    it("sells a SOL listing and pays the verified creator", async () => {
+      const creator = await newUser(env);
+      const fixture = await createListing(env, market, {
+        price: 1_000_000_000,
+        paymentMint: null,
+        creators: [{ address: creator.publicKey, share: 10, verified: true }],
+      });
+      const buyer = await newUser(env);
+      const buyerNftAccount = getAssociatedTokenAddressSync(fixture.nftMint, buyer.publicKey);
+      const secondBidder = await newUser(env);
+
+      const sellerBefore = await lamports(env, fixture.seller.publicKey);
+      const creatorBefore = await lamports(env, creator.publicKey);
+      const feeBefore = await lamports(env, feeVaultPda(env.program));
+      const secondBidderBefore = await lamports(env, secondBidder.publicKey);
+
+      await env.program.methods
+        // Claiming a second bid without passing it pays the second bidder nothing
+        .executeSaleSol(new anchor.BN(1_000_000_000), new anchor.BN(1_000_000_000), null, new anchor.BN(1))
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          buyerNftAccount,
+          secondBidderAccount: secondBidder.publicKey,
+          secondBid: null,
+          metadata: fixture.metadata,
+          collectionMint: fixture.nftMint,
+          whitelist: null,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([{ pubkey: creator.publicKey, isWritable: true, isSigner: false }])
+        .signers([buyer])
+        .rpc();
+
+      // 10% royalty, then 2.5% of the remainder as the marketplace fee
+      assert.equal(await tokenBalance(env, buyerNftAccount), BigInt(1));
+      assert.equal((await lamports(env, creator.publicKey)) - creatorBefore, BigInt(100_000_000));
+      assert.equal((await lamports(env, feeVaultPda(env.program))) - feeBefore, BigInt(22_500_000));
+      assert.equal(await lamports(env, secondBidder.publicKey), secondBidderBefore);
+      assert.isFalse(await accountExists(env, fixture.vault));
+      assert.isAtLeast(
+        Number((await lamports(env, fixture.seller.publicKey)) - sellerBefore),
+        877_500_000
+      );
+    });
+
+    it("collects SOL fees on the fee vault and withdraws them above its rent", async () => {
+      const fixture = await createListing(env, market, { price: 1_000_000_000, paymentMint: null });
+      const buyer = await newUser(env);
+      const feeVault = feeVaultPda(env.program);
+      const feeBefore = await lamports(env, feeVault);
+
+      await env.program.methods
+        .executeSaleSol(new anchor.BN(1_000_000_000), new anchor.BN(0), null, new anchor.BN(1))
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          buyerNftAccount: getAssociatedTokenAddressSync(fixture.nftMint, buyer.publicKey),
+          secondBidderAccount: (await newUser(env)).publicKey,
+          secondBid: null,
+          metadata: fixture.metadata,
+          collectionMint: fixture.nftMint,
+          whitelist: null,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+      assert.equal((await lamports(env, feeVault)) - feeBefore, BigInt(25_000_000));
+
+      const destination = (await newUser(env)).publicKey;
+      await env.program.methods
+        .updateConfig(new anchor.BN(250), destination)
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+      const destinationBefore = await lamports(env, destination);
+      const withdraw = (amount: number, to = destination) =>
+        env.program.methods
+          .withdrawSolFees(new anchor.BN(amount))
+          .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program), destination: to })
+          .rpc();
+
+      await expectError(withdraw(25_000_000, (await newUser(env)).publicKey), "InvalidFeeRecipient");
+      await expectError(withdraw(25_000_001), "InsufficientFeeBalance");
+      await withdraw(25_000_000);
+
+      assert.equal((await lamports(env, destination)) - destinationBefore, BigInt(25_000_000));
+      assert.equal(await lamports(env, feeVault), feeBefore);
+    });
+
+    it("treats the wrapped SOL mint as native SOL", async () => {
+      const fixture = await createListing(env, market, { paymentMint: NATIVE_MINT });
+
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.isNull(listing.paymentMint);
+    });
+
+    it("rejects paying a SOL listing with tokens", async () => {
+      const fixture = await createListing(env, market, { paymentMint: null });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(executeSale(env, market, fixture, buyer), "PaymentMintMismatch");
+    });
+
+    it("escrows a SOL bid and pays the seller when accepted", async () => {
+      const owner = await newUser(env);
+      const nftMint = await createMint(env);
+      const ownerNftAccount = await mintTo(env, nftMint, owner.publicKey, 1);
+      const metadata = setMetadata(env, nftMint);
+      const bidder = await newUser(env);
+      const bid = bidPda(env.program, bidder.publicKey, nftMint);
+
+      await env.program.methods
+        .placeBidSol(new anchor.BN(1_000_000_000), new anchor.BN(0))
+        .accountsPartial({ bidder: bidder.publicKey, nftMint, bid })
+        .signers([bidder])
+        .rpc();
+
+      const ownerBefore = await lamports(env, owner.publicKey);
+      const feeBefore = await lamports(env, feeVaultPda(env.program));
+      const secondBidder = await newUser(env);
+
+      await env.program.methods
+        .acceptBidSol(new anchor.BN(0))
+        .accountsPartial({
+          seller: owner.publicKey,
+          bid,
+          nftMint,
+          sellerNftAccount: ownerNftAccount,
+          bidderNftAccount: getAssociatedTokenAddressSync(nftMint, bidder.publicKey),
+          secondBidderAccount: secondBidder.publicKey,
+          secondBid: null,
+          metadata,
+          collectionMint: nftMint,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+
+      assert.equal(
+        await tokenBalance(env, getAssociatedTokenAddressSync(nftMint, bidder.publicKey)),
+        BigInt(1)
+      );
+      assert.equal((await lamports(env, feeVaultPda(env.program))) - feeBefore, BigInt(25_000_000));
+      assert.isAtLeast(Number((await lamports(env, owner.publicKey)) - ownerBefore), 900_000_000);
+      assert.isFalse(await accountExists(env, bid));
+    });
+  });
+
+  describe("reserve price", () => {
+    async function acceptHighestBid(fixture: ListingFixture, bid: BidFixture, secondHighestBid = 0) {
+      return env.program.methods
+        .acceptHighestBid(new anchor.BN(secondHighestBid))
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          bid: bid.bid,
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          bidderNftAccount: getAssociatedTokenAddressSync(fixture.nftMint, bid.bidder.publicKey),
+          paymentMint: market.paymentMint,
+          escrowPaymentAccount: bid.escrowPaymentAccount,
+          sellerPaymentAccount: fixture.sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          secondBidderAccount: await createAta(env, market.paymentMint, (await newUser(env)).publicKey),
+          secondBid: null,
+          metadata: fixture.metadata,
+          collectionMint: fixture.collectionMint,
+          marketStats: null,
+          tokenProgram: fixture.tokenProgram,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.seller])
+        .rpc();
+    }
+
+    it("rejects bids below the reserve", async () => {
+      const fixture = await createListing(env, market, { reservePrice: 800_000 });
+      const bid = await placeBid(env, market, fixture.nftMint, 799_999);
+
+      await expectError(acceptHighestBid(fixture, bid), "ReserveNotMet");
+    });
+
+    it("settles a bid at the reserve", async () => {
+      const fixture = await createListing(env, market, { reservePrice: 800_000 });
+      const bid = await placeBid(env, market, fixture.nftMint, 800_000);
+      await acceptHighestBid(fixture, bid);
+
+      const bidderNftAccount = getAssociatedTokenAddressSync(fixture.nftMint, bid.bidder.publicKey);
+      assert.equal(await tokenBalance(env, bidderNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(780_000));
+      assert.isFalse(await accountExists(env, fixture.listing));
+      assert.isFalse(await accountExists(env, fixture.vault));
+
+      const stats = await env.program.account.collectionStats.fetch(statsPda(env.program, fixture.collectionMint));
+      assert.equal(stats.saleCount.toNumber(), 1);
+      const sellerStats = await env.program.account.sellerStats.fetch(
+        pda(env.program, Buffer.from("seller_stats"), fixture.seller.publicKey.toBuffer())
+      );
+      assert.equal(sellerStats.totalSales.toNumber(), 1);
+    });
+
+    it("settles a bid above the reserve", async () => {
+      const fixture = await createListing(env, market, { reservePrice: 800_000 });
+      const bid = await placeBid(env, market, fixture.nftMint, 1_000_000);
+      await acceptHighestBid(fixture, bid);
+
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+    });
+
+    it("pays no second-bidder fee without a live second bid", async () => {
+      const fixture = await createListing(env, market, { reservePrice: 800_000 });
+      const bid = await placeBid(env, market, fixture.nftMint, 1_000_000);
+      const feesBefore = await tokenBalance(env, market.feeAccount);
+      await acceptHighestBid(fixture, bid, 900_000);
+
+      // The whole 2.5% fee stays with the marketplace
+      assert.equal((await tokenBalance(env, market.feeAccount)) - feesBefore, BigInt(25_000));
+    });
+
+    it("lets the seller lower but not raise the reserve", async () => {
+      const fixture = await createListing(env, market, { reservePrice: 800_000 });
+      const bid = await placeBid(env, market, fixture.nftMint, 700_000);
+      const updateReservePrice = (reservePrice: number) =>
+        env.program.methods
+          .updateReservePrice(new anchor.BN(reservePrice))
+          .accountsPartial({ seller: fixture.seller.publicKey, listing: fixture.listing })
+          .signers([fixture.seller])
+          .rpc();
+
+      await expectError(updateReservePrice(900_000), "ReservePriceIncrease");
+      await updateReservePrice(700_000);
+
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.equal(listing.reservePrice.toNumber(), 700_000);
+      await acceptHighestBid(fixture, bid);
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(682_500));
+    });
+  });
+
+  describe("accept_bid_for_listing", () => {
+    async function acceptBidForListing(
+      fixture: ListingFixture,
+      bid: BidFixture,
+      secondBid?: BidFixture,
+      secondHighestBid = secondBid ? 1_000 : 0
+    ) {
+      return env.program.methods
+        .acceptBidForListing(new anchor.BN(secondHighestBid))
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          bid: bid.bid,
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          sellerNftAccount: fixture.sellerNftAccount,
+          bidderNftAccount: getAssociatedTokenAddressSync(fixture.nftMint, bid.bidder.publicKey),
+          paymentMint: market.paymentMint,
+          escrowPaymentAccount: bid.escrowPaymentAccount,
+          sellerPaymentAccount: fixture.sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          secondBidderAccount:
+            secondBid?.bidderPaymentAccount ??
+            (await createAta(env, market.paymentMint, (await newUser(env)).publicKey)),
+          secondBid: secondBid?.bid ?? null,
+          metadata: fixture.metadata,
+          collectionMint: fixture.collectionMint,
+          tokenProgram: fixture.tokenProgram,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.seller])
+        .rpc();
+    }
+
+    it("sells the listed NFT to the bidder and closes the listing and the bid", async () => {
+      const fixture = await createListing(env, market);
+      const bid = await placeBid(env, market, fixture.nftMint, 1_000_000);
+      await acceptBidForListing(fixture, bid);
+
+      const bidderNftAccount = getAssociatedTokenAddressSync(fixture.nftMint, bid.bidder.publicKey);
+      assert.equal(await tokenBalance(env, bidderNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+      assert.isFalse(await accountExists(env, fixture.vault));
+      assert.isFalse(await accountExists(env, fixture.listing));
+      assert.isFalse(await accountExists(env, bid.bid));
+    });
+
+    it("returns the remaining units of a multi-edition listing to the seller", async () => {
+      const fixture = await createListing(env, market, { quantity: 3 });
+      const bid = await placeBid(env, market, fixture.nftMint, 1_000_000);
+      await acceptBidForListing(fixture, bid);
+
+      assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(2));
+      assert.isFalse(await accountExists(env, fixture.vault));
+      assert.isFalse(await accountExists(env, fixture.listing));
+    });
+
+    it("pays the second bidder only for a live escrowed second bid", async () => {
+      const fixture = await createListing(env, market);
+      const bid = await placeBid(env, market, fixture.nftMint, 1_000_000);
+      const secondBid = await placeBid(env, market, fixture.nftMint, 1_000);
+
+      await expectError(acceptBidForListing(fixture, bid, secondBid, 2_000), "InvalidSecondBid");
+      await acceptBidForListing(fixture, bid, secondBid);
+
+      assert.equal(await tokenBalance(env, secondBid.bidderPaymentAccount), BigInt(10_000_000));
+    });
+  });
+
+  describe("listing bids", () => {
+    async function bidOnListing(fixture: ListingFixture, price: number) {
+      const bidder = await newUser(env);
+      const bidderPaymentAccount = await mintTo(env, market.paymentMint, bidder.publicKey, 10_000_000);
+      const listingBid = pda(
+        env.program,
+        Buffer.from("listing_bid"),
+        fixture.listing.toBuffer(),
+        bidder.publicKey.toBuffer()
+      );
+      await env.program.methods
+        .bidOnListing(
+          new anchor.BN(price),
+          new anchor.BN(0),
+          new anchor.BN(((await now(env)) + BigInt(60)).toString())
+        )
+        .accountsPartial({
+          bidder: bidder.publicKey,
+          listing: fixture.listing,
+          listingBid,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([bidder])
+        .rpc();
+      return { bidder, bidderPaymentAccount, listingBid };
+    }
+
+    function acceptListingBid(
+      fixture: ListingFixture,
+      bid: { bidder: anchor.web3.Keypair; listingBid: PublicKey }
+    ) {
+      return env.program.methods
+        .acceptListingBid()
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          listingBid: bid.listingBid,
+          bidder: bid.bidder.publicKey,
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          paymentMint: market.paymentMint,
+          sellerPaymentAccount: fixture.sellerPaymentAccount,
+          marketplaceFeeAccount: market.feeAccount,
+          metadata: fixture.metadata,
+          collectionMint: fixture.collectionMint,
+          tokenProgram: fixture.tokenProgram,
+          paymentTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.seller])
+        .rpc();
+    }
+
+    it("sells a unit of the listing the bid was placed on", async () => {
+      const fixture = await createListing(env, market, { quantity: 2 });
+      const bid = await bidOnListing(fixture, 900_000);
+      await acceptListingBid(fixture, bid);
+
+      const bidderNftAccount = getAssociatedTokenAddressSync(fixture.nftMint, bid.bidder.publicKey);
+      assert.equal(await tokenBalance(env, bidderNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(877_500));
+      assert.isFalse(await accountExists(env, bid.listingBid));
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.equal(listing.quantity.toNumber(), 1);
+    });
+
+    it("rejects a bid from before the NFT was relisted", async () => {
+      const fixture = await createListing(env, market);
+      const bid = await bidOnListing(fixture, 900_000);
+
+      // The vault isn't closed on cancel yet, so stand in for a relisting at
+      // the same PDA by rewriting the listing's creation time and price.
+      const info = await env.context.banksClient.getAccount(fixture.listing);
+      const decoded = env.program.coder.accounts.decode(
+        "listing",
+        Buffer.from(info.data)
+      );
+      decoded.createdAt = decoded.createdAt.addn(10);
+      decoded.price = new anchor.BN(2_000_000);
+      const encoded = await env.program.coder.accounts.encode(
+        "listing",
+        decoded
+      );
+      const data = Buffer.alloc(info.data.length);
+      encoded.copy(data);
+      env.context.setAccount(fixture.listing, { ...info, data });
+
+      await expectError(acceptListingBid(fixture, bid), "BidListingMismatch");
+    });
+
+    it("refunds the escrow when the bidder cancels", async () => {
+      const fixture = await createListing(env, market);
+      const bid = await bidOnListing(fixture, 900_000);
+      await env.program.methods
+        .cancelListingBid()
+        .accountsPartial({
+          bidder: bid.bidder.publicKey,
+          listingBid: bid.listingBid,
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount: bid.bidderPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([bid.bidder])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, bid.bidderPaymentAccount), BigInt(10_000_000));
+      assert.isFalse(await accountExists(env, bid.listingBid));
+    });
+  });
+
+  describe("nft loans", () => {
+    const collateral = 1_000_000_000;
+
+    async function requestLoan(fixture: ListingFixture, duration = 3_600) {
+      const borrower = await newUser(env);
+      const loanEscrow = pda(env.program, Buffer.from("loan_escrow"), fixture.listing.toBuffer());
+      const borrowerNftAccount = getAssociatedTokenAddressSync(fixture.nftMint, borrower.publicKey);
+      await env.program.methods
+        .requestNftLoan(new anchor.BN(collateral), new anchor.BN(duration), new anchor.BN(500))
+        .accountsPartial({
+          borrower: borrower.publicKey,
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          borrowerNftAccount,
+          loanEscrow,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([borrower, fixture.seller])
+        .rpc();
+      return { borrower, loanEscrow, borrowerNftAccount };
+    }
+
+    it("lends a listed NFT against collateral and takes it back on repayment", async () => {
+      const fixture = await createListing(env, market);
+      const loan = await requestLoan(fixture);
+
+      assert.equal(await tokenBalance(env, loan.borrowerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(0));
+      const lockedLamports = await lamports(env, loan.loanEscrow);
+
+      const lenderBefore = await lamports(env, fixture.seller.publicKey);
+      await env.program.methods
+        .repayNftLoan()
+        .accountsPartial({
+          borrower: loan.borrower.publicKey,
+          lender: fixture.seller.publicKey,
+          loanEscrow: loan.loanEscrow,
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          borrowerNftAccount: loan.borrowerNftAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([loan.borrower])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(1));
+      assert.equal(await lamports(env, fixture.seller.publicKey), lenderBefore + BigInt(50_000_000));
+      assert.equal(await lamports(env, loan.loanEscrow), lockedLamports - BigInt(collateral));
+      const state = await env.program.account.loanEscrow.fetch(loan.loanEscrow);
+      assert.deepEqual(state.status, { repaid: {} });
+    });
+
+    it("gives the lender the collateral once the loan is past due", async () => {
+      const fixture = await createListing(env, market);
+      const loan = await requestLoan(fixture);
+      const liquidate = () =>
+        env.program.methods
+          .liquidateDefaultedLoan()
+          .accountsPartial({
+            seller: fixture.seller.publicKey,
+            listing: fixture.listing,
+            loanEscrow: loan.loanEscrow,
+            vaultNftAccount: fixture.vault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([fixture.seller])
+          .rpc();
+
+      await expectError(liquidate(), "LoanNotDue");
+
+      await warpTo(env, (await now(env)) + BigInt(3_601));
+      const lenderBefore = await lamports(env, fixture.seller.publicKey);
+      await liquidate();
+
+      assert.isTrue((await lamports(env, fixture.seller.publicKey)) - lenderBefore > BigInt(collateral));
+      assert.isFalse(await accountExists(env, fixture.listing));
+      assert.isFalse(await accountExists(env, fixture.vault));
+      assert.equal(await tokenBalance(env, loan.borrowerNftAccount), BigInt(1));
+      const state = await env.program.account.loanEscrow.fetch(loan.loanEscrow);
+      assert.deepEqual(state.status, { liquidated: {} });
+    });
+  });
+
+  describe("market stats", () => {
+    it("keeps running totals across sales", async () => {
+      const marketStats = pda(env.program, Buffer.from("stats"));
+      const sell = async (price: number, accounts: Record<string, PublicKey> = {}) => {
+        const fixture = await createListing(env, market, { price });
+        const buyer = await newBuyer(env, market, fixture.nftMint);
+        await executeSale(env, market, fixture, buyer, { accounts });
+      };
+
+      // Sales made before the stats account exists are simply not counted
+      await sell(5_000_000);
+      await env.program.methods
+        .initializeMarketStats()
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program), marketStats })
+        .rpc();
+
+      await sell(1_000_000, { marketStats });
+      await sell(2_000_000, { marketStats });
+
+      const stats = await env.program.account.marketStats.fetch(marketStats);
+      assert.equal(stats.totalSales.toNumber(), 2);
+      assert.equal(stats.totalVolume.toString(), "3000000");
+      assert.equal(stats.totalFeesCollected.toString(), "75000");
+    });
+  });
+
+  describe("listing events", () => {
+    it("emits the vault the NFT is escrowed in", async () => {
+      const seller = await newUser(env);
+      const nftMint = await createMint(env);
+      const sellerNftAccount = await mintTo(env, nftMint, seller.publicKey, 1);
+      const metadata = setMetadata(env, nftMint);
+
+      const { events } = await env.program.methods
+        .createListing(
+          new anchor.BN(1_000_000),
+          new anchor.BN(1),
+          new anchor.BN(0),
+          false,
+          market.paymentMint,
+          { mandatory: {} } as any,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          new anchor.BN(0),
+          null,
+          ""
+        )
+        .accountsPartial({
+          seller: seller.publicKey,
+          nftMint,
+          listing: pda(env.program, Buffer.from("listing"), seller.publicKey.toBuffer(), nftMint.toBuffer()),
+          sellerNftAccount,
+          vaultNftAccount: vaultPda(env.program, seller.publicKey, nftMint),
+          metadata,
+          collectionPolicy: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([seller])
+        .simulate();
+      const created = events.find((event) => event.name === "listingCreated").data;
+
+      const [vault, vaultBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), seller.publicKey.toBuffer(), nftMint.toBuffer()],
+        env.program.programId
+      );
+      assert.ok(created.vault.equals(vault));
+      assert.equal(created.vaultBump, vaultBump);
+    });
+  });
+
+  describe("collection policy", () => {
+    async function setPolicy(collection: PublicKey, minPrice: number, maxPrice: number, authority = env.payer) {
+      const collectionPolicy = pda(env.program, Buffer.from("policy"), collection.toBuffer());
+      await env.program.methods
+        .setCollectionPolicy(new anchor.BN(minPrice), new anchor.BN(maxPrice))
+        .accountsPartial({
+          authority: authority.publicKey,
+          collectionMint: collection,
+          collectionMetadata: setMetadata(env, collection),
+          collectionPolicy,
+        })
+        .signers([authority])
+        .rpc();
+      return collectionPolicy;
+    }
+
+    it("rejects listings priced below the floor or above the ceiling", async () => {
+      const collection = await createMint(env);
+      const collectionPolicy = await setPolicy(collection, 500_000, 2_000_000);
+
+      await expectError(
+        createListing(env, market, { collection, collectionPolicy, price: 499_999 }),
+        "PriceOutOfPolicyRange"
+      );
+      await expectError(
+        createListing(env, market, { collection, collectionPolicy, price: 2_000_001 }),
+        "PriceOutOfPolicyRange"
+      );
+      await createListing(env, market, { collection, collectionPolicy, price: 2_000_000 });
+    });
+
+    it("can't be skipped by leaving the policy account out", async () => {
+      const collection = await createMint(env);
+      await setPolicy(collection, 500_000, 2_000_000);
+
+      await expectError(
+        createListing(env, market, { collection, collectionPolicy: null, price: 1 }),
+        "InvalidCollectionPolicy"
+      );
+      await expectError(createListing(env, market, { collection, price: 1 }), "PriceOutOfPolicyRange");
+    });
+
+    it("only applies to NFTs in the policy's collection", async () => {
+      const collectionPolicy = await setPolicy(await createMint(env), 500_000, 2_000_000);
+
+      await expectError(
+        createListing(env, market, { collection: await createMint(env), collectionPolicy }),
+        "InvalidCollectionPolicy"
+      );
+    });
+
+    it("can only be set by the collection's update authority", async () => {
+      await expectError(
+        setPolicy(await createMint(env), 0, 1_000_000, await newUser(env)),
+        "UnauthorizedCollectionPolicy"
+      );
+    });
+  });
+
+  describe("listing validation", () => {
+    it("rejects a zero price", async () => {
+      await expectError(createListing(env, market, { price: 0 }), "InvalidPrice");
+    });
+
+    it("rejects a zero quantity", async () => {
+      await expectError(createListing(env, market, { quantity: 0 }), "InvalidQuantity");
+    });
+
+    it("rejects an expiry in the past", async () => {
+      const expiry = (await now(env)) - BigInt(1);
+      await expectError(createListing(env, market, { expiry }), "ListingExpired");
+    });
+  });
+
+  describe("cancel_listing", () => {
+    it("returns the NFT from the vault PDA to the seller", async () => {
+      const fixture = await createListing(env, market);
+      assert.ok(fixture.vault.equals(vaultPda(env.program, fixture.seller.publicKey, fixture.nftMint)));
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(1));
+
+      await env.program.methods
+        .cancelListing()
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          sellerNftAccount: fixture.sellerNftAccount,
+          vaultNftAccount: fixture.vault,
+          tokenProgram: fixture.tokenProgram,
+        })
+        .signers([fixture.seller])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(1));
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(0));
+      assert.isFalse(await accountExists(env, fixture.listing));
+    });
+
+    it("records the listing as cancelled as it closes", async () => {
+      const fixture = await createListing(env, market);
+
+      const { events } = await env.program.methods
+        .cancelListing()
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          sellerNftAccount: fixture.sellerNftAccount,
+          vaultNftAccount: fixture.vault,
+          tokenProgram: fixture.tokenProgram,
+        })
+        .signers([fixture.seller])
+        .simulate();
+      const closed = events.find((event) => event.name === "listingClosed").data;
+
+      assert.ok(closed.listingId.equals(fixture.listing));
+      assert.ok(closed.status.cancelled);
+    });
+
+    it("keeps each seller's units of a shared mint in their own vault", async () => {
+      const first = await createListing(env, market, { quantity: 2 });
+      const second = await createListing(env, market, { nftMint: first.nftMint, quantity: 3 });
+      assert.isFalse(first.vault.equals(second.vault));
+
+      await env.program.methods
+        .cancelListing()
+        .accountsPartial({
+          seller: first.seller.publicKey,
+          listing: first.listing,
+          nftMint: first.nftMint,
+          sellerNftAccount: first.sellerNftAccount,
+          vaultNftAccount: first.vault,
+          tokenProgram: first.tokenProgram,
+        })
+        .signers([first.seller])
+        .rpc();
+
+      assert.equal(await tokenBalance(env, first.sellerNftAccount), BigInt(2));
+      assert.equal(await tokenBalance(env, second.vault), BigInt(3));
+    });
+
+    it("refunds the rent of both the listing and the vault", async () => {
+      const fixture = await createListing(env, market);
+      const sellerStats = pda(env.program, Buffer.from("seller_stats"), fixture.seller.publicKey.toBuffer());
+      const listingRent = await lamports(env, fixture.listing);
+      const vaultRent = await lamports(env, fixture.vault);
+      const sellerBefore = await lamports(env, fixture.seller.publicKey);
+
+      await env.program.methods
+        .cancelListing()
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          sellerNftAccount: fixture.sellerNftAccount,
+          vaultNftAccount: fixture.vault,
+          tokenProgram: fixture.tokenProgram,
+        })
+        .signers([fixture.seller])
+        .rpc();
+
+      assert.isFalse(await accountExists(env, fixture.vault));
+      // The seller also funds their stats account on first cancel
+      const sellerAfter = (await lamports(env, fixture.seller.publicKey)) + (await lamports(env, sellerStats));
+      assert.equal(sellerAfter - sellerBefore, listingRent + vaultRent);
+    });
+  });
+
+  describe("batch_cancel_expired_listings", () => {
+    const crankAccounts = (fixture: ListingFixture) =>
+      [
+        fixture.listing,
+        fixture.seller.publicKey,
+        fixture.nftMint,
+        fixture.vault,
+        fixture.sellerNftAccount,
+      ].map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }));
+
+    const crank = (crankOperator: anchor.web3.Keypair, fixtures: ListingFixture[]) =>
+      env.program.methods
+        .batchCancelExpiredListings()
+        .accountsPartial({
+          crankOperator: crankOperator.publicKey,
+          config: configPda(env.program),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(fixtures.flatMap(crankAccounts))
+        .signers([crankOperator])
+        .rpc();
+
+    it("returns expired NFTs and splits the listing rent with the crank", async () => {
+      const reward = 100_000;
+      await env.program.methods
+        .setCrankReward(new anchor.BN(reward))
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+
+      const expiry = (await now(env)) + BigInt(60);
+      const fixtures = [
+        await createListing(env, market, { expiry }),
+        await createListing(env, market, { expiry }),
+      ];
+      const crankOperator = await newUser(env);
+      const rent = await lamports(env, fixtures[0].listing);
+      const sellerBefore = await lamports(env, fixtures[0].seller.publicKey);
+      const crankBefore = await lamports(env, crankOperator.publicKey);
+
+      await expectError(crank(crankOperator, fixtures), "ListingNotExpired");
+
+      await warpTo(env, expiry + BigInt(1));
+      await crank(crankOperator, fixtures);
+
+      for (const fixture of fixtures) {
+        assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(1));
+        assert.isFalse(await accountExists(env, fixture.listing));
+      }
+      assert.equal(
+        await lamports(env, crankOperator.publicKey),
+        crankBefore + BigInt(2 * reward)
+      );
+      assert.equal(
+        await lamports(env, fixtures[0].seller.publicKey),
+        sellerBefore + rent - BigInt(reward)
+      );
+    });
+
+    it("never closes a listing without an expiry", async () => {
+      const fixture = await createListing(env, market, { expiry: 0 });
+      const crankOperator = await newUser(env);
+
+      await warpTo(env, (await now(env)) + BigInt(365 * 24 * 60 * 60));
+      await expectError(crank(crankOperator, [fixture]), "ListingNotExpired");
+    });
+  });
+
+  describe("sweep_expired_listings", () => {
+    const sweep = (fixtures: ListingFixture[]) =>
+      env.program.methods
+        .sweepExpiredListings()
+        .accountsPartial({ crankOperator: env.payer.publicKey })
+        .remainingAccounts(
+          fixtures.map((fixture) => ({ pubkey: fixture.listing, isSigner: false, isWritable: true }))
+        )
+        .rpc();
+
+    it("marks expired listings as Expired and leaves them open", async () => {
+      const expiry = (await now(env)) + BigInt(60);
+      const fixtures = [
+        await createListing(env, market, { expiry }),
+        await createListing(env, market, { expiry }),
+      ];
+      const listing = await env.program.account.listing.fetch(fixtures[0].listing);
+      assert.ok(listing.listingStatus.active);
+
+      await expectError(sweep(fixtures), "ListingNotExpired");
+
+      await warpTo(env, expiry + BigInt(1));
+      await sweep(fixtures);
+
+      for (const fixture of fixtures) {
+        const swept = await env.program.account.listing.fetch(fixture.listing);
+        assert.ok(swept.listingStatus.expired);
+        assert.equal(await tokenBalance(env, fixture.vault), BigInt(1));
+      }
+      await expectError(sweep(fixtures), "ListingNotActive");
+    });
+  });
+
+  describe("close_empty_vault", () => {
+    it("returns the vault rent once the listing has closed", async () => {
+      const expiry = (await now(env)) + BigInt(60);
+      const fixture = await createListing(env, market, { expiry });
+      const closeVault = () =>
+        env.program.methods
+          .closeEmptyVault()
+          .accountsPartial({
+            seller: fixture.seller.publicKey,
+            nftMint: fixture.nftMint,
+            vaultNftAccount: fixture.vault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([fixture.seller])
+          .rpc();
+
+      await expectError(closeVault(), "VaultNotEmpty");
+
+      await warpTo(env, expiry + BigInt(1));
+      await env.program.methods
+        .closeExpiredListing()
+        .accountsPartial({
+          keeper: env.payer.publicKey,
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          config: configPda(env.program),
+          nftMint: fixture.nftMint,
+          sellerNftAccount: fixture.sellerNftAccount,
+          vaultNftAccount: fixture.vault,
+          tokenProgram: fixture.tokenProgram,
+        })
+        .rpc();
+      assert.isFalse(await accountExists(env, fixture.listing));
+      const vaultRent = await lamports(env, fixture.vault);
+      const sellerBefore = await lamports(env, fixture.seller.publicKey);
+
+      // Only the seller whose vault it is can reclaim the rent
+      const intruder = await newUser(env);
+      await expectError(
+        env.program.methods
+          .closeEmptyVault()
+          .accountsPartial({
+            seller: intruder.publicKey,
+            nftMint: fixture.nftMint,
+            vaultNftAccount: fixture.vault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([intruder])
+          .rpc(),
+        "ConstraintSeeds"
+      );
+
+      await closeVault();
+
+      assert.isFalse(await accountExists(env, fixture.vault));
+      assert.equal(await lamports(env, fixture.seller.publicKey), sellerBefore + vaultRent);
+    });
+  });
+
+  describe("close_expired_listing", () => {
+    it("lets a third party return an expired listing to the seller", async () => {
+      const expiry = (await now(env)) + BigInt(60);
+      const fixture = await createListing(env, market, { expiry });
+      const keeper = await newUser(env);
+      const close = () =>
+        env.program.methods
+          .closeExpiredListing()
+          .accountsPartial({
+            keeper: keeper.publicKey,
+            seller: fixture.seller.publicKey,
+            listing: fixture.listing,
+            config: configPda(env.program),
+            nftMint: fixture.nftMint,
+            sellerNftAccount: fixture.sellerNftAccount,
+            vaultNftAccount: fixture.vault,
+            tokenProgram: fixture.tokenProgram,
+          })
+          .signers([keeper])
+          .rpc();
+
+      await expectError(close(), "ListingNotExpired");
+
+      const rent = await lamports(env, fixture.listing);
+      const sellerBefore = await lamports(env, fixture.seller.publicKey);
+      await warpTo(env, expiry + BigInt(1));
+      await close();
+
+      assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(1));
+      assert.isFalse(await accountExists(env, fixture.listing));
+      assert.equal(await lamports(env, fixture.seller.publicKey), sellerBefore + rent);
+    });
+  });
+
+  describe("refund expired bids", () => {
+    const reward = 100_000;
+
+    beforeEach(async () => {
+      await env.program.methods
+        .setCrankReward(new anchor.BN(reward))
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+    });
+
+    async function expiringBid(expiry: bigint) {
+      return placeBid(env, market, await createMint(env), 1_000_000, 10_000_000, undefined, undefined, expiry);
+    }
+
+    function refund(keeper: anchor.web3.Keypair, fixture: BidFixture) {
+      return env.program.methods
+        .refundExpiredBid()
+        .accountsPartial({
+          keeper: keeper.publicKey,
+          bidder: fixture.bidder.publicKey,
+          bid: fixture.bid,
+          config: configPda(env.program),
+          paymentMint: market.paymentMint,
+          bidderPaymentAccount: fixture.bidderPaymentAccount,
+          escrowPaymentAccount: fixture.escrowPaymentAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([keeper])
+        .rpc();
+    }
+
+    function batchRefund(crankOperator: anchor.web3.Keypair, fixtures: BidFixture[]) {
+      return env.program.methods
+        .batchRefundExpiredBids()
+        .accountsPartial({
+          crankOperator: crankOperator.publicKey,
+          config: configPda(env.program),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          fixtures
+            .flatMap((fixture) => [
+              fixture.bid,
+              fixture.bidder.publicKey,
+              pda(env.program, Buffer.from("bid_book"), fixture.nftMint.toBuffer()),
+              market.paymentMint,
+              fixture.escrowPaymentAccount,
+              fixture.bidderPaymentAccount,
+            ])
+            .map((pubkey) => ({ pubkey, isSigner: false, isWritable: !pubkey.equals(market.paymentMint) }))
+        )
+        .signers([crankOperator])
+        .rpc();
+    }
+
+    it("returns the escrow of an expired bid and tips the keeper", async () => {
+      const expiry = (await now(env)) + BigInt(60);
+      const fixture = await expiringBid(expiry);
+      const keeper = await newUser(env);
+      const rent = (await lamports(env, fixture.bid)) + (await lamports(env, fixture.escrowPaymentAccount));
+      const bidderBefore = await lamports(env, fixture.bidder.publicKey);
+      const keeperBefore = await lamports(env, keeper.publicKey);
+
+      await expectError(refund(keeper, fixture), "BidNotExpired");
+
+      await warpTo(env, expiry + BigInt(1));
+      await refund(keeper, fixture);
+
+      assert.equal(await tokenBalance(env, fixture.bidderPaymentAccount), BigInt(10_000_000));
+      assert.isFalse(await accountExists(env, fixture.bid));
+      assert.isFalse(await accountExists(env, fixture.escrowPaymentAccount));
+      assert.equal(await lamports(env, keeper.publicKey), keeperBefore + BigInt(reward));
+      assert.equal(
+        await lamports(env, fixture.bidder.publicKey),
+        bidderBefore + rent - BigInt(reward)
+      );
+    });
+
+    it("never refunds a bid without an expiry", async () => {
+      const fixture = await expiringBid(BigInt(0));
+      const keeper = await newUser(env);
+
+      await warpTo(env, (await now(env)) + BigInt(365 * 24 * 60 * 60));
+      await expectError(refund(keeper, fixture), "BidNotExpired");
+      await expectError(batchRefund(keeper, [fixture]), "BidNotExpired");
+    });
+
+    it("refunds a batch of expired bids but rejects one that is still live", async () => {
+      const expiry = (await now(env)) + BigInt(60);
+      const expired = [await expiringBid(expiry), await expiringBid(expiry)];
+      const live = await expiringBid(expiry + BigInt(600));
+      const crankOperator = await newUser(env);
+      const crankBefore = await lamports(env, crankOperator.publicKey);
+
+      await warpTo(env, expiry + BigInt(1));
+      await expectError(batchRefund(crankOperator, [...expired, live]), "BidNotExpired");
+      await batchRefund(crankOperator, expired);
+
+      for (const fixture of expired) {
+        assert.equal(await tokenBalance(env, fixture.bidderPaymentAccount), BigInt(10_000_000));
+        assert.isFalse(await accountExists(env, fixture.bid));
+      }
+      assert.isTrue(await accountExists(env, live.bid));
+      assert.equal(await tokenBalance(env, live.escrowPaymentAccount), BigInt(1_000_000));
+      assert.equal(
+        await lamports(env, crankOperator.publicKey),
+        crankBefore + BigInt(2 * reward)
+      );
+    });
+
+    it("returns the lamports of expired SOL bids", async () => {
+      const expiry = (await now(env)) + BigInt(60);
+      async function solBid() {
+        const bidder = await newUser(env);
+        const nftMint = await createMint(env);
+        const bid = bidPda(env.program, bidder.publicKey, nftMint);
+        await env.program.methods
+          .placeBidSol(new anchor.BN(1_000_000_000), new anchor.BN(expiry.toString()))
+          .accountsPartial({ bidder: bidder.publicKey, nftMint, bid })
+          .signers([bidder])
+          .rpc();
+        const held = (await lamports(env, bidder.publicKey)) + (await lamports(env, bid));
+        return { bidder, nftMint, bid, held };
+      }
+      const single = await solBid();
+      const batched = await solBid();
+      const keeper = await newUser(env);
+      const keeperBefore = await lamports(env, keeper.publicKey);
+
+      await warpTo(env, expiry + BigInt(1));
+      await env.program.methods
+        .refundExpiredBid()
+        .accountsPartial({
+          keeper: keeper.publicKey,
+          bidder: single.bidder.publicKey,
+          bid: single.bid,
+          config: configPda(env.program),
+          paymentMint: null,
+          bidderPaymentAccount: null,
+          escrowPaymentAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([keeper])
+        .rpc();
+      // SOL bids pass the program id in place of the token accounts
+      await env.program.methods
+        .batchRefundExpiredBids()
+        .accountsPartial({
+          crankOperator: keeper.publicKey,
+          config: configPda(env.program),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          [
+            batched.bid,
+            batched.bidder.publicKey,
+            pda(env.program, Buffer.from("bid_book"), batched.nftMint.toBuffer()),
+          ]
+            .map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+            .concat(
+              Array(3).fill({ pubkey: env.program.programId, isSigner: false, isWritable: false })
+            )
+        )
+        .signers([keeper])
+        .rpc();
+
+      for (const fixture of [single, batched]) {
+        assert.isFalse(await accountExists(env, fixture.bid));
+        assert.equal(
+          await lamports(env, fixture.bidder.publicKey),
+          fixture.held - BigInt(reward)
+        );
+      }
+      assert.equal(await lamports(env, keeper.publicKey), keeperBefore + BigInt(2 * reward));
+    });
+  });
+
+  describe("expiry grace period", () => {
+    async function setGrace(secs: number) {
+      await env.program.methods
+        .setExpiryGrace(new anchor.BN(secs))
+        .accountsPartial({ authority: env.payer.publicKey, config: configPda(env.program) })
+        .rpc();
+    }
+
+    function closeExpired(fixture: ListingFixture) {
+      return env.program.methods
+        .closeExpiredListing()
+        .accountsPartial({
+          keeper: env.payer.publicKey,
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          config: configPda(env.program),
+          nftMint: fixture.nftMint,
+          sellerNftAccount: fixture.sellerNftAccount,
+          vaultNftAccount: fixture.vault,
+          tokenProgram: fixture.tokenProgram,
+        })
+        .rpc();
+    }
+
+    it("sells at expiry and within the grace period", async () => {
+      await setGrace(300);
+      const expiry = (await now(env)) + BigInt(60);
+      const atExpiry = await createListing(env, market, { expiry });
+      const inGrace = await createListing(env, market, { expiry });
+
+      await warpTo(env, expiry);
+      await executeSale(env, market, atExpiry, await newBuyer(env, market, atExpiry.nftMint));
+
+      await warpTo(env, expiry + BigInt(300));
+      await expectError(closeExpired(inGrace), "ListingNotExpired");
+      await executeSale(env, market, inGrace, await newBuyer(env, market, inGrace.nftMint));
+
+      assert.isFalse(await accountExists(env, atExpiry.listing));
+      assert.isFalse(await accountExists(env, inGrace.listing));
+    });
+
+    it("applies the same grace period to SOL listings", async () => {
+      await setGrace(300);
+      const expiry = (await now(env)) + BigInt(60);
+      const fixture = await createListing(env, market, { expiry, price: 1_000_000_000, paymentMint: null });
+      const buyer = await newUser(env);
+
+      await warpTo(env, expiry + BigInt(300));
+      await env.program.methods
+        .executeSaleSol(new anchor.BN(1_000_000_000), new anchor.BN(0), null, new anchor.BN(1))
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          vaultNftAccount: fixture.vault,
+          buyerNftAccount: getAssociatedTokenAddressSync(fixture.nftMint, buyer.publicKey),
+          secondBidderAccount: (await newUser(env)).publicKey,
+          secondBid: null,
+          metadata: fixture.metadata,
+          collectionMint: fixture.nftMint,
+          whitelist: null,
+          marketStats: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+
+      assert.isFalse(await accountExists(env, fixture.listing));
+    });
+
+    it("rejects sales and allows closing once the grace period is over", async () => {
+      await setGrace(300);
+      const expiry = (await now(env)) + BigInt(60);
+      const fixture = await createListing(env, market, { expiry });
+
+      await warpTo(env, expiry + BigInt(301));
+      await expectError(
+        executeSale(env, market, fixture, await newBuyer(env, market, fixture.nftMint)),
+        "ListingExpired"
+      );
+      await closeExpired(fixture);
+
+      assert.equal(await tokenBalance(env, fixture.sellerNftAccount), BigInt(1));
+    });
+  });
+
+  describe("sale events", () => {
+    it("breaks the sale price down into fees, royalties and seller proceeds", async () => {
+      const creator = await newUser(env);
+      const fixture = await createListing(env, market, {
+        price: 1_000_000,
+        creators: [{ address: creator.publicKey, share: 10, verified: true }],
+      });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const creatorAccount = await createAta(env, market.paymentMint, creator.publicKey);
+
+      const { events } = await executeSaleMethod(env, market, fixture, buyer)
+        .remainingAccounts([{ pubkey: creatorAccount, isSigner: false, isWritable: true }])
+        .simulate();
+      const sale = events.find((event) => event.name === "saleExecuted").data;
+
+      assert.equal(sale.totalCreatorRoyalty.toNumber(), 100_000);
+      assert.equal(
+        sale.marketplaceFee.toNumber() +
+          sale.secondBidderFee.toNumber() +
+          sale.totalCreatorRoyalty.toNumber() +
+          sale.sellerPayment.toNumber(),
+        sale.price.toNumber()
+      );
+    });
+
+    it("names the second bidder and what they were paid", async () => {
+      const fixture = await createListing(env, market, { price: 1_000_000 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      const secondBid = await placeBid(env, market, fixture.nftMint, 500_000);
+
+      const { events } = await executeSaleMethod(env, market, fixture, buyer, {
+        secondHighestBid: 500_000,
+        accounts: { secondBid: secondBid.bid, secondBidderAccount: secondBid.bidderPaymentAccount },
+      }).simulate();
+      const sale = events.find((event) => event.name === "saleExecuted").data;
+
+      assert.ok(sale.secondBidder.equals(secondBid.bidder.publicKey));
+      assert.equal(sale.secondBidderFee.toNumber(), 2_500);
+    });
+
+    it("records the listing as sold out once its last unit sells", async () => {
+      const fixture = await createListing(env, market, { quantity: 2 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      const partial = await executeSaleMethod(env, market, fixture, buyer, { buyQuantity: 1 }).simulate();
+      assert.isUndefined(partial.events.find((event) => event.name === "listingClosed"));
+
+      const { events } = await executeSaleMethod(env, market, fixture, buyer, { buyQuantity: 2 }).simulate();
+      const closed = events.find((event) => event.name === "listingClosed").data;
+      assert.ok(closed.listingId.equals(fixture.listing));
+      assert.ok(closed.status.soldOut);
+    });
+  });
+
+  describe("private listings", () => {
+    it("only sells to the allowlisted buyer until the seller clears it", async () => {
+      const counterparty = anchor.web3.Keypair.generate().publicKey;
+      const fixture = await createListing(env, market, { buyerAllowlist: counterparty });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(executeSale(env, market, fixture, buyer), "UnauthorizedBuyer");
+
+      await env.program.methods
+        .updateListingBuyerAllowlist(buyer.buyer.publicKey)
+        .accountsPartial({ seller: fixture.seller.publicKey, listing: fixture.listing })
+        .signers([fixture.seller])
+        .rpc();
+      await executeSale(env, market, fixture, buyer);
+
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(1));
+    });
+
+    it("sells to the buyer named when the listing was created", async () => {
+      const allowed = await newUser(env);
+      const fixture = await createListing(env, market, { buyerAllowlist: allowed.publicKey });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      await expectError(executeSale(env, market, fixture, buyer), "UnauthorizedBuyer");
+
+      const allowedPaymentAccount = await mintTo(env, market.paymentMint, allowed.publicKey, 1_000_000_000);
+      await executeSale(env, market, fixture, {
+        ...buyer,
+        buyer: allowed,
+        buyerPaymentAccount: allowedPaymentAccount,
+        buyerNftAccount: getAssociatedTokenAddressSync(fixture.nftMint, allowed.publicKey),
+      });
+
+      assert.equal(await tokenBalance(env, fixture.sellerPaymentAccount), BigInt(975_000));
+    });
+
+    it("opens a private listing to everyone when cleared", async () => {
+      const counterparty = anchor.web3.Keypair.generate().publicKey;
+      const fixture = await createListing(env, market, { buyerAllowlist: counterparty });
+
+      await env.program.methods
+        .updateListingBuyerAllowlist(null)
+        .accountsPartial({ seller: fixture.seller.publicKey, listing: fixture.listing })
+        .signers([fixture.seller])
+        .rpc();
+
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.isNull(listing.buyerAllowlist);
+    });
+  });
+
+  // Needs a program built with `anchor build -- --features compute-logging`
+  (process.env.COMPUTE_LOGGING ? describe : describe.skip)("compute logging", () => {
+    it("logs the remaining compute units around execute_sale", async () => {
+      const fixture = await createListing(env, market);
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      const { raw } = await executeSaleMethod(env, market, fixture, buyer).simulate();
+      const start = raw.findIndex((log) => log.includes("execute_sale: start"));
+      const end = raw.findIndex((log) => log.includes("execute_sale: end"));
+
+      assert.isAbove(start, -1);
+      assert.isAbove(end, start);
+      assert.match(raw[start + 1], /consumption: \d+ units remaining/);
+      assert.match(raw[end + 1], /consumption: \d+ units remaining/);
+    });
+  });
+
+  describe("scheduled drops", () => {
+    it("rejects purchases until the listing goes live", async () => {
+      const goesLiveAt = (await now(env)) + BigInt(3_600);
+      const fixture = await createListing(env, market, { quantity: 2, goesLiveAt });
+
+      await expectError(
+        executeSale(env, market, fixture, await newBuyer(env, market, fixture.nftMint)),
+        "ListingNotYetLive"
+      );
+
+      await warpTo(env, goesLiveAt);
+      await executeSale(env, market, fixture, await newBuyer(env, market, fixture.nftMint));
+
+      await warpTo(env, goesLiveAt + BigInt(60));
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      await executeSale(env, market, fixture, buyer);
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(1));
+    });
+
+    it("rejects a listing that goes live after it expires", async () => {
+      const expiry = (await now(env)) + BigInt(3_600);
+      await expectError(
+        createListing(env, market, { expiry, goesLiveAt: expiry + BigInt(1) }),
+        "InvalidGoLiveTime"
+      );
+    });
+  });
+
+  describe("purchase limits", () => {
+    it("caps the units a single buyer can take", async () => {
+      const fixture = await createListing(env, market, { quantity: 3, maxQuantityPerBuyer: 1 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, { buyQuantity: 2 }),
+        "PurchaseLimitExceeded"
+      );
+      await executeSale(env, market, fixture, buyer);
+      await expectError(executeSale(env, market, fixture, buyer), "PurchaseLimitExceeded");
+
+      const other = await newBuyer(env, market, fixture.nftMint);
+      await executeSale(env, market, fixture, other);
+      const buyerRecord = pda(
+        env.program,
+        Buffer.from("buyer_record"),
+        fixture.listing.toBuffer(),
+        other.buyer.publicKey.toBuffer()
+      );
+      const record = await env.program.account.buyerPurchaseRecord.fetch(buyerRecord);
+      assert.equal(record.purchased.toNumber(), 1);
+    });
+
+    it("closes the purchase record with the listing", async () => {
+      const fixture = await createListing(env, market, { maxQuantityPerBuyer: 1 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+      await executeSale(env, market, fixture, buyer);
+
+      const buyerRecord = pda(
+        env.program,
+        Buffer.from("buyer_record"),
+        fixture.listing.toBuffer(),
+        buyer.buyer.publicKey.toBuffer()
+      );
+      assert.isFalse(await accountExists(env, fixture.listing));
+      assert.isFalse(await accountExists(env, buyerRecord));
+    });
+  });
+
+  describe("seller stats", () => {
+    it("accumulates sales and cancellations for a seller", async () => {
+      const fixture = await createListing(env, market, { price: 1_000_000, quantity: 3 });
+      await executeSale(env, market, fixture, await newBuyer(env, market, fixture.nftMint));
+      await executeSale(env, market, fixture, await newBuyer(env, market, fixture.nftMint));
+
+      await env.program.methods
+        .cancelListing()
+        .accountsPartial({
+          seller: fixture.seller.publicKey,
+          listing: fixture.listing,
+          nftMint: fixture.nftMint,
+          sellerNftAccount: fixture.sellerNftAccount,
+          vaultNftAccount: fixture.vault,
+          tokenProgram: fixture.tokenProgram,
+        })
+        .signers([fixture.seller])
+        .rpc();
+
+      const stats = await env.program.methods
+        .getSellerStats()
+        .accountsPartial({
+          sellerStats: pda(env.program, Buffer.from("seller_stats"), fixture.seller.publicKey.toBuffer()),
+        })
+        .view();
+      assert.ok(stats.seller.equals(fixture.seller.publicKey));
+      assert.equal(stats.totalSales.toNumber(), 2);
+      assert.equal(stats.totalVolume.toNumber(), 2_000_000);
+      assert.equal(stats.totalCancellations.toNumber(), 1);
+    });
+  });
+
+  describe("account space", () => {
+    it("allocates listings and bids at their derived size", async () => {
+      const fixture = await createListing(env, market);
+      const bid = await placeBid(env, market, fixture.nftMint, 500_000);
+
+      const listingInfo = await env.context.banksClient.getAccount(fixture.listing);
+      const bidInfo = await env.context.banksClient.getAccount(bid.bid);
+      assert.equal(listingInfo.data.length, env.program.account.listing.size);
+      assert.equal(bidInfo.data.length, env.program.account.bid.size);
+    });
+  });
+
+  describe("partial fills", () => {
+    it("buys 3 of 5 units and then the remaining 2", async () => {
+      const fixture = await createListing(env, market, { price: 100_000, quantity: 5 });
+      const buyer = await newBuyer(env, market, fixture.nftMint);
+
+      await executeSale(env, market, fixture, buyer, { buyQuantity: 3 });
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(3));
+      assert.equal(await tokenBalance(env, buyer.buyerPaymentAccount), BigInt(1_000_000_000 - 300_000));
+      const listing = await env.program.account.listing.fetch(fixture.listing);
+      assert.equal(listing.quantity.toNumber(), 2);
+      assert.equal(await tokenBalance(env, fixture.vault), BigInt(2));
+
+      await expectError(
+        executeSale(env, market, fixture, buyer, { buyQuantity: 3 }),
+        "InsufficientQuantity"
+      );
+
+      await executeSale(env, market, fixture, buyer, { buyQuantity: 2 });
+      assert.equal(await tokenBalance(env, buyer.buyerNftAccount), BigInt(5));
+      assert.isFalse(await accountExists(env, fixture.listing));
+      assert.isFalse(await accountExists(env, fixture.vault));
+    });
   });
 });
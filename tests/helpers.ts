@@ -0,0 +1,625 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import {
+  AddressLookupTableAccount,
+  AddressLookupTableProgram,
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+} from "@solana/web3.js";
+import {
+  ExtensionType,
+  MINT_SIZE,
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  createAssociatedTokenAccountIdempotentInstruction,
+  createInitializeMint2Instruction,
+  createInitializeTransferFeeConfigInstruction,
+  createMintToInstruction,
+  getAssociatedTokenAddressSync,
+  getMintLen,
+  unpackAccount,
+} from "@solana/spl-token";
+import {
+  TokenStandard,
+  getMetadataAccountDataSerializer,
+} from "@metaplex-foundation/mpl-token-metadata";
+import { none, publicKey as umiPublicKey, some } from "@metaplex-foundation/umi";
+import { keccak_256 } from "@noble/hashes/sha3";
+import { BankrunProvider } from "anchor-bankrun";
+import { Clock, ProgramTestContext, startAnchor } from "solana-bankrun";
+import { FlypMarketplace } from "../target/types/flyp_marketplace";
+
+const IDL = require("../target/idl/flyp_marketplace.json");
+
+export const TOKEN_METADATA_PROGRAM_ID = new PublicKey(
+  "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+);
+
+export const U64_MAX = BigInt("18446744073709551615");
+
+export type Env = {
+  context: ProgramTestContext;
+  provider: BankrunProvider;
+  program: Program<FlypMarketplace>;
+  payer: Keypair;
+};
+
+export async function setup(): Promise<Env> {
+  const context = await startAnchor(".", [], []);
+  const provider = new BankrunProvider(context);
+  anchor.setProvider(provider);
+  const program = new Program<FlypMarketplace>(IDL, provider);
+  return { context, provider, program, payer: context.payer };
+}
+
+export function pda(program: Program<FlypMarketplace>, ...seeds: (Buffer | Uint8Array)[]) {
+  return PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+}
+
+export function configPda(program: Program<FlypMarketplace>) {
+  return pda(program, Buffer.from("config"));
+}
+
+export function feeVaultPda(program: Program<FlypMarketplace>) {
+  return pda(program, Buffer.from("fee_vault"));
+}
+
+export function listingPda(program: Program<FlypMarketplace>, seller: PublicKey, mint: PublicKey) {
+  return pda(program, Buffer.from("listing"), seller.toBuffer(), mint.toBuffer());
+}
+
+export function vaultPda(program: Program<FlypMarketplace>, seller: PublicKey, mint: PublicKey) {
+  return pda(program, Buffer.from("vault"), seller.toBuffer(), mint.toBuffer());
+}
+
+export function statsPda(program: Program<FlypMarketplace>, collectionMint: PublicKey) {
+  return pda(program, Buffer.from("stats"), collectionMint.toBuffer());
+}
+
+export function bidPda(
+  program: Program<FlypMarketplace>,
+  bidder: PublicKey,
+  mint: PublicKey,
+  nonce: number | bigint = 0
+) {
+  const nonceBytes = new anchor.BN(nonce.toString()).toArrayLike(Buffer, "le", 8);
+  return pda(program, Buffer.from("bid"), bidder.toBuffer(), mint.toBuffer(), nonceBytes);
+}
+
+// The nonce the bidder's next bid will be created with
+export async function nextBidNonce(env: Env, bidder: PublicKey) {
+  const counter = await env.program.account.bidCounter.fetchNullable(
+    pda(env.program, Buffer.from("bid_counter"), bidder.toBuffer())
+  );
+  return counter ? BigInt(counter.nextNonce.toString()) : BigInt(0);
+}
+
+export async function fund(env: Env, to: PublicKey, lamports = 10_000_000_000) {
+  const tx = new Transaction().add(
+    SystemProgram.transfer({ fromPubkey: env.payer.publicKey, toPubkey: to, lamports })
+  );
+  await env.provider.sendAndConfirm(tx, [env.payer]);
+}
+
+export async function newUser(env: Env): Promise<Keypair> {
+  const user = Keypair.generate();
+  await fund(env, user.publicKey);
+  return user;
+}
+
+export async function createMint(
+  env: Env,
+  decimals = 0,
+  programId = TOKEN_PROGRAM_ID
+): Promise<PublicKey> {
+  const mint = Keypair.generate();
+  const rent = await env.context.banksClient.getRent();
+  const tx = new Transaction().add(
+    SystemProgram.createAccount({
+      fromPubkey: env.payer.publicKey,
+      newAccountPubkey: mint.publicKey,
+      space: MINT_SIZE,
+      lamports: Number(rent.minimumBalance(BigInt(MINT_SIZE))),
+      programId,
+    }),
+    createInitializeMint2Instruction(mint.publicKey, decimals, env.payer.publicKey, null, programId)
+  );
+  await env.provider.sendAndConfirm(tx, [env.payer, mint]);
+  return mint.publicKey;
+}
+
+export async function createTransferFeeMint(
+  env: Env,
+  transferFeeBasisPoints: number,
+  maximumFee: bigint
+): Promise<PublicKey> {
+  const mint = Keypair.generate();
+  const space = getMintLen([ExtensionType.TransferFeeConfig]);
+  const rent = await env.context.banksClient.getRent();
+  const tx = new Transaction().add(
+    SystemProgram.createAccount({
+      fromPubkey: env.payer.publicKey,
+      newAccountPubkey: mint.publicKey,
+      space,
+      lamports: Number(rent.minimumBalance(BigInt(space))),
+      programId: TOKEN_2022_PROGRAM_ID,
+    }),
+    createInitializeTransferFeeConfigInstruction(
+      mint.publicKey,
+      env.payer.publicKey,
+      env.payer.publicKey,
+      transferFeeBasisPoints,
+      maximumFee,
+      TOKEN_2022_PROGRAM_ID
+    ),
+    createInitializeMint2Instruction(mint.publicKey, 0, env.payer.publicKey, null, TOKEN_2022_PROGRAM_ID)
+  );
+  await env.provider.sendAndConfirm(tx, [env.payer, mint]);
+  return mint.publicKey;
+}
+
+export async function createAta(
+  env: Env,
+  mint: PublicKey,
+  owner: PublicKey,
+  programId = TOKEN_PROGRAM_ID
+): Promise<PublicKey> {
+  const ata = getAssociatedTokenAddressSync(mint, owner, true, programId);
+  const tx = new Transaction().add(
+    createAssociatedTokenAccountIdempotentInstruction(env.payer.publicKey, ata, owner, mint, programId)
+  );
+  await env.provider.sendAndConfirm(tx, [env.payer]);
+  return ata;
+}
+
+export async function mintTo(
+  env: Env,
+  mint: PublicKey,
+  owner: PublicKey,
+  amount: number | bigint,
+  programId = TOKEN_PROGRAM_ID
+) {
+  const ata = await createAta(env, mint, owner, programId);
+  const tx = new Transaction().add(
+    createMintToInstruction(mint, ata, env.payer.publicKey, amount, [], programId)
+  );
+  await env.provider.sendAndConfirm(tx, [env.payer]);
+  return ata;
+}
+
+export async function tokenBalance(env: Env, account: PublicKey): Promise<bigint> {
+  const info = await env.context.banksClient.getAccount(account);
+  if (!info) return BigInt(0);
+  return unpackAccount(account, { ...info, data: Buffer.from(info.data) }, info.owner).amount;
+}
+
+export async function tokenAccount(env: Env, account: PublicKey) {
+  const info = await env.context.banksClient.getAccount(account);
+  return unpackAccount(account, { ...info, data: Buffer.from(info.data) }, info.owner);
+}
+
+export async function lamports(env: Env, address: PublicKey): Promise<bigint> {
+  return env.context.banksClient.getBalance(address);
+}
+
+export async function accountExists(env: Env, address: PublicKey): Promise<boolean> {
+  const info = await env.context.banksClient.getAccount(address);
+  return info !== null && info.lamports > 0;
+}
+
+export async function now(env: Env): Promise<bigint> {
+  const clock = await env.context.banksClient.getClock();
+  return clock.unixTimestamp;
+}
+
+export async function warpTo(env: Env, unixTimestamp: bigint) {
+  const clock = await env.context.banksClient.getClock();
+  env.context.setClock(
+    new Clock(
+      clock.slot + BigInt(1),
+      clock.epochStartTimestamp,
+      clock.epoch,
+      clock.leaderScheduleEpoch,
+      unixTimestamp
+    )
+  );
+}
+
+export async function expectError(promise: Promise<unknown>, code: string) {
+  try {
+    await promise;
+  } catch (err) {
+    const message = String(err?.error?.errorCode?.code ?? err?.message ?? err);
+    if (!message.includes(code)) {
+      throw new Error(`expected ${code}, got ${message}`);
+    }
+    return;
+  }
+  throw new Error(`expected ${code}, but the transaction succeeded`);
+}
+
+export function metadataPda(mint: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM_ID.toBuffer(), mint.toBuffer()],
+    TOKEN_METADATA_PROGRAM_ID
+  )[0];
+}
+
+export type CreatorInput = { address: PublicKey; share: number; verified: boolean };
+
+// Writes a Metaplex metadata account directly into the bank so sales can read creators
+export function setMetadata(
+  env: Env,
+  mint: PublicKey,
+  creators: CreatorInput[] = [],
+  collection?: PublicKey
+) {
+  const data = getMetadataAccountDataSerializer().serialize({
+    updateAuthority: umiPublicKey(env.payer.publicKey.toBase58()),
+    mint: umiPublicKey(mint.toBase58()),
+    name: "Flyp",
+    symbol: "FLYP",
+    uri: "",
+    sellerFeeBasisPoints: 500,
+    creators: creators.length
+      ? some(
+          creators.map((c) => ({
+            address: umiPublicKey(c.address.toBase58()),
+            share: c.share,
+            verified: c.verified,
+          }))
+        )
+      : none(),
+    primarySaleHappened: false,
+    isMutable: true,
+    editionNonce: none(),
+    tokenStandard: some(TokenStandard.NonFungible),
+    collection: collection
+      ? some({ verified: true, key: umiPublicKey(collection.toBase58()) })
+      : none(),
+    uses: none(),
+    collectionDetails: none(),
+    programmableConfig: none(),
+  });
+  const address = metadataPda(mint);
+  env.context.setAccount(address, {
+    lamports: 1_000_000_000,
+    data: Buffer.from(data),
+    owner: TOKEN_METADATA_PROGRAM_ID,
+    executable: false,
+  });
+  return address;
+}
+
+// Writes an active address lookup table straight into the bank, so tests can
+// send transactions that reference more accounts than fit in a legacy message
+export function lookupTable(env: Env, addresses: PublicKey[]): AddressLookupTableAccount {
+  const key = Keypair.generate().publicKey;
+  const meta = Buffer.alloc(56);
+  meta.writeUInt32LE(1, 0); // LookupTable discriminator
+  meta.writeBigUInt64LE(U64_MAX, 4); // never deactivated
+  meta.writeBigUInt64LE(BigInt(0), 12); // last extended slot
+  meta.writeUInt8(addresses.length, 20); // every address is already active
+  const data = Buffer.concat([meta, ...addresses.map((address) => address.toBuffer())]);
+  env.context.setAccount(key, {
+    lamports: 1_000_000_000,
+    data,
+    owner: AddressLookupTableProgram.programId,
+    executable: false,
+  });
+  return new AddressLookupTableAccount({
+    key,
+    state: AddressLookupTableAccount.deserialize(data),
+  });
+}
+
+export type Market = {
+  paymentMint: PublicKey;
+  feeAccount: PublicKey;
+};
+
+export async function initializeMarketplace(env: Env, feeBps = 250): Promise<Market> {
+  const paymentMint = await createMint(env, 6);
+  // Fees accumulate in the fee vault's token account for the payment mint
+  const feeAccount = await createAta(env, paymentMint, feeVaultPda(env.program));
+  await env.program.methods
+    .initializeMarketplace(new anchor.BN(feeBps))
+    .accountsPartial({
+      authority: env.payer.publicKey,
+      feeRecipient: env.payer.publicKey,
+    })
+    .rpc();
+  return { paymentMint, feeAccount };
+}
+
+export type ListingFixture = {
+  seller: Keypair;
+  nftMint: PublicKey;
+  listing: PublicKey;
+  vault: PublicKey;
+  metadata: PublicKey;
+  collectionMint: PublicKey;
+  sellerNftAccount: PublicKey;
+  sellerPaymentAccount: PublicKey;
+  tokenProgram: PublicKey;
+};
+
+export async function createListing(
+  env: Env,
+  market: Market,
+  opts: {
+    price?: number | bigint;
+    quantity?: number;
+    expiry?: number | bigint;
+    creators?: CreatorInput[];
+    tokenProgram?: PublicKey;
+    collection?: PublicKey;
+    whitelistEnabled?: boolean;
+    paymentMint?: PublicKey | null;
+    royaltyEnforcement?: "mandatory" | "optional" | "disabled" | "lazy";
+    reservePrice?: number;
+    maxQuantityPerBuyer?: number;
+    nftMint?: PublicKey;
+    goesLiveAt?: number | bigint;
+    buyerAllowlist?: PublicKey | null;
+    seller?: Keypair;
+    cacheCreators?: boolean;
+    collectionPolicy?: PublicKey | null;
+    listingMetadata?: string;
+  } = {}
+): Promise<ListingFixture> {
+  const {
+    price = 1_000_000,
+    quantity = 1,
+    expiry = 0,
+    creators = [],
+    tokenProgram = TOKEN_PROGRAM_ID,
+    collection,
+    whitelistEnabled = false,
+    paymentMint = market.paymentMint,
+    royaltyEnforcement = "mandatory",
+    reservePrice = 0,
+    maxQuantityPerBuyer = 0,
+    goesLiveAt = 0,
+    buyerAllowlist = null,
+    cacheCreators = true,
+    // Any verified collection needs its policy PDA, whether or not a policy is set
+    collectionPolicy = collection ? pda(env.program, Buffer.from("policy"), collection.toBuffer()) : null,
+    listingMetadata = "",
+  } = opts;
+  const seller = opts.seller ?? (await newUser(env));
+  const nftMint = opts.nftMint ?? (await createMint(env, 0, tokenProgram));
+  const sellerNftAccount = await mintTo(env, nftMint, seller.publicKey, quantity, tokenProgram);
+  const sellerPaymentAccount = await createAta(env, market.paymentMint, seller.publicKey);
+  const metadata = setMetadata(env, nftMint, creators, collection);
+  const listing = listingPda(env.program, seller.publicKey, nftMint);
+  const vault = vaultPda(env.program, seller.publicKey, nftMint);
+
+  await env.program.methods
+    .createListing(
+      new anchor.BN(price.toString()),
+      new anchor.BN(quantity),
+      new anchor.BN(expiry.toString()),
+      whitelistEnabled,
+      paymentMint,
+      { [royaltyEnforcement]: {} } as any,
+      new anchor.BN(reservePrice),
+      new anchor.BN(maxQuantityPerBuyer),
+      new anchor.BN(goesLiveAt.toString()),
+      buyerAllowlist,
+      listingMetadata
+    )
+    .accountsPartial({
+      seller: seller.publicKey,
+      nftMint,
+      listing,
+      sellerNftAccount,
+      vaultNftAccount: vault,
+      metadata,
+      collectionPolicy,
+      tokenProgram,
+    })
+    .signers([seller])
+    .rpc();
+
+  if (!cacheCreators) {
+    // Stand in for a listing made before creators were cached by clearing the cache
+    const info = await env.context.banksClient.getAccount(listing);
+    const decoded = env.program.coder.accounts.decode("listing", Buffer.from(info.data));
+    decoded.creatorCache = [];
+    const data = Buffer.alloc(info.data.length);
+    (await env.program.coder.accounts.encode("listing", decoded)).copy(data);
+    env.context.setAccount(listing, { ...info, data });
+  }
+
+  return {
+    seller,
+    nftMint,
+    listing,
+    vault,
+    metadata,
+    collectionMint: collection ?? nftMint,
+    sellerNftAccount,
+    sellerPaymentAccount,
+    tokenProgram,
+  };
+}
+
+export type Buyer = {
+  buyer: Keypair;
+  buyerPaymentAccount: PublicKey;
+  buyerNftAccount: PublicKey;
+  secondBidderAccount: PublicKey;
+};
+
+export async function newBuyer(
+  env: Env,
+  market: Market,
+  nftMint: PublicKey,
+  funds: number | bigint = 1_000_000_000,
+  tokenProgram = TOKEN_PROGRAM_ID
+): Promise<Buyer> {
+  const buyer = await newUser(env);
+  const buyerPaymentAccount = await mintTo(env, market.paymentMint, buyer.publicKey, funds);
+  const buyerNftAccount = getAssociatedTokenAddressSync(nftMint, buyer.publicKey, false, tokenProgram);
+  const secondBidderAccount = await createAta(env, market.paymentMint, Keypair.generate().publicKey);
+  return { buyer, buyerPaymentAccount, buyerNftAccount, secondBidderAccount };
+}
+
+export type SaleOptions = {
+  maxPrice?: bigint;
+  secondHighestBid?: number;
+  whitelistProof?: number[][] | null;
+  buyQuantity?: number;
+  accounts?: Record<string, PublicKey>;
+};
+
+export function executeSaleMethod(
+  env: Env,
+  market: Market,
+  fixture: ListingFixture,
+  buyer: Buyer,
+  opts: SaleOptions = {}
+) {
+  const {
+    maxPrice = U64_MAX,
+    secondHighestBid = 0,
+    whitelistProof = null,
+    buyQuantity = 1,
+    accounts = {},
+  } = opts;
+  return env.program.methods
+    .executeSale(
+      new anchor.BN(maxPrice.toString()),
+      new anchor.BN(secondHighestBid),
+      whitelistProof,
+      new anchor.BN(buyQuantity)
+    )
+    .accountsPartial({
+      buyer: buyer.buyer.publicKey,
+      seller: fixture.seller.publicKey,
+      listing: fixture.listing,
+      config: configPda(env.program),
+      nftMint: fixture.nftMint,
+      vaultNftAccount: fixture.vault,
+      buyerNftAccount: buyer.buyerNftAccount,
+      paymentMint: market.paymentMint,
+      buyerPaymentAccount: buyer.buyerPaymentAccount,
+      sellerPaymentAccount: fixture.sellerPaymentAccount,
+      marketplaceFeeAccount: market.feeAccount,
+      secondBidderAccount: buyer.secondBidderAccount,
+      secondBid: null,
+      metadata: fixture.metadata,
+      collectionMint: fixture.collectionMint,
+      whitelist: null,
+      marketStats: null,
+      tokenProgram: fixture.tokenProgram,
+      paymentTokenProgram: TOKEN_PROGRAM_ID,
+      ...accounts,
+    })
+    .signers([buyer.buyer]);
+}
+
+export function executeSale(
+  env: Env,
+  market: Market,
+  fixture: ListingFixture,
+  buyer: Buyer,
+  opts: SaleOptions = {}
+) {
+  return executeSaleMethod(env, market, fixture, buyer, opts).rpc();
+}
+
+export type BidFixture = {
+  bidder: Keypair;
+  nftMint: PublicKey;
+  bid: PublicKey;
+  bidderPaymentAccount: PublicKey;
+  escrowPaymentAccount: PublicKey;
+};
+
+export async function placeBid(
+  env: Env,
+  market: Market,
+  nftMint: PublicKey,
+  price: number,
+  funds = 10_000_000,
+  bidder?: Keypair,
+  deadline?: bigint,
+  expiry = BigInt(0)
+): Promise<BidFixture> {
+  bidder = bidder ?? (await newUser(env));
+  deadline = deadline ?? (await now(env)) + BigInt(60);
+  const bidderPaymentAccount = await mintTo(env, market.paymentMint, bidder.publicKey, funds);
+  const nonce = await nextBidNonce(env, bidder.publicKey);
+  const bid = bidPda(env.program, bidder.publicKey, nftMint, nonce);
+  const escrowPaymentAccount = getAssociatedTokenAddressSync(market.paymentMint, bid, true);
+
+  await env.program.methods
+    .placeBid(
+      new anchor.BN(price),
+      new anchor.BN(expiry.toString()),
+      new anchor.BN(deadline.toString())
+    )
+    .accountsPartial({
+      bidder: bidder.publicKey,
+      nftMint,
+      bid,
+      paymentMint: market.paymentMint,
+      bidderPaymentAccount,
+      escrowPaymentAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    .signers([bidder])
+    .rpc();
+
+  return { bidder, nftMint, bid, bidderPaymentAccount, escrowPaymentAccount };
+}
+
+export function cancelBid(env: Env, market: Market, fixture: BidFixture) {
+  return env.program.methods
+    .cancelBid()
+    .accountsPartial({
+      bidder: fixture.bidder.publicKey,
+      bid: fixture.bid,
+      paymentMint: market.paymentMint,
+      bidderPaymentAccount: fixture.bidderPaymentAccount,
+      escrowPaymentAccount: fixture.escrowPaymentAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    .signers([fixture.bidder])
+    .rpc();
+}
+
+// Merkle tree over keccak(pubkey) leaves with sorted-pair hashing, matching the on-chain verifier
+export function merkleTree(keys: PublicKey[]) {
+  const hashPair = (a: Uint8Array, b: Uint8Array) =>
+    Buffer.compare(Buffer.from(a), Buffer.from(b)) <= 0
+      ? keccak_256(new Uint8Array([...a, ...b]))
+      : keccak_256(new Uint8Array([...b, ...a]));
+
+  const levels: Uint8Array[][] = [keys.map((key) => keccak_256(key.toBytes()))];
+  while (levels[levels.length - 1].length > 1) {
+    const level = levels[levels.length - 1];
+    const next: Uint8Array[] = [];
+    for (let i = 0; i < level.length; i += 2) {
+      next.push(i + 1 < level.length ? hashPair(level[i], level[i + 1]) : level[i]);
+    }
+    levels.push(next);
+  }
+
+  const root = Array.from(levels[levels.length - 1][0]);
+  const proof = (key: PublicKey) => {
+    let index = keys.findIndex((k) => k.equals(key));
+    const path: number[][] = [];
+    for (const level of levels.slice(0, -1)) {
+      const sibling = index ^ 1;
+      if (sibling < level.length) path.push(Array.from(level[sibling]));
+      index = Math.floor(index / 2);
+    }
+    return path;
+  };
+  return { root, proof };
+}